@@ -0,0 +1,15 @@
+// Build script - captures the git commit and build time for src/build_info.rs
+//
+// Outside a git checkout (e.g. a source tarball) vergen still emits the
+// variables, with a placeholder value and a build warning.
+
+use vergen::EmitBuilder;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    EmitBuilder::builder()
+        .build_timestamp()
+        .git_sha(false)
+        .git_dirty(false)
+        .emit()?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+// Adaptive module - load-based collect intervals
+//
+// Collecting every few seconds on a box that is already saturated adds to
+// the problem. With `adaptive.enabled`, each task looks at the latest
+// `load_1min_per_core` of the LoadAverage metric, read from the scheduler's
+// `LastValues` cache, at the start of every store window. Above
+// `high_load_per_core` its collect interval is multiplied by `factor`, up to
+// `max_interval_secs` (and never past `store_timeout`, so every window still
+// gets a sample); below `low_load_per_core` it is divided again, down to the
+// configured interval.
+//
+// Collecting less lowers the load, which would narrow the interval, which
+// raises the load again. Three things keep that loop from oscillating:
+// - the two thresholds leave a dead band in which the interval is held;
+// - narrowing waits `cooldown_secs` after the last change, and goes one step
+//   at a time, while widening reacts at once;
+// - the 1-minute load average reacts slowly to begin with, and is read only
+//   once per store window.
+// LoadAverage itself always keeps its configured interval, so the loop never
+// loses its input, and so does the Canary, whose absence is alerted on.
+
+use bson::{Bson, Document};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::config::AdaptiveSettings;
+
+/// Metric whose load drives the adaptive intervals
+pub const LOAD_METRIC: &str = "LoadAverage";
+
+/// Field of `LOAD_METRIC` compared with the thresholds
+const LOAD_FIELD: &str = "load_1min_per_core";
+
+/// Latest collected document of each metric of one scheduler, shared by its tasks
+#[derive(Clone, Default)]
+pub struct LastValues {
+    latest: Arc<Mutex<HashMap<String, (Instant, Document)>>>,
+}
+
+impl LastValues {
+    /// Replaces `metric_name`'s latest document
+    pub fn record(&self, metric_name: &str, doc: &Document) {
+        let mut latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        latest.insert(metric_name.to_string(), (Instant::now(), doc.clone()));
+    }
+
+    /// Numeric `field` of `metric_name`'s latest document, unless it was
+    /// collected longer than `max_age` ago
+    pub fn get_f64(&self, metric_name: &str, field: &str, max_age: Duration) -> Option<f64> {
+        let latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        let (at, doc) = latest.get(metric_name)?;
+        if at.elapsed() > max_age {
+            return None;
+        }
+        match doc.get(field)? {
+            Bson::Double(v) => Some(*v).filter(|v| v.is_finite()),
+            Bson::Int32(v) => Some(f64::from(*v)),
+            Bson::Int64(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    /// Latest per-core 1-minute load, if fresher than `max_age`
+    pub fn load_per_core(&self, max_age: Duration) -> Option<f64> {
+        self.get_f64(LOAD_METRIC, LOAD_FIELD, max_age)
+    }
+}
+
+/// One task's current collect interval
+#[derive(Default)]
+pub struct AdaptiveInterval {
+    /// Interval in use; None while at the configured one
+    current: Option<u64>,
+    /// When `current` last changed
+    changed_at: Option<Instant>,
+}
+
+impl AdaptiveInterval {
+    /// Interval for the next store window, in seconds
+    ///
+    /// `base_secs` is the configured interval and `ceiling_secs` the store
+    /// interval. Without a (fresh) `load_per_core`, or with adaptive
+    /// intervals off, returns to `base_secs` straight away.
+    pub fn next(
+        &mut self,
+        metric_name: &str,
+        base_secs: u64,
+        ceiling_secs: u64,
+        load_per_core: Option<f64>,
+        settings: &AdaptiveSettings,
+        now: Instant,
+    ) -> u64 {
+        let (true, Some(load)) = (settings.enabled, load_per_core) else {
+            if self.current.take().is_some() {
+                info!("'{}' back to its {}s collect interval", metric_name, base_secs);
+            }
+            return base_secs;
+        };
+        let max = settings.max_interval_secs.min(ceiling_secs).max(base_secs);
+        let current = self.current.unwrap_or(base_secs).clamp(base_secs, max);
+        let cooled_down = self
+            .changed_at
+            .is_none_or(|at| now.duration_since(at) >= Duration::from_secs(settings.cooldown_secs));
+
+        let next = if load > settings.high_load_per_core {
+            ((current as f64 * settings.factor).ceil() as u64).min(max)
+        } else if load < settings.low_load_per_core && cooled_down {
+            ((current as f64 / settings.factor) as u64).max(base_secs)
+        } else {
+            current
+        };
+        if next != current {
+            info!(
+                "'{}' collect interval {}s -> {}s (load per core {:.2})",
+                metric_name, current, next, load
+            );
+            self.changed_at = Some(now);
+        }
+        self.current = (next != base_secs).then_some(next);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widens_at_once_and_narrows_after_cooldown() {
+        let settings = AdaptiveSettings { enabled: true, max_interval_secs: 30, cooldown_secs: 300, ..AdaptiveSettings::default() };
+        let mut interval = AdaptiveInterval::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert_eq!(interval.next("Memory", 5, 60, Some(3.0), &settings, at(0)), 10);
+        assert_eq!(interval.next("Memory", 5, 60, Some(3.0), &settings, at(60)), 20);
+        assert_eq!(interval.next("Memory", 5, 60, Some(3.0), &settings, at(120)), 30);
+        // Dead band: held
+        assert_eq!(interval.next("Memory", 5, 60, Some(1.5), &settings, at(180)), 30);
+        // Low, but within the cooldown of the last change
+        assert_eq!(interval.next("Memory", 5, 60, Some(0.2), &settings, at(240)), 30);
+        assert_eq!(interval.next("Memory", 5, 60, Some(0.2), &settings, at(420)), 15);
+        assert_eq!(interval.next("Memory", 5, 60, Some(0.2), &settings, at(720)), 7);
+        assert_eq!(interval.next("Memory", 5, 60, Some(0.2), &settings, at(1020)), 5);
+        // Capped at the store interval
+        assert_eq!(interval.next("Memory", 5, 8, Some(9.0), &settings, at(1080)), 8);
+        // Load unknown: straight back
+        assert_eq!(interval.next("Memory", 5, 8, None, &settings, at(1140)), 5);
+    }
+}
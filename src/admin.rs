@@ -0,0 +1,113 @@
+// Admin module - optional HTTP server for operating a live process
+//
+// Started only when `--admin-addr <host:port>` is given. Bind it to localhost
+// or a management network: endpoints are unauthenticated.
+//
+// Endpoints:
+//   GET  /loglevel   → current tracing filter
+//   POST /loglevel   {"filter": "debug"} → replace the tracing filter at runtime
+//   GET  /pause      → metrics currently paused
+//   POST /pause/{metric}  → skip collection of a metric until resumed
+//   POST /resume/{metric} → collect it again
+
+use std::net::SocketAddr;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::scheduler::PauseFlags;
+
+/// Handle for swapping the global tracing filter (created in `init_logging`)
+pub type LogHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Shared state handed to every admin route
+#[derive(Clone)]
+pub struct AdminState {
+    pub log_handle: LogHandle,
+    pub pause: PauseFlags,
+}
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    /// `EnvFilter` directive, e.g. "debug" or "info,metrics_collector::scheduler=trace"
+    filter: String,
+}
+
+/// Binds `addr` and serves admin requests in a background task
+pub async fn spawn_admin_server(addr: SocketAddr, state: AdminState) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/loglevel", get(get_log_level).post(set_log_level))
+        .route("/pause", get(get_paused))
+        .route("/pause/:metric", post(pause_metric))
+        .route("/resume/:metric", post(resume_metric))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Admin server listening on http://{}", listener.local_addr()?);
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("Admin server stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn get_log_level(State(state): State<AdminState>) -> (StatusCode, Json<Value>) {
+    match state.log_handle.with_current(|filter| filter.to_string()) {
+        Ok(filter) => (StatusCode::OK, Json(json!({ "filter": filter }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+async fn set_log_level(
+    State(state): State<AdminState>,
+    Json(request): Json<LogLevelRequest>,
+) -> (StatusCode, Json<Value>) {
+    let filter = match EnvFilter::try_new(&request.filter) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("invalid filter '{}': {}", request.filter, e) })),
+            )
+        }
+    };
+
+    match state.log_handle.reload(filter) {
+        Ok(()) => {
+            info!("Log filter changed to '{}' via admin API", request.filter);
+            (StatusCode::OK, Json(json!({ "filter": request.filter })))
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+async fn get_paused(State(state): State<AdminState>) -> Json<Value> {
+    Json(json!({ "paused": state.pause.paused() }))
+}
+
+async fn pause_metric(State(state): State<AdminState>, Path(metric): Path<String>) -> (StatusCode, Json<Value>) {
+    set_paused(&state, metric, true)
+}
+
+async fn resume_metric(State(state): State<AdminState>, Path(metric): Path<String>) -> (StatusCode, Json<Value>) {
+    set_paused(&state, metric, false)
+}
+
+fn set_paused(state: &AdminState, metric: String, paused: bool) -> (StatusCode, Json<Value>) {
+    if !state.pause.set(&metric, paused) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("no collection task for metric '{}'", metric) })),
+        );
+    }
+    info!("'{}' {} via admin API", metric, if paused { "paused" } else { "resumed" });
+    (StatusCode::OK, Json(json!({ "metric": metric, "paused": paused })))
+}
@@ -5,11 +5,21 @@
 
 use std::collections::HashMap;
 use bson::{doc, Bson, Document};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+
+use crate::config::SizeUnits;
+use crate::metrics::numeric::{size_field, Scale};
 
 // These fields are stored as plain values rather than {avg, min, max}
 // because they are constant within a collection window.
-const PASSTHROUGH_FIELDS: &[&str] = &["cpu_cores", "total_mb", "swap_total_mb"];
+// Memory totals are named after `numeric.size_units`.
+pub const PASSTHROUGH_FIELDS: &[&str] = &[
+    "cpu_cores", "total_mib", "swap_total_mib", "total_mb", "swap_total_mb",
+];
+
+// These flags are true for the window if any sample set them, so a
+// condition that cleared before the flush is still recorded.
+pub const ANY_SAMPLE_FLAGS: &[&str] = &["overloaded"];
 
 // ---------------------------------------------------------------------------
 // MetricBuffer
@@ -18,6 +28,8 @@ const PASSTHROUGH_FIELDS: &[&str] = &["cpu_cores", "total_mb", "swap_total_mb"];
 pub struct MetricBuffer {
     samples: Vec<HashMap<String, f64>>,
     last_raw: Option<Document>,
+    /// `ANY_SAMPLE_FLAGS` seen in the window, OR-ed over its samples
+    flags: HashMap<String, bool>,
 }
 
 impl MetricBuffer {
@@ -25,13 +37,20 @@ impl MetricBuffer {
         MetricBuffer {
             samples: Vec::new(),
             last_raw: None,
+            flags: HashMap::new(),
         }
     }
 
     /// Push a raw collected document into the buffer.
-    /// Extracts top-level numeric fields; non-numeric fields (arrays, subdocs) are skipped.
+    /// Extracts top-level numeric fields; non-numeric fields (flags, arrays, subdocs)
+    /// are carried over from the latest sample on flush.
     pub fn push(&mut self, doc: &Document) {
         self.last_raw = Some(doc.clone());
+        for flag in ANY_SAMPLE_FLAGS {
+            if let Ok(set) = doc.get_bool(flag) {
+                *self.flags.entry(flag.to_string()).or_default() |= set;
+            }
+        }
 
         let mut map = HashMap::new();
         for (key, val) in doc.iter() {
@@ -39,7 +58,8 @@ impl MetricBuffer {
                 continue;
             }
             let num = match val {
-                Bson::Double(v)  => Some(*v),
+                // NaN/Infinity would turn the whole window's avg/min/max into NaN
+                Bson::Double(v) if v.is_finite() => Some(*v),
                 Bson::Int32(v)   => Some(*v as f64),
                 Bson::Int64(v)   => Some(*v as f64),
                 _                => None,
@@ -58,10 +78,14 @@ impl MetricBuffer {
     ///
     /// - If 2+ samples with numeric fields: returns aggregated doc with avg/min/max per field
     ///   (passthrough fields stored as plain values preserving their original BSON type).
+    ///   Non-numeric fields (e.g. `cgroup_limited`) are copied from the latest sample,
+    ///   except `ANY_SAMPLE_FLAGS`, which are true if any sample set them.
     /// - If no numeric samples (e.g. DiskSpace): returns the last raw document as-is,
     ///   with an updated timestamp.
     /// - If never collected: returns None.
-    pub fn flush(&mut self, node_id: &str) -> Option<Document> {
+    ///
+    /// `now` becomes the timestamp of the returned document.
+    pub fn flush(&mut self, node_id: &str, now: DateTime<Utc>) -> Option<Document> {
         if self.samples.len() >= 2 {
             let field_names: Vec<String> = {
                 let mut set = std::collections::HashSet::new();
@@ -78,7 +102,7 @@ impl MetricBuffer {
             let sample_count = self.samples.len() as i32;
             let mut result = doc! {
                 "node": node_id,
-                "timestamp": Utc::now(),
+                "timestamp": now,
                 "sample_count": sample_count,
             };
 
@@ -103,15 +127,26 @@ impl MetricBuffer {
                 }
             }
 
+            if let Some(raw) = self.last_raw.take() {
+                for (key, val) in raw {
+                    if key != "node" && key != "timestamp" && !result.contains_key(&key) && !is_numeric(&val) {
+                        result.insert(key, val);
+                    }
+                }
+            }
+            for (flag, set) in self.flags.drain() {
+                result.insert(flag, set);
+            }
+
             self.samples.clear();
-            self.last_raw = None;
             return Some(result);
         }
 
         // No numeric samples — fall back to returning the last raw document (e.g. DiskSpace)
         self.samples.clear();
+        self.flags.clear();
         if let Some(mut raw) = self.last_raw.take() {
-            raw.insert("timestamp", Utc::now());
+            raw.insert("timestamp", now);
             Some(raw)
         } else {
             None
@@ -119,10 +154,14 @@ impl MetricBuffer {
     }
 }
 
+fn is_numeric(val: &Bson) -> bool {
+    matches!(val, Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_))
+}
+
 fn bson_for_passthrough(field: &str, value: f64) -> Bson {
     match field {
         "cpu_cores" => Bson::Int32(value as i32),
-        _           => Bson::Int64(value as i64), // total_mb, swap_total_mb
+        _           => Bson::Int64(value as i64), // total_mib, swap_total_mib
     }
 }
 
@@ -132,20 +171,34 @@ fn bson_for_passthrough(field: &str, value: f64) -> Bson {
 
 struct ContainerSample {
     id: String,
-    cpu_percent: f64,
-    memory_used_mb: f64,
-    memory_limit_mb: f64,
+    // None on a container's first frame (`cpu_warming_up`)
+    cpu_percent: Option<f64>,
+    // sizes in the unit of `DockerMetricBuffer::units`
+    memory_used: f64,
+    memory_limit: f64,
     memory_percent: f64,
-    network_rx_mb: f64,
-    network_tx_mb: f64,
-    block_read_mb: f64,
-    block_write_mb: f64,
+    network_rx: f64,
+    network_tx: f64,
+    block_read: f64,
+    block_write: f64,
+    // only with the `gpu` feature and NVML present
+    gpu_memory: Option<f64>,
+    gpu_percent: Option<f64>,
+    // image / image_id / labels when metadata collection is enabled
+    metadata: Document,
+    // set when the collector couldn't read stats for this tick
+    error: Option<String>,
 }
 
+// Descriptive per-container fields copied from the latest sample as-is
+const CONTAINER_METADATA_FIELDS: &[&str] = &["image", "image_id", "labels"];
+
 pub struct DockerMetricBuffer {
     // container name → ordered list of per-tick samples
     container_samples: HashMap<String, Vec<ContainerSample>>,
     last_raw: Option<Document>,
+    // names the size fields: memory_used_mib or memory_used_mb, ...
+    units: SizeUnits,
 }
 
 impl DockerMetricBuffer {
@@ -153,9 +206,20 @@ impl DockerMetricBuffer {
         DockerMetricBuffer {
             container_samples: HashMap::new(),
             last_raw: None,
+            units: SizeUnits::default(),
         }
     }
 
+    /// Sets the `numeric.size_units` the collector names its fields with
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
+    }
+
+    fn mb(&self, stem: &str) -> String {
+        size_field(stem, Scale::Mega, self.units)
+    }
+
     pub fn push(&mut self, doc: &Document) {
         self.last_raw = Some(doc.clone());
 
@@ -177,14 +241,20 @@ impl DockerMetricBuffer {
 
             let sample = ContainerSample {
                 id:               get_str(c, "id"),
-                cpu_percent:      get_f64(c, "cpu_percent"),
-                memory_used_mb:   get_f64(c, "memory_used_mb"),
-                memory_limit_mb:  get_f64(c, "memory_limit_mb"),
+                cpu_percent:      get_opt_f64(c, "cpu_percent"),
+                memory_used:      get_f64(c, &self.mb("memory_used")),
+                memory_limit:     get_f64(c, &self.mb("memory_limit")),
                 memory_percent:   get_f64(c, "memory_percent"),
-                network_rx_mb:    get_f64(c, "network_rx_mb"),
-                network_tx_mb:    get_f64(c, "network_tx_mb"),
-                block_read_mb:    get_f64(c, "block_read_mb"),
-                block_write_mb:   get_f64(c, "block_write_mb"),
+                network_rx:       get_f64(c, &self.mb("network_rx")),
+                network_tx:       get_f64(c, &self.mb("network_tx")),
+                block_read:       get_f64(c, &self.mb("block_read")),
+                block_write:      get_f64(c, &self.mb("block_write")),
+                gpu_memory:       get_opt_f64(c, &self.mb("gpu_memory")),
+                gpu_percent:      get_opt_f64(c, "gpu_percent"),
+                metadata:         CONTAINER_METADATA_FIELDS.iter()
+                    .filter_map(|k| c.get(*k).map(|v| (k.to_string(), v.clone())))
+                    .collect(),
+                error:            c.get_str("error").ok().map(str::to_string),
             };
 
             self.container_samples
@@ -194,10 +264,10 @@ impl DockerMetricBuffer {
         }
     }
 
-    pub fn flush(&mut self, node_id: &str) -> Option<Document> {
+    pub fn flush(&mut self, node_id: &str, now: DateTime<Utc>) -> Option<Document> {
         if self.container_samples.is_empty() {
             return self.last_raw.take().map(|mut raw| {
-                raw.insert("timestamp", Utc::now());
+                raw.insert("timestamp", now);
                 raw
             });
         }
@@ -210,40 +280,80 @@ impl DockerMetricBuffer {
 
         let mut container_docs: Vec<Bson> = self.container_samples
             .iter()
-            .map(|(name, samples)| {
-                let cpu_vals:      Vec<f64> = samples.iter().map(|s| s.cpu_percent).collect();
-                let mem_used_vals: Vec<f64> = samples.iter().map(|s| s.memory_used_mb).collect();
+            .map(|(name, all_samples)| {
+                // Failed ticks carry no numbers; keep them out of the statistics
+                let samples: Vec<&ContainerSample> = all_samples.iter()
+                    .filter(|s| s.error.is_none())
+                    .collect();
+                let last_any = all_samples.last().unwrap();
+                let error_count = all_samples.len() - samples.len();
+
+                if samples.is_empty() {
+                    // Never read successfully this window: keep it visible with its error
+                    let mut container = doc! {
+                        "id":          &last_any.id,
+                        "name":        name,
+                        "error":       last_any.error.clone().unwrap_or_default(),
+                        "error_count": error_count as i32,
+                    };
+                    container.extend(last_any.metadata.clone());
+                    return Bson::Document(container);
+                }
+
+                let cpu_vals:      Vec<f64> = samples.iter().filter_map(|s| s.cpu_percent).collect();
+                let mem_used_vals: Vec<f64> = samples.iter().map(|s| s.memory_used).collect();
                 let mem_pct_vals:  Vec<f64> = samples.iter().map(|s| s.memory_percent).collect();
 
-                let (cpu_avg, cpu_min, cpu_max)               = stats(cpu_vals.iter().copied());
                 let (mem_used_avg, mem_used_min, mem_used_max) = stats(mem_used_vals.iter().copied());
                 let (mem_pct_avg, mem_pct_min, mem_pct_max)   = stats(mem_pct_vals.iter().copied());
 
                 // constant per container
-                let memory_limit_mb = samples[0].memory_limit_mb;
+                let memory_limit = samples[0].memory_limit;
                 let id = samples[0].id.clone();
 
                 // last-sample cumulative counters
                 let last = samples.last().unwrap();
 
-                Bson::Document(doc! {
+                let mut container = doc! {
                     "id":               id,
                     "name":             name,
-                    "memory_limit_mb":  memory_limit_mb,
-                    "cpu_percent": {
-                        "avg": cpu_avg, "min": cpu_min, "max": cpu_max,
-                    },
-                    "memory_used_mb": {
+                    self.mb("memory_limit"): memory_limit,
+                    "cpu_percent":      Bson::Null,
+                    self.mb("memory_used"): {
                         "avg": mem_used_avg, "min": mem_used_min, "max": mem_used_max,
                     },
                     "memory_percent": {
                         "avg": mem_pct_avg, "min": mem_pct_min, "max": mem_pct_max,
                     },
-                    "network_rx_mb":  last.network_rx_mb,
-                    "network_tx_mb":  last.network_tx_mb,
-                    "block_read_mb":  last.block_read_mb,
-                    "block_write_mb": last.block_write_mb,
-                })
+                    self.mb("network_rx"):  last.network_rx,
+                    self.mb("network_tx"):  last.network_tx,
+                    self.mb("block_read"):  last.block_read,
+                    self.mb("block_write"): last.block_write,
+                };
+                if cpu_vals.is_empty() {
+                    // Only warming-up frames this window: no CPU reading yet
+                    container.insert("cpu_warming_up", true);
+                } else {
+                    let (cpu_avg, cpu_min, cpu_max) = stats(cpu_vals.iter().copied());
+                    container.insert("cpu_percent", doc! { "avg": cpu_avg, "min": cpu_min, "max": cpu_max });
+                }
+                let gpu_mem_vals: Vec<f64> = samples.iter().filter_map(|s| s.gpu_memory).collect();
+                if !gpu_mem_vals.is_empty() {
+                    let gpu_pct_vals: Vec<f64> = samples.iter().filter_map(|s| s.gpu_percent).collect();
+                    let (mem_avg, mem_min, mem_max) = stats(gpu_mem_vals.iter().copied());
+                    let (pct_avg, pct_min, pct_max) = stats(gpu_pct_vals.iter().copied());
+                    container.insert(self.mb("gpu_memory"), doc! { "avg": mem_avg, "min": mem_min, "max": mem_max });
+                    container.insert("gpu_percent", doc! { "avg": pct_avg, "min": pct_min, "max": pct_max });
+                }
+                container.extend(last.metadata.clone());
+                if let Some(error) = &last_any.error {
+                    container.insert("error", error.clone());
+                }
+                if error_count > 0 {
+                    container.insert("error_count", error_count as i32);
+                }
+
+                Bson::Document(container)
             })
             .collect();
 
@@ -254,12 +364,17 @@ impl DockerMetricBuffer {
             name_a.cmp(name_b)
         });
 
-        let result = doc! {
+        let mut result = doc! {
             "node":         node_id,
-            "timestamp":    Utc::now(),
+            "timestamp":    now,
             "sample_count": sample_count,
+            "container_count": container_docs.len() as i32,
             "containers":   container_docs,
         };
+        // Running containers at the last tick (more than `containers` when capped)
+        if let Some(total) = self.last_raw.as_ref().and_then(|raw| raw.get("total_containers")) {
+            result.insert("total_containers", total.clone());
+        }
 
         self.container_samples.clear();
         self.last_raw = None;
@@ -272,7 +387,7 @@ impl DockerMetricBuffer {
 // ---------------------------------------------------------------------------
 
 fn stats(iter: impl Iterator<Item = f64>) -> (f64, f64, f64) {
-    let values: Vec<f64> = iter.collect();
+    let values: Vec<f64> = iter.filter(|v| v.is_finite()).collect();
     if values.is_empty() {
         return (0.0, 0.0, 0.0);
     }
@@ -283,14 +398,139 @@ fn stats(iter: impl Iterator<Item = f64>) -> (f64, f64, f64) {
 }
 
 fn get_f64(doc: &Document, key: &str) -> f64 {
+    get_opt_f64(doc, key).unwrap_or(0.0)
+}
+
+fn get_opt_f64(doc: &Document, key: &str) -> Option<f64> {
     match doc.get(key) {
-        Some(Bson::Double(v))  => *v,
-        Some(Bson::Int32(v))   => *v as f64,
-        Some(Bson::Int64(v))   => *v as f64,
-        _                      => 0.0,
+        Some(Bson::Double(v))  => Some(*v),
+        Some(Bson::Int32(v))   => Some(*v as f64),
+        Some(Bson::Int64(v))   => Some(*v as f64),
+        _                      => None,
     }
 }
 
 fn get_str(doc: &Document, key: &str) -> String {
     doc.get_str(key).unwrap_or("").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, FakeClock};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_metric_buffer_flush_uses_clock() {
+        let clock = FakeClock::new(Utc.with_ymd_and_hms(2026, 4, 8, 12, 0, 0).unwrap());
+
+        let mut buffer = MetricBuffer::new();
+        buffer.push(&doc! { "node": "n1", "timestamp": clock.now(), "load_1min": 1.0, "cpu_cores": 8 });
+        clock.advance(chrono::Duration::seconds(5));
+        buffer.push(&doc! { "node": "n1", "timestamp": clock.now(), "load_1min": 3.0, "cpu_cores": 8 });
+        clock.advance(chrono::Duration::seconds(55));
+
+        let flushed = buffer.flush("n1", clock.now()).unwrap();
+
+        assert_eq!(
+            flushed.get_datetime("timestamp").unwrap().timestamp_millis(),
+            Utc.with_ymd_and_hms(2026, 4, 8, 12, 1, 0).unwrap().timestamp_millis()
+        );
+        assert_eq!(flushed.get_i32("sample_count").unwrap(), 2);
+        assert_eq!(flushed.get_i32("cpu_cores").unwrap(), 8);
+        let load = flushed.get_document("load_1min").unwrap();
+        assert_eq!(load.get_f64("avg").unwrap(), 2.0);
+        assert_eq!(load.get_f64("min").unwrap(), 1.0);
+        assert_eq!(load.get_f64("max").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_metric_buffer_keeps_overload_seen_mid_window() {
+        let now = Utc.with_ymd_and_hms(2026, 4, 8, 12, 0, 0).unwrap();
+        let sample = |load: f64, overloaded: bool| {
+            doc! { "node": "n1", "timestamp": now, "load_1min": load, "cpu_cores": 2, "overloaded": overloaded }
+        };
+
+        let mut buffer = MetricBuffer::new();
+        buffer.push(&sample(1.0, false));
+        buffer.push(&sample(9.5, true));
+        buffer.push(&sample(1.2, false));
+        let flushed = buffer.flush("n1", now).unwrap();
+        assert!(flushed.get_bool("overloaded").unwrap());
+
+        // The next window starts clear
+        buffer.push(&sample(1.0, false));
+        buffer.push(&sample(1.1, false));
+        assert!(!buffer.flush("n1", now).unwrap().get_bool("overloaded").unwrap());
+    }
+
+    #[test]
+    fn test_docker_buffer_keeps_container_without_stats() {
+        let now = Utc.with_ymd_and_hms(2026, 4, 8, 12, 0, 0).unwrap();
+        let healthy = doc! { "id": "aaa", "name": "web", "cpu_percent": 10.0, "memory_used_mib": 100.0 };
+        let missing = doc! { "id": "bbb", "name": "db", "error": "no stats frame returned by the daemon" };
+
+        let mut buffer = DockerMetricBuffer::new();
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [healthy.clone(), missing.clone()] });
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [healthy, missing] });
+
+        let flushed = buffer.flush("n1", now).unwrap();
+        let containers = flushed.get_array("containers").unwrap();
+        assert_eq!(containers.len(), 2);
+
+        let db = containers[0].as_document().unwrap();
+        assert_eq!(db.get_str("name").unwrap(), "db");
+        assert_eq!(db.get_str("error").unwrap(), "no stats frame returned by the daemon");
+        assert_eq!(db.get_i32("error_count").unwrap(), 2);
+        assert!(db.get("cpu_percent").is_none());
+
+        let web = containers[1].as_document().unwrap();
+        assert_eq!(web.get_document("cpu_percent").unwrap().get_f64("avg").unwrap(), 10.0);
+        assert!(web.get("error").is_none());
+    }
+
+    #[test]
+    fn test_docker_buffer_aggregates_gpu_when_present() {
+        let now = Utc.with_ymd_and_hms(2026, 4, 8, 12, 0, 0).unwrap();
+        let gpu = |mib: f64, pct: f64| doc! { "id": "aaa", "name": "train", "gpu_memory_mib": mib, "gpu_percent": pct };
+        let no_gpu = doc! { "id": "bbb", "name": "web", "cpu_percent": 5.0 };
+
+        let mut buffer = DockerMetricBuffer::new();
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [gpu(1024.0, 80.0), no_gpu.clone()] });
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [gpu(2048.0, 100.0), no_gpu] });
+
+        let flushed = buffer.flush("n1", now).unwrap();
+        let containers = flushed.get_array("containers").unwrap();
+
+        let train = containers[0].as_document().unwrap();
+        assert_eq!(train.get_document("gpu_memory_mib").unwrap(), &doc! { "avg": 1536.0, "min": 1024.0, "max": 2048.0 });
+        assert_eq!(train.get_document("gpu_percent").unwrap(), &doc! { "avg": 90.0, "min": 80.0, "max": 100.0 });
+
+        // Built without the `gpu` feature (or no NVML): no GPU fields at all
+        let web = containers[1].as_document().unwrap();
+        assert!(web.get("gpu_memory_mib").is_none());
+    }
+
+    #[test]
+    fn test_docker_buffer_skips_warming_up_cpu() {
+        let now = Utc.with_ymd_and_hms(2026, 4, 8, 12, 0, 0).unwrap();
+        let warming = |name: &str| doc! { "id": "aaa", "name": name, "cpu_percent": Bson::Null, "cpu_warming_up": true };
+        let warm = doc! { "id": "aaa", "name": "web", "cpu_percent": 30.0 };
+
+        let mut buffer = DockerMetricBuffer::new();
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [warming("web"), warming("job")] });
+        buffer.push(&doc! { "node": "n1", "timestamp": now, "containers": [warm] });
+
+        let flushed = buffer.flush("n1", now).unwrap();
+        let containers = flushed.get_array("containers").unwrap();
+
+        // The warming-up frame doesn't count as 0%
+        let job = containers[0].as_document().unwrap();
+        assert_eq!(job.get("cpu_percent"), Some(&Bson::Null));
+        assert!(job.get_bool("cpu_warming_up").unwrap());
+
+        let web = containers[1].as_document().unwrap();
+        assert_eq!(web.get_document("cpu_percent").unwrap(), &doc! { "avg": 30.0, "min": 30.0, "max": 30.0 });
+        assert!(web.get("cpu_warming_up").is_none());
+    }
+}
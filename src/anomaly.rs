@@ -0,0 +1,121 @@
+// Anomaly module - z-score flagging of collected values
+//
+// Threshold alerts need a threshold per field and per host. For the fields
+// listed in `metrics.<Name>.anomaly_fields`, the scheduler instead keeps the
+// last `anomalies.window` values of each field and, when a new value lies
+// more than `metrics.<Name>.anomaly_zscore` standard deviations from their
+// mean, writes a document to `anomalies.collection`:
+// `{node, timestamp, metric, field, value, zscore, mean, stddev}`.
+// Values are checked as collected, before aggregation, so a spike averaged
+// away in the stored window is still caught.
+
+use bson::{doc, Bson, Document};
+use std::collections::{HashMap, VecDeque};
+
+use crate::config::{AnomalySettings, MetricSettings};
+
+/// Rolling values per watched field of one metric
+#[derive(Default)]
+pub struct AnomalyDetector {
+    windows: HashMap<String, VecDeque<f64>>,
+}
+
+impl AnomalyDetector {
+    /// Adds the watched fields of `doc` to their windows, returning an
+    /// anomaly document for each value too far from the window before it
+    ///
+    /// A field needs `anomalies.min_samples` earlier values before it can be
+    /// flagged; one that never varied (stddev 0) is never flagged. Fields
+    /// missing from `doc` or not numeric are skipped.
+    pub fn observe(
+        &mut self,
+        doc: &Document,
+        metric_name: &str,
+        metric: &MetricSettings,
+        settings: &AnomalySettings,
+    ) -> Vec<Document> {
+        let mut anomalies = Vec::new();
+        for field in &metric.anomaly_fields {
+            let Some(value) = numeric_field(doc, field) else {
+                continue;
+            };
+            let window = self.windows.entry(field.clone()).or_default();
+            if window.len() >= settings.min_samples.max(2) {
+                let (mean, stddev) = mean_stddev(window);
+                let zscore = if stddev > 0.0 { (value - mean) / stddev } else { 0.0 };
+                if zscore.abs() > metric.anomaly_zscore {
+                    anomalies.push(doc! {
+                        "node": doc.get("node").cloned().unwrap_or(Bson::Null),
+                        "timestamp": doc.get("timestamp").cloned().unwrap_or(Bson::Null),
+                        "metric": metric_name,
+                        "field": field,
+                        "value": value,
+                        "zscore": zscore,
+                        "mean": mean,
+                        "stddev": stddev,
+                    });
+                }
+            }
+            window.push_back(value);
+            while window.len() > settings.window.max(2) {
+                window.pop_front();
+            }
+        }
+        // Fields no longer watched after a reload
+        self.windows.retain(|field, _| metric.anomaly_fields.contains(field));
+        anomalies
+    }
+}
+
+/// Value at a dotted path through sub-documents, if numeric
+fn numeric_field(doc: &Document, path: &str) -> Option<f64> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parents, key)) => {
+            let mut parent = doc;
+            for part in parents.split('.') {
+                parent = parent.get_document(part).ok()?;
+            }
+            (parent, key)
+        }
+        None => (doc, path),
+    };
+    match parent.get(key)? {
+        Bson::Double(v) => Some(*v).filter(|v| v.is_finite()),
+        Bson::Int32(v) => Some(f64::from(*v)),
+        Bson::Int64(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+/// Mean and population standard deviation
+fn mean_stddev(values: &VecDeque<f64>) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spike_is_flagged_after_min_samples() {
+        let metric = MetricSettings {
+            anomaly_fields: vec!["used_percent".to_string(), "cpu.avg".to_string()],
+            ..MetricSettings::default()
+        };
+        let settings = AnomalySettings { window: 5, min_samples: 4, ..AnomalySettings::default() };
+        let mut detector = AnomalyDetector::default();
+        let sample = |used: f64| doc! { "node": "n1", "used_percent": used, "cpu": { "avg": 5 } };
+
+        // Too few samples yet to judge the first jump, which then leaves the window
+        for used in [40.0, 90.0, 41.0, 39.0, 40.0, 41.0, 39.0, 40.0] {
+            assert!(detector.observe(&sample(used), "Memory", &metric, &settings).is_empty());
+        }
+        let anomalies = detector.observe(&sample(95.0), "Memory", &metric, &settings);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].get_str("field").unwrap(), "used_percent");
+        assert!(anomalies[0].get_f64("zscore").unwrap() > 3.0);
+    }
+}
@@ -0,0 +1,84 @@
+// Build info module - collector version and build details captured at compile time
+//
+// `build.rs` (vergen) records the git commit and build timestamp; together
+// with the package version they identify exactly which build a node runs.
+// With `agent_info.enabled`, they are written as tags to a periodic
+// `agent_info` document per node rather than to every metric document, so an
+// anomaly can be matched to a build rolled out to part of the fleet.
+
+use bson::{doc, Document};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::clock::SharedClock;
+use crate::config::AgentInfoSettings;
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// Package version from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Full commit hash the binary was built from
+pub const GIT_COMMIT: &str = env!("VERGEN_GIT_SHA");
+
+/// "true" when the working tree had uncommitted changes at build time
+pub const GIT_DIRTY: &str = env!("VERGEN_GIT_DIRTY");
+
+/// RFC 3339 UTC time of the build
+pub const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
+
+/// One-line summary for logs, e.g. `0.1.0 (ade8923, built 2026-10-16T10:52:50Z)`
+pub fn summary() -> String {
+    let dirty = if GIT_DIRTY == "true" { "-dirty" } else { "" };
+    format!("{} ({}{}, built {})", VERSION, short_commit(), dirty, BUILD_TIMESTAMP)
+}
+
+fn short_commit() -> &'static str {
+    GIT_COMMIT.get(..7).unwrap_or(GIT_COMMIT)
+}
+
+/// Build details as a `tags` sub-document
+pub fn tags() -> Document {
+    doc! {
+        "version": VERSION,
+        "git_commit": GIT_COMMIT,
+        "git_dirty": GIT_DIRTY == "true",
+        "build_timestamp": BUILD_TIMESTAMP,
+    }
+}
+
+/// Writes one `agent_info` document per interval until shutdown
+///
+/// The first document is written right away, so every restart (and thus
+/// every upgrade) is visible.
+pub async fn run_agent_info_task(
+    storage: Arc<MetricStorage>,
+    node_id: String,
+    settings: AgentInfoSettings,
+    clock: SharedClock,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!(
+        "Starting agent info task every {}s → '{}'",
+        settings.interval_secs, settings.collection
+    );
+
+    let mut timer = tokio::time::interval(Duration::from_secs(settings.interval_secs.max(1)));
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        let agent_info = doc! {
+            "node": &node_id,
+            "timestamp": clock.now(),
+            "tags": tags(),
+        };
+        storage
+            .store_metric_safe(&settings.collection, "AgentInfo", agent_info)
+            .await;
+    }
+}
@@ -0,0 +1,113 @@
+// Clock module - injectable source of "now" for collectors and the scheduler
+//
+// Collectors and buffers never call `Utc::now()` directly; they ask a `Clock`.
+// Production code uses `SystemClock`; tests use `FakeClock` to control time
+// precisely (timestamps, poll windows, rate calculations) without sleeping.
+//
+// `TimestampPrecision` (`--timestamp-precision`) sets how finely the
+// scheduler stamps stored documents. BSON dates only hold milliseconds, so
+// `timestamp` stays a millisecond date for MongoDB's indexes and TTLs; `us`
+// and `ns` add the full value as an integer epoch next to it.
+
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+/// Source of wall-clock time
+pub trait Clock: Send + Sync {
+    /// Returns the current time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Shared clock handle passed to collectors and scheduler tasks
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Real wall clock backed by `Utc::now()`
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Returns a shared handle to the system clock
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Precision of stored document times (`--timestamp-precision`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimestampPrecision {
+    /// `timestamp` only, a BSON date in milliseconds (default)
+    #[default]
+    Millis,
+    /// Also `timestamp_us`, epoch microseconds
+    Micros,
+    /// Also `timestamp_ns`, epoch nanoseconds
+    Nanos,
+}
+
+impl TimestampPrecision {
+    /// Name as given to `--timestamp-precision`
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimestampPrecision::Millis => "ms",
+            TimestampPrecision::Micros => "us",
+            TimestampPrecision::Nanos => "ns",
+        }
+    }
+
+    /// Integer field holding the full-precision time; None for milliseconds,
+    /// which `timestamp` holds by itself
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            TimestampPrecision::Millis => None,
+            TimestampPrecision::Micros => Some("timestamp_us"),
+            TimestampPrecision::Nanos => Some("timestamp_ns"),
+        }
+    }
+
+    /// `at` in this precision's units since the Unix epoch
+    ///
+    /// Nanoseconds overflow an i64 after 2262; they saturate there.
+    pub fn epoch(&self, at: DateTime<Utc>) -> i64 {
+        match self {
+            TimestampPrecision::Millis => at.timestamp_millis(),
+            TimestampPrecision::Micros => at.timestamp_micros(),
+            TimestampPrecision::Nanos => at.timestamp_nanos_opt().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// Manually driven clock for tests
+///
+/// Starts at a fixed instant and only moves when `set` or `advance` is called.
+#[cfg(test)]
+pub struct FakeClock {
+    now: std::sync::Mutex<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        FakeClock {
+            now: std::sync::Mutex::new(start),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
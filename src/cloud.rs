@@ -0,0 +1,153 @@
+// Cloud module - automatic tags from the instance metadata service
+//
+// With `--cloud-metadata`, startup asks the link-local metadata endpoint of
+// AWS (IMDSv2), GCP and Azure at once for the provider, region and instance
+// ID; whichever answers becomes `cloud_provider`, `region` and `instance_id`
+// tags on every document, alongside the Kubernetes tags. Off-cloud nothing
+// answers: after `METADATA_TIMEOUT` at most, no tags are added.
+
+use futures_util::future::{select_ok, FutureExt};
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Upper bound on the whole probe, so startup is never held up for long
+const METADATA_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Link-local address shared by all three providers' metadata services
+const METADATA_HOST: &str = "http://169.254.169.254";
+
+/// Queries the metadata services and returns the tags of the first that answers
+///
+/// Empty (with a debug log) when none is reachable in time.
+pub async fn metadata_tags() -> BTreeMap<String, String> {
+    let http = match Client::builder().timeout(METADATA_TIMEOUT).no_proxy().build() {
+        Ok(http) => http,
+        Err(e) => {
+            debug!("Cloud metadata client unavailable: {}", e);
+            return BTreeMap::new();
+        }
+    };
+
+    // First provider to answer wins; the others are dropped
+    let probes = vec![aws(&http).boxed(), gcp(&http).boxed(), azure(&http).boxed()]
+        .into_iter()
+        .map(|probe| probe.map(|tags| tags.ok_or(())));
+    let result = tokio::time::timeout(METADATA_TIMEOUT, select_ok(probes)).await;
+    match result {
+        Ok(Ok((tags, _))) => {
+            info!("Cloud metadata tags: {:?}", tags);
+            tags
+        }
+        _ => {
+            debug!("No cloud metadata service reachable, no cloud tags added");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Fetches a JSON document; any error or non-2xx reply is None
+async fn get_json(request: reqwest::RequestBuilder) -> Option<Value> {
+    request.send().await.ok()?.error_for_status().ok()?.json().await.ok()
+}
+
+/// AWS IMDSv2: a session token first, then the instance identity document
+async fn aws(http: &Client) -> Option<BTreeMap<String, String>> {
+    let token = http
+        .put(format!("{}/latest/api/token", METADATA_HOST))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    let identity = get_json(
+        http.get(format!("{}/latest/dynamic/instance-identity/document", METADATA_HOST))
+            .header("X-aws-ec2-metadata-token", token),
+    )
+    .await?;
+    parse_aws(&identity)
+}
+
+async fn gcp(http: &Client) -> Option<BTreeMap<String, String>> {
+    let instance = get_json(
+        http.get(format!("{}/computeMetadata/v1/instance/?recursive=true", METADATA_HOST))
+            .header("Metadata-Flavor", "Google"),
+    )
+    .await?;
+    parse_gcp(&instance)
+}
+
+async fn azure(http: &Client) -> Option<BTreeMap<String, String>> {
+    let compute = get_json(
+        http.get(format!("{}/metadata/instance/compute?api-version=2021-02-01", METADATA_HOST))
+            .header("Metadata", "true"),
+    )
+    .await?;
+    parse_azure(&compute)
+}
+
+fn tags(provider: &str, region: &str, instance_id: String) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("cloud_provider".to_string(), provider.to_string()),
+        ("region".to_string(), region.to_string()),
+        ("instance_id".to_string(), instance_id),
+    ])
+}
+
+/// `{"region": "eu-west-1", "instanceId": "i-0abc...", ...}`
+fn parse_aws(identity: &Value) -> Option<BTreeMap<String, String>> {
+    let region = identity["region"].as_str()?;
+    let instance_id = identity["instanceId"].as_str()?;
+    Some(tags("aws", region, instance_id.to_string()))
+}
+
+/// `{"id": 4520031799277581759, "zone": "projects/123/zones/us-central1-a", ...}`
+///
+/// The region is the zone without its last `-<letter>` part.
+fn parse_gcp(instance: &Value) -> Option<BTreeMap<String, String>> {
+    let zone = instance["zone"].as_str()?.rsplit('/').next()?;
+    let region = zone.rsplit_once('-').map_or(zone, |(region, _)| region);
+    let instance_id = match &instance["id"] {
+        Value::Number(id) => id.to_string(),
+        Value::String(id) => id.clone(),
+        _ => return None,
+    };
+    Some(tags("gcp", region, instance_id))
+}
+
+/// `{"location": "westeurope", "vmId": "02aab8a4-...", ...}`
+fn parse_azure(compute: &Value) -> Option<BTreeMap<String, String>> {
+    let region = compute["location"].as_str()?;
+    let instance_id = compute["vmId"].as_str()?;
+    Some(tags("azure", region, instance_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_metadata_documents() {
+        let aws = json!({ "region": "eu-west-1", "instanceId": "i-0abc123", "availabilityZone": "eu-west-1b" });
+        assert_eq!(parse_aws(&aws), Some(tags("aws", "eu-west-1", "i-0abc123".to_string())));
+
+        let gcp = json!({ "id": 4520031799277581759u64, "zone": "projects/123/zones/us-central1-a" });
+        assert_eq!(parse_gcp(&gcp), Some(tags("gcp", "us-central1", "4520031799277581759".to_string())));
+
+        let azure = json!({ "location": "westeurope", "vmId": "02aab8a4-74ef-476e-8182-f6d2ba4166a6" });
+        assert_eq!(
+            parse_azure(&azure),
+            Some(tags("azure", "westeurope", "02aab8a4-74ef-476e-8182-f6d2ba4166a6".to_string()))
+        );
+
+        // A reply from a different provider's service doesn't parse
+        assert_eq!(parse_aws(&azure), None);
+    }
+}
@@ -0,0 +1,80 @@
+// Collection stats module - periodic size report for the metric collections
+//
+// For capacity planning: every interval, reads the document count and
+// on-disk size of each metric collection with `collStats` and logs one line
+// per collection. Optionally each reading is also stored as a document, so
+// growth can be charted from the same database.
+//
+// Runs once per process (not per node) since the collections are shared.
+
+use bson::doc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::clock::SharedClock;
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// Collection receiving stored readings (`--store-collection-stats`)
+pub const COLLECTION_STATS_COLLECTION: &str = "collection_stats";
+
+/// Polls `collStats` for every collection in `collections` until shutdown
+///
+/// The first reading is taken right away, then once per `interval`.
+pub async fn run_collection_stats_task(
+    storage: Arc<MetricStorage>,
+    collections: Vec<String>,
+    interval: Duration,
+    store: bool,
+    clock: SharedClock,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!(
+        "Starting collection stats task: {} collection(s) every {}s{}",
+        collections.len(),
+        interval.as_secs(),
+        if store { format!(" → '{}'", COLLECTION_STATS_COLLECTION) } else { String::new() }
+    );
+
+    let mut timer = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        for collection in &collections {
+            let (doc_count, storage_size_bytes) = match storage.collection_stats(collection).await {
+                Ok(Some(stats)) => stats,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Failed to read stats for '{}': {}", collection, e);
+                    continue;
+                }
+            };
+            info!(
+                collection = collection.as_str(),
+                doc_count,
+                storage_size_bytes,
+                "Collection '{}': {} document(s), {:.1} MiB on disk",
+                collection,
+                doc_count,
+                storage_size_bytes as f64 / (1024.0 * 1024.0)
+            );
+
+            if store {
+                let reading = doc! {
+                    "timestamp": clock.now(),
+                    "collection": collection,
+                    "doc_count": doc_count,
+                    "storage_size_bytes": storage_size_bytes,
+                };
+                storage
+                    .store_metric_safe(COLLECTION_STATS_COLLECTION, "CollectionStats", reading)
+                    .await;
+            }
+        }
+    }
+}
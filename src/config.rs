@@ -1,7 +1,20 @@
 // Configuration module - handles MongoDB connection and settings retrieval
+//
+// Settings documents are read through a `SettingsSource`: MongoDB's
+// `MonitoringSettings` collection by default, or a Consul KV prefix
+// (`--settings-source consul`). The MongoDB client is always created because
+// metric storage uses it.
 
+pub mod change_stream;
+pub mod consul;
+
+use async_trait::async_trait;
+use bson::{Bson, Document};
+use futures_util::stream::TryStreamExt;
+use mongodb::options::{AuthMechanism, ClientOptions, ServerAddress, Tls, TlsOptions};
 use mongodb::{Client, Collection, Database};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 use tracing::{info, warn};
 
@@ -14,10 +27,12 @@ pub enum ConfigError {
     #[error("Settings document not found for key: {0}")]
     SettingsNotFound(String),
 
-    #[allow(dead_code)]
     #[error("Invalid settings format: {0}")]
     InvalidSettings(String),
 
+    #[error("{source_name} request failed: {message}")]
+    SourceError { source_name: &'static str, message: String },
+
     #[allow(dead_code)]
     #[error("Missing required setting: {0}")]
     MissingRequiredSetting(String),
@@ -34,7 +49,7 @@ pub enum ConfigError {
 ///   "store_timeout": 60
 /// }
 /// ```
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MonitoringSettings {
     /// Unique identifier for this configuration (e.g., "0001-0001")
     pub key: String,
@@ -47,23 +62,1439 @@ pub struct MonitoringSettings {
 
     /// How often (seconds) to flush the aggregated buffer to MongoDB
     pub store_timeout: u64,
+
+    /// Revision of this settings document, bumped by whoever manages it
+    /// (e.g. a git commit count or hash); stamped onto every metric document
+    /// as `config_revision`. A string or an integer (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_revision: Option<Bson>,
+
+    /// Seconds after startup during which documents are collected and logged
+    /// but not stored, so delta-based metrics settle first (default: 0, off;
+    /// read once at startup)
+    #[serde(default)]
+    pub startup_grace_secs: u64,
+
+    /// LoadAverage collector options (read once at startup)
+    #[serde(default)]
+    pub load: LoadSettings,
+
+    /// DiskSpace collector options (read once at startup)
+    #[serde(default)]
+    pub disk: DiskSettings,
+
+    /// DockerStats collector options (read once at startup)
+    #[serde(default)]
+    pub docker: DockerSettings,
+
+    /// Network collector options (read once at startup)
+    #[serde(default)]
+    pub network: NetworkSettings,
+
+    /// Process snapshot collector options (read once at startup)
+    #[serde(default)]
+    pub processes: ProcessSettings,
+
+    /// DiskSmart collector options (read once at startup)
+    #[serde(default)]
+    pub smart: SmartSettings,
+
+    /// Canary liveness collector options (read once at startup)
+    #[serde(default)]
+    pub canary: CanarySettings,
+
+    /// DockerDisk collector options (read once at startup)
+    #[serde(default)]
+    pub docker_disk: DockerDiskSettings,
+
+    /// Collect this node over SSH instead of locally (read once at startup;
+    /// needs the `ssh` feature)
+    #[serde(default)]
+    pub remote: RemoteSettings,
+
+    /// BSON types for large values (read once at startup)
+    #[serde(default)]
+    pub numeric: NumericSettings,
+
+    /// Periodic rollup of aggregated metrics into summary collections
+    #[serde(default)]
+    pub rollup: RollupSettings,
+
+    /// Count-based retention per node (read once at startup)
+    #[serde(default)]
+    pub retention: RetentionSettings,
+
+    /// Collect-interval drift detection (re-read on every settings reload)
+    #[serde(default)]
+    pub drift: DriftSettings,
+
+    /// Where and how `metrics.<Name>.anomaly_fields` are checked (re-read on
+    /// every settings reload)
+    #[serde(default)]
+    pub anomalies: AnomalySettings,
+
+    /// Load-based widening of collect intervals (re-read on every settings reload)
+    #[serde(default)]
+    pub adaptive: AdaptiveSettings,
+
+    /// Lifecycle event audit trail (read once at startup)
+    #[serde(default)]
+    pub events: EventSettings,
+
+    /// Units/type reference documents (read once at startup)
+    #[serde(default)]
+    pub schema: SchemaSettings,
+
+    /// Peer liveness through shared heartbeat documents (read once at startup)
+    #[serde(default)]
+    pub heartbeat: HeartbeatSettings,
+
+    /// Periodic collector version/build report (read once at startup)
+    #[serde(default)]
+    pub agent_info: AgentInfoSettings,
+
+    /// Snapshots of the effective settings on startup and reload (read once at startup)
+    #[serde(default)]
+    pub config_history: ConfigHistorySettings,
+
+    /// Ordered document transforms applied before storage
+    /// (re-read on every settings reload)
+    #[serde(default)]
+    pub transforms: Vec<TransformSettings>,
+
+    /// Secret scrubbing of string values before storage, after the transforms
+    /// (re-read on every settings reload)
+    #[serde(default)]
+    pub scrub: ScrubSettings,
+
+    /// Collection name for metrics without a `metrics.<Name>.collection`,
+    /// e.g. "{metric_snake}_metrics"; `{metric}`, `{metric_lowercase}` and
+    /// `{metric_snake}` stand for "LoadAverage", "loadaverage" and
+    /// "load_average" (default: unset, the built-in names; read once at startup)
+    #[serde(default)]
+    pub collection_template: Option<String>,
+
+    /// Per-metric overrides keyed by metric name (e.g. "Memory", "DockerStats").
+    /// Re-read on every settings reload.
+    #[serde(default)]
+    pub metrics: HashMap<String, MetricSettings>,
+}
+
+/// Renders a `collection_template` for one metric
+pub fn render_collection_template(template: &str, metric_name: &str) -> String {
+    template
+        .replace("{metric}", metric_name)
+        .replace("{metric_lowercase}", &metric_name.to_lowercase())
+        .replace("{metric_snake}", &snake_case(metric_name))
+}
+
+/// "ProcessCPUSnapshot" → "process_cpu_snapshot"
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let after_lower = !chars[i - 1].is_uppercase();
+            let acronym_end = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if after_lower || acronym_end {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// Why `name` can't be a MongoDB collection name, if it can't
+fn invalid_collection_name(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("must not be empty")
+    } else if name.contains('$') || name.contains('\0') {
+        Some("must not contain '$' or NUL")
+    } else if name.starts_with("system.") {
+        Some("must not start with 'system.'")
+    } else {
+        None
+    }
+}
+
+impl MonitoringSettings {
+    /// Returns the overrides for one metric, or the defaults if none are configured
+    pub fn metric(&self, metric_name: &str) -> MetricSettings {
+        self.metrics.get(metric_name).cloned().unwrap_or_default()
+    }
+
+    /// Node id for one metric's documents: its `node_id` override, else the key
+    pub fn metric_node_id(&self, metric_name: &str) -> String {
+        self.metric(metric_name).node_id.unwrap_or_else(|| self.key.clone())
+    }
+
+    /// Rejects values serde accepts but the collector can't use
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field: String, reason: &str| {
+            Err(ConfigError::InvalidSettings(format!("'{}': {} {}", self.key, field, reason)))
+        };
+        for (metric_name, metric) in &self.metrics {
+            if metric.node_id.as_ref().is_some_and(|id| id.trim().is_empty()) {
+                return invalid(format!("metrics.{}.node_id", metric_name), "must not be empty");
+            }
+            if let Some(reason) = metric.collection.as_deref().and_then(invalid_collection_name) {
+                return invalid(format!("metrics.{}.collection", metric_name), reason);
+            }
+            if !(metric.anomaly_zscore > 0.0 && metric.anomaly_zscore.is_finite()) {
+                return invalid(format!("metrics.{}.anomaly_zscore", metric_name), "must be a number > 0");
+            }
+        }
+        match &self.config_revision {
+            None | Some(Bson::Int32(_) | Bson::Int64(_)) => {}
+            Some(Bson::String(revision)) if !revision.trim().is_empty() => {}
+            Some(_) => return invalid("config_revision".to_string(), "must be a non-empty string or an integer"),
+        }
+        if let Some(reason) = invalid_collection_name(&self.docker.container_collection) {
+            return invalid("docker.container_collection".to_string(), reason);
+        }
+        if let Some(reason) = invalid_collection_name(&self.anomalies.collection) {
+            return invalid("anomalies.collection".to_string(), reason);
+        }
+        if self.anomalies.min_samples < 2 || self.anomalies.min_samples > self.anomalies.window {
+            return invalid("anomalies.min_samples".to_string(), "must be between 2 and anomalies.window");
+        }
+        if self.adaptive.enabled {
+            let adaptive = &self.adaptive;
+            if !(adaptive.low_load_per_core >= 0.0 && adaptive.low_load_per_core < adaptive.high_load_per_core) {
+                return invalid("adaptive.low_load_per_core".to_string(), "must be >= 0 and below adaptive.high_load_per_core");
+            }
+            if !(adaptive.factor > 1.0 && adaptive.factor.is_finite()) {
+                return invalid("adaptive.factor".to_string(), "must be a number > 1");
+            }
+            if adaptive.max_interval_secs == 0 {
+                return invalid("adaptive.max_interval_secs".to_string(), "must not be 0");
+            }
+        }
+        if !(self.load.overload_factor >= 0.0 && self.load.overload_factor.is_finite()) {
+            return invalid("load.overload_factor".to_string(), "must be a number >= 0");
+        }
+        if !(self.disk.low_space_gib >= 0.0 && self.disk.low_space_gib.is_finite()) {
+            return invalid("disk.low_space_gib".to_string(), "must be a number >= 0");
+        }
+        if !(0.0..=100.0).contains(&self.disk.low_space_percent) {
+            return invalid("disk.low_space_percent".to_string(), "must be between 0 and 100");
+        }
+        if self.scrub.enabled {
+            for (i, pattern) in self.scrub.patterns.iter().enumerate() {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    return invalid(format!("scrub.patterns[{}]", i), &format!("is not a valid regex: {}", e));
+                }
+            }
+        }
+        if self.disk.forecast_samples == 1 {
+            return invalid("disk.forecast_samples".to_string(), "must be 0 or at least 2");
+        }
+        if self.remote.is_enabled() {
+            if self.remote.user.trim().is_empty() {
+                return invalid("remote.user".to_string(), "must not be empty");
+            }
+            if self.remote.port == 0 {
+                return invalid("remote.port".to_string(), "must not be 0");
+            }
+        }
+        if let Some(template) = &self.collection_template {
+            // Without a placeholder every metric would share one collection
+            let rendered = render_collection_template(template, "Metric");
+            if rendered == *template {
+                return invalid("collection_template".to_string(), "must contain a {metric...} placeholder");
+            }
+            if rendered.contains(['{', '}']) {
+                return invalid("collection_template".to_string(), "has an unknown placeholder");
+            }
+            if let Some(reason) = invalid_collection_name(&rendered) {
+                return invalid("collection_template".to_string(), reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-metric options (`metrics.<MetricName>` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "metrics": {
+///   "DockerLogs": { "enabled": false },
+///   "ProcessCPUSnapshot": { "sample_rate": 0.25 },
+///   "Memory": { "id_bucket_secs": 60 },
+///   "DiskSpace": { "store_on_change_only": true, "max_unchanged_secs": 3600 },
+///   "SystemEvents": { "integrity": "chain" },
+///   "DockerStats": { "unwind": "containers" },
+///   "LoadAverage": { "mark_gaps": true },
+///   "Network": { "validate": true },
+///   "Pressure": { "node_id": "0001-0002" },
+///   "DiskSmart": { "collection": "disk_health" },
+///   "DockerStats": { "monotonic": true },
+///   "LoadAverage": { "anomaly_fields": ["load_1min"], "anomaly_zscore": 4 },
+///   "ProcessCPUSnapshot": { "collect_immediately": false },
+///   "DockerStats": { "required": true }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricSettings {
+    /// Whether the metric's collector runs at all (default: true, read once at startup)
+    pub enabled: bool,
+
+    /// Fraction of documents written to MongoDB, 0.0–1.0 (default: 1.0).
+    /// Collection and aggregation still see every sample; only storage is sampled.
+    pub sample_rate: f64,
+
+    /// When set, `_id` is derived from node, metric and the timestamp rounded
+    /// down to this many seconds, so re-inserting the same logical sample is a
+    /// no-op instead of a duplicate (default: unset, MongoDB generates `_id`).
+    pub id_bucket_secs: Option<u64>,
+
+    /// Skip storing a document identical to the last stored one, ignoring
+    /// `timestamp` (default: false)
+    pub store_on_change_only: bool,
+
+    /// With `store_on_change_only`, store anyway once this many seconds have
+    /// passed since the last stored document (default: 600)
+    pub max_unchanged_secs: u64,
+
+    /// Tamper-evidence hash stored in `_integrity` (default: "off")
+    pub integrity: IntegrityMode,
+
+    /// Array field to store as one flat document per element, e.g. "disks"
+    /// or "containers" (default: unset, one document with the array)
+    pub unwind: Option<String>,
+
+    /// At startup, store a `status: "gap"` document covering the time since
+    /// the node's last stored document if it exceeds two storage intervals
+    /// (default: false, read once at startup)
+    pub mark_gaps: bool,
+
+    /// Create (or update) a `$jsonSchema` validator on the metric's
+    /// collection at startup, so MongoDB rejects malformed documents
+    /// (default: false, read once at startup)
+    pub validate: bool,
+
+    /// Node id passed to the collector and stamped on its documents instead
+    /// of the settings key, for one process collecting on behalf of several
+    /// logical nodes (default: unset, the key; read once at startup)
+    pub node_id: Option<String>,
+
+    /// Collection the metric's documents are stored in, over
+    /// `collection_template` (default: unset; read once at startup)
+    pub collection: Option<String>,
+
+    /// Add `monotonic_ns`, nanoseconds since process start on a clock that
+    /// NTP steps don't move, next to `timestamp` (default: false)
+    pub monotonic: bool,
+
+    /// Numeric fields of the collected document, dotted for sub-documents,
+    /// checked for anomalies (default: empty, none)
+    pub anomaly_fields: Vec<String>,
+
+    /// Standard deviations from the rolling mean past which a value is an
+    /// anomaly (default: 3)
+    pub anomaly_zscore: f64,
+
+    /// Store documents whose main array (`containers`, `disks`, `events`...)
+    /// is empty; false skips them, logging at debug level (default: true)
+    pub store_when_empty: bool,
+
+    /// Collect as soon as the task starts; false waits one full collect
+    /// interval first (default: true, read once at startup)
+    pub collect_immediately: bool,
+
+    /// Exit at startup, instead of running without it, when the metric's
+    /// collector is missing or its trial collection fails (default: false,
+    /// read once at startup)
+    pub required: bool,
+}
+
+/// How stored documents are sealed (`metrics.<Name>.integrity`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityMode {
+    /// No `_integrity` field
+    #[default]
+    Off,
+    /// SHA-256 of the document alone
+    Hash,
+    /// SHA-256 also covering the node's previous hash, forming a chain
+    Chain,
+}
+
+impl Default for MetricSettings {
+    fn default() -> Self {
+        MetricSettings {
+            enabled: true,
+            sample_rate: 1.0,
+            id_bucket_secs: None,
+            store_on_change_only: false,
+            max_unchanged_secs: 600,
+            integrity: IntegrityMode::Off,
+            unwind: None,
+            mark_gaps: false,
+            validate: false,
+            node_id: None,
+            collection: None,
+            monotonic: false,
+            anomaly_fields: Vec::new(),
+            anomaly_zscore: 3.0,
+            store_when_empty: true,
+            collect_immediately: true,
+            required: false,
+        }
+    }
+}
+
+/// Options for the LoadAverage collector (`load` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "load": { "overload_factor": 8 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadSettings {
+    /// Set `overloaded` when the 1-minute load exceeds this many times the
+    /// CPU core count; 0 leaves the flag out (default: 4)
+    pub overload_factor: f64,
+}
+
+impl Default for LoadSettings {
+    fn default() -> Self {
+        LoadSettings { overload_factor: 4.0 }
+    }
+}
+
+/// Options for the DiskSpace collector (`disk` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "disk": { "dedupe_by_device": true, "min_used_percent": 80, "all_on_breach": true,
+///           "low_space_gib": 10, "low_space_percent": 5, "forecast_samples": 60 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskSettings {
+    /// Report each underlying device once instead of once per mount point.
+    /// Bind mounts and btrfs subvolumes share a device and would otherwise
+    /// be counted several times. When false (default), every mount is reported.
+    pub dedupe_by_device: bool,
+
+    /// Only report disks at or above this usage percentage (default: 0, report all)
+    pub min_used_percent: f64,
+
+    /// Mount points reported regardless of `min_used_percent` (default: ["/"])
+    pub pinned_mounts: Vec<String>,
+
+    /// Report every disk whenever at least one disk is over the threshold (default: false)
+    pub all_on_breach: bool,
+
+    /// Report every disk on every Nth collection so below-threshold disks are
+    /// still seen occasionally (default: 0, never)
+    pub full_report_every: u64,
+
+    /// Seconds to wait for one mount's capacity query (Linux), or for the whole
+    /// disk list (elsewhere), before reporting with an `error` (default: 5)
+    pub stat_timeout_secs: u64,
+
+    /// Set `low_space` when a disk has less than this many GiB available,
+    /// whatever `numeric.size_units`; 0 disables this check (default: 2)
+    pub low_space_gib: f64,
+
+    /// Set `low_space` when a disk has less than this percentage of its
+    /// capacity available; 0 disables this check (default: 5)
+    pub low_space_percent: f64,
+
+    /// Recent samples per mount a linear fit of used space is taken over for
+    /// `estimated_days_until_full`; 0 leaves the field out (default: 0)
+    pub forecast_samples: usize,
+}
+
+impl Default for DiskSettings {
+    fn default() -> Self {
+        DiskSettings {
+            dedupe_by_device: false,
+            min_used_percent: 0.0,
+            pinned_mounts: vec!["/".to_string()],
+            all_on_breach: false,
+            full_report_every: 0,
+            stat_timeout_secs: 5,
+            low_space_gib: 2.0,
+            low_space_percent: 5.0,
+            forecast_samples: 0,
+        }
+    }
+}
+
+/// Options for the DockerStats collector (`docker` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "docker": { "include_metadata": true, "labels": ["owner", "app"], "max_containers": 50,
+///             "delta_snapshots": true, "delta_threshold_percent": 5, "full_snapshot_secs": 3600,
+///             "storage": "per_container", "container_collection": "docker_container_metrics" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerSettings {
+    /// Add `image`, `image_id` and the selected `labels` to each container (default: false)
+    pub include_metadata: bool,
+
+    /// Label keys to copy into each container's `labels` sub-document.
+    /// Labels not listed here are never stored.
+    pub labels: Vec<String>,
+
+    /// Most containers to read stats for per collection; when more are
+    /// running, successive collections take turns (round-robin by container
+    /// ID) so every container is covered over time (default: 0, no limit)
+    pub max_containers: usize,
+
+    /// Between full snapshots, store compact delta documents holding only the
+    /// container fields that changed (default: false; ignored with `unwind`)
+    pub delta_snapshots: bool,
+
+    /// With `delta_snapshots`, store a full snapshot when a container's
+    /// average CPU or memory percentage moved more than this many points
+    /// from the last full snapshot (default: 5.0)
+    pub delta_threshold_percent: f64,
+
+    /// With `delta_snapshots`, store a full snapshot at least this often
+    /// (default: 3600)
+    pub full_snapshot_secs: u64,
+
+    /// One document per flush with a `containers` array, or one row per
+    /// container in a time-series collection (default: "combined")
+    pub storage: DockerStorage,
+
+    /// With `storage: "per_container"`, the time-series collection the rows
+    /// go to, `container` being its meta field (default: "docker_container_metrics")
+    pub container_collection: String,
+}
+
+/// Shape of stored DockerStats documents (`docker.storage`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DockerStorage {
+    /// One document per flush, every container in its `containers` array
+    #[default]
+    Combined,
+    /// One document per container per flush, with `container: { id, name }`
+    PerContainer,
+}
+
+impl Default for DockerSettings {
+    fn default() -> Self {
+        DockerSettings {
+            include_metadata: false,
+            labels: Vec::new(),
+            max_containers: 0,
+            delta_snapshots: false,
+            delta_threshold_percent: 5.0,
+            full_snapshot_secs: 3600,
+            storage: DockerStorage::Combined,
+            container_collection: "docker_container_metrics".to_string(),
+        }
+    }
+}
+
+/// Options for the Network collector (`network` sub-document)
+///
+/// Patterns match interface names; `*` matches any run of characters.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "network": { "include": ["eth*", "en*"], "exclude": ["lo", "docker*", "veth*"], "ip_addresses": true }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Interfaces to report; empty (default) means every interface not excluded
+    pub include: Vec<String>,
+
+    /// Interfaces to skip, checked after `include`
+    /// (default: loopback and common container/VM virtual interfaces)
+    pub exclude: Vec<String>,
+
+    /// Add an `ip_addresses` inventory of the included interfaces' addresses
+    /// (default: false)
+    pub ip_addresses: bool,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            include: Vec::new(),
+            exclude: [
+                "lo", "docker*", "veth*", "br-*", "virbr*", "cni*", "flannel*", "cali*",
+                "vxlan*", "tun*", "tap*",
+            ]
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+            ip_addresses: false,
+        }
+    }
+}
+
+/// Options for the ProcessCPUSnapshot/ProcessRAMSnapshot collectors (`processes` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "processes": { "container_attribution": true, "docker_names": true }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessSettings {
+    /// Add a `container` sub-document to processes running inside a container,
+    /// read from /proc/<pid>/cgroup (default: true, Linux only)
+    pub container_attribution: bool,
+
+    /// Look up container names from the Docker daemon. Disable on hosts without
+    /// Docker; the container id is still reported (default: true)
+    pub docker_names: bool,
+}
+
+impl Default for ProcessSettings {
+    fn default() -> Self {
+        ProcessSettings {
+            container_attribution: true,
+            docker_names: true,
+        }
+    }
+}
+
+/// Options for the DiskSmart collector (`smart` sub-document)
+///
+/// The collector runs `smartctl` from smartmontools, which needs root.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "smart": { "enabled": true, "devices": ["/dev/sda", "/dev/nvme0"], "interval_secs": 1800 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartSettings {
+    /// Register the DiskSmart collector (default: false, Linux only)
+    pub enabled: bool,
+
+    /// Devices to query; empty (default) means every device `smartctl --scan` finds
+    pub devices: Vec<String>,
+
+    /// Seconds between SMART readings, instead of `collect_timeout` (default: 3600)
+    pub interval_secs: u64,
+
+    /// Seconds to wait for one device's `smartctl` before reporting it with
+    /// an `error` (default: 30)
+    pub command_timeout_secs: u64,
+}
+
+impl Default for SmartSettings {
+    fn default() -> Self {
+        SmartSettings {
+            enabled: false,
+            devices: Vec::new(),
+            interval_secs: 3600,
+            command_timeout_secs: 30,
+        }
+    }
+}
+
+/// Options for the Canary collector (`canary` sub-document)
+///
+/// The canary writes a bare `{node, timestamp, seq}` document every
+/// `interval_secs`, which proves the process and its storage path are up even
+/// while every real collector fails.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "canary": { "enabled": true, "interval_secs": 30 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CanarySettings {
+    /// Register the Canary collector (default: false)
+    pub enabled: bool,
+
+    /// Seconds between canary documents, instead of `collect_timeout` (default: 60)
+    pub interval_secs: u64,
+}
+
+impl Default for CanarySettings {
+    fn default() -> Self {
+        CanarySettings {
+            enabled: false,
+            interval_secs: 60,
+        }
+    }
+}
+
+/// Options for the DockerDisk collector (`docker_disk` sub-document)
+///
+/// The collector reads the daemon's `/system/df`, which sizes every layer and
+/// volume and can take seconds on a busy host.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "docker_disk": { "enabled": true, "interval_secs": 1800 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerDiskSettings {
+    /// Register the DockerDisk collector (default: false)
+    pub enabled: bool,
+
+    /// Seconds between readings, instead of `collect_docker_timeout` (default: 3600)
+    pub interval_secs: u64,
+}
+
+impl Default for DockerDiskSettings {
+    fn default() -> Self {
+        DockerDiskSettings {
+            enabled: false,
+            interval_secs: 3600,
+        }
+    }
+}
+
+/// Agentless collection over SSH (`remote` sub-document)
+///
+/// For appliances the binary can't be installed on: with `host` set, this
+/// settings key's LoadAverage, Memory and DiskSpace are read by running
+/// `cat` and `df` on the host over SSH, and the other metrics are skipped.
+/// Authentication tries `key_path`, then `password_file`, then ssh-agent.
+/// Credentials are file paths on the collector's host, so no secret is
+/// stored in MongoDB.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "remote": { "host": "10.0.4.20", "user": "monitor", "key_path": "/etc/metrics-collector/id_ed25519" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSettings {
+    /// Host name or address to collect from; empty (default) collects locally
+    pub host: String,
+
+    /// SSH port (default: 22)
+    pub port: u16,
+
+    /// User to log in as (default: "monitor"); needs no privileges
+    pub user: String,
+
+    /// Private key file (OpenSSH format, unencrypted)
+    pub key_path: Option<String>,
+
+    /// File holding the password, read on every connect
+    pub password_file: Option<String>,
+
+    /// known_hosts file the host key must be listed in, `~` expanded; empty
+    /// skips the check (default: "~/.ssh/known_hosts")
+    pub known_hosts: String,
+
+    /// Seconds to wait for connecting or for one command (default: 10)
+    pub command_timeout_secs: u64,
+}
+
+impl RemoteSettings {
+    /// Whether this node is collected over SSH
+    pub fn is_enabled(&self) -> bool {
+        !self.host.trim().is_empty()
+    }
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        RemoteSettings {
+            host: String::new(),
+            port: 22,
+            user: "monitor".to_string(),
+            key_path: None,
+            password_file: None,
+            known_hosts: "~/.ssh/known_hosts".to_string(),
+            command_timeout_secs: 10,
+        }
+    }
+}
+
+/// BSON type and unit choices for large values (`numeric` sub-document)
+///
+/// Read once at startup.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "numeric": { "byte_counts": "decimal128", "size_units": "decimal" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumericSettings {
+    /// Type of raw byte counters such as cumulative interface bytes (default: "int64")
+    pub byte_counts: ByteCountType,
+
+    /// Base and field suffixes of converted sizes such as `total_mib` (default: "binary")
+    pub size_units: SizeUnits,
+}
+
+/// How sizes converted from bytes are scaled and named
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeUnits {
+    /// Powers of 1024, fields `_kib` / `_mib` / `_gib`
+    #[default]
+    Binary,
+    /// Powers of 1000, fields `_kb` / `_mb` / `_gb` (as `docker stats` and `df -H` show)
+    Decimal,
+    /// Powers of 1024 under the `_kb` / `_mb` / `_gb` names of older versions
+    Legacy,
+}
+
+/// BSON type used for raw byte counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteCountType {
+    /// Int64, saturating at i64::MAX
+    #[default]
+    Int64,
+    /// Decimal128, exact for the full u64 range
+    Decimal128,
+}
+
+/// Options for the background rollup writer (`rollup` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "rollup": { "enabled": true, "period_secs": 3600, "suffix": "_hourly" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RollupSettings {
+    /// Whether the rollup task runs at all (default: false)
+    pub enabled: bool,
+
+    /// Length of each rollup window in seconds (default: 3600)
+    pub period_secs: u64,
+
+    /// Suffix appended to the source collection name (default: "_hourly")
+    pub suffix: String,
+}
+
+impl Default for RollupSettings {
+    fn default() -> Self {
+        RollupSettings {
+            enabled: false,
+            period_secs: 3600,
+            suffix: "_hourly".to_string(),
+        }
+    }
+}
+
+/// Options for the count-based pruning task (`retention` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "retention": {
+///   "keep_last": { "memory_metrics": 10080, "process_cpu_logs": 2000 },
+///   "interval_secs": 300,
+///   "max_deletes": 1000
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Documents kept per node, keyed by collection; empty disables pruning
+    /// (default: empty)
+    pub keep_last: HashMap<String, u64>,
+
+    /// Seconds between pruning passes (default: 300)
+    pub interval_secs: u64,
+
+    /// Cap on documents deleted per collection per pass (default: 1000)
+    pub max_deletes: u64,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            keep_last: HashMap::new(),
+            interval_secs: 300,
+            max_deletes: 1000,
+        }
+    }
+}
+
+/// Options for collect-interval drift detection (`drift` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "drift": { "tolerance_percent": 50, "record_events": true }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DriftSettings {
+    /// Allowed deviation between consecutive collect ticks, as a percentage of
+    /// the configured interval, before a warning is logged (default: 50)
+    pub tolerance_percent: f64,
+
+    /// Also write one `interval_drift` lifecycle event per window with drifted
+    /// ticks (requires `events.enabled`, default: false)
+    pub record_events: bool,
+}
+
+impl Default for DriftSettings {
+    fn default() -> Self {
+        DriftSettings {
+            tolerance_percent: 50.0,
+            record_events: false,
+        }
+    }
+}
+
+/// Options for anomaly detection (`anomalies` sub-document)
+///
+/// Which fields are watched, and how closely, is set per metric with
+/// `metrics.<Name>.anomaly_fields` and `anomaly_zscore`.
+///
+/// # Example MongoDB Document
+/// ```json
+/// "anomalies": { "collection": "anomalies", "window": 120, "min_samples": 30 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalySettings {
+    /// Target collection (default: "anomalies")
+    pub collection: String,
+
+    /// Number of recent values per field the mean and standard deviation are
+    /// taken over (default: 60)
+    pub window: usize,
+
+    /// Values a field needs before it can be flagged (default: 10)
+    pub min_samples: usize,
+}
+
+impl Default for AnomalySettings {
+    fn default() -> Self {
+        AnomalySettings {
+            collection: "anomalies".to_string(),
+            window: 60,
+            min_samples: 10,
+        }
+    }
+}
+
+/// Options for load-based adaptive collect intervals (`adaptive` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "adaptive": { "enabled": true, "high_load_per_core": 2, "low_load_per_core": 1,
+///               "max_interval_secs": 60 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveSettings {
+    /// Widen collect intervals under load; needs the LoadAverage metric
+    /// (default: false)
+    pub enabled: bool,
+
+    /// Widen when the latest `load_1min_per_core` is above this (default: 2)
+    pub high_load_per_core: f64,
+
+    /// Narrow again once it is below this; the gap to `high_load_per_core`
+    /// keeps the interval from flapping (default: 1)
+    pub low_load_per_core: f64,
+
+    /// Each step multiplies or divides the interval by this (default: 2)
+    pub factor: f64,
+
+    /// Widest interval, also capped at `store_timeout` (default: 60)
+    pub max_interval_secs: u64,
+
+    /// Time an interval is held before it may narrow a step (default: 300)
+    pub cooldown_secs: u64,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            enabled: false,
+            high_load_per_core: 2.0,
+            low_load_per_core: 1.0,
+            factor: 2.0,
+            max_interval_secs: 60,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+/// Options for the lifecycle event recorder (`events` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "events": { "enabled": true, "collection": "events" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventSettings {
+    /// Whether lifecycle events are written at all (default: false)
+    pub enabled: bool,
+
+    /// Target collection (default: "events")
+    pub collection: String,
+}
+
+impl Default for EventSettings {
+    fn default() -> Self {
+        EventSettings {
+            enabled: false,
+            collection: "events".to_string(),
+        }
+    }
+}
+
+/// Options for the collector build report (`agent_info` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "agent_info": { "enabled": true, "interval_secs": 3600 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentInfoSettings {
+    /// Whether `agent_info` documents are written (default: false)
+    pub enabled: bool,
+
+    /// Seconds between documents; one is also written at startup (default: 3600)
+    pub interval_secs: u64,
+
+    /// Target collection (default: "agent_info")
+    pub collection: String,
+}
+
+impl Default for AgentInfoSettings {
+    fn default() -> Self {
+        AgentInfoSettings {
+            enabled: false,
+            interval_secs: 3600,
+            collection: "agent_info".to_string(),
+        }
+    }
+}
+
+/// Options for the settings audit trail (`config_history` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "config_history": { "enabled": true, "collection": "config_history" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigHistorySettings {
+    /// Whether settings snapshots are written (default: false)
+    pub enabled: bool,
+
+    /// Target collection (default: "config_history")
+    pub collection: String,
+}
+
+impl Default for ConfigHistorySettings {
+    fn default() -> Self {
+        ConfigHistorySettings {
+            enabled: false,
+            collection: "config_history".to_string(),
+        }
+    }
+}
+
+/// Options for the secret scrubbing pass (`scrub` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "scrub": {
+///   "enabled": true,
+///   "patterns": ["\\bAKIA[0-9A-Z]{16}\\b", "(?i)\\bsession=([^\\s;]+)"],
+///   "replacement": "***"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrubSettings {
+    /// Whether string values are scrubbed (default: false)
+    pub enabled: bool,
+
+    /// Regexes of secrets to replace; when a pattern has a capture group only
+    /// the first group is replaced, so `password=` stays readable (default:
+    /// AWS access keys, bearer tokens, GitHub and Slack tokens, private key
+    /// blocks, URL passwords and `password=`/`token=`-style pairs)
+    pub patterns: Vec<String>,
+
+    /// Text each secret is replaced with (default: "***")
+    pub replacement: String,
+}
+
+impl Default for ScrubSettings {
+    fn default() -> Self {
+        ScrubSettings {
+            enabled: false,
+            patterns: [
+                r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+                r"(?i)\bbearer\s+([A-Za-z0-9\-._~+/]+=*)",
+                r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+                r"\bxox[abposr]-[A-Za-z0-9-]{10,}",
+                r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+                r"://[^/\s:@]+:([^/\s@]+)@",
+                r#"(?i)\b(?:password|passwd|pwd|secret|token|api[_-]?key|access[_-]?key)\s*[=:]\s*([^\s&;,"']+)"#,
+            ]
+            .map(String::from)
+            .to_vec(),
+            replacement: "***".to_string(),
+        }
+    }
+}
+
+/// Options for the peer heartbeat mesh (`heartbeat` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "heartbeat": { "enabled": true, "interval_secs": 30, "timeout_secs": 120 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatSettings {
+    /// Whether heartbeats are written and peers watched (default: false)
+    pub enabled: bool,
+
+    /// Seconds between heartbeats, and between peer checks (default: 30)
+    pub interval_secs: u64,
+
+    /// A peer silent for longer than this is reported lost (default: 120)
+    pub timeout_secs: u64,
+
+    /// A peer silent for longer than this is forgotten, e.g. a decommissioned
+    /// node (default: 86400)
+    pub forget_after_secs: u64,
+
+    /// Collection holding one heartbeat document per node (default: "node_heartbeat")
+    pub collection: String,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        HeartbeatSettings {
+            enabled: false,
+            interval_secs: 30,
+            timeout_secs: 120,
+            forget_after_secs: 86400,
+            collection: "node_heartbeat".to_string(),
+        }
+    }
+}
+
+/// Options for the `metric_schema` reference documents (`schema` sub-document)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "schema": { "enabled": true, "collection": "metric_schema" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchemaSettings {
+    /// Whether schema documents are written at startup (default: false)
+    pub enabled: bool,
+
+    /// Metadata collection holding one document per metric collection
+    /// (default: "metric_schema")
+    pub collection: String,
+}
+
+impl Default for SchemaSettings {
+    fn default() -> Self {
+        SchemaSettings {
+            enabled: false,
+            collection: "metric_schema".to_string(),
+        }
+    }
+}
+
+/// One step of the document transform pipeline (`transforms` array entry)
+///
+/// # Example MongoDB Document
+/// ```json
+/// "transforms": [
+///   { "type": "tags", "tags": { "env": "prod", "dc": "fra1" } },
+///   { "type": "round", "decimals": 2 },
+///   { "type": "project", "exclude": ["cgroup_limited"], "metrics": ["Memory"] },
+///   { "type": "coerce", "fields": { "used_percent": "int32" }, "rounding": "floor" }
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformSettings {
+    #[serde(flatten)]
+    pub kind: TransformKind,
+
+    /// Metric names this step applies to (default: empty, every metric)
+    #[serde(default)]
+    pub metrics: Vec<String>,
+}
+
+/// Built-in document transforms, selected by `type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TransformKind {
+    /// Adds a `tags` sub-document with fixed key/value pairs
+    Tags { tags: BTreeMap<String, String> },
+
+    /// Rounds every double (including nested avg/min/max) to `decimals` places
+    Round { decimals: u32 },
+
+    /// Keeps only `include` (when non-empty), then drops `exclude`;
+    /// top-level fields only, `node` and `timestamp` are always kept
+    Project {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+
+    /// Converts numbers at dotted paths (`disks.used_percent`) to a BSON
+    /// type; a sub-document or array found there has all its numbers converted
+    Coerce {
+        fields: BTreeMap<String, NumericType>,
+        #[serde(default)]
+        rounding: Rounding,
+    },
+}
+
+/// Target type of a `coerce` transform field
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumericType {
+    Int32,
+    Int64,
+    Double,
+}
+
+/// How a `coerce` transform turns a double into an integer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// To the nearest integer, halves away from zero
+    #[default]
+    Round,
+    Floor,
+    Ceil,
+    /// Towards zero
+    Trunc,
+}
+
+/// Where settings documents are read from
+///
+/// `fetch` is called at startup and again after every flush (hot reload), so
+/// implementations should be cheap to call repeatedly.
+#[async_trait]
+pub trait SettingsSource: Send + Sync {
+    /// Short source name for logs (e.g. "mongodb", "consul")
+    fn name(&self) -> &'static str;
+
+    /// Fetches the settings for one key
+    async fn fetch(&self, key: &str) -> Result<MonitoringSettings, ConfigError>;
+
+    /// Fetches every settings document (used by `--all-keys`)
+    async fn fetch_all(&self) -> Result<Vec<MonitoringSettings>, ConfigError>;
+}
+
+/// Default source: the `MonitoringSettings` collection
+pub struct MongoSettingsSource {
+    database: Database,
+}
+
+impl MongoSettingsSource {
+    pub fn new(client: &Client, database_name: &str) -> Self {
+        MongoSettingsSource {
+            database: client.database(database_name),
+        }
+    }
+}
+
+#[async_trait]
+impl SettingsSource for MongoSettingsSource {
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    async fn fetch(&self, key: &str) -> Result<MonitoringSettings, ConfigError> {
+        let collection: Collection<MonitoringSettings> = self.database.collection("MonitoringSettings");
+        let filter = mongodb::bson::doc! { "key": key };
+
+        match collection.find_one(filter, None).await? {
+            Some(settings) => Ok(settings),
+            None => {
+                warn!("No settings found for key: {}", key);
+                Err(ConfigError::SettingsNotFound(key.to_string()))
+            }
+        }
+    }
+
+    /// Documents that fail to deserialize are skipped with a warning so one
+    /// malformed entry doesn't prevent the rest of the fleet from loading.
+    async fn fetch_all(&self) -> Result<Vec<MonitoringSettings>, ConfigError> {
+        let collection: Collection<Document> = self.database.collection("MonitoringSettings");
+        let mut cursor = collection.find(None, None).await?;
+
+        let mut all = Vec::new();
+        while let Some(raw) = cursor.try_next().await? {
+            match bson::from_document::<MonitoringSettings>(raw) {
+                Ok(settings) => all.push(settings),
+                Err(e) => warn!("Skipping malformed settings document: {}", e),
+            }
+        }
+        Ok(all)
+    }
+}
+
+/// MongoDB credential fields given outside the connection string
+///
+/// Each field that is set overrides the same field of any credential in the
+/// URI; when none is set, the URI's credentials are used unchanged.
+#[derive(Clone, Default)]
+pub struct MongoAuth {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Database the user is defined in (`authSource`)
+    pub source: Option<String>,
+    pub mechanism: Option<AuthMechanism>,
+}
+
+impl MongoAuth {
+    fn is_empty(&self) -> bool {
+        self.username.is_none() && self.password.is_none() && self.source.is_none() && self.mechanism.is_none()
+    }
+
+    /// Merges these fields into the credential parsed from the URI
+    fn apply(&self, options: &mut ClientOptions) {
+        if self.is_empty() {
+            return;
+        }
+        let mut credential = options.credential.take().unwrap_or_default();
+        if let Some(username) = &self.username {
+            credential.username = Some(username.clone());
+        }
+        if let Some(password) = &self.password {
+            credential.password = Some(password.clone());
+        }
+        if let Some(source) = &self.source {
+            credential.source = Some(source.clone());
+        }
+        if let Some(mechanism) = &self.mechanism {
+            credential.mechanism = Some(mechanism.clone());
+        }
+        options.credential = Some(credential);
+    }
+}
+
+/// Where to connect: a `--mongodb` URI, or the discrete `--mongo-*` flags
+#[derive(Clone, Debug)]
+pub enum MongoTarget {
+    Uri(String),
+    Hosts {
+        /// `host` or `host:port` entries; a bare host gets `port`
+        hosts: Vec<String>,
+        port: u16,
+        replica_set: Option<String>,
+        tls: bool,
+    },
+}
+
+/// Port used for `--mongo-host` entries without one
+pub const DEFAULT_MONGO_PORT: u16 = 27017;
+
+impl MongoTarget {
+    async fn client_options(&self) -> Result<ClientOptions, ConfigError> {
+        match self {
+            MongoTarget::Uri(uri) => Ok(ClientOptions::parse(uri).await?),
+            MongoTarget::Hosts { hosts, port, replica_set, tls } => {
+                let hosts = hosts
+                    .iter()
+                    .map(|host| {
+                        Ok(match ServerAddress::parse(host)? {
+                            ServerAddress::Tcp { host, port: None } => ServerAddress::Tcp { host, port: Some(*port) },
+                            address => address,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, ConfigError>>()?;
+                let mut options = ClientOptions::default();
+                options.hosts = hosts;
+                options.repl_set_name = replica_set.clone();
+                options.tls = tls.then(|| Tls::Enabled(TlsOptions::default()));
+                Ok(options)
+            }
+        }
+    }
+}
+
+/// The equivalent connection string, for logs; credentials are the URI's own
+impl std::fmt::Display for MongoTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MongoTarget::Uri(uri) => f.write_str(uri),
+            MongoTarget::Hosts { hosts, port, replica_set, tls } => {
+                let hosts: Vec<String> = hosts
+                    .iter()
+                    .map(|host| match ServerAddress::parse(host) {
+                        Ok(ServerAddress::Tcp { port: None, .. }) => format!("{}:{}", host, port),
+                        _ => host.clone(),
+                    })
+                    .collect();
+                write!(f, "mongodb://{}/", hosts.join(","))?;
+                let mut query = Vec::new();
+                if let Some(replica_set) = replica_set {
+                    query.push(format!("replicaSet={}", replica_set));
+                }
+                if *tls {
+                    query.push("tls=true".to_string());
+                }
+                if !query.is_empty() {
+                    write!(f, "?{}", query.join("&"))?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 /// Configuration manager for the monitoring application
 pub struct ConfigManager {
     client: Client,
     database_name: String,
+
+    /// Where settings are read from (MongoDB unless replaced)
+    source: Box<dyn SettingsSource>,
 }
 
 impl ConfigManager {
     /// Creates a new ConfigManager and establishes MongoDB connection
+    ///
+    /// `auth` overrides credential fields from the connection string
+    /// (`MongoAuth::default()` keeps them as they are).
     pub async fn new(
-        connection_string: &str,
+        target: &MongoTarget,
         database_name: Option<&str>,
+        auth: &MongoAuth,
     ) -> Result<Self, ConfigError> {
-        info!("Connecting to MongoDB at: {}", connection_string);
-
-        let client = Client::with_uri_str(connection_string).await?;
+        let mut options = target.client_options().await?;
+        auth.apply(&mut options);
+        if let MongoTarget::Hosts { .. } = target {
+            info!(
+                "Built MongoDB client options: hosts={:?}, replica_set={:?}, tls={}, username={:?}, password_set={}",
+                options.hosts.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                options.repl_set_name,
+                options.tls.is_some(),
+                options.credential.as_ref().and_then(|c| c.username.as_ref()),
+                options.credential.as_ref().is_some_and(|c| c.password.is_some()),
+            );
+        }
+        let client = Client::with_options(options)?;
 
         match client.list_database_names(None, None).await {
             Ok(_) => info!("Successfully connected to MongoDB"),
@@ -75,21 +1506,28 @@ impl ConfigManager {
 
         let database_name = database_name.unwrap_or("monitoring").to_string();
 
+        let source = Box::new(MongoSettingsSource::new(&client, &database_name));
+
         Ok(ConfigManager {
             client,
             database_name,
+            source,
         })
     }
 
-    fn get_database(&self) -> Database {
-        self.client.database(&self.database_name)
+    /// Reads settings from `source` instead of MongoDB (storage still uses MongoDB)
+    pub fn with_source(mut self, source: Box<dyn SettingsSource>) -> Self {
+        info!("Reading monitoring settings from {}", source.name());
+        self.source = source;
+        self
     }
 
     /// Fetches monitoring settings from MongoDB for a specific key (called at startup)
     pub async fn load_settings(&self, key: &str) -> Result<MonitoringSettings, ConfigError> {
         info!("Loading monitoring settings for key: {}", key);
 
-        let settings = self.fetch_settings(key).await?;
+        let settings = self.source.fetch(key).await?;
+        settings.validate()?;
 
         info!(
             "Settings loaded — collect: {}s, docker: {}s, store: {}s",
@@ -103,7 +1541,8 @@ impl ConfigManager {
     pub async fn reload_settings(&self, key: &str) -> Result<MonitoringSettings, ConfigError> {
         info!("Reloading monitoring settings for key: {}", key);
 
-        let settings = self.fetch_settings(key).await?;
+        let settings = self.source.fetch(key).await?;
+        settings.validate()?;
 
         info!(
             "Settings reloaded — collect: {}s, docker: {}s, store: {}s",
@@ -113,18 +1552,21 @@ impl ConfigManager {
         Ok(settings)
     }
 
-    async fn fetch_settings(&self, key: &str) -> Result<MonitoringSettings, ConfigError> {
-        let db = self.get_database();
-        let collection: Collection<MonitoringSettings> = db.collection("MonitoringSettings");
-        let filter = mongodb::bson::doc! { "key": key };
+    /// Fetches every settings document from the source (used by `--all-keys`)
+    pub async fn load_all_settings(&self) -> Result<Vec<MonitoringSettings>, ConfigError> {
+        info!("Loading monitoring settings for all keys");
 
-        match collection.find_one(filter, None).await? {
-            Some(settings) => Ok(settings),
-            None => {
-                warn!("No settings found for key: {}", key);
-                Err(ConfigError::SettingsNotFound(key.to_string()))
+        let mut all = self.source.fetch_all().await?;
+        all.retain(|settings| match settings.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Skipping settings document: {}", e);
+                false
             }
-        }
+        });
+
+        info!("Loaded {} settings document(s)", all.len());
+        Ok(all)
     }
 
     pub fn client(&self) -> &Client {
@@ -134,4 +1576,98 @@ impl ConfigManager {
     pub fn database_name(&self) -> &str {
         &self.database_name
     }
+
+    /// Manager whose settings come from `source`, without connecting to MongoDB
+    ///
+    /// The client points at a closed local port and gives up after 100ms, so
+    /// reads that still go to MongoDB (e.g. seq resume) fail fast.
+    #[cfg(test)]
+    pub async fn for_tests(source: Box<dyn SettingsSource>) -> Self {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=100")
+            .await
+            .expect("static test URI parses");
+        ConfigManager {
+            client,
+            database_name: "monitoring_test".to_string(),
+            source,
+        }
+    }
+}
+
+/// Settings source serving one fixed document (tests)
+#[cfg(test)]
+pub struct StaticSettingsSource(pub MonitoringSettings);
+
+#[cfg(test)]
+#[async_trait]
+impl SettingsSource for StaticSettingsSource {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn fetch(&self, _key: &str) -> Result<MonitoringSettings, ConfigError> {
+        Ok(self.0.clone())
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<MonitoringSettings>, ConfigError> {
+        Ok(vec![self.0.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mongo_target_from_discrete_flags() {
+        let target = MongoTarget::Hosts {
+            hosts: vec!["db1.example.net".to_string(), "db2.example.net:27018".to_string()],
+            port: 27019,
+            replica_set: Some("rs0".to_string()),
+            tls: true,
+        };
+        assert_eq!(
+            target.to_string(),
+            "mongodb://db1.example.net:27019,db2.example.net:27018/?replicaSet=rs0&tls=true"
+        );
+
+        let options = target.client_options().await.unwrap();
+        let hosts: Vec<String> = options.hosts.iter().map(ToString::to_string).collect();
+        assert_eq!(hosts, ["db1.example.net:27019", "db2.example.net:27018"]);
+        assert_eq!(options.repl_set_name.as_deref(), Some("rs0"));
+        assert!(matches!(options.tls, Some(Tls::Enabled(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_empty_node_id_override() {
+        let mut settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "0001-0001", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "metrics": { "DockerStats": { "node_id": "0001-0002" } },
+        }))
+        .unwrap();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.metric_node_id("DockerStats"), "0001-0002");
+        assert_eq!(settings.metric_node_id("Memory"), "0001-0001");
+
+        settings.metrics.get_mut("DockerStats").unwrap().node_id = Some(" ".to_string());
+        assert!(matches!(settings.validate(), Err(ConfigError::InvalidSettings(_))));
+    }
+
+    #[test]
+    fn test_collection_template() {
+        assert_eq!(render_collection_template("{metric}/{metric_lowercase}", "LoadAverage"), "LoadAverage/loadaverage");
+        assert_eq!(render_collection_template("{metric_snake}_metrics", "ProcessCPUSnapshot"), "process_cpu_snapshot_metrics");
+        assert_eq!(render_collection_template("{metric_snake}", "DiskSmart"), "disk_smart");
+
+        let mut settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "0001-0001", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "collection_template": "{metric_lowercase}_metrics",
+        }))
+        .unwrap();
+        assert!(settings.validate().is_ok());
+        for template in ["metrics", "{metric_camel}_metrics", "system.{metric}", "${metric}"] {
+            settings.collection_template = Some(template.to_string());
+            assert!(settings.validate().is_err(), "{}", template);
+        }
+    }
 }
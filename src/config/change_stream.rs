@@ -0,0 +1,101 @@
+// Settings change stream - reloads a node as soon as its settings change
+//
+// Settings are otherwise re-read after each flush, so a change can take up to
+// `store_timeout` to apply. With `--watch-settings`, a MongoDB change stream
+// on the `MonitoringSettings` collection, filtered to this process's keys,
+// fires the changed key's reload signal: that node's tasks flush their window
+// early and reload their settings, exactly as on SIGHUP.
+//
+// Change streams need a replica set or mongos. If the stream can't be opened
+// at startup (standalone server, missing `changeStream` privilege), this logs
+// a warning and returns, leaving the reload after each flush as the only way
+// changes are picked up.
+
+use bson::{doc, Document};
+use futures_util::StreamExt;
+use mongodb::change_stream::event::ResumeToken;
+use mongodb::options::{ChangeStreamOptions, FullDocumentType};
+use mongodb::Database;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::scheduler::wait_for_shutdown;
+
+/// Pause before reopening the stream after an error
+const WATCH_RETRY: Duration = Duration::from_secs(5);
+
+/// Watches `MonitoringSettings` until shutdown, sending on the reload
+/// channel of each key whose document is inserted, updated or replaced
+///
+/// After an error the stream is reopened where it left off, so no change is
+/// missed; only a failure to open it the first time falls back to polling.
+pub async fn run_settings_watch(
+    database: Database,
+    reloads: Arc<HashMap<String, watch::Sender<()>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let keys: Vec<&String> = reloads.keys().collect();
+    let pipeline = [doc! { "$match": { "fullDocument.key": { "$in": keys } } }];
+    let collection = database.collection::<Document>("MonitoringSettings");
+
+    let mut resume_token: Option<ResumeToken> = None;
+    let mut opened = false;
+    loop {
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .resume_after(resume_token.clone())
+            .build();
+        match collection.watch(pipeline.clone(), options).await {
+            Ok(mut stream) => {
+                if !opened {
+                    info!("Watching MonitoringSettings for changes to {} key(s)", reloads.len());
+                    opened = true;
+                }
+                loop {
+                    let event = tokio::select! {
+                        event = stream.next() => event,
+                        _ = wait_for_shutdown(&mut shutdown) => return,
+                    };
+                    match event {
+                        Some(Ok(event)) => {
+                            resume_token = stream.resume_token();
+                            let key = event.full_document.as_ref().and_then(|d| d.get_str("key").ok());
+                            if let Some((key, reload)) = key.and_then(|key| reloads.get_key_value(key)) {
+                                info!("Settings for '{}' changed, flushing and reloading", key);
+                                reload.send_replace(());
+                            }
+                        }
+                        Some(Err(e)) => {
+                            warn!("Settings change stream failed, reopening in {:?}: {}", WATCH_RETRY, e);
+                            break;
+                        }
+                        None => {
+                            // Invalidated (collection dropped or renamed): a resume token
+                            // can't continue past that, so start from now
+                            debug!("Settings change stream closed, reopening");
+                            resume_token = None;
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) if !opened => {
+                warn!(
+                    "Cannot watch MonitoringSettings ({}); change streams need a replica set or mongos. \
+                     Settings are reloaded after each flush instead",
+                    e
+                );
+                return;
+            }
+            Err(e) => warn!("Failed to reopen the settings change stream, retrying in {:?}: {}", WATCH_RETRY, e),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(WATCH_RETRY) => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+    }
+}
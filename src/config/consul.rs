@@ -0,0 +1,241 @@
+// Consul settings source - reads MonitoringSettings from a Consul KV prefix
+//
+// Each settings document is stored as JSON at `<prefix>/<key>`, in the same
+// shape as the MongoDB document; `key` is filled in from the path if missing.
+//
+// The first fetch of a key starts a watch (Consul blocking query) that keeps a
+// cached copy current. The scheduler's reload after each flush is then served
+// from memory and sees a change as soon as Consul reports it.
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use super::{ConfigError, MonitoringSettings, SettingsSource};
+
+/// How long Consul may hold a blocking query open before answering unchanged
+const WATCH_WAIT: &str = "5m";
+
+/// Client-side timeout for a blocking query (Consul adds up to wait/16 jitter)
+const WATCH_TIMEOUT: Duration = Duration::from_secs(6 * 60);
+
+/// Timeout for ordinary (non-blocking) requests
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause before re-establishing a watch after an error
+const WATCH_RETRY: Duration = Duration::from_secs(5);
+
+type Cache = Arc<RwLock<HashMap<String, MonitoringSettings>>>;
+
+/// Settings source backed by the Consul KV HTTP API
+pub struct ConsulSettingsSource {
+    http: reqwest::Client,
+    /// Agent address including scheme, e.g. "http://127.0.0.1:8500"
+    base_url: String,
+    /// KV prefix without leading/trailing slashes
+    prefix: String,
+    /// Latest settings per key, kept current by the watch tasks
+    cache: Cache,
+}
+
+impl ConsulSettingsSource {
+    /// Creates a source reading `<prefix>/<key>` from the agent at `addr`
+    ///
+    /// `addr` may omit the scheme (`127.0.0.1:8500` means `http://127.0.0.1:8500`).
+    pub fn new(addr: &str, prefix: &str) -> Result<Self, ConfigError> {
+        let base_url = if addr.contains("://") {
+            addr.trim_end_matches('/').to_string()
+        } else {
+            format!("http://{}", addr.trim_end_matches('/'))
+        };
+        let http = reqwest::Client::builder()
+            .build()
+            .map_err(|e| source_error(e.to_string()))?;
+
+        Ok(ConsulSettingsSource {
+            http,
+            base_url,
+            prefix: prefix.trim_matches('/').to_string(),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    fn key_url(&self, key: &str) -> String {
+        format!("{}/v1/kv/{}/{}", self.base_url, self.prefix, key)
+    }
+
+    /// Keeps the cached settings for `key` current until the process exits
+    fn spawn_watch(&self, key: String, mut index: u64) {
+        let http = self.http.clone();
+        let url = self.key_url(&key);
+        let cache = Arc::clone(&self.cache);
+
+        tokio::spawn(async move {
+            debug!("Watching Consul key '{}' from index {}", url, index);
+            loop {
+                match get_settings(&http, &url, &key, Some(index)).await {
+                    Ok(Some((new_index, settings))) => {
+                        // Consul may reset the index (e.g. after a snapshot restore)
+                        if new_index < index {
+                            index = 0;
+                            continue;
+                        }
+                        if new_index != index {
+                            info!("Settings for '{}' changed in Consul (index {})", key, new_index);
+                            cache.write().unwrap_or_else(|e| e.into_inner()).insert(key.clone(), settings);
+                        }
+                        index = new_index;
+                    }
+                    Ok(None) => {
+                        warn!("Consul key '{}' was deleted; keeping the last known settings", url);
+                        index = 0;
+                        tokio::time::sleep(WATCH_RETRY).await;
+                    }
+                    Err(e) => {
+                        warn!("Consul watch for '{}' failed: {}", key, e);
+                        tokio::time::sleep(WATCH_RETRY).await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn source_error(message: String) -> ConfigError {
+    ConfigError::SourceError {
+        source_name: "consul",
+        message,
+    }
+}
+
+/// Reads one key's raw value; `index` turns the request into a blocking query
+///
+/// Returns the KV modify index (`X-Consul-Index`) with the parsed settings,
+/// or None if the key does not exist.
+async fn get_settings(
+    http: &reqwest::Client,
+    url: &str,
+    key: &str,
+    index: Option<u64>,
+) -> Result<Option<(u64, MonitoringSettings)>, ConfigError> {
+    let mut request = http.get(url).query(&[("raw", "")]);
+    request = match index {
+        Some(index) => request
+            .query(&[("index", index.to_string()), ("wait", WATCH_WAIT.to_string())])
+            .timeout(WATCH_TIMEOUT),
+        None => request.timeout(REQUEST_TIMEOUT),
+    };
+
+    let response = request.send().await.map_err(|e| source_error(e.to_string()))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = response.error_for_status().map_err(|e| source_error(e.to_string()))?;
+
+    let modify_index = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let body = response.bytes().await.map_err(|e| source_error(e.to_string()))?;
+
+    Ok(Some((modify_index, parse_settings(key, &body)?)))
+}
+
+/// Parses a settings JSON document, taking `key` from the KV path if absent
+fn parse_settings(key: &str, body: &[u8]) -> Result<MonitoringSettings, ConfigError> {
+    let invalid = |e: serde_json::Error| ConfigError::InvalidSettings(format!("'{}': {}", key, e));
+
+    let mut value: Value = serde_json::from_slice(body).map_err(invalid)?;
+    if let Value::Object(map) = &mut value {
+        map.entry("key").or_insert_with(|| Value::String(key.to_string()));
+    }
+    serde_json::from_value(value).map_err(invalid)
+}
+
+#[async_trait]
+impl SettingsSource for ConsulSettingsSource {
+    fn name(&self) -> &'static str {
+        "consul"
+    }
+
+    async fn fetch(&self, key: &str) -> Result<MonitoringSettings, ConfigError> {
+        if let Some(settings) = self.cache.read().unwrap_or_else(|e| e.into_inner()).get(key) {
+            return Ok(settings.clone());
+        }
+
+        let url = self.key_url(key);
+        let Some((index, settings)) = get_settings(&self.http, &url, key, None).await? else {
+            warn!("No settings found in Consul at '{}'", url);
+            return Err(ConfigError::SettingsNotFound(key.to_string()));
+        };
+
+        let first = self
+            .cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), settings.clone())
+            .is_none();
+        if first {
+            self.spawn_watch(key.to_string(), index);
+        }
+        Ok(settings)
+    }
+
+    /// Lists the keys directly under the prefix; malformed documents are
+    /// skipped with a warning, as with MongoDB.
+    async fn fetch_all(&self) -> Result<Vec<MonitoringSettings>, ConfigError> {
+        let url = format!("{}/v1/kv/{}/", self.base_url, self.prefix);
+        let response = self
+            .http
+            .get(&url)
+            .query(&[("keys", ""), ("separator", "/")])
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| source_error(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let paths: Vec<String> = response
+            .error_for_status()
+            .map_err(|e| source_error(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| source_error(e.to_string()))?;
+
+        let folder = format!("{}/", self.prefix);
+        let mut all = Vec::new();
+        for path in &paths {
+            let Some(key) = path.strip_prefix(&folder).filter(|k| !k.is_empty() && !k.ends_with('/')) else {
+                continue;
+            };
+            match self.fetch(key).await {
+                Ok(settings) => all.push(settings),
+                Err(ConfigError::InvalidSettings(e)) => warn!("Skipping malformed settings document: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings_takes_key_from_path() {
+        let body = br#"{ "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60 }"#;
+        let settings = parse_settings("0001-0001", body).unwrap();
+        assert_eq!(settings.key, "0001-0001");
+        assert_eq!(settings.store_timeout, 60);
+
+        assert!(matches!(parse_settings("0001-0001", b"{}"), Err(ConfigError::InvalidSettings(_))));
+    }
+}
@@ -0,0 +1,141 @@
+// Config history module - audit trail of the settings each node ran with
+//
+// With `config_history.enabled`, the scheduler stores a snapshot of the
+// effective `MonitoringSettings` and the resolved tags (cloud, Kubernetes)
+// into `config_history` at startup and whenever a reload changes them.
+// Each snapshot carries a SHA-256 `hash` of its content; a snapshot with the
+// same hash as the node's previous one is not stored, so restarts and reloads
+// without a change add nothing. Complements the `config_reload` events, which
+// record that settings changed but not what they were.
+
+use bson::{doc, Bson, Document};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::clock::SharedClock;
+use crate::config::{ConfigHistorySettings, MonitoringSettings};
+use crate::integrity::compute_hash;
+use crate::storage::MetricStorage;
+
+/// Content a snapshot's hash covers: the settings and the resolved tags
+///
+/// Returns None if the settings can't be represented as BSON (an integer
+/// beyond the i64 range).
+fn snapshot_content(settings: &MonitoringSettings, tags: &BTreeMap<String, String>) -> Option<Document> {
+    let settings = match bson::to_bson(settings) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Cannot snapshot settings: {}", e);
+            return None;
+        }
+    };
+    let tags: Document = tags.iter().map(|(k, v)| (k.clone(), Bson::from(v.as_str()))).collect();
+    Some(doc! { "settings": settings, "tags": tags })
+}
+
+/// Stores settings snapshots for one node; a no-op when `config_history.enabled` is false
+///
+/// Clones share the last stored hash, so metric tasks reloading the same
+/// change concurrently store it once.
+#[derive(Clone)]
+pub struct ConfigHistory {
+    storage: Arc<MetricStorage>,
+    node_id: String,
+    settings: ConfigHistorySettings,
+    tags: BTreeMap<String, String>,
+    clock: SharedClock,
+    /// Hash of the last snapshot stored for this node; None until first read
+    last_hash: Arc<Mutex<Option<String>>>,
+}
+
+impl ConfigHistory {
+    pub fn new(
+        storage: Arc<MetricStorage>,
+        node_id: String,
+        settings: ConfigHistorySettings,
+        tags: BTreeMap<String, String>,
+        clock: SharedClock,
+    ) -> Self {
+        ConfigHistory {
+            storage,
+            node_id,
+            settings,
+            tags,
+            clock,
+            last_hash: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Stores a snapshot of `settings` unless it matches the previous one
+    ///
+    /// `reason` is "start" or "reload". The first call compares against the
+    /// newest snapshot already stored, so a restart with unchanged settings
+    /// stores nothing either.
+    pub async fn record(&self, settings: &MonitoringSettings, reason: &str) {
+        if !self.settings.enabled {
+            return;
+        }
+        let Some(content) = snapshot_content(settings, &self.tags) else {
+            return;
+        };
+        let hash = compute_hash(&content, None);
+
+        let mut last_hash = self.last_hash.lock().await;
+        if last_hash.is_none() {
+            match self.storage.latest_hash(&self.settings.collection, &self.node_id).await {
+                Ok(stored) => *last_hash = stored,
+                Err(e) => warn!("Failed to read the last settings snapshot: {}", e),
+            }
+        }
+        if last_hash.as_deref() == Some(hash.as_str()) {
+            debug!("Settings unchanged since the last snapshot ({}), not storing", &hash[..12]);
+            return;
+        }
+
+        let mut snapshot = doc! {
+            "node": &self.node_id,
+            "timestamp": self.clock.now(),
+            "reason": reason,
+            "hash": &hash,
+        };
+        snapshot.extend(content);
+        if self
+            .storage
+            .store_metric_safe(&self.settings.collection, "ConfigHistory", snapshot)
+            .await
+        {
+            info!("Stored settings snapshot {} ({})", &hash[..12], reason);
+            *last_hash = Some(hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricSettings;
+
+    #[test]
+    fn test_snapshot_hash_ignores_map_order() {
+        let tags = BTreeMap::from([("zone".to_string(), "eu-1a".to_string())]);
+        let mut settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+        }))
+        .unwrap();
+        let mut reordered = settings.clone();
+        for name in ["Memory", "DiskSpace", "LoadAverage", "Network"] {
+            settings.metrics.insert(name.to_string(), MetricSettings::default());
+        }
+        for name in ["Network", "LoadAverage", "DiskSpace", "Memory"] {
+            reordered.metrics.insert(name.to_string(), MetricSettings::default());
+        }
+
+        let hash = |settings: &MonitoringSettings| compute_hash(&snapshot_content(settings, &tags).unwrap(), None);
+        assert_eq!(hash(&settings), hash(&reordered));
+
+        reordered.store_timeout += 1;
+        assert_ne!(hash(&settings), hash(&reordered));
+    }
+}
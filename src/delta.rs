@@ -0,0 +1,253 @@
+// Delta module - compact DockerStats documents between full snapshots
+//
+// A DockerStats document repeats every container's name, limits, counters
+// and metadata on every flush, though on an idle host little of it changes.
+// With `docker.delta_snapshots`, the task stores a full document (marked
+// `snapshot: "full"`) only when the container set changes, a container's
+// average CPU or memory percentage moves more than
+// `docker.delta_threshold_percent` points from that snapshot, or
+// `docker.full_snapshot_secs` have passed. In between it stores a delta
+// (`snapshot: "delta"`, `base_seq` = the full snapshot's `seq`) whose
+// containers hold only `id` and the fields that differ from the snapshot.
+//
+// `reconstruct` rebuilds the full state from a snapshot and one of its
+// deltas. Every delta is checked against it before being stored; one that
+// wouldn't reconstruct exactly is stored as a full snapshot instead.
+
+use bson::{Bson, Document};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use tracing::debug;
+
+use crate::config::DockerSettings;
+
+/// `snapshot` value of a self-contained document
+pub const SNAPSHOT_FULL: &str = "full";
+
+/// `snapshot` value of a document that needs its base to be read
+pub const SNAPSHOT_DELTA: &str = "delta";
+
+/// Fields describing a delta rather than the containers
+const DELTA_FIELDS: &[&str] = &["snapshot", "base_seq", "seq", "_id", "_integrity"];
+
+/// The last full snapshot stored, as it was before `seq` was assigned
+struct Base {
+    doc: Document,
+    seq: i64,
+    timestamp: DateTime<Utc>,
+}
+
+/// Decides per flush between a full snapshot and a delta against the last one
+pub struct DeltaEncoder {
+    threshold: f64,
+    full_every_secs: i64,
+    base: Option<Base>,
+    /// Full snapshot handed out by `encode`, becoming the base once stored
+    pending: Option<Base>,
+}
+
+impl DeltaEncoder {
+    pub fn new(settings: &DockerSettings) -> Self {
+        DeltaEncoder {
+            threshold: settings.delta_threshold_percent,
+            full_every_secs: settings.full_snapshot_secs as i64,
+            base: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the document to store for `doc`: a delta against the last
+    /// full snapshot if possible, else `doc` itself marked as a snapshot
+    ///
+    /// `seq` is the sequence number the returned document will be stored under.
+    pub fn encode(&mut self, doc: Document, seq: i64) -> Document {
+        let timestamp = doc.get_datetime("timestamp").map(|t| t.to_chrono()).unwrap_or_else(|_| Utc::now());
+        if let Some(base) = &self.base {
+            if (timestamp - base.timestamp).num_seconds() < self.full_every_secs {
+                if let Some(mut delta) = delta(&base.doc, &doc, self.threshold) {
+                    delta.insert("snapshot", SNAPSHOT_DELTA);
+                    delta.insert("base_seq", base.seq);
+                    if reconstruct(&base.doc, &delta) == doc {
+                        self.pending = None;
+                        return delta;
+                    }
+                    debug!("Docker delta doesn't reconstruct its document, storing a full snapshot");
+                }
+            }
+        }
+
+        self.pending = Some(Base { doc: doc.clone(), seq, timestamp });
+        let mut full = doc;
+        full.insert("snapshot", SNAPSHOT_FULL);
+        full
+    }
+
+    /// Records whether the document from the last `encode` was stored; a
+    /// stored full snapshot becomes the base of the following deltas
+    pub fn stored(&mut self, stored: bool) {
+        if let Some(pending) = self.pending.take() {
+            if stored {
+                self.base = Some(pending);
+            }
+        }
+    }
+}
+
+/// Rebuilds the full document a delta stands for from its base snapshot
+///
+/// Top-level fields come from the delta (its `seq` and `_id` included), the
+/// containers from the delta's list, each the base container with the same
+/// `id` overlaid with the delta's fields. `snapshot`, `base_seq` and
+/// `_integrity` are left out: the result was never stored as such.
+pub fn reconstruct(base: &Document, delta: &Document) -> Document {
+    let mut full: Document = base
+        .iter()
+        .filter(|(key, _)| !DELTA_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    for (key, value) in delta {
+        if !matches!(key.as_str(), "snapshot" | "base_seq" | "_integrity") {
+            full.insert(key.clone(), value.clone());
+        }
+    }
+
+    let base_containers = containers(base);
+    let merged: Vec<Bson> = containers(delta)
+        .into_iter()
+        .map(|changed| {
+            let mut container = base_containers
+                .iter()
+                .find(|c| c.get("id") == changed.get("id"))
+                .map(|c| (*c).clone())
+                .unwrap_or_default();
+            container.extend(changed.clone());
+            Bson::Document(container)
+        })
+        .collect();
+    full.insert("containers", merged);
+    full
+}
+
+/// Delta of `doc` against `base`, or None when a full snapshot is due: the
+/// top-level fields or container set differ, a container gained or lost a
+/// field, or its CPU/memory average moved more than `threshold` points
+fn delta(base: &Document, doc: &Document, threshold: f64) -> Option<Document> {
+    let keys = |d: &Document| -> HashSet<String> {
+        d.keys().filter(|k| !DELTA_FIELDS.contains(&k.as_str())).cloned().collect()
+    };
+    if keys(base) != keys(doc) {
+        return None;
+    }
+    let ids = |d: &Document| -> Option<HashSet<String>> {
+        containers(d).iter().map(|c| c.get_str("id").ok().map(str::to_string)).collect()
+    };
+    let (base_ids, ids) = (ids(base)?, ids(doc)?);
+    if base_ids != ids || ids.len() != containers(doc).len() {
+        return None;
+    }
+
+    let base_containers = containers(base);
+    let mut changed = Vec::new();
+    for container in containers(doc) {
+        let old = base_containers.iter().find(|c| c.get("id") == container.get("id"))?;
+        if keys(old) != keys(container) {
+            return None;
+        }
+        for field in ["cpu_percent", "memory_percent"] {
+            match (average(old, field), average(container, field)) {
+                (Some(a), Some(b)) if (a - b).abs() <= threshold => {}
+                (None, None) => {}
+                _ => return None,
+            }
+        }
+        let mut fields = Document::new();
+        fields.insert("id", container.get("id").cloned().unwrap_or(Bson::Null));
+        for (key, value) in container {
+            if old.get(key) != Some(value) {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+        changed.push(Bson::Document(fields));
+    }
+
+    let mut delta: Document = doc
+        .iter()
+        .filter(|(key, _)| key.as_str() != "containers")
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    delta.insert("containers", changed);
+    Some(delta)
+}
+
+fn containers(doc: &Document) -> Vec<&Document> {
+    doc.get_array("containers")
+        .map(|items| items.iter().filter_map(Bson::as_document).collect())
+        .unwrap_or_default()
+}
+
+/// `<field>.avg` of a container, if it has a numeric one
+fn average(container: &Document, field: &str) -> Option<f64> {
+    match container.get_document(field).ok()?.get("avg")? {
+        Bson::Double(v) => Some(*v),
+        Bson::Int32(v) => Some(f64::from(*v)),
+        Bson::Int64(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    fn docker_doc(secs: i64, cpu: f64, rx: f64) -> Document {
+        doc! {
+            "node": "n1",
+            "timestamp": bson::DateTime::from_millis(secs * 1000),
+            "sample_count": 3,
+            "containers": [
+                { "id": "a1", "name": "api", "memory_limit_mib": 512.0,
+                  "cpu_percent": { "avg": cpu, "min": cpu, "max": cpu },
+                  "memory_percent": { "avg": 20.0, "min": 20.0, "max": 20.0 },
+                  "network_rx_mib": rx, "image": "api:1.4" },
+                { "id": "b2", "name": "db", "memory_limit_mib": 2048.0,
+                  "cpu_percent": { "avg": 1.0, "min": 1.0, "max": 1.0 },
+                  "memory_percent": { "avg": 60.0, "min": 60.0, "max": 60.0 },
+                  "network_rx_mib": 7.0, "image": "postgres:16" },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_delta_between_snapshots_reconstructs() {
+        let settings = DockerSettings { delta_snapshots: true, ..Default::default() };
+        let mut encoder = DeltaEncoder::new(&settings);
+
+        let first = encoder.encode(docker_doc(0, 10.0, 1.0), 1);
+        assert_eq!(first.get_str("snapshot").unwrap(), SNAPSHOT_FULL);
+        encoder.stored(true);
+
+        let current = docker_doc(60, 12.0, 1.5);
+        let delta = encoder.encode(current.clone(), 2);
+        encoder.stored(true);
+        assert_eq!(delta.get_str("snapshot").unwrap(), SNAPSHOT_DELTA);
+        assert_eq!(delta.get_i64("base_seq").unwrap(), 1);
+        // The idle database container is down to its id
+        assert_eq!(containers(&delta)[1], &doc! { "id": "b2" });
+        assert_eq!(reconstruct(&docker_doc(0, 10.0, 1.0), &delta), current);
+
+        // CPU moved 20 points from the snapshot: full again
+        let jump = encoder.encode(docker_doc(120, 30.0, 2.0), 3);
+        assert_eq!(jump.get_str("snapshot").unwrap(), SNAPSHOT_FULL);
+    }
+
+    #[test]
+    fn test_unstored_snapshot_is_not_a_base() {
+        let mut encoder = DeltaEncoder::new(&DockerSettings::default());
+        encoder.encode(docker_doc(0, 10.0, 1.0), 1);
+        encoder.stored(false);
+
+        let next = encoder.encode(docker_doc(60, 10.0, 1.0), 2);
+        assert_eq!(next.get_str("snapshot").unwrap(), SNAPSHOT_FULL);
+    }
+}
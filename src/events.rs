@@ -0,0 +1,69 @@
+// Events module - audit trail of collector lifecycle moments
+//
+// Writes `{node, timestamp, event_type, detail}` documents for process
+// start/stop, settings changes, storage recovery, task panics, scheduler
+// drift and peer heartbeats, so gaps in
+// metric data can be correlated with what the collector was doing at the time.
+
+use bson::doc;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::clock::SharedClock;
+use crate::config::EventSettings;
+use crate::storage::MetricStorage;
+
+/// Lifecycle event types written to the events collection
+pub const EVENT_START: &str = "start";
+pub const EVENT_STOP: &str = "stop";
+pub const EVENT_CONFIG_RELOAD: &str = "config_reload";
+pub const EVENT_STORAGE_RECOVERED: &str = "storage_recovered";
+pub const EVENT_TASK_PANIC: &str = "task_panic";
+pub const EVENT_INTERVAL_DRIFT: &str = "interval_drift";
+pub const EVENT_PEER_LOST: &str = "peer_lost";
+pub const EVENT_PEER_RECOVERED: &str = "peer_recovered";
+
+/// Records lifecycle events for one node; a no-op when `events.enabled` is false
+#[derive(Clone)]
+pub struct EventRecorder {
+    storage: Arc<MetricStorage>,
+    node_id: String,
+    settings: EventSettings,
+    clock: SharedClock,
+}
+
+impl EventRecorder {
+    pub fn new(
+        storage: Arc<MetricStorage>,
+        node_id: String,
+        settings: EventSettings,
+        clock: SharedClock,
+    ) -> Self {
+        EventRecorder {
+            storage,
+            node_id,
+            settings,
+            clock,
+        }
+    }
+
+    /// Stores one event document (failures are logged by storage, never returned)
+    pub async fn record(&self, event_type: &str, detail: impl Into<String>) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let detail = detail.into();
+        debug!("Recording '{}' event for node {}: {}", event_type, self.node_id, detail);
+
+        let event = doc! {
+            "node": &self.node_id,
+            "timestamp": self.clock.now(),
+            "event_type": event_type,
+            "detail": detail,
+        };
+        self.storage
+            .store_metric_safe(&self.settings.collection, "Event", event)
+            .await;
+    }
+}
@@ -0,0 +1,164 @@
+// Heartbeat module - peer liveness mesh over the shared storage
+//
+// For small clusters without central monitoring. With `heartbeat.enabled`,
+// every node upserts its own `{node, timestamp}` document into
+// `node_heartbeat` each interval, then reads everyone's latest heartbeat from
+// the same collection. Peers are simply the other `node` values found there;
+// one that stops heartbeating for longer than `timeout_secs` is logged (and
+// recorded as a `peer_lost` event), and again when it comes back.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use bson::doc;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::clock::SharedClock;
+use crate::config::HeartbeatSettings;
+use crate::events::{EventRecorder, EVENT_PEER_LOST, EVENT_PEER_RECOVERED};
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// A peer crossing the timeout in either direction
+#[derive(Debug, PartialEq)]
+enum PeerChange {
+    Lost { node: String, silent_secs: i64 },
+    Recovered { node: String },
+}
+
+/// Liveness state of the peers seen so far
+struct PeerTracker {
+    node_id: String,
+    timeout_secs: i64,
+    forget_after_secs: i64,
+    /// Peers currently past the timeout
+    lost: HashSet<String>,
+}
+
+impl PeerTracker {
+    fn new(node_id: &str, settings: &HeartbeatSettings) -> Self {
+        PeerTracker {
+            node_id: node_id.to_string(),
+            timeout_secs: settings.timeout_secs as i64,
+            forget_after_secs: settings.forget_after_secs.max(settings.timeout_secs) as i64,
+            lost: HashSet::new(),
+        }
+    }
+
+    /// Compares each peer's latest heartbeat with `now`, returning state changes
+    ///
+    /// Peers silent for longer than `forget_after_secs` are dropped without
+    /// a change, so a node already gone at startup doesn't raise an alert.
+    fn update(&mut self, latest: &HashMap<String, DateTime<Utc>>, now: DateTime<Utc>) -> Vec<PeerChange> {
+        let peers: BTreeMap<&String, &DateTime<Utc>> =
+            latest.iter().filter(|(node, _)| **node != self.node_id).collect();
+
+        let mut changes = Vec::new();
+        for (node, last) in peers {
+            let silent_secs = (now - *last).num_seconds();
+            if silent_secs > self.forget_after_secs {
+                if self.lost.remove(node) {
+                    debug!("Forgetting peer '{}', silent for {}s", node, silent_secs);
+                }
+            } else if silent_secs > self.timeout_secs {
+                if self.lost.insert(node.clone()) {
+                    changes.push(PeerChange::Lost { node: node.clone(), silent_secs });
+                }
+            } else if self.lost.remove(node) {
+                changes.push(PeerChange::Recovered { node: node.clone() });
+            }
+        }
+        changes
+    }
+}
+
+/// Writes heartbeats and watches peers until shutdown
+///
+/// The first heartbeat is written right away, then once per `interval_secs`.
+pub async fn run_heartbeat_task(
+    storage: Arc<MetricStorage>,
+    node_id: String,
+    settings: HeartbeatSettings,
+    events: EventRecorder,
+    clock: SharedClock,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!(
+        "Starting heartbeat task every {}s → '{}' (peer timeout {}s)",
+        settings.interval_secs, settings.collection, settings.timeout_secs
+    );
+
+    let mut tracker = PeerTracker::new(&node_id, &settings);
+    let mut timer = tokio::time::interval(Duration::from_secs(settings.interval_secs.max(1)));
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        let now = clock.now();
+        let heartbeat = doc! { "node": &node_id, "timestamp": now };
+        if let Err(e) = storage.replace_by_id(&settings.collection, &node_id, heartbeat).await {
+            warn!("Failed to write heartbeat: {}", e);
+        }
+
+        let latest = match storage.latest_timestamps(&settings.collection).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                warn!("Failed to read peer heartbeats: {}", e);
+                continue;
+            }
+        };
+        debug!("Heartbeat written; {} node(s) in '{}'", latest.len(), settings.collection);
+
+        for change in tracker.update(&latest, now) {
+            match change {
+                PeerChange::Lost { node, silent_secs } => {
+                    warn!("Peer '{}' lost: no heartbeat for {}s", node, silent_secs);
+                    events
+                        .record(EVENT_PEER_LOST, format!("{} silent for {}s", node, silent_secs))
+                        .await;
+                }
+                PeerChange::Recovered { node } => {
+                    info!("Peer '{}' is heartbeating again", node);
+                    events.record(EVENT_PEER_RECOVERED, node).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_tracker_reports_transitions_once() {
+        let settings = HeartbeatSettings { timeout_secs: 60, forget_after_secs: 3600, ..Default::default() };
+        let mut tracker = PeerTracker::new("n1", &settings);
+        let start = Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+
+        // n3 was already gone long before startup: ignored
+        let mut latest = HashMap::from([
+            ("n1".to_string(), at(0)),
+            ("n2".to_string(), at(0)),
+            ("n3".to_string(), at(-7200)),
+        ]);
+        assert_eq!(tracker.update(&latest, at(30)), []);
+
+        assert_eq!(
+            tracker.update(&latest, at(90)),
+            [PeerChange::Lost { node: "n2".to_string(), silent_secs: 90 }]
+        );
+        assert_eq!(tracker.update(&latest, at(120)), []);
+
+        latest.insert("n2".to_string(), at(150));
+        assert_eq!(
+            tracker.update(&latest, at(160)),
+            [PeerChange::Recovered { node: "n2".to_string() }]
+        );
+    }
+}
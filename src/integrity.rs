@@ -0,0 +1,191 @@
+// Integrity module - tamper-evident hashes on stored documents
+//
+// With `metrics.<Name>.integrity` set, each document is sealed right before
+// it is stored: a SHA-256 over its canonical form goes into `_integrity`.
+// In "chain" mode the hash also covers the previous hash stored for the same
+// node and collection, so removing or altering a document breaks every later
+// link, not just its own hash.
+//
+// Canonical form: the document without `_id` and `_integrity`, keys sorted
+// at every depth, encoded as BSON. Values keep their BSON types, so a
+// document read back from MongoDB hashes the same as the one written.
+//
+// `--verify-integrity <collection>` re-reads a collection and checks both.
+
+use bson::{doc, Bson, Document};
+use futures_util::stream::TryStreamExt;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::storage::{MetricStorage, StorageError};
+
+/// Field holding `{ alg, hash, prev }`
+pub const INTEGRITY_FIELD: &str = "_integrity";
+
+const ALGORITHM: &str = "sha256";
+
+/// Returns `value` with document keys sorted at every depth
+fn canonical(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(doc) => {
+            let mut entries: Vec<(&String, &Bson)> = doc.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Bson::Document(entries.into_iter().map(|(k, v)| (k.clone(), canonical(v))).collect())
+        }
+        Bson::Array(items) => Bson::Array(items.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+/// SHA-256 (lowercase hex) of `prev` (if any) followed by the canonical document
+pub fn compute_hash(document: &Document, prev: Option<&str>) -> String {
+    let mut content = document.clone();
+    content.remove("_id");
+    content.remove(INTEGRITY_FIELD);
+    let Bson::Document(content) = canonical(&Bson::Document(content)) else {
+        unreachable!("canonical form of a document is a document")
+    };
+    let mut bytes = Vec::new();
+    content
+        .to_writer(&mut bytes)
+        .expect("writing BSON to a Vec cannot fail");
+
+    let mut hasher = Sha256::new();
+    if let Some(prev) = prev {
+        hasher.update(prev.as_bytes());
+    }
+    hasher.update(&bytes);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Adds `_integrity` to `document` and returns its hash
+///
+/// `prev` is the node's previous hash in chain mode, None otherwise.
+pub fn seal(document: &mut Document, prev: Option<&str>) -> String {
+    let hash = compute_hash(document, prev);
+    let mut integrity = doc! { "alg": ALGORITHM, "hash": &hash };
+    if let Some(prev) = prev {
+        integrity.insert("prev", prev);
+    }
+    document.insert(INTEGRITY_FIELD, integrity);
+    hash
+}
+
+/// A document that failed verification
+#[derive(Error, Debug, PartialEq)]
+pub enum IntegrityError {
+    #[error("{node} {id}: content does not match its hash (altered)")]
+    HashMismatch { node: String, id: String },
+
+    #[error("{node} {id}: previous hash does not match the preceding document (removed or reordered)")]
+    BrokenChain { node: String, id: String },
+
+    #[error("{node} {id}: unsupported or malformed {INTEGRITY_FIELD}")]
+    Malformed { node: String, id: String },
+}
+
+/// Checks sealed documents of one collection, in store order per node
+#[derive(Default)]
+pub struct Verifier {
+    /// Hash of the last sealed document seen per node
+    last: HashMap<String, String>,
+    pub checked: u64,
+    pub unsealed: u64,
+}
+
+impl Verifier {
+    /// Verifies the next document; documents without `_integrity` are skipped
+    pub fn check(&mut self, document: &Document) -> Result<(), IntegrityError> {
+        let Ok(integrity) = document.get_document(INTEGRITY_FIELD) else {
+            self.unsealed += 1;
+            return Ok(());
+        };
+        self.checked += 1;
+
+        let node = document.get_str("node").unwrap_or_default().to_string();
+        let id = document.get("_id").map(ToString::to_string).unwrap_or_default();
+        let (Ok(ALGORITHM), Ok(hash)) = (integrity.get_str("alg"), integrity.get_str("hash")) else {
+            return Err(IntegrityError::Malformed { node, id });
+        };
+        let prev = integrity.get_str("prev").ok();
+
+        let previous = self.last.insert(node.clone(), hash.to_string());
+        if compute_hash(document, prev) != hash {
+            return Err(IntegrityError::HashMismatch { node, id });
+        }
+        // The oldest document's `prev` may point at one already expired by retention
+        if let (Some(prev), Some(previous)) = (prev, previous) {
+            if prev != previous {
+                return Err(IntegrityError::BrokenChain { node, id });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `--verify-integrity`: checks every sealed document in `collection`
+///
+/// Logs each failure and a summary; returns the number of failures.
+pub async fn verify_collection(storage: &MetricStorage, collection: &str) -> Result<u64, StorageError> {
+    info!("Verifying integrity of collection '{}'", collection);
+
+    let mut cursor = storage.find_in_store_order(collection).await?;
+    let mut verifier = Verifier::default();
+    let mut failures = 0;
+    while let Some(document) = cursor.try_next().await? {
+        if let Err(e) = verifier.check(&document) {
+            warn!("Integrity check failed: {}", e);
+            failures += 1;
+        }
+    }
+
+    info!(
+        "Collection '{}': {} sealed document(s) checked across {} node(s), {} unsealed skipped, {} failure(s)",
+        collection,
+        verifier.checked,
+        verifier.last.len(),
+        verifier.unsealed,
+        failures
+    );
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_detects_alteration_and_removal() {
+        let timestamp = bson::DateTime::from_millis(1_700_000_000_000);
+        let mut chain = Vec::new();
+        let mut prev: Option<String> = None;
+        for seq in 1..=4_i64 {
+            let mut document = doc! { "node": "n1", "timestamp": timestamp, "seq": seq, "load": { "max": 1.5, "avg": 1.0 } };
+            prev = Some(seal(&mut document, prev.as_deref()));
+            document.insert("_id", bson::oid::ObjectId::new());
+            chain.push(document);
+        }
+
+        // Key order doesn't matter, so a round trip through MongoDB still verifies
+        let mut reordered = chain[0].clone();
+        let load = reordered.remove("load").unwrap();
+        reordered.insert("load", load);
+        let mut verifier = Verifier::default();
+        assert_eq!(verifier.check(&reordered), Ok(()));
+        assert!(chain[1..].iter().all(|d| verifier.check(d).is_ok()));
+
+        // Altered value
+        let mut altered = chain.clone();
+        altered[2].insert("seq", 30_i64);
+        let mut verifier = Verifier::default();
+        let results: Vec<bool> = altered.iter().map(|d| verifier.check(d).is_ok()).collect();
+        assert_eq!(results, [true, true, false, true]);
+
+        // Removed document: the next one's link no longer matches
+        let mut verifier = Verifier::default();
+        let results: Vec<bool> = [&chain[0], &chain[1], &chain[3]].iter().map(|d| verifier.check(d).is_ok()).collect();
+        assert_eq!(results, [true, true, false]);
+    }
+}
@@ -0,0 +1,103 @@
+// Kubernetes module - automatic tags from the downward API
+//
+// When the collector runs as a DaemonSet, the pod spec can expose its node,
+// pod name and resource limits through environment variables or a downward-API
+// volume. Whatever is found becomes tags on every document, merged in before
+// the configured transforms run. Outside Kubernetes nothing is found and no
+// tags are added.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Directory of the downward-API volume when `K8S_PODINFO_DIR` is not set
+const DEFAULT_PODINFO_DIR: &str = "/etc/podinfo";
+
+/// Tag name, environment variable and downward-API volume file, in that order
+const SOURCES: [(&str, &str, &str); 4] = [
+    ("k8s_node", "K8S_NODE_NAME", "node_name"),   // fieldRef: spec.nodeName
+    ("k8s_pod", "K8S_POD_NAME", "pod_name"),      // fieldRef: metadata.name
+    ("cpu_limit", "K8S_CPU_LIMIT", "cpu_limit"),  // resourceFieldRef: limits.cpu
+    ("mem_limit", "K8S_MEM_LIMIT", "mem_limit"),  // resourceFieldRef: limits.memory
+];
+
+/// Reads the downward-API tags from the process environment and volume
+///
+/// Empty when not running in Kubernetes.
+pub fn downward_api_tags() -> BTreeMap<String, String> {
+    let tags = collect_tags(
+        |name| std::env::var(name).ok(),
+        |path| std::fs::read_to_string(path).ok(),
+    );
+    if !tags.is_empty() {
+        info!("Kubernetes tags: {:?}", tags);
+    }
+    tags
+}
+
+/// Environment variables win over volume files; blank values are ignored
+///
+/// The volume is only read when `K8S_PODINFO_DIR` is set or the process runs
+/// in a pod (`KUBERNETES_SERVICE_HOST` is always set there), so a stray
+/// /etc/podinfo on a plain host is never picked up.
+fn collect_tags(
+    env: impl Fn(&str) -> Option<String>,
+    read_file: impl Fn(&Path) -> Option<String>,
+) -> BTreeMap<String, String> {
+    let podinfo_dir: Option<PathBuf> = match env("K8S_PODINFO_DIR") {
+        Some(dir) => Some(dir.into()),
+        None if env("KUBERNETES_SERVICE_HOST").is_some() => Some(DEFAULT_PODINFO_DIR.into()),
+        None => None,
+    };
+
+    let mut tags = BTreeMap::new();
+    for (tag, var, file) in SOURCES {
+        let value = env(var)
+            .or_else(|| podinfo_dir.as_ref().and_then(|dir| read_file(&dir.join(file))))
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        match value {
+            Some(value) => {
+                tags.insert(tag.to_string(), value);
+            }
+            None => debug!("Kubernetes tag '{}' not available ({} unset)", tag, var),
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_collect_tags_env_and_volume() {
+        let files: HashMap<PathBuf, String> = [
+            (PathBuf::from("/etc/podinfo/node_name"), "ignored\n".to_string()),
+            (PathBuf::from("/etc/podinfo/mem_limit"), "536870912\n".to_string()),
+        ]
+        .into();
+        let read_file = |path: &Path| files.get(path).cloned();
+
+        let in_pod: HashMap<&str, &str> = [
+            ("KUBERNETES_SERVICE_HOST", "10.96.0.1"),
+            ("K8S_NODE_NAME", "worker-3"),
+            ("K8S_POD_NAME", "metrics-collector-x7k2p"),
+            ("K8S_CPU_LIMIT", " "),
+        ]
+        .into();
+        let tags = collect_tags(|name| in_pod.get(name).map(|v| v.to_string()), read_file);
+        assert_eq!(
+            tags,
+            BTreeMap::from([
+                ("k8s_node".to_string(), "worker-3".to_string()),
+                ("k8s_pod".to_string(), "metrics-collector-x7k2p".to_string()),
+                ("mem_limit".to_string(), "536870912".to_string()),
+            ])
+        );
+
+        // Outside a pod the default volume path is not read
+        assert!(collect_tags(|_| None, read_file).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+// Memory guard module - caps the collector's own memory footprint
+//
+// A monitoring agent must not become the problem on the host it watches. The
+// one buffer that grows without a bound in time is the outage buffer: during
+// a long MongoDB outage it fills every collection's queue. With
+// `--max-rss-mib <N>`, the process's resident set size is read every few
+// seconds, and while it is above N MiB the older half of every outage buffer
+// queue is dropped and logged as an error.
+//
+// Freed memory isn't always handed back to the OS at once, so RSS can stay
+// above the ceiling for a while after shedding; each check then sheds again,
+// which may empty the buffer. Runs once per process (not per node).
+
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{Pid, System};
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// How often the process's RSS is read
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+const MIB: u64 = 1024 * 1024;
+
+/// Resident set size of this process in bytes, if it can be read
+fn own_rss(sys: &mut System, pid: Pid) -> Option<u64> {
+    sys.refresh_process(pid);
+    sys.process(pid).map(|process| process.memory())
+}
+
+/// Sheds held documents whenever RSS exceeds `max_rss_mib`, until shutdown
+pub async fn run_memory_guard_task(
+    storage: Arc<MetricStorage>,
+    max_rss_mib: u64,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let pid = match sysinfo::get_current_pid() {
+        Ok(pid) => pid,
+        Err(e) => {
+            warn!("Cannot find the collector's own PID ({}); --max-rss-mib is not enforced", e);
+            return;
+        }
+    };
+    info!("Memory guard: shedding held documents above {} MiB RSS", max_rss_mib);
+
+    let mut sys = System::new();
+    let mut timer = tokio::time::interval(CHECK_INTERVAL);
+    // Whether the last check was above the ceiling, to log each episode once
+    let mut over = false;
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        let Some(rss) = own_rss(&mut sys, pid) else {
+            debug!("Could not read the collector's own RSS");
+            continue;
+        };
+        let rss_mib = rss / MIB;
+        if rss_mib <= max_rss_mib {
+            if over {
+                info!("RSS back to {} MiB, within --max-rss-mib {}", rss_mib, max_rss_mib);
+                over = false;
+            }
+            continue;
+        }
+
+        match storage.shed_outage_buffer() {
+            Some(dropped) if dropped > 0 => error!(
+                rss_mib,
+                dropped,
+                "RSS {} MiB exceeds --max-rss-mib {}: dropped {} held document(s), oldest first",
+                rss_mib,
+                max_rss_mib,
+                dropped
+            ),
+            _ if !over => error!(
+                rss_mib,
+                "RSS {} MiB exceeds --max-rss-mib {}, but no held documents are left to drop",
+                rss_mib,
+                max_rss_mib
+            ),
+            _ => debug!("RSS still {} MiB, nothing left to shed", rss_mib),
+        }
+        over = true;
+    }
+}
@@ -0,0 +1,74 @@
+// Canary liveness collector
+//
+// When every real collector fails, their collections go quiet exactly as if
+// the process were down. The canary reads nothing from the host, so it cannot
+// fail: with `canary.enabled` it writes a bare document to `canary` every
+// `canary.interval_secs`. A fresh one proves the process is running and its
+// storage path works end to end; its `seq` shows any write that was lost.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use std::error::Error;
+use tracing::debug;
+
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+
+/// Liveness collector
+///
+/// # What is Collected
+/// Nothing beyond `node` and `timestamp`; the scheduler adds `seq` as it does
+/// for every stored document.
+///
+/// # Platform Support
+/// - All platforms; registered only with `canary.enabled`
+pub struct CanaryCollector {
+    clock: SharedClock,
+}
+
+impl CanaryCollector {
+    /// Creates a new CanaryCollector instance
+    pub fn new() -> Self {
+        CanaryCollector {
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[async_trait]
+impl MetricCollector for CanaryCollector {
+    /// Returns the metric name
+    fn name(&self) -> &str {
+        "Canary"
+    }
+
+    /// Returns the liveness document; never fails
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "..."
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting canary");
+
+        Ok(doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+        })
+    }
+}
+
+impl Default for CanaryCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
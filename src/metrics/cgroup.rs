@@ -0,0 +1,138 @@
+// Cgroup attribution - maps host processes to the container they run in
+//
+// Used by the process snapshot collectors. The container id is read from
+// /proc/<pid>/cgroup; the container name comes from Docker's container list
+// when the Docker join is enabled.
+
+use bollard::Docker;
+use bson::{doc, Document};
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::config::ProcessSettings;
+
+/// Length of a full Docker/containerd/podman container id (hex sha256)
+const CONTAINER_ID_LEN: usize = 64;
+
+/// Resolves processes to container `{ id, name }` sub-documents
+pub struct ContainerAttribution {
+    settings: ProcessSettings,
+
+    /// Docker client for the name join; None when the join is disabled
+    docker: Option<Docker>,
+}
+
+impl ContainerAttribution {
+    /// Creates a resolver using the `processes` section of the monitoring settings
+    pub fn with_settings(settings: ProcessSettings) -> Self {
+        let docker = if settings.container_attribution && settings.docker_names {
+            Docker::connect_with_socket_defaults()
+                .or_else(|_| Docker::connect_with_local_defaults())
+                .ok()
+        } else {
+            None
+        };
+        ContainerAttribution { settings, docker }
+    }
+
+    /// Returns the `container` sub-document for every given pid that runs in a container
+    ///
+    /// Pids outside any container (or whose cgroup file can't be read) are
+    /// omitted. Names are added only when the Docker join is enabled and the
+    /// daemon answers; otherwise the sub-document carries the id alone.
+    pub async fn resolve(&self, pids: &[u32]) -> HashMap<u32, Document> {
+        if !self.settings.container_attribution {
+            return HashMap::new();
+        }
+
+        let ids: HashMap<u32, String> = pids
+            .iter()
+            .filter_map(|pid| {
+                let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+                container_id_from_cgroup(&cgroup).map(|id| (*pid, id))
+            })
+            .collect();
+
+        let names = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.container_names().await
+        };
+
+        ids.into_iter()
+            .map(|(pid, id)| {
+                let mut container = doc! { "id": &id[..12] };
+                if let Some(name) = names.get(&id) {
+                    container.insert("name", name.as_str());
+                }
+                (pid, container)
+            })
+            .collect()
+    }
+
+    /// Full container id → name for running Docker containers (empty on any failure)
+    async fn container_names(&self) -> HashMap<String, String> {
+        let Some(docker) = &self.docker else {
+            return HashMap::new();
+        };
+
+        match docker.list_containers::<String>(None).await {
+            Ok(containers) => containers
+                .into_iter()
+                .filter_map(|c| {
+                    let name = c.names?.first()?.trim_start_matches('/').to_string();
+                    Some((c.id?, name))
+                })
+                .collect(),
+            Err(e) => {
+                debug!("Docker unavailable for process attribution: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+}
+
+/// Extracts the container id from the contents of /proc/<pid>/cgroup
+///
+/// Handles cgroup v1 and v2 layouts from Docker (`/docker/<id>`,
+/// `docker-<id>.scope`), containerd/Kubernetes (`cri-containerd-<id>.scope`,
+/// `/kubepods/.../<id>`) and podman (`libpod-<id>.scope`).
+pub fn container_id_from_cgroup(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        // hierarchy-id:controllers:path
+        let path = line.splitn(3, ':').nth(2)?;
+        path.rsplit('/').find_map(|segment| {
+            let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+            let candidate = segment.rsplit('-').next()?;
+            let is_id = candidate.len() == CONTAINER_ID_LEN
+                && candidate.bytes().all(|b| b.is_ascii_hexdigit());
+            is_id.then(|| candidate.to_string())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f4e8a1b2c9d0e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f";
+
+    #[test]
+    fn test_container_id_from_cgroup_layouts() {
+        let layouts = [
+            format!("12:memory:/docker/{}\n11:cpu:/docker/{}", ID, ID),
+            format!("0::/system.slice/docker-{}.scope", ID),
+            format!("0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-{}.scope", ID),
+            format!("0::/machine.slice/libpod-{}.scope/container", ID),
+        ];
+        for cgroup in &layouts {
+            assert_eq!(container_id_from_cgroup(cgroup).as_deref(), Some(ID), "{}", cgroup);
+        }
+    }
+
+    #[test]
+    fn test_host_process_has_no_container() {
+        assert_eq!(container_id_from_cgroup("0::/user.slice/user-1000.slice/session-2.scope"), None);
+        assert_eq!(container_id_from_cgroup("0::/init.scope"), None);
+    }
+}
@@ -0,0 +1,340 @@
+// Describe module - catalog of the collectors this binary supports
+//
+// A config UI or linter needs to know, without running anything, which
+// metrics exist, where they are stored, which fields they emit and which
+// settings they honour. `--describe-metrics --json` dumps that catalog, built
+// from the table below plus the schema field tables, so a `MonitoringSettings`
+// document can be generated or checked against this exact build.
+// `--emit-settings-template` goes one step further and prints a complete
+// settings document with every section at its defaults and one `metrics`
+// entry per collector, ready to edit and insert.
+
+use serde_json::{json, Value};
+
+use crate::build_info;
+use crate::config::{
+    CanarySettings, DiskSettings, DockerDiskSettings, DockerSettings, LoadSettings, MetricSettings, MonitoringSettings, NetworkSettings, NumericSettings,
+    ProcessSettings, SizeUnits, SmartSettings,
+};
+use crate::scheduler::{collection_for, is_log_metric};
+use crate::schema;
+
+/// Static description of one collector
+pub struct MetricDescriptor {
+    /// Metric name, as returned by `MetricCollector::name` and used as key
+    /// under `metrics`
+    pub name: &'static str,
+
+    /// One-line summary of what is collected
+    pub description: &'static str,
+
+    /// Operating systems (`std::env::consts::OS` values) it runs on
+    pub platforms: &'static [&'static str],
+
+    /// Runtime requirements beyond the platform, checked when it starts
+    pub requires: &'static [&'static str],
+
+    /// `MonitoringSettings` field setting its collection interval
+    pub interval_setting: &'static str,
+
+    /// Collector-specific `MonitoringSettings` sections it reads
+    pub sections: &'static [&'static str],
+}
+
+const ALL_PLATFORMS: &[&str] = &["linux", "macos", "windows"];
+const LINUX: &[&str] = &["linux"];
+
+/// Every collector in the binary, in registration order
+pub const METRICS: &[MetricDescriptor] = &[
+    MetricDescriptor {
+        name: "LoadAverage",
+        description: "1, 5 and 15 minute load averages, also per CPU core, and an overload flag",
+        platforms: &["linux", "macos"],
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["load"],
+    },
+    MetricDescriptor {
+        name: "Memory",
+        description: "Total, available and swap memory, honouring cgroup limits",
+        platforms: ALL_PLATFORMS,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["numeric"],
+    },
+    MetricDescriptor {
+        name: "DiskSpace",
+        description: "Capacity, usage and a low-space flag for every mounted filesystem",
+        platforms: ALL_PLATFORMS,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["disk", "numeric"],
+    },
+    MetricDescriptor {
+        name: "Network",
+        description: "Byte counters and throughput per physical interface, and IP addresses",
+        platforms: ALL_PLATFORMS,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["network", "numeric"],
+    },
+    MetricDescriptor {
+        name: "DockerStats",
+        description: "CPU, memory, network and block I/O per running container",
+        platforms: ALL_PLATFORMS,
+        requires: &["Docker daemon"],
+        interval_setting: "collect_docker_timeout",
+        sections: &["docker", "numeric"],
+    },
+    MetricDescriptor {
+        name: "ProcessCPUSnapshot",
+        description: "Top host processes by CPU usage, with container attribution",
+        platforms: ALL_PLATFORMS,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["processes", "numeric"],
+    },
+    MetricDescriptor {
+        name: "ProcessRAMSnapshot",
+        description: "Top host processes by memory usage, with container attribution",
+        platforms: ALL_PLATFORMS,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &["processes", "numeric"],
+    },
+    MetricDescriptor {
+        name: "DockerEvents",
+        description: "Container lifecycle events (start, stop, die, OOM kill, restart)",
+        platforms: ALL_PLATFORMS,
+        requires: &["Docker daemon"],
+        interval_setting: "collect_docker_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "DockerLogs",
+        description: "stdout/stderr lines of every running container",
+        platforms: ALL_PLATFORMS,
+        requires: &["Docker daemon"],
+        interval_setting: "collect_docker_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "SystemEvents",
+        description: "Kernel and systemd error events from the journal",
+        platforms: LINUX,
+        requires: &["journalctl"],
+        interval_setting: "collect_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "Entropy",
+        description: "Available kernel entropy in bits",
+        platforms: LINUX,
+        requires: &[],
+        interval_setting: "collect_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "Pressure",
+        description: "Pressure stall information for CPU, I/O and memory",
+        platforms: LINUX,
+        requires: &["Linux 4.20+ with PSI enabled"],
+        interval_setting: "collect_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "KernelLog",
+        description: "OOM kills and kernel errors from the kernel ring buffer",
+        platforms: LINUX,
+        requires: &["/dev/kmsg readable (root or CAP_SYSLOG)"],
+        interval_setting: "collect_timeout",
+        sections: &[],
+    },
+    MetricDescriptor {
+        name: "DiskSmart",
+        description: "SMART health, reallocated sectors, temperature and power-on hours per disk",
+        platforms: LINUX,
+        requires: &["smart.enabled", "smartctl (smartmontools)", "root"],
+        interval_setting: "smart.interval_secs",
+        sections: &["smart"],
+    },
+    MetricDescriptor {
+        name: "DockerDisk",
+        description: "Size and reclaimable size of Docker images, containers, volumes and build cache",
+        platforms: ALL_PLATFORMS,
+        requires: &["docker_disk.enabled", "Docker daemon"],
+        interval_setting: "docker_disk.interval_secs",
+        sections: &["docker_disk", "numeric"],
+    },
+    MetricDescriptor {
+        name: "Canary",
+        description: "Bare liveness document proving the process and storage path are up",
+        platforms: ALL_PLATFORMS,
+        requires: &["canary.enabled"],
+        interval_setting: "canary.interval_secs",
+        sections: &["canary"],
+    },
+];
+
+impl MetricDescriptor {
+    /// Whether this build runs the collector on the OS it was built for
+    pub fn supported(&self) -> bool {
+        self.platforms.contains(&std::env::consts::OS)
+    }
+
+    /// Fields this build emits, with binary size units
+    ///
+    /// Per-container GPU fields are only emitted with the `gpu` feature.
+    fn fields(&self) -> Vec<Value> {
+        let aggregated = !is_log_metric(self.name);
+        schema::fields(self.name, aggregated, SizeUnits::Binary)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(name, _, _)| cfg!(feature = "gpu") || !name.starts_with("containers.gpu_"))
+            .map(|(name, unit, kind)| json!({ "field": name, "unit": unit, "type": kind }))
+            .collect()
+    }
+
+    fn to_json(&self) -> Value {
+        let sections: serde_json::Map<String, Value> = self
+            .sections
+            .iter()
+            .map(|section| (section.to_string(), section_defaults(section)))
+            .collect();
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "collection": collection_for(self.name),
+            "aggregated": !is_log_metric(self.name),
+            "interval_setting": self.interval_setting,
+            "platforms": self.platforms,
+            "supported": self.supported(),
+            "requires": self.requires,
+            "settings": sections,
+            "fields": self.fields(),
+        })
+    }
+}
+
+/// Default values of one collector-specific settings section
+fn section_defaults(section: &str) -> Value {
+    let defaults = match section {
+        "load" => serde_json::to_value(LoadSettings::default()),
+        "disk" => serde_json::to_value(DiskSettings::default()),
+        "docker" => serde_json::to_value(DockerSettings::default()),
+        "network" => serde_json::to_value(NetworkSettings::default()),
+        "processes" => serde_json::to_value(ProcessSettings::default()),
+        "smart" => serde_json::to_value(SmartSettings::default()),
+        "canary" => serde_json::to_value(CanarySettings::default()),
+        "docker_disk" => serde_json::to_value(DockerDiskSettings::default()),
+        "numeric" => serde_json::to_value(NumericSettings::default()),
+        _ => Ok(Value::Null),
+    };
+    defaults.unwrap_or(Value::Null)
+}
+
+/// The whole catalog: build, per-metric options with defaults, and every metric
+pub fn catalog() -> Value {
+    json!({
+        "version": build_info::VERSION,
+        "git_commit": build_info::GIT_COMMIT,
+        "schema_version": schema::SCHEMA_VERSION,
+        "size_units_note": "sizes are listed in binary units; numeric.size_units \"decimal\" renames _mib to _mb etc.",
+        "metric_options": serde_json::to_value(MetricSettings::default()).unwrap_or(Value::Null),
+        "metrics": METRICS.iter().map(MetricDescriptor::to_json).collect::<Vec<_>>(),
+    })
+}
+
+/// A complete `MonitoringSettings` document for `key`, for
+/// `--emit-settings-template`
+///
+/// The three required intervals get the values the README uses (5, 20, 60),
+/// every section its defaults, and every collector in `METRICS` a
+/// `metrics.<Name>` entry with the default options and its built-in
+/// collection spelled out. Unset options show as null. A pinned `collection`
+/// takes precedence over `collection_template`, so remove those entries
+/// before setting one.
+pub fn settings_template(key: &str) -> serde_json::Result<Value> {
+    let settings: MonitoringSettings = serde_json::from_value(json!({
+        "key": key,
+        "collect_timeout": 5,
+        "collect_docker_timeout": 20,
+        "store_timeout": 60,
+    }))?;
+    let mut template = serde_json::to_value(settings)?;
+    // Built here rather than through the `metrics` HashMap, to keep `METRICS` order
+    let metrics = METRICS
+        .iter()
+        .map(|metric| {
+            let options = MetricSettings {
+                collection: Some(collection_for(metric.name).to_string()),
+                ..MetricSettings::default()
+            };
+            Ok((metric.name.to_string(), serde_json::to_value(options)?))
+        })
+        .collect::<serde_json::Result<serde_json::Map<_, _>>>()?;
+    template["metrics"] = Value::Object(metrics);
+    Ok(template)
+}
+
+/// One line per metric, for `--describe-metrics` without `--json`
+pub fn summary() -> String {
+    METRICS
+        .iter()
+        .map(|metric| {
+            let support = if metric.supported() { "" } else { " (unsupported on this platform)" };
+            format!("{:<20} {}{}", metric.name, metric.description, support)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::collect_timeout_for;
+
+    #[test]
+    fn test_descriptors_match_scheduler_and_schema() {
+        let settings: crate::config::MonitoringSettings = serde_json::from_value(json!({
+            "key": "n1", "collect_timeout": 1, "collect_docker_timeout": 2, "store_timeout": 3,
+            "smart": { "interval_secs": 4 }, "canary": { "interval_secs": 6 }, "docker_disk": { "interval_secs": 7 },
+        }))
+        .unwrap();
+
+        for metric in METRICS {
+            assert_ne!(collection_for(metric.name), "unknown_metrics", "{}", metric.name);
+            assert!(!metric.fields().is_empty(), "{} has no schema fields", metric.name);
+            let expected = match collect_timeout_for(metric.name, &settings) {
+                2 => "collect_docker_timeout",
+                4 => "smart.interval_secs",
+                6 => "canary.interval_secs",
+                7 => "docker_disk.interval_secs",
+                _ => "collect_timeout",
+            };
+            assert_eq!(metric.interval_setting, expected, "{}", metric.name);
+            for section in metric.sections {
+                assert!(section_defaults(section).is_object(), "{}: section '{}'", metric.name, section);
+            }
+        }
+    }
+
+    #[test]
+    fn test_settings_template_is_valid_and_complete() {
+        let template = settings_template("0001-0001").unwrap();
+        let settings: crate::config::MonitoringSettings = serde_json::from_value(template.clone()).unwrap();
+        settings.validate().unwrap();
+        assert_eq!(settings.key, "0001-0001");
+
+        for metric in METRICS {
+            let entry = &template["metrics"][metric.name];
+            assert_eq!(entry["collection"], collection_for(metric.name), "{}", metric.name);
+            assert_eq!(entry["enabled"], true, "{}", metric.name);
+            for section in metric.sections {
+                assert_eq!(template[*section], section_defaults(section), "{}: section '{}'", metric.name, section);
+            }
+        }
+        let names: Vec<&String> = template["metrics"].as_object().unwrap().keys().collect();
+        assert_eq!(names, METRICS.iter().map(|m| m.name).collect::<Vec<_>>());
+    }
+}
@@ -4,13 +4,18 @@
 // Provides information about total, used, and available space
 
 use async_trait::async_trait;
-use bson::{doc, Document};
-use chrono::Utc;
+use bson::{doc, Bson, Document};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use sysinfo::Disks;
-use tracing::debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
 
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use super::numeric::{size, size_field, size_unit, Scale};
+use crate::config::{DiskSettings, SizeUnits};
 
 /// Disk space metric collector
 ///
@@ -19,6 +24,7 @@ use super::MetricCollector;
 ///
 /// # What is Collected
 /// - Mount point (e.g., "/", "/home", "/mnt/data")
+/// - Device (e.g., "/dev/sda1" — the first field of `/proc/mounts` on Linux)
 /// - Filesystem type (e.g., "ext4", "xfs", "apfs")
 /// - Total space
 /// - Used space
@@ -29,23 +35,196 @@ use super::MetricCollector;
 /// - Linux: Full support via statvfs
 /// - macOS: Full support
 /// - Windows: Full support (drive letters)
-pub struct DiskCollector;
+///
+/// # Device Deduplication
+/// With `disk.dedupe_by_device` enabled, mounts that share the same
+/// underlying device (bind mounts, btrfs subvolumes) are collapsed into a
+/// single entry so capacity is only counted once. The entry keeps the
+/// shortest mount point and lists every alias under `mount_points`.
+///
+/// # Usage Threshold
+/// With `disk.min_used_percent` set, only disks at or above that usage are
+/// reported, plus the `disk.pinned_mounts` (the root filesystem by default).
+/// `disk.all_on_breach` reports every disk while any disk is over the
+/// threshold, and `disk.full_report_every` reports every disk on every Nth
+/// collection so the quiet ones remain visible.
+///
+/// # Low Space
+/// Each readable disk gets `low_space: true` when its available space is
+/// under `disk.low_space_gib` or under `disk.low_space_percent` of its
+/// capacity, whichever trips first: a percentage alone misses a full 50 GB
+/// disk and cries wolf on a 20 TB one. Low-space disks are always reported.
+/// With both thresholds at 0 the field is left out.
+///
+/// # Forecast
+/// With `disk.forecast_samples` set, the last that many (time, used bytes)
+/// readings of each mount are kept in memory, and a least-squares line
+/// through them gives the fill rate. `estimated_days_until_full` is the
+/// available space divided by that rate: null when usage is flat or
+/// shrinking, and left out until the window is full.
+///
+/// # Failing Mounts
+/// On Linux each mount is queried with `statvfs` on a blocking thread,
+/// bounded by `disk.stat_timeout_secs`. Network filesystems (NFS, CIFS) are
+/// included. A mount that errors or times out is reported with an `error`
+/// field; a mount whose query is still hung from an earlier collection is
+/// not queried again until it returns.
+///
+/// Elsewhere sysinfo reads every disk in one call, run on a blocking thread
+/// under the same timeout. When it times out, the disks from the last
+/// successful refresh are reported with an `error` field, and no new refresh
+/// starts until the hung one returns.
+pub struct DiskCollector {
+    settings: DiskSettings,
+    /// Number of collections so far, drives `full_report_every`
+    collections: AtomicU64,
+    /// Mount points (Linux) or the disk refresh whose blocking call has not
+    /// returned yet
+    pending: Arc<Mutex<HashSet<String>>>,
+    /// (device, mount point, filesystem) of every disk in the last successful
+    /// sysinfo refresh, reported when a refresh times out
+    #[cfg(not(target_os = "linux"))]
+    known_disks: Mutex<Vec<(String, String, String)>>,
+    clock: SharedClock,
+    /// `numeric.size_units`: `total_gib` etc., or `_gb` fields
+    units: SizeUnits,
+    /// Recent (seconds, used bytes) readings per mount point, oldest first
+    history: Mutex<HashMap<String, VecDeque<(f64, f64)>>>,
+}
+
+/// One mounted filesystem and its capacity, or why it couldn't be read
+struct MountUsage {
+    mount_point: String,
+    device: String,
+    filesystem: String,
+    /// (total bytes, available bytes)
+    space: Result<(u64, u64), String>,
+}
 
 impl DiskCollector {
-    /// Creates a new DiskCollector instance
+    /// Creates a new DiskCollector instance with default settings
     pub fn new() -> Self {
-        DiskCollector
+        Self::with_settings(DiskSettings::default())
+    }
+
+    /// Creates a DiskCollector using the `disk` section of the monitoring settings
+    pub fn with_settings(settings: DiskSettings) -> Self {
+        DiskCollector {
+            settings,
+            collections: AtomicU64::new(0),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            #[cfg(not(target_os = "linux"))]
+            known_disks: Mutex::new(Vec::new()),
+            clock: system_clock(),
+            units: SizeUnits::default(),
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of the size fields (`numeric.size_units`)
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Lists mounted filesystems with their capacity (Linux: /proc/mounts + statvfs)
+    #[cfg(target_os = "linux")]
+    async fn mount_usage(&self) -> Vec<MountUsage> {
+        let contents = match tokio::fs::read_to_string("/proc/mounts").await {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Failed to read /proc/mounts: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let timeout = Duration::from_secs(self.settings.stat_timeout_secs.max(1));
+        let queries = parse_mounts(&contents)
+            .into_iter()
+            .map(|(device, mount_point, filesystem)| async move {
+                let space = self.stat_mount(&mount_point, timeout).await;
+                MountUsage { mount_point, device, filesystem, space }
+            });
+
+        futures_util::future::join_all(queries)
+            .await
+            .into_iter()
+            // Like sysinfo: zero-sized pseudo filesystems are not disks
+            .filter(|m| !matches!(m.space, Ok((0, _))))
+            .collect()
     }
 
-    /// Converts bytes to gigabytes for more readable storage
+    /// Runs statvfs for one mount point on a blocking thread, bounded by `timeout`
+    #[cfg(target_os = "linux")]
+    async fn stat_mount(&self, mount_point: &str, timeout: Duration) -> Result<(u64, u64), String> {
+        let path = mount_point.to_string();
+        run_bounded(&self.pending, mount_point, "statvfs", timeout, move || statvfs_space(&path))
+            .await?
+            .map_err(|e| format!("statvfs failed: {}", e))
+    }
+
+    /// Lists mounted filesystems with their capacity (sysinfo), bounded by
+    /// `disk.stat_timeout_secs`
+    ///
+    /// sysinfo refreshes all disks in one call, so a hung network mount stalls
+    /// the whole list; on timeout the disks of the last successful refresh are
+    /// returned with the error instead.
+    #[cfg(not(target_os = "linux"))]
+    async fn mount_usage(&self) -> Vec<MountUsage> {
+        let timeout = Duration::from_secs(self.settings.stat_timeout_secs.max(1));
+        let refresh = run_bounded(&self.pending, "", "disk refresh", timeout, || {
+            sysinfo::Disks::new_with_refreshed_list()
+                .list()
+                .iter()
+                .map(|disk| MountUsage {
+                    mount_point: disk.mount_point().to_string_lossy().to_string(),
+                    device: disk.name().to_string_lossy().to_string(),
+                    filesystem: disk.file_system().to_string_lossy().to_string(),
+                    space: Ok((disk.total_space(), disk.available_space())),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let refresh = refresh.await;
+        let mut known_disks = self.known_disks.lock().unwrap_or_else(|e| e.into_inner());
+        match refresh {
+            Ok(mounts) => {
+                *known_disks = mounts
+                    .iter()
+                    .map(|m| (m.device.clone(), m.mount_point.clone(), m.filesystem.clone()))
+                    .collect();
+                mounts
+            }
+            Err(e) => {
+                warn!("{}; reporting the {} disk(s) of the last successful refresh", e, known_disks.len());
+                known_disks
+                    .iter()
+                    .map(|(device, mount_point, filesystem)| MountUsage {
+                        mount_point: mount_point.clone(),
+                        device: device.clone(),
+                        filesystem: filesystem.clone(),
+                        space: Err(e.clone()),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Converts bytes to gigabytes (GiB or GB, per `numeric.size_units`)
     ///
     /// # Arguments
     /// * `bytes` - Value in bytes
     ///
     /// # Returns
-    /// Value in gigabytes (GB) as f64
-    fn bytes_to_gb(bytes: u64) -> f64 {
-        bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+    /// Value in gigabytes as f64
+    fn gigabytes(&self, bytes: u64) -> f64 {
+        size(bytes, Scale::Giga, self.units)
     }
 
     /// Calculates percentage of disk used
@@ -63,6 +242,118 @@ impl DiskCollector {
             (used as f64 / total as f64) * 100.0
         }
     }
+
+    /// Whether `available` of `total` bytes is under either low-space
+    /// threshold; None when both are disabled
+    pub(super) fn is_low_space(available: u64, total: u64, settings: &DiskSettings) -> Option<bool> {
+        if settings.low_space_gib <= 0.0 && settings.low_space_percent <= 0.0 {
+            return None;
+        }
+        let under_gib = (available as f64) < settings.low_space_gib * 1024.0 * 1024.0 * 1024.0;
+        let under_percent = total > 0 && (available as f64 / total as f64) * 100.0 < settings.low_space_percent;
+        Some(under_gib || under_percent)
+    }
+
+    /// Orders disk documents by mount point so arrays are stable between documents
+    fn sort_by_mount_point(disks: &mut [Document]) {
+        disks.sort_by(|a, b| a.get_str("mount_point").unwrap_or("").cmp(b.get_str("mount_point").unwrap_or("")));
+    }
+
+    /// Collapses disk documents that share a device into one entry per device.
+    ///
+    /// Only real device paths (starting with `/`) are merged — pseudo sources
+    /// like `overlay` or `tmpfs` reuse the same name for unrelated filesystems.
+    /// The entry with the shortest mount point is kept and gains a
+    /// `mount_points` array listing every mount of that device.
+    fn dedupe_by_device(disks: Vec<Document>) -> Vec<Document> {
+        let mut result: Vec<Document> = Vec::new();
+        let mut mounts_by_index: Vec<Vec<String>> = Vec::new();
+
+        for disk in disks {
+            let device = disk.get_str("device").unwrap_or("").to_string();
+            let mount_point = disk.get_str("mount_point").unwrap_or("").to_string();
+
+            let existing = if device.starts_with('/') {
+                result
+                    .iter()
+                    .position(|d| d.get_str("device").unwrap_or("") == device)
+            } else {
+                None
+            };
+
+            match existing {
+                Some(idx) => {
+                    mounts_by_index[idx].push(mount_point.clone());
+                    let kept = result[idx].get_str("mount_point").unwrap_or("");
+                    if mount_point.len() < kept.len() {
+                        result[idx] = disk;
+                    }
+                }
+                None => {
+                    result.push(disk);
+                    mounts_by_index.push(vec![mount_point]);
+                }
+            }
+        }
+
+        for (disk, mounts) in result.iter_mut().zip(mounts_by_index) {
+            disk.insert("mount_points", mounts);
+        }
+
+        result
+    }
+
+    /// Adds a reading to the mount's window, returning the field value once
+    /// the window is full: days until full, or null if usage isn't growing
+    fn forecast(&self, mount_point: &str, now_secs: f64, used: u64, available: u64) -> Option<Bson> {
+        let window = self.settings.forecast_samples;
+        if window == 0 {
+            return None;
+        }
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = history.entry(mount_point.to_string()).or_default();
+        samples.push_back((now_secs, used as f64));
+        while samples.len() > window {
+            samples.pop_front();
+        }
+        if samples.len() < window {
+            return None;
+        }
+        let bytes_per_sec = fill_rate(samples)?;
+        if bytes_per_sec <= 0.0 {
+            return Some(Bson::Null);
+        }
+        Some(Bson::Double(available as f64 / bytes_per_sec / 86_400.0))
+    }
+
+    /// Drops disks below `min_used_percent` unless they are pinned or low on space
+    ///
+    /// Everything is kept when no threshold is set, when `full_report` is
+    /// true, or when `all_on_breach` is on and any disk is over the threshold.
+    fn filter_by_usage(disks: Vec<Document>, settings: &DiskSettings, full_report: bool) -> Vec<Document> {
+        if settings.min_used_percent <= 0.0 || full_report {
+            return disks;
+        }
+
+        // Failing and low-space mounts are always reported
+        let over = |disk: &Document| {
+            disk.contains_key("error")
+                || disk.get_bool("low_space").unwrap_or(false)
+                || disk.get_f64("used_percent").unwrap_or(0.0) >= settings.min_used_percent
+        };
+        if settings.all_on_breach && disks.iter().any(over) {
+            return disks;
+        }
+
+        let pinned = |disk: &Document| {
+            let mount_point = disk.get_str("mount_point").unwrap_or("");
+            let aliases = disk.get_array("mount_points").map(|a| a.as_slice()).unwrap_or(&[]);
+            settings.pinned_mounts.iter().any(|p| {
+                p == mount_point || aliases.iter().any(|m| m.as_str() == Some(p.as_str()))
+            })
+        };
+        disks.into_iter().filter(|d| over(d) || pinned(d)).collect()
+    }
 }
 
 #[async_trait]
@@ -82,42 +373,69 @@ impl MetricCollector for DiskCollector {
     ///   "disks": [
     ///     {
     ///       "mount_point": "/",
+    ///       "device": "/dev/sda1",
     ///       "filesystem": "ext4",
-    ///       "total_gb": 500.0,
-    ///       "used_gb": 250.0,
-    ///       "available_gb": 250.0,
-    ///       "used_percent": 50.0
+    ///       "total_gib": 500.0,
+    ///       "used_gib": 250.0,
+    ///       "available_gib": 250.0,
+    ///       "used_percent": 50.0,
+    ///       "low_space": false
     ///     },
     ///     {
     ///       "mount_point": "/mnt/data",
+    ///       "device": "/dev/sdb1",
     ///       "filesystem": "xfs",
-    ///       "total_gb": 1000.0,
-    ///       "used_gb": 750.0,
-    ///       "available_gb": 250.0,
-    ///       "used_percent": 75.0
+    ///       "total_gib": 1000.0,
+    ///       "used_gib": 750.0,
+    ///       "available_gib": 250.0,
+    ///       "used_percent": 75.0,
+    ///       "low_space": false
     ///     }
     ///   ]
     /// }
     /// ```
     ///
-    /// # Errors
-    /// Returns error if disk information cannot be retrieved (rare)
+    /// A mount whose capacity can't be read (e.g. a hung NFS mount hitting
+    /// `disk.stat_timeout_secs`) is reported with zeroed capacities and an
+    /// `error` message; the other disks are unaffected.
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
         debug!("Collecting disk space metrics");
 
-        // Get all disk information
-        // This queries the OS for all mounted filesystems
-        let disks = Disks::new_with_refreshed_list();
+        // Query every mounted filesystem; a failing mount is reported with
+        // an error instead of failing the whole collection
+        let mounts = self.mount_usage().await;
+        let mount_count = mounts.len();
+        let now = self.clock.now();
+        let now_secs = now.timestamp_millis() as f64 / 1000.0;
+        if self.settings.forecast_samples > 0 {
+            // Forget unmounted filesystems
+            let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+            history.retain(|mount_point, _| mounts.iter().any(|m| m.mount_point == *mount_point));
+        }
 
         // Build array of disk information
         let mut disk_array = Vec::new();
+        let gb = |stem| size_field(stem, Scale::Giga, self.units);
 
-        for disk in disks.list() {
-            // Get disk statistics
-            let mount_point = disk.mount_point().to_string_lossy().to_string();
-            let filesystem = disk.file_system().to_string_lossy().to_string();
-            let total_space = disk.total_space();
-            let available_space = disk.available_space();
+        for mount in mounts {
+            let (total_space, available_space) = match &mount.space {
+                Ok(space) => *space,
+                Err(e) => {
+                    warn!("Disk {} ({}): {}", mount.mount_point, mount.filesystem, e);
+                    let disk_doc = doc! {
+                        "mount_point": mount.mount_point,
+                        "device": mount.device,
+                        "filesystem": mount.filesystem,
+                        gb("total"): 0.0,
+                        gb("used"): 0.0,
+                        gb("available"): 0.0,
+                        "used_percent": 0.0,
+                        "error": e.as_str(),
+                    };
+                    disk_array.push(disk_doc);
+                    continue;
+                }
+            };
 
             // Calculate used space
             // used = total - available
@@ -126,60 +444,357 @@ impl MetricCollector for DiskCollector {
             // Calculate usage percentage
             let used_percent = Self::calculate_percentage(used_space, total_space);
 
+            debug!(
+                "Disk {}: {:.1}/{:.1} {} ({:.1}%)",
+                mount.mount_point,
+                self.gigabytes(used_space),
+                self.gigabytes(total_space),
+                size_unit(Scale::Giga, self.units),
+                used_percent
+            );
+
+            let low_space = Self::is_low_space(available_space, total_space, &self.settings);
+            if low_space == Some(true) {
+                warn!(
+                    "Disk {} low on space: {:.1} {} available ({:.1}% used)",
+                    mount.mount_point,
+                    self.gigabytes(available_space),
+                    size_unit(Scale::Giga, self.units),
+                    used_percent
+                );
+            }
+
+            let days_until_full = self.forecast(&mount.mount_point, now_secs, used_space, available_space);
+
             // Create disk info document
-            let disk_doc = doc! {
+            let mut disk_doc = doc! {
                 // Where this disk is mounted (e.g., "/", "/home")
-                "mount_point": mount_point.clone(),
+                "mount_point": mount.mount_point,
+
+                // Underlying device (e.g., "/dev/sda1"), used for deduplication
+                "device": mount.device,
 
                 // Filesystem type (e.g., "ext4", "xfs", "apfs")
-                "filesystem": filesystem,
+                "filesystem": mount.filesystem,
 
                 // Total capacity of the disk
-                "total_gb": Self::bytes_to_gb(total_space),
+                gb("total"): self.gigabytes(total_space),
 
                 // Space currently in use
-                "used_gb": Self::bytes_to_gb(used_space),
+                gb("used"): self.gigabytes(used_space),
 
                 // Space available for new files
                 // Note: May be less than (total - used) due to reserved blocks
-                "available_gb": Self::bytes_to_gb(available_space),
+                gb("available"): self.gigabytes(available_space),
 
                 // Percentage of disk space used
                 "used_percent": used_percent,
             };
 
-            debug!(
-                "Disk {}: {:.1}/{:.1} GB ({:.1}%)",
-                mount_point,
-                Self::bytes_to_gb(used_space),
-                Self::bytes_to_gb(total_space),
-                used_percent
-            );
+            if let Some(low_space) = low_space {
+                disk_doc.insert("low_space", low_space);
+            }
+            if let Some(days) = days_until_full {
+                disk_doc.insert("estimated_days_until_full", days);
+            }
 
             disk_array.push(disk_doc);
         }
 
+        // sysinfo lists disks in mount-table order, which can change between
+        // calls; sorted input also makes dedupe's choice between equally
+        // short mount points stable
+        Self::sort_by_mount_point(&mut disk_array);
+
+        if self.settings.dedupe_by_device {
+            disk_array = Self::dedupe_by_device(disk_array);
+            // A kept entry may have been swapped for a shorter mount point
+            Self::sort_by_mount_point(&mut disk_array);
+        }
+
+        let collection = self.collections.fetch_add(1, Ordering::Relaxed);
+        let every = self.settings.full_report_every;
+        let full_report = every > 0 && collection.is_multiple_of(every);
+        disk_array = Self::filter_by_usage(disk_array, &self.settings, full_report);
+
+        let disk_array_len = disk_array.len();
+
         // Create main document with array of all disks
         let doc = doc! {
             // Node identifier (from configuration key)
             "node": node_id,
 
             // Timestamp when metric was collected (UTC)
-            "timestamp": Utc::now(),
+            "timestamp": now,
 
             // Array of disk information for all mounted filesystems
             // Each element contains info about one disk/partition
             "disks": disk_array,
         };
 
-        debug!("Collected information for {} disk(s)", disks.list().len());
+        debug!(
+            "Collected information for {} disk(s) ({} mount(s))",
+            disk_array_len,
+            mount_count
+        );
 
         Ok(doc)
     }
 }
 
+/// Slope of a least-squares line through (seconds, bytes) samples, in bytes
+/// per second; None if they all share one timestamp
+fn fill_rate(samples: &VecDeque<(f64, f64)>) -> Option<f64> {
+    let n = samples.len() as f64;
+    let mean_t = samples.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_used = samples.iter().map(|(_, used)| used).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (t, used) in samples {
+        covariance += (t - mean_t) * (used - mean_used);
+        variance += (t - mean_t).powi(2);
+    }
+    (variance > 0.0).then(|| covariance / variance)
+}
+
 impl Default for DiskCollector {
     fn default() -> Self {
         Self::new()
     }
 }
+
+/// Runs `query` on a blocking thread, bounded by `timeout`
+///
+/// A call that can hang in the kernel (statvfs or a disk refresh on a stale
+/// NFS mount) must not run on the async executor. A timed-out call keeps its
+/// thread until it returns, so `key` stays in `pending` until then and calls
+/// for the same key fail at once instead of tying up another thread.
+async fn run_bounded<T, F>(
+    pending: &Arc<Mutex<HashSet<String>>>,
+    key: &str,
+    what: &str,
+    timeout: Duration,
+    query: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    if !pending.lock().unwrap_or_else(|e| e.into_inner()).insert(key.to_string()) {
+        return Err(format!("{} still blocked from an earlier collection", what));
+    }
+
+    let key = key.to_string();
+    let pending = Arc::clone(pending);
+    let task = tokio::task::spawn_blocking(move || {
+        let result = query();
+        pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+        result
+    });
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => Err(format!("{} task failed: {}", what, e)),
+        Err(_) => Err(format!("{} timed out after {:?}", what, timeout)),
+    }
+}
+
+/// Filesystem types that never hold user data
+#[cfg(target_os = "linux")]
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "rootfs", "sysfs", "proc", "devtmpfs", "tmpfs", "cgroup", "cgroup2", "pstore", "squashfs",
+    "rpc_pipefs", "iso9660", "devpts", "mqueue", "debugfs", "tracefs", "securityfs", "configfs",
+    "fusectl", "binfmt_misc", "autofs", "hugetlbfs", "bpf", "nsfs",
+];
+
+/// Parses /proc/mounts into (device, mount point, filesystem) for real filesystems
+///
+/// Applies the same exclusions as sysinfo, except that network filesystems
+/// are kept (their queries are bounded by the stat timeout).
+#[cfg(target_os = "linux")]
+fn parse_mounts(contents: &str) -> Vec<(String, String, String)> {
+    // /proc/mounts escapes space, tab, newline and backslash as octal
+    let unescape = |field: &str| {
+        field
+            .replace("\\040", " ")
+            .replace("\\011", "\t")
+            .replace("\\012", "\n")
+            .replace("\\134", "\\")
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = unescape(fields.next()?);
+            let mount_point = unescape(fields.next()?);
+            let filesystem = fields.next()?.to_string();
+            Some((device, mount_point, filesystem))
+        })
+        .filter(|(device, mount_point, filesystem)| {
+            !(PSEUDO_FILESYSTEMS.contains(&filesystem.as_str())
+                || mount_point.starts_with("/sys")
+                || mount_point.starts_with("/proc")
+                || (mount_point.starts_with("/run") && !mount_point.starts_with("/run/media"))
+                || device.starts_with("sunrpc"))
+        })
+        .collect()
+}
+
+/// Returns (total bytes, available bytes) for the filesystem at `path`
+#[cfg(target_os = "linux")]
+fn statvfs_space(path: &str) -> std::io::Result<(u64, u64)> {
+    let c_path = std::ffi::CString::new(path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    loop {
+        // SAFETY: c_path is NUL-terminated and stat is a valid, writable statvfs
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } == 0 {
+            let fragment = stat.f_frsize as u64;
+            return Ok((
+                fragment.saturating_mul(stat.f_blocks as u64),
+                fragment.saturating_mul(stat.f_bavail as u64),
+            ));
+        }
+
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(mount_point: &str, used_percent: f64) -> Document {
+        doc! { "mount_point": mount_point, "used_percent": used_percent }
+    }
+
+    fn mounts(disks: &[Document]) -> Vec<&str> {
+        disks.iter().map(|d| d.get_str("mount_point").unwrap()).collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_mounts_keeps_network_filesystems() {
+        let mounts = parse_mounts(
+            "/dev/sda1 / ext4 rw,relatime 0 0\n\
+             proc /proc proc rw 0 0\n\
+             tmpfs /run tmpfs rw 0 0\n\
+             nas:/export /mnt/my\\040share nfs4 rw,hard 0 0\n",
+        );
+        let mount_points: Vec<&str> = mounts.iter().map(|(_, m, _)| m.as_str()).collect();
+        assert_eq!(mount_points, ["/", "/mnt/my share"]);
+    }
+
+    #[test]
+    fn test_filter_by_usage_keeps_over_threshold_and_pinned_root() {
+        let settings = DiskSettings { min_used_percent: 80.0, ..DiskSettings::default() };
+        let mut stale = disk("/mnt/nfs", 0.0);
+        stale.insert("error", "statvfs timed out after 5s");
+        let disks = vec![disk("/", 10.0), disk("/data", 85.0), disk("/backup", 40.0), stale];
+
+        let kept = DiskCollector::filter_by_usage(disks.clone(), &settings, false);
+        assert_eq!(mounts(&kept), ["/", "/data", "/mnt/nfs"]);
+
+        let all = DiskCollector::filter_by_usage(disks.clone(), &settings, true);
+        assert_eq!(all.len(), 4);
+
+        let breach = DiskSettings { all_on_breach: true, ..settings };
+        assert_eq!(DiskCollector::filter_by_usage(disks, &breach, false).len(), 4);
+    }
+
+    fn mounted(device: &str, mount_point: &str) -> Document {
+        doc! { "device": device, "mount_point": mount_point }
+    }
+
+    fn mount_points(disk: &Document) -> Vec<&str> {
+        disk.get_array("mount_points").unwrap().iter().map(|m| m.as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_dedupe_by_device_collapses_bind_mounts() {
+        // The bind mount is seen first, the shorter real mount point wins
+        let disks = vec![
+            mounted("/dev/sda1", "/var/lib/docker"),
+            mounted("/dev/sda1", "/"),
+            mounted("/dev/sdb1", "/data"),
+            mounted("/dev/sda1", "/srv/www"),
+        ];
+
+        let deduped = DiskCollector::dedupe_by_device(disks);
+        assert_eq!(mounts(&deduped), ["/", "/data"]);
+        assert_eq!(mount_points(&deduped[0]), ["/var/lib/docker", "/", "/srv/www"]);
+        assert_eq!(mount_points(&deduped[1]), ["/data"]);
+    }
+
+    #[test]
+    fn test_dedupe_by_device_keeps_pseudo_devices_apart() {
+        let disks = vec![
+            mounted("overlay", "/var/lib/docker/overlay2/a/merged"),
+            mounted("overlay", "/var/lib/docker/overlay2/b/merged"),
+            mounted("tmpfs", "/run"),
+            mounted("tmpfs", "/dev/shm"),
+        ];
+
+        let deduped = DiskCollector::dedupe_by_device(disks);
+        assert_eq!(deduped.len(), 4);
+        for disk in &deduped {
+            assert_eq!(mount_points(disk), [disk.get_str("mount_point").unwrap()]);
+        }
+    }
+
+    #[test]
+    fn test_low_space_takes_whichever_threshold_trips_first() {
+        const GIB: u64 = 1 << 30;
+        let settings = DiskSettings::default();
+        // 1 GiB free of 20 GiB: 5% free, but under 2 GiB
+        assert_eq!(DiskCollector::is_low_space(GIB, 20 * GIB, &settings), Some(true));
+        // 100 GiB free of 20 TiB: plenty of GiB, but under 5%
+        assert_eq!(DiskCollector::is_low_space(100 * GIB, 20_480 * GIB, &settings), Some(true));
+        assert_eq!(DiskCollector::is_low_space(10 * GIB, 100 * GIB, &settings), Some(false));
+
+        let off = DiskSettings { low_space_gib: 0.0, low_space_percent: 0.0, ..settings };
+        assert_eq!(DiskCollector::is_low_space(0, 20 * GIB, &off), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_times_out_and_skips_until_the_call_returns() {
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let timeout = Duration::from_millis(50);
+
+        // A refresh hung on a stale mount
+        let hung = run_bounded(&pending, "/mnt/nfs", "statvfs", timeout, move || blocked.recv().is_ok()).await;
+        assert_eq!(hung, Err("statvfs timed out after 50ms".to_string()));
+
+        // Still hung: fails at once without another thread
+        let again = run_bounded(&pending, "/mnt/nfs", "statvfs", timeout, || true).await;
+        assert_eq!(again, Err("statvfs still blocked from an earlier collection".to_string()));
+        assert_eq!(run_bounded(&pending, "/", "statvfs", timeout, || 7).await, Ok(7));
+
+        // Once the call returns, the mount is queried again
+        release.send(()).unwrap();
+        while pending.lock().unwrap().contains("/mnt/nfs") {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(run_bounded(&pending, "/mnt/nfs", "statvfs", timeout, || true).await, Ok(true));
+    }
+
+    #[test]
+    fn test_forecast_needs_a_full_window_and_growth() {
+        let collector = DiskCollector::with_settings(DiskSettings { forecast_samples: 3, ..DiskSettings::default() });
+        const GIB: u64 = 1 << 30;
+
+        // 1 GiB more used per hour, 48 GiB free: two days
+        assert_eq!(collector.forecast("/", 0.0, 10 * GIB, 50 * GIB), None);
+        assert_eq!(collector.forecast("/", 3600.0, 11 * GIB, 49 * GIB), None);
+        let days = collector.forecast("/", 7200.0, 12 * GIB, 48 * GIB).unwrap();
+        assert!((days.as_f64().unwrap() - 2.0).abs() < 1e-9, "{}", days);
+
+        // Not growing: the window now holds 11, 12, 11 GiB
+        assert_eq!(collector.forecast("/", 10800.0, 11 * GIB, 49 * GIB), Some(Bson::Null));
+    }
+}
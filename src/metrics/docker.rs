@@ -4,15 +4,26 @@
 // Focuses on CPU and memory consumption per container
 
 use async_trait::async_trait;
-use bollard::container::StatsOptions;
+use bollard::container::{Stats, StatsOptions};
 use bollard::Docker;
 use bson::{doc, Document};
-use chrono::Utc;
 use futures_util::stream::StreamExt;
 use std::error::Error;
-use tracing::{debug, warn};
+use std::sync::Mutex;
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
 
 use super::MetricCollector;
+#[cfg(feature = "gpu")]
+use super::gpu::{ContainerGpu, GpuAttribution};
+use crate::clock::{system_clock, SharedClock};
+use super::numeric::{size, size_field, size_unit, Scale};
+use crate::config::{DockerSettings, SizeUnits};
+
+/// Oldest Docker Engine API version the collector supports (Docker 1.12)
+///
+/// The one-shot stats and container list fields used here are not reliable below it.
+const MIN_DOCKER_API_VERSION: (usize, usize) = (1, 24);
 
 /// Docker container stats collector
 ///
@@ -26,6 +37,13 @@ use super::MetricCollector;
 /// - Memory usage (current, limit, percentage)
 /// - Network I/O (bytes sent/received)
 /// - Block I/O (bytes read/written)
+/// - Optionally: image, image ID and selected labels (`docker.include_metadata`)
+/// - With the `gpu` feature and an NVIDIA driver: GPU memory and utilization,
+///   summed over the container's processes on all GPUs
+///
+/// `total_containers` holds the number of running containers. With
+/// `docker.max_containers` set and exceeded, only that many are sampled per
+/// collection, taking turns so none is permanently skipped.
 ///
 /// # Requirements
 /// - Docker daemon must be running
@@ -41,6 +59,26 @@ pub struct DockerCollector {
     /// Docker client instance
     /// Uses default connection (Unix socket on Linux/macOS)
     docker: Docker,
+
+    /// Client downgraded to the daemon's API version, negotiated on first use
+    negotiated: OnceCell<Docker>,
+
+    /// Metadata options from the `docker` settings section
+    settings: DockerSettings,
+
+    /// ID of the last container sampled when `max_containers` applies;
+    /// the next collection continues after it
+    round_robin_after: Mutex<Option<String>>,
+
+    /// Source of document timestamps
+    clock: SharedClock,
+
+    /// Units of the size fields (`numeric.size_units`)
+    units: SizeUnits,
+
+    /// Per-process GPU usage from NVML
+    #[cfg(feature = "gpu")]
+    gpu: GpuAttribution,
 }
 
 impl DockerCollector {
@@ -49,6 +87,11 @@ impl DockerCollector {
     /// Attempts to connect to Docker using the default socket.
     /// Falls back to environment variables if default connection fails.
     pub fn new() -> Self {
+        Self::with_settings(DockerSettings::default())
+    }
+
+    /// Creates a DockerCollector using the `docker` section of the monitoring settings
+    pub fn with_settings(settings: DockerSettings) -> Self {
         // Try to connect to Docker using default socket
         // On Linux/macOS: /var/run/docker.sock
         // On Windows: npipe:////./pipe/docker_engine
@@ -60,12 +103,225 @@ impl DockerCollector {
                     .expect("Failed to connect to Docker daemon")
             });
 
-        DockerCollector { docker }
+        DockerCollector {
+            docker,
+            negotiated: OnceCell::new(),
+            settings,
+            round_robin_after: Mutex::new(None),
+            clock: system_clock(),
+            units: SizeUnits::default(),
+            #[cfg(feature = "gpu")]
+            gpu: GpuAttribution::init(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of the size fields: `memory_used_mib` or `memory_used_mb`, ...
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Returns a client negotiated against the daemon's API version
+    ///
+    /// On first use, queries `/version`, logs the daemon and API versions and
+    /// rejects daemons older than `MIN_DOCKER_API_VERSION` with an upgrade hint.
+    /// Failures are not cached, so the check is retried on the next collection.
+    async fn client(&self) -> Result<&Docker, Box<dyn Error + Send + Sync>> {
+        self.negotiated
+            .get_or_try_init(|| async {
+                let version = self.docker.version().await?;
+                let daemon_version = version.version.unwrap_or_else(|| "unknown".to_string());
+                let api_version = version.api_version.unwrap_or_default();
+
+                if let Some(api) = parse_api_version(&api_version) {
+                    if api < MIN_DOCKER_API_VERSION {
+                        let (major, minor) = MIN_DOCKER_API_VERSION;
+                        return Err(format!(
+                            "Docker daemon {} speaks API {}, but at least API {}.{} is required; \
+                             upgrade Docker to >= 1.12",
+                            daemon_version, api_version, major, minor
+                        )
+                        .into());
+                    }
+                }
+
+                let docker = self.docker.clone().negotiate_version().await?;
+                let client = docker.client_version();
+                info!(
+                    "Connected to Docker {} (daemon API {}, using API {}.{})",
+                    daemon_version, api_version, client.major_version, client.minor_version
+                );
+                Ok(docker)
+            })
+            .await
+    }
+
+    /// Builds one container's entry from its stats frame
+    ///
+    /// A missing frame or a stats error still yields an entry with `id`,
+    /// `name` and an `error` message, so the container shows up as failing
+    /// instead of vanishing from the document.
+    fn container_entry(
+        container_id: &str,
+        container_name: &str,
+        frame: Option<Result<Stats, bollard::errors::Error>>,
+        units: SizeUnits,
+    ) -> Document {
+        match frame {
+            Some(Ok(stats)) => Self::stats_entry(container_id, container_name, &stats, units),
+            Some(Err(e)) => {
+                warn!("Failed to get stats for container {}: {}", container_name, e);
+                Self::error_entry(container_id, container_name, &e.to_string())
+            }
+            None => {
+                warn!("Docker returned no stats frame for container {}", container_name);
+                Self::error_entry(container_id, container_name, "no stats frame returned by the daemon")
+            }
+        }
     }
 
-    /// Converts bytes to megabytes for more readable storage
-    fn bytes_to_mb(bytes: u64) -> f64 {
-        bytes as f64 / (1024.0 * 1024.0)
+    /// Entry for a container whose stats could not be read
+    fn error_entry(container_id: &str, container_name: &str, error: &str) -> Document {
+        doc! {
+            "id": short_id(container_id),
+            "name": container_name,
+            "error": error,
+        }
+    }
+
+    /// Entry for a container with a stats frame
+    fn stats_entry(container_id: &str, container_name: &str, stats: &Stats, units: SizeUnits) -> Document {
+        // Calculate CPU percentage (None while the container is warming up)
+        let cpu_percent = Self::calculate_cpu_percent(stats);
+
+        // Get memory stats
+        let memory_used = stats.memory_stats.usage.unwrap_or(0);
+        let memory_limit = stats.memory_stats.limit.unwrap_or(1);
+        let memory_percent = if memory_limit > 0 {
+            (memory_used as f64 / memory_limit as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        // Get network I/O stats
+        // Sum all network interfaces
+        let (network_rx, network_tx) = stats
+            .networks
+            .as_ref()
+            .map(|networks| {
+                networks.values().fold((0u64, 0u64), |(rx, tx), net| {
+                    (
+                        rx + net.rx_bytes,
+                        tx + net.tx_bytes,
+                    )
+                })
+            })
+            .unwrap_or((0, 0));
+
+        // Get block I/O stats
+        let (block_read, block_write) = stats
+            .blkio_stats
+            .io_service_bytes_recursive
+            .as_ref()
+            .map(|io_stats| {
+                io_stats.iter().fold((0u64, 0u64), |(read, write), stat| {
+                    match stat.op.as_str() {
+                        "read" | "Read" => (read + stat.value, write),
+                        "write" | "Write" => (read, write + stat.value),
+                        _ => (read, write),
+                    }
+                })
+            })
+            .unwrap_or((0, 0));
+
+        let mb = |stem| size_field(stem, Scale::Mega, units);
+        let megabytes = |bytes| size(bytes, Scale::Mega, units);
+
+        // Create container stats document
+        let mut container_doc = doc! {
+            // Container unique identifier (short format)
+            "id": short_id(container_id),
+
+            // Container name (without leading slash)
+            "name": container_name,
+
+            // CPU usage as percentage of total system CPU
+            // e.g., 50% means using half of one CPU core; null while warming up
+            "cpu_percent": cpu_percent,
+
+            // Current memory usage in MiB (or MB)
+            mb("memory_used"): megabytes(memory_used),
+
+            // Memory limit configured for container in MiB (or MB)
+            mb("memory_limit"): megabytes(memory_limit),
+
+            // Memory usage as percentage of limit
+            "memory_percent": memory_percent,
+
+            // Total bytes received over network (all interfaces)
+            mb("network_rx"): megabytes(network_rx),
+
+            // Total bytes transmitted over network (all interfaces)
+            mb("network_tx"): megabytes(network_tx),
+
+            // Total bytes read from block devices
+            mb("block_read"): megabytes(block_read),
+
+            // Total bytes written to block devices
+            mb("block_write"): megabytes(block_write),
+        };
+
+        if cpu_percent.is_none() {
+            container_doc.insert("cpu_warming_up", true);
+        }
+
+        debug!(
+            "Container {}: CPU={}, Mem={:.1}/{:.1}{} ({:.1}%)",
+            container_name,
+            cpu_percent.map_or_else(|| "warming up".to_string(), |cpu| format!("{:.1}%", cpu)),
+            megabytes(memory_used),
+            megabytes(memory_limit),
+            size_unit(Scale::Mega, units),
+            memory_percent
+        );
+
+        container_doc
+    }
+
+    /// GPU fields for a container; zero when it runs no GPU process
+    #[cfg(feature = "gpu")]
+    fn gpu_entry(usage: Option<&ContainerGpu>, units: SizeUnits) -> Document {
+        let usage = usage.map_or((0, 0.0), |u| (u.memory_bytes, u.percent));
+        doc! {
+            size_field("gpu_memory", Scale::Mega, units): size(usage.0, Scale::Mega, units),
+            "gpu_percent": usage.1,
+        }
+    }
+
+    /// Builds the optional metadata fields for a container from the list response
+    ///
+    /// Only label keys listed in `docker.labels` are copied; missing labels are skipped.
+    fn container_metadata(&self, container: &bollard::models::ContainerSummary) -> Document {
+        let mut labels = Document::new();
+        if let Some(all_labels) = container.labels.as_ref() {
+            for key in &self.settings.labels {
+                if let Some(value) = all_labels.get(key) {
+                    labels.insert(key, value.clone());
+                }
+            }
+        }
+
+        doc! {
+            "image": container.image.clone().unwrap_or_default(),
+            "image_id": container.image_id.clone().unwrap_or_default(),
+            "labels": labels,
+        }
     }
 
     /// Calculates CPU usage percentage from Docker stats
@@ -75,7 +331,15 @@ impl DockerCollector {
     ///
     /// # Formula
     /// cpu_percent = (cpu_delta / system_cpu_delta) * num_cpus * 100.0
-    fn calculate_cpu_percent(stats: &bollard::container::Stats) -> f64 {
+    ///
+    /// Returns None for the first frame after a container starts: its
+    /// `precpu_stats` are still empty, so there is no previous reading to
+    /// take a delta from and the result would be a bogus 0%.
+    fn calculate_cpu_percent(stats: &bollard::container::Stats) -> Option<f64> {
+        if stats.precpu_stats.system_cpu_usage.unwrap_or(0) == 0 {
+            return None;
+        }
+
         // Get CPU usage values
         let cpu_total = stats
             .cpu_stats
@@ -96,7 +360,7 @@ impl DockerCollector {
 
         // Avoid division by zero
         if system_delta <= 0.0 || cpu_delta <= 0.0 {
-            return 0.0;
+            return Some(0.0);
         }
 
         // Get number of CPUs
@@ -106,7 +370,7 @@ impl DockerCollector {
             .unwrap_or_else(|| num_cpus::get() as u64) as f64;
 
         // Calculate percentage
-        (cpu_delta / system_delta) * num_cpus * 100.0
+        Some((cpu_delta / system_delta) * num_cpus * 100.0)
     }
 }
 
@@ -124,18 +388,26 @@ impl MetricCollector for DockerCollector {
     /// {
     ///   "node": "1111-1111",
     ///   "timestamp": "2024-01-15T10:30:00Z",
+    ///   "total_containers": 1,
+    ///   "container_count": 1,
     ///   "containers": [
     ///     {
     ///       "id": "abc123...",
     ///       "name": "my-app",
     ///       "cpu_percent": 25.5,
-    ///       "memory_used_mb": 512.0,
-    ///       "memory_limit_mb": 2048.0,
+    ///       "memory_used_mib": 512.0,
+    ///       "memory_limit_mib": 2048.0,
     ///       "memory_percent": 25.0,
-    ///       "network_rx_mb": 10.5,
-    ///       "network_tx_mb": 5.2,
-    ///       "block_read_mb": 100.0,
-    ///       "block_write_mb": 50.0
+    ///       "network_rx_mib": 10.5,
+    ///       "network_tx_mib": 5.2,
+    ///       "block_read_mib": 100.0,
+    ///       "block_write_mib": 50.0,
+    ///       "cpu_warming_up": true,           // only on a fresh container's first frame (cpu_percent null)
+    ///       "gpu_memory_mib": 1024.0,         // only with the `gpu` feature and NVML present
+    ///       "gpu_percent": 85.0,
+    ///       "image": "nginx:1.25",            // only with docker.include_metadata
+    ///       "image_id": "sha256:…",
+    ///       "labels": { "owner": "team-a" }
     ///     }
     ///   ]
     /// }
@@ -153,8 +425,10 @@ impl MetricCollector for DockerCollector {
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
         debug!("Collecting Docker container statistics");
 
+        let docker = self.client().await?;
+
         // List all running containers
-        let containers = match self.docker.list_containers::<String>(None).await {
+        let containers = match docker.list_containers::<String>(None).await {
             Ok(containers) => containers,
             Err(e) => {
                 warn!("Failed to list Docker containers: {}", e);
@@ -162,13 +436,33 @@ impl MetricCollector for DockerCollector {
             }
         };
 
+        let total_containers = containers.len();
+        debug!("Found {} running container(s)", total_containers);
+
+        let max = self.settings.max_containers;
+        let containers = if max > 0 && total_containers > max {
+            let mut after = self.round_robin_after.lock().unwrap_or_else(|e| e.into_inner());
+            let batch = round_robin(containers, |c| c.id.as_deref().unwrap_or(""), max, after.as_deref());
+            *after = batch.last().and_then(|c| c.id.clone());
+            debug!("Sampling {} of {} container(s) this tick", batch.len(), total_containers);
+            batch
+        } else {
+            containers
+        };
         let container_count = containers.len();
-        debug!("Found {} running container(s)", container_count);
+
+        // One NVML read per tick, shared by all containers
+        #[cfg(feature = "gpu")]
+        let gpu = self.gpu.per_container();
 
         // Collect stats for each container
         let mut container_stats = Vec::new();
 
         for container in containers {
+            let metadata = self
+                .settings
+                .include_metadata
+                .then(|| self.container_metadata(&container));
             let container_id = container.id.clone().unwrap_or_default();
             let container_name = container
                 .names
@@ -177,124 +471,54 @@ impl MetricCollector for DockerCollector {
 
             debug!("Collecting stats for container: {}", container_name);
 
-            // Get container stats (one-shot, not streaming)
+            // Non-streaming request: the daemon answers with a single frame and
+            // closes. `one_shot` stays off because it omits precpu_stats, which
+            // the CPU percentage is computed from.
             let stats_options = StatsOptions {
-                stream: false, // Get single snapshot, not continuous stream
-                ..Default::default()
+                stream: false,
+                one_shot: false,
             };
+            let frame = docker.stats(&container_id, Some(stats_options)).next().await;
 
-            let mut stats_stream = self.docker.stats(&container_id, Some(stats_options));
-
-            // Get the first (and only) stats snapshot
-            if let Some(stats_result) = stats_stream.next().await {
-                match stats_result {
-                    Ok(stats) => {
-                        // Calculate CPU percentage
-                        let cpu_percent = Self::calculate_cpu_percent(&stats);
-
-                        // Get memory stats
-                        let memory_used = stats.memory_stats.usage.unwrap_or(0);
-                        let memory_limit = stats.memory_stats.limit.unwrap_or(1);
-                        let memory_percent = if memory_limit > 0 {
-                            (memory_used as f64 / memory_limit as f64) * 100.0
-                        } else {
-                            0.0
-                        };
-
-                        // Get network I/O stats
-                        // Sum all network interfaces
-                        let (network_rx, network_tx) = stats
-                            .networks
-                            .as_ref()
-                            .map(|networks| {
-                                networks.values().fold((0u64, 0u64), |(rx, tx), net| {
-                                    (
-                                        rx + net.rx_bytes,
-                                        tx + net.tx_bytes,
-                                    )
-                                })
-                            })
-                            .unwrap_or((0, 0));
-
-                        // Get block I/O stats
-                        let (block_read, block_write) = stats
-                            .blkio_stats
-                            .io_service_bytes_recursive
-                            .as_ref()
-                            .map(|io_stats| {
-                                io_stats.iter().fold((0u64, 0u64), |(read, write), stat| {
-                                    match stat.op.as_str() {
-                                        "read" | "Read" => (read + stat.value, write),
-                                        "write" | "Write" => (read, write + stat.value),
-                                        _ => (read, write),
-                                    }
-                                })
-                            })
-                            .unwrap_or((0, 0));
-
-                        // Create container stats document
-                        let container_doc = doc! {
-                            // Container unique identifier (short format)
-                            "id": &container_id[..12.min(container_id.len())],
-
-                            // Container name (without leading slash)
-                            "name": container_name.clone(),
-
-                            // CPU usage as percentage of total system CPU
-                            // e.g., 50% means using half of one CPU core
-                            "cpu_percent": cpu_percent,
-
-                            // Current memory usage in MB
-                            "memory_used_mb": Self::bytes_to_mb(memory_used),
-
-                            // Memory limit configured for container in MB
-                            "memory_limit_mb": Self::bytes_to_mb(memory_limit),
-
-                            // Memory usage as percentage of limit
-                            "memory_percent": memory_percent,
-
-                            // Total bytes received over network (all interfaces)
-                            "network_rx_mb": Self::bytes_to_mb(network_rx),
-
-                            // Total bytes transmitted over network (all interfaces)
-                            "network_tx_mb": Self::bytes_to_mb(network_tx),
-
-                            // Total bytes read from block devices
-                            "block_read_mb": Self::bytes_to_mb(block_read),
-
-                            // Total bytes written to block devices
-                            "block_write_mb": Self::bytes_to_mb(block_write),
-                        };
-
-                        debug!(
-                            "Container {}: CPU={:.1}%, Mem={:.1}/{:.1}MB ({:.1}%)",
-                            container_name,
-                            cpu_percent,
-                            Self::bytes_to_mb(memory_used),
-                            Self::bytes_to_mb(memory_limit),
-                            memory_percent
-                        );
-
-                        container_stats.push(container_doc);
-                    }
-                    Err(e) => {
-                        warn!("Failed to get stats for container {}: {}", container_name, e);
-                        // Continue with other containers even if one fails
-                    }
-                }
+            let mut container_doc = Self::container_entry(&container_id, &container_name, frame, self.units);
+
+            #[cfg(feature = "gpu")]
+            if let (Some(gpu), false) = (&gpu, container_doc.contains_key("error")) {
+                container_doc.extend(Self::gpu_entry(gpu.get(&container_id), self.units));
+            }
+
+            // Image and selected labels (only when enabled in settings)
+            if let Some(metadata) = metadata {
+                container_doc.extend(metadata);
             }
+
+            container_stats.push(container_doc);
         }
 
+        // The daemon's list order is arbitrary; sort by name (then ID) so
+        // consecutive documents line up
+        container_stats.sort_by(|a, b| {
+            let name = |d: &Document| d.get_str("name").unwrap_or("").to_owned();
+            let id = |d: &Document| d.get_str("id").unwrap_or("").to_owned();
+            name(a).cmp(&name(b)).then_with(|| id(a).cmp(&id(b)))
+        });
+
         // Create main document with array of all container stats
         let doc = doc! {
             // Node identifier (from configuration key)
             "node": node_id,
 
             // Timestamp when metric was collected (UTC)
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
+
+            // Running containers, including any not sampled this tick
+            "total_containers": total_containers as i32,
+
+            // Entries in `containers`: 0 when none are running
+            "container_count": container_stats.len() as i32,
 
             // Array of container statistics
-            // One entry per running container
+            // One entry per sampled container
             "containers": container_stats,
         };
 
@@ -304,8 +528,60 @@ impl MetricCollector for DockerCollector {
     }
 }
 
+/// Takes up to `max` items in ID order, starting after `after` and wrapping
+///
+/// Keyed on the ID rather than a position, so containers starting or stopping
+/// between ticks don't make the rotation skip or repeat others.
+fn round_robin<T>(mut items: Vec<T>, id: impl Fn(&T) -> &str, max: usize, after: Option<&str>) -> Vec<T> {
+    items.sort_by(|a, b| id(a).cmp(id(b)));
+    let start = after
+        .and_then(|after| items.iter().position(|item| id(item) > after))
+        .unwrap_or(0);
+    items.rotate_left(start);
+    items.truncate(max);
+    items
+}
+
+/// Short (12-character) form of a container ID, as shown by `docker ps`
+fn short_id(container_id: &str) -> &str {
+    &container_id[..12.min(container_id.len())]
+}
+
+/// Parses an API version string such as "1.43" into (major, minor)
+fn parse_api_version(version: &str) -> Option<(usize, usize)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
 impl Default for DockerCollector {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_without_stats_frame_gets_error_marker() {
+        let entry = DockerCollector::container_entry("0123456789abcdef", "db", None, SizeUnits::default());
+
+        assert_eq!(entry.get_str("id").unwrap(), "0123456789ab");
+        assert_eq!(entry.get_str("name").unwrap(), "db");
+        assert_eq!(entry.get_str("error").unwrap(), "no stats frame returned by the daemon");
+        assert!(entry.get("cpu_percent").is_none());
+    }
+
+    #[test]
+    fn test_round_robin_covers_every_container() {
+        let ids = vec!["e", "a", "d", "b", "c"];
+        let pick = |after| round_robin(ids.clone(), |s| s, 2, after);
+
+        assert_eq!(pick(None), ["a", "b"]);
+        assert_eq!(pick(Some("b")), ["c", "d"]);
+        assert_eq!(pick(Some("d")), ["e", "a"]);
+        // The last sampled container is gone: continue with the next ID
+        assert_eq!(pick(Some("bb")), ["c", "d"]);
+    }
+}
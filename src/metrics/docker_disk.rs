@@ -0,0 +1,240 @@
+// Docker disk usage collector
+//
+// Dangling images, stopped containers, orphaned volumes and build cache fill
+// the host disk without showing up in any container's stats. This asks the
+// daemon's `/system/df` endpoint, the data behind `docker system df`, for the
+// size and reclaimable size of each. The call walks every layer and volume,
+// so it runs at `docker_disk.interval_secs` (hourly by default) and only with
+// `docker_disk.enabled`.
+
+use async_trait::async_trait;
+use bollard::models::SystemDataUsageResponse;
+use bollard::Docker;
+use bson::{doc, Document};
+use std::error::Error;
+use tracing::debug;
+
+use super::numeric::{size, size_field, Scale};
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::SizeUnits;
+
+/// Container states whose writable layer can't be pruned
+const ACTIVE_STATES: &[&str] = &["running", "paused", "restarting"];
+
+/// Docker disk usage collector
+///
+/// # What is Collected
+/// For images, containers, volumes and build cache: the count (build cache
+/// excepted), the size and the reclaimable size, computed as `docker system
+/// df` does:
+/// - images: all layers; reclaimable is what no container uses
+/// - containers: writable layers; reclaimable is that of stopped containers
+/// - volumes: reclaimable is that of volumes no container references
+/// - build cache: entries not shared with images; reclaimable is those not in use
+///
+/// plus `total_size_mib` and `reclaimable_mib` over all four.
+///
+/// # Platform Support
+/// - All platforms; registered only with `docker_disk.enabled`, needs the
+///   Docker daemon
+pub struct DockerDiskCollector {
+    docker: Docker,
+    clock: SharedClock,
+    /// `numeric.size_units`: `images_size_mib` etc., or `_mb` fields
+    units: SizeUnits,
+}
+
+impl DockerDiskCollector {
+    pub fn new() -> Self {
+        let docker = Docker::connect_with_socket_defaults().unwrap_or_else(|_| {
+            Docker::connect_with_local_defaults().expect("Failed to connect to Docker daemon")
+        });
+        DockerDiskCollector {
+            docker,
+            clock: system_clock(),
+            units: SizeUnits::default(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of the size fields (`numeric.size_units`)
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
+    }
+}
+
+/// Count, size and reclaimable size (bytes) of one kind of Docker object
+#[derive(Debug, Default, PartialEq)]
+struct Usage {
+    count: usize,
+    size: u64,
+    reclaimable: u64,
+}
+
+/// Bytes from a Docker size, which is -1 when unknown
+fn bytes(value: i64) -> u64 {
+    value.max(0) as u64
+}
+
+/// Splits a `/system/df` response into (images, containers, volumes, build cache)
+fn usage(df: &SystemDataUsageResponse) -> [Usage; 4] {
+    let images = df.images.as_deref().unwrap_or_default();
+    let image_size = bytes(df.layers_size.unwrap_or_default());
+    // Unique size of images a container still uses; shared layers stay counted
+    let image_used: u64 = images
+        .iter()
+        .filter(|image| image.containers > 0 && image.size >= 0 && image.shared_size >= 0)
+        .map(|image| bytes(image.size - image.shared_size))
+        .sum();
+
+    let containers = df.containers.as_deref().unwrap_or_default();
+    let container_size = |active: bool| -> u64 {
+        containers
+            .iter()
+            .filter(|c| !active || !ACTIVE_STATES.contains(&c.state.as_deref().unwrap_or("")))
+            .map(|c| bytes(c.size_rw.unwrap_or_default()))
+            .sum()
+    };
+
+    let volumes = df.volumes.as_deref().unwrap_or_default();
+    let volume_size = |unused_only: bool| -> u64 {
+        volumes
+            .iter()
+            .filter_map(|v| v.usage_data.as_ref())
+            .filter(|usage| !unused_only || usage.ref_count == 0)
+            .map(|usage| bytes(usage.size))
+            .sum()
+    };
+
+    let cache = df.build_cache.as_deref().unwrap_or_default();
+    let cache_size = |idle_only: bool| -> u64 {
+        cache
+            .iter()
+            .filter(|entry| !entry.shared.unwrap_or(false))
+            .filter(|entry| !idle_only || !entry.in_use.unwrap_or(false))
+            .map(|entry| bytes(entry.size.unwrap_or_default()))
+            .sum()
+    };
+
+    [
+        Usage { count: images.len(), size: image_size, reclaimable: image_size.saturating_sub(image_used) },
+        Usage { count: containers.len(), size: container_size(false), reclaimable: container_size(true) },
+        Usage { count: volumes.len(), size: volume_size(false), reclaimable: volume_size(true) },
+        Usage { count: cache.len(), size: cache_size(false), reclaimable: cache_size(true) },
+    ]
+}
+
+#[async_trait]
+impl MetricCollector for DockerDiskCollector {
+    fn name(&self) -> &str {
+        "DockerDisk"
+    }
+
+    /// Collects Docker's disk usage by object kind
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "1111-1111",
+    ///   "timestamp": "2024-01-15T10:00:00Z",
+    ///   "images_count": 42,
+    ///   "images_size_mib": 18234.5,
+    ///   "images_reclaimable_mib": 11020.1,
+    ///   "containers_count": 12,
+    ///   "containers_size_mib": 310.2,
+    ///   "containers_reclaimable_mib": 95.0,
+    ///   "volumes_count": 9,
+    ///   "volumes_size_mib": 5120.0,
+    ///   "volumes_reclaimable_mib": 2048.0,
+    ///   "build_cache_size_mib": 4096.0,
+    ///   "build_cache_reclaimable_mib": 4096.0,
+    ///   "total_size_mib": 27870.7,
+    ///   "reclaimable_mib": 17259.1
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting Docker disk usage");
+
+        let df = self.docker.df().await?;
+        let [images, containers, volumes, build_cache] = usage(&df);
+        let mb = |stem| size_field(stem, Scale::Mega, self.units);
+        let megabytes = |bytes| size(bytes, Scale::Mega, self.units);
+        let total = images.size + containers.size + volumes.size + build_cache.size;
+        let reclaimable = images.reclaimable + containers.reclaimable + volumes.reclaimable + build_cache.reclaimable;
+
+        Ok(doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+            "images_count": images.count as i64,
+            mb("images_size"): megabytes(images.size),
+            mb("images_reclaimable"): megabytes(images.reclaimable),
+            "containers_count": containers.count as i64,
+            mb("containers_size"): megabytes(containers.size),
+            mb("containers_reclaimable"): megabytes(containers.reclaimable),
+            "volumes_count": volumes.count as i64,
+            mb("volumes_size"): megabytes(volumes.size),
+            mb("volumes_reclaimable"): megabytes(volumes.reclaimable),
+            mb("build_cache_size"): megabytes(build_cache.size),
+            mb("build_cache_reclaimable"): megabytes(build_cache.reclaimable),
+            mb("total_size"): megabytes(total),
+            mb("reclaimable"): megabytes(reclaimable),
+        })
+    }
+}
+
+impl Default for DockerDiskCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{BuildCache, ContainerSummary, ImageSummary, Volume, VolumeUsageData};
+
+    fn image(size: i64, shared_size: i64, containers: i64) -> ImageSummary {
+        ImageSummary { size, shared_size, containers, ..Default::default() }
+    }
+
+    fn container(state: &str, size_rw: i64) -> ContainerSummary {
+        ContainerSummary { state: Some(state.to_string()), size_rw: Some(size_rw), ..Default::default() }
+    }
+
+    fn volume(size: i64, ref_count: i64) -> Volume {
+        Volume { usage_data: Some(VolumeUsageData { size, ref_count }), ..Default::default() }
+    }
+
+    fn cache(size: i64, in_use: bool, shared: bool) -> BuildCache {
+        BuildCache { size: Some(size), in_use: Some(in_use), shared: Some(shared), ..Default::default() }
+    }
+
+    #[test]
+    fn test_usage_matches_docker_system_df() {
+        let df = SystemDataUsageResponse {
+            layers_size: Some(1000),
+            // A used image with 100 bytes of its own, an unused one, one of unknown size
+            images: Some(vec![image(400, 300, 1), image(600, 300, 0), image(-1, -1, 2)]),
+            containers: Some(vec![container("running", 50), container("exited", 20), container("created", -1)]),
+            volumes: Some(vec![volume(500, 1), volume(200, 0), volume(-1, 0)]),
+            build_cache: Some(vec![cache(30, false, false), cache(70, true, false), cache(900, false, true)]),
+        };
+
+        let [images, containers, volumes, build_cache] = usage(&df);
+        assert_eq!(images, Usage { count: 3, size: 1000, reclaimable: 900 });
+        assert_eq!(containers, Usage { count: 3, size: 70, reclaimable: 20 });
+        assert_eq!(volumes, Usage { count: 3, size: 700, reclaimable: 200 });
+        assert_eq!(build_cache, Usage { count: 3, size: 100, reclaimable: 30 });
+
+        // A daemon with nothing on it
+        let empty = usage(&SystemDataUsageResponse::default());
+        assert!(empty.iter().all(|kind| *kind == Usage::default()));
+    }
+}
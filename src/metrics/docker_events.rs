@@ -15,6 +15,7 @@ use tokio::sync::Mutex;
 use tracing::{debug, warn};
 
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
 
 /// Docker lifecycle event collector
 ///
@@ -26,6 +27,8 @@ pub struct DockerEventsCollector {
     docker: Docker,
     /// Tracks the end time of the previous poll window
     last_poll: Mutex<Option<DateTime<Utc>>>,
+    /// Source of poll windows and document timestamps
+    clock: SharedClock,
 }
 
 impl DockerEventsCollector {
@@ -36,8 +39,15 @@ impl DockerEventsCollector {
         DockerEventsCollector {
             docker,
             last_poll: Mutex::new(None),
+            clock: system_clock(),
         }
     }
+
+    /// Replaces the clock used for poll windows and timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 #[async_trait]
@@ -49,7 +59,7 @@ impl MetricCollector for DockerEventsCollector {
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
         debug!("Collecting Docker events");
 
-        let now = Utc::now();
+        let now = self.clock.now();
         let mut last_poll = self.last_poll.lock().await;
         // On first run, look back 60 seconds
         let since = last_poll.unwrap_or_else(|| now - chrono::Duration::seconds(60));
@@ -122,7 +132,7 @@ impl MetricCollector for DockerEventsCollector {
 
         let doc = doc! {
             "node": node_id,
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
             "events": events,
         };
 
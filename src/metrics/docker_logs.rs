@@ -15,6 +15,7 @@ use tokio::sync::Mutex;
 use tracing::{debug, warn};
 
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
 
 /// Maximum total log lines stored per interval across all containers.
 /// Prevents document bloat from noisy containers.
@@ -28,6 +29,8 @@ pub struct DockerLogsCollector {
     docker: Docker,
     /// Tracks the end time of the previous poll window
     last_poll: Mutex<Option<DateTime<Utc>>>,
+    /// Source of poll windows and document timestamps
+    clock: SharedClock,
 }
 
 impl DockerLogsCollector {
@@ -38,8 +41,15 @@ impl DockerLogsCollector {
         DockerLogsCollector {
             docker,
             last_poll: Mutex::new(None),
+            clock: system_clock(),
         }
     }
+
+    /// Replaces the clock used for poll windows and timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 #[async_trait]
@@ -51,7 +61,7 @@ impl MetricCollector for DockerLogsCollector {
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
         debug!("Collecting Docker logs");
 
-        let now = Utc::now();
+        let now = self.clock.now();
         let mut last_poll = self.last_poll.lock().await;
         // On first run, look back 60 seconds
         let since = last_poll.unwrap_or_else(|| now - chrono::Duration::seconds(60));
@@ -165,7 +175,7 @@ impl MetricCollector for DockerLogsCollector {
 
         let doc = doc! {
             "node": node_id,
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
             "containers": container_docs,
         };
 
@@ -0,0 +1,94 @@
+// Entropy metric collector
+//
+// Reports the kernel's available entropy. Low entropy stalls TLS handshakes
+// and other blocking reads of /dev/random on some VMs.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use std::error::Error;
+use tracing::debug;
+
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+
+/// Kernel random pool availability collector
+///
+/// # What is Collected
+/// - `entropy_avail`: bits of entropy in the input pool
+///   (`/proc/sys/kernel/random/entropy_avail`)
+///
+/// Since Linux 5.18 the value is fixed at 256 once the pool is initialised,
+/// so it is mainly useful on older kernels, where it drops under load.
+///
+/// # Platform Support
+/// - Linux: Full support (registered only on Linux)
+/// - Other platforms: no-op, the document carries no entropy field
+pub struct EntropyCollector {
+    clock: SharedClock,
+}
+
+impl EntropyCollector {
+    /// Creates a new EntropyCollector instance
+    pub fn new() -> Self {
+        EntropyCollector {
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn read_entropy_avail() -> Result<Option<i64>, Box<dyn Error + Send + Sync>> {
+    let contents = tokio::fs::read_to_string("/proc/sys/kernel/random/entropy_avail").await?;
+    Ok(Some(contents.trim().parse()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn read_entropy_avail() -> Result<Option<i64>, Box<dyn Error + Send + Sync>> {
+    Ok(None)
+}
+
+#[async_trait]
+impl MetricCollector for EntropyCollector {
+    /// Returns the metric name
+    fn name(&self) -> &str {
+        "Entropy"
+    }
+
+    /// Collects the currently available kernel entropy
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "...",
+    ///   "entropy_avail": 3712
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting entropy metrics");
+
+        let mut doc = doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+        };
+
+        if let Some(entropy_avail) = read_entropy_avail().await? {
+            debug!("Entropy: {} bits available", entropy_avail);
+            doc.insert("entropy_avail", entropy_avail);
+        }
+
+        Ok(doc)
+    }
+}
+
+impl Default for EntropyCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,170 @@
+// GPU attribution - per-container GPU usage for the Docker stats collector
+//
+// NVML reports GPU memory and utilization per host process. Each process is
+// mapped to its container through /proc/<pid>/cgroup (see cgroup.rs) and the
+// numbers are summed per container. Compiled with the `gpu` feature only;
+// on hosts without an NVIDIA driver NVML fails to load and this is a no-op.
+
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Nvml;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use super::cgroup::container_id_from_cgroup;
+
+/// GPU usage of one container, summed over its processes and all GPUs
+#[derive(Debug, Default, PartialEq)]
+pub struct ContainerGpu {
+    /// GPU memory held by the container's processes, in bytes
+    pub memory_bytes: u64,
+    /// SM (compute) utilization; 100 per fully busy GPU
+    pub percent: f64,
+}
+
+/// One GPU process as reported by NVML
+struct GpuProcess {
+    pid: u32,
+    memory_bytes: u64,
+    sm_util: u32,
+}
+
+/// Reads per-process GPU usage through NVML
+pub struct GpuAttribution {
+    /// None when NVML couldn't be loaded (no NVIDIA driver)
+    nvml: Option<Nvml>,
+
+    /// Newest utilization sample seen per device index, so each call only
+    /// considers samples taken since the previous one
+    last_seen: Mutex<HashMap<u32, u64>>,
+}
+
+impl GpuAttribution {
+    /// Loads NVML, logging once if it is unavailable
+    pub fn init() -> Self {
+        let nvml = match Nvml::init() {
+            Ok(nvml) => {
+                let count = nvml.device_count().unwrap_or(0);
+                info!("NVML loaded: per-container GPU usage from {} GPU(s)", count);
+                Some(nvml)
+            }
+            Err(e) => {
+                info!("NVML unavailable, no per-container GPU usage: {}", e);
+                None
+            }
+        };
+        GpuAttribution {
+            nvml,
+            last_seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// GPU usage per full container id; None without NVML
+    ///
+    /// Containers running no GPU process are absent from the map.
+    pub fn per_container(&self) -> Option<HashMap<String, ContainerGpu>> {
+        let nvml = self.nvml.as_ref()?;
+        let processes = match self.processes(nvml) {
+            Ok(processes) => processes,
+            Err(e) => {
+                warn!("Failed to read GPU processes: {}", e);
+                return Some(HashMap::new());
+            }
+        };
+        Some(attribute(&processes, |pid| {
+            let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+            container_id_from_cgroup(&cgroup)
+        }))
+    }
+
+    /// Compute and graphics processes on every GPU, with their utilization
+    fn processes(&self, nvml: &Nvml) -> Result<Vec<GpuProcess>, NvmlError> {
+        let mut last_seen = self.last_seen.lock().unwrap_or_else(|e| e.into_inner());
+        let mut processes = Vec::new();
+        for index in 0..nvml.device_count()? {
+            let device = nvml.device_by_index(index)?;
+
+            let mut memory: HashMap<u32, u64> = HashMap::new();
+            let running = device
+                .running_compute_processes()?
+                .into_iter()
+                .chain(device.running_graphics_processes()?);
+            for process in running {
+                // A process using the GPU for both compute and graphics is listed twice
+                let used = match process.used_gpu_memory {
+                    UsedGpuMemory::Used(bytes) => bytes,
+                    UsedGpuMemory::Unavailable => 0,
+                };
+                let entry = memory.entry(process.pid).or_default();
+                *entry = (*entry).max(used);
+            }
+
+            // Latest sample per pid since the previous call; NotFound means none
+            let mut utilization: HashMap<u32, (u64, u32)> = HashMap::new();
+            let since = last_seen.get(&index).copied();
+            match device.process_utilization_stats(since) {
+                Ok(samples) => {
+                    for sample in samples {
+                        let latest = utilization.entry(sample.pid).or_default();
+                        if sample.timestamp >= latest.0 {
+                            *latest = (sample.timestamp, sample.sm_util);
+                        }
+                        let seen = last_seen.entry(index).or_default();
+                        *seen = (*seen).max(sample.timestamp);
+                    }
+                }
+                Err(NvmlError::NotFound) => {}
+                Err(e) => debug!("No GPU {} utilization samples: {}", index, e),
+            }
+
+            for (pid, memory_bytes) in memory {
+                let sm_util = utilization.get(&pid).map_or(0, |(_, util)| *util);
+                processes.push(GpuProcess { pid, memory_bytes, sm_util });
+            }
+        }
+        Ok(processes)
+    }
+}
+
+/// Sums GPU processes per container; `container_of` maps a pid to its
+/// container id (None for host processes, which are skipped)
+fn attribute(processes: &[GpuProcess], container_of: impl Fn(u32) -> Option<String>) -> HashMap<String, ContainerGpu> {
+    let mut containers: HashMap<String, ContainerGpu> = HashMap::new();
+    for process in processes {
+        let Some(id) = container_of(process.pid) else {
+            continue;
+        };
+        let usage = containers.entry(id).or_default();
+        usage.memory_bytes += process.memory_bytes;
+        usage.percent += f64::from(process.sm_util);
+    }
+    containers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribute_sums_processes_per_container() {
+        let processes = [
+            GpuProcess { pid: 10, memory_bytes: 1 << 30, sm_util: 40 },
+            // Same container on a second GPU
+            GpuProcess { pid: 11, memory_bytes: 1 << 29, sm_util: 90 },
+            GpuProcess { pid: 20, memory_bytes: 1 << 20, sm_util: 5 },
+            // Host process (Xorg)
+            GpuProcess { pid: 1, memory_bytes: 1 << 24, sm_util: 1 },
+        ];
+        let container_of = |pid| match pid {
+            10 | 11 => Some("train".to_string()),
+            20 => Some("serve".to_string()),
+            _ => None,
+        };
+
+        let containers = attribute(&processes, container_of);
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers["train"], ContainerGpu { memory_bytes: 3 << 29, percent: 130.0 });
+        assert_eq!(containers["serve"], ContainerGpu { memory_bytes: 1 << 20, percent: 5.0 });
+    }
+}
@@ -0,0 +1,210 @@
+// Kernel log metric collector
+//
+// Counts OOM kills and kernel errors from the kernel ring buffer (/dev/kmsg)
+// since the previous tick. The OOM killer otherwise only leaves a line in
+// dmesg: a killed process just disappears from every other metric.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+
+/// Kernel ring buffer, one record per read(2)
+const KMSG_PATH: &str = "/dev/kmsg";
+
+/// Records longer than this are truncated by the kernel anyway
+const MAX_RECORD_LEN: usize = 8192;
+
+/// Syslog levels up to and including `err` (emerg, alert, crit, err)
+const MAX_ERROR_LEVEL: u8 = 3;
+
+/// Kernel log collector
+///
+/// # What is Collected
+/// For the records logged since the previous collection:
+/// - `oom_kills`: processes killed by the OOM killer, global or cgroup
+/// - `kernel_errors`: kernel records at `err` level or worse (OOM kills
+///   included, userspace writes to /dev/kmsg excluded)
+/// - `last_oom_process`: name of the last process killed, or null
+///
+/// The file descriptor stays open between ticks, so the kernel tracks the
+/// read position and each record is counted once. The first collection
+/// starts from the end of the buffer: records from before startup (e.g. the
+/// boot log) are not counted.
+///
+/// # Platform Support
+/// - Linux: registered only if /dev/kmsg can be opened (root or
+///   `CAP_SYSLOG` when `kernel.dmesg_restrict=1`)
+/// - Other platforms: not compiled
+pub struct KernelLogCollector {
+    kmsg: Mutex<File>,
+    clock: SharedClock,
+}
+
+impl KernelLogCollector {
+    /// Opens /dev/kmsg positioned after the newest record
+    pub fn open() -> std::io::Result<Self> {
+        let mut kmsg = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(KMSG_PATH)?;
+        kmsg.seek(SeekFrom::End(0))?;
+        Ok(KernelLogCollector {
+            kmsg: Mutex::new(kmsg),
+            clock: system_clock(),
+        })
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Reads every record available without blocking into a tally
+    fn read_new_records(&self) -> std::io::Result<Tally> {
+        let mut kmsg = self.kmsg.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tally = Tally::default();
+        let mut buf = vec![0u8; MAX_RECORD_LEN];
+        loop {
+            match kmsg.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => tally.add(&String::from_utf8_lossy(&buf[..n])),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // The ring buffer wrapped past our position; the next read resumes at the oldest record
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                    warn!("Kernel log overwritten before it was read; some records were not counted");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(tally)
+    }
+}
+
+/// Counts accumulated over one collection
+#[derive(Debug, Default, PartialEq)]
+struct Tally {
+    oom_kills: i64,
+    kernel_errors: i64,
+    last_oom_process: Option<String>,
+}
+
+impl Tally {
+    /// Adds one /dev/kmsg record: `<prefix>,<seq>,<usec>,<flags>[,...];<message>`
+    /// followed by optional ` KEY=value` continuation lines
+    fn add(&mut self, record: &str) {
+        let Some((header, rest)) = record.split_once(';') else {
+            return;
+        };
+        let Some(prefix) = header.split(',').next().and_then(|p| p.parse::<u32>().ok()) else {
+            return;
+        };
+        let message = rest.lines().next().unwrap_or_default();
+        let (facility, level) = (prefix >> 3, (prefix & 7) as u8);
+        if facility != 0 {
+            return;
+        }
+
+        if level <= MAX_ERROR_LEVEL {
+            self.kernel_errors += 1;
+        }
+        if let Some(process) = oom_victim(message) {
+            self.oom_kills += 1;
+            self.last_oom_process = Some(process.to_string());
+        }
+    }
+}
+
+/// Process name from an OOM kill line, e.g.
+/// `Out of memory: Killed process 4242 (java) total-vm:...` or
+/// `Memory cgroup out of memory: Killed process 4242 (java) ...`
+///
+/// Older kernels log `Out of memory: Kill process ...` and then a separate
+/// `Killed process ...` line; only the latter is matched, so each kill
+/// counts once.
+fn oom_victim(message: &str) -> Option<&str> {
+    let (_, after) = message.split_once("Killed process ")?;
+    let (_, name) = after.split_once('(')?;
+    name.split_once(')').map(|(name, _)| name)
+}
+
+#[async_trait]
+impl MetricCollector for KernelLogCollector {
+    /// Returns the metric name
+    fn name(&self) -> &str {
+        "KernelLog"
+    }
+
+    /// Counts the kernel records logged since the previous collection
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "...",
+    ///   "oom_kills": 1,
+    ///   "kernel_errors": 2,
+    ///   "last_oom_process": "java"
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting kernel log counts");
+
+        let tally = self.read_new_records()?;
+        if tally.oom_kills > 0 {
+            warn!(
+                "OOM killer fired {} time(s), last victim '{}'",
+                tally.oom_kills,
+                tally.last_oom_process.as_deref().unwrap_or_default()
+            );
+        }
+
+        Ok(doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+            "oom_kills": tally.oom_kills,
+            "kernel_errors": tally.kernel_errors,
+            "last_oom_process": tally.last_oom_process,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally_kmsg_records() {
+        let records = [
+            "6,1001,5000000,-;eth0: link up\n",
+            // Old-style OOM report: the "Kill process" line must not count twice
+            "3,1002,5000100,-;Out of memory: Kill process 4242 (java) score 912 or sacrifice child\n",
+            "3,1003,5000101,-;Killed process 4242 (java) total-vm:8000kB, anon-rss:7000kB\n",
+            "3,1004,6000000,-;Memory cgroup out of memory: Killed process 77 (node worker) total-vm:1kB\n",
+            "2,1005,7000000,-;EXT4-fs error (device sda1): ext4_find_entry:1455: inode #2\n SUBSYSTEM=block\n",
+            // Userspace (facility 3, daemon) writing to /dev/kmsg
+            "27,1006,8000000,-;systemd[1]: Failed to start foo.service\n",
+        ];
+
+        let mut tally = Tally::default();
+        for record in records {
+            tally.add(record);
+        }
+        assert_eq!(
+            tally,
+            Tally {
+                oom_kills: 2,
+                kernel_errors: 4,
+                last_oom_process: Some("node worker".to_string()),
+            }
+        );
+    }
+}
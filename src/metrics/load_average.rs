@@ -7,11 +7,12 @@
 use sysinfo::System;
 use async_trait::async_trait;
 use bson::{doc, Document};
-use chrono::Utc;
 use std::error::Error;
 use tracing::debug;
 
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::LoadSettings;
 
 /// Load Average metric collector
 ///
@@ -23,16 +24,38 @@ use super::MetricCollector;
 /// - Values > number of CPU cores = system is experiencing high load
 /// - Values significantly > cores = system is overloaded
 ///
+/// `overloaded` flags the last case: the 1-minute load above
+/// `load.overload_factor` times the core count (4 by default), so alerts
+/// don't each reimplement the cores-relative threshold. The aggregator
+/// keeps it true for a window if any sample in it was overloaded.
+///
 /// # Platform Support
 /// - Linux: Full support via /proc/loadavg
 /// - macOS: Full support via sysctl
 /// - Windows: Not available (returns 0.0)
-pub struct LoadAverageCollector {}
+pub struct LoadAverageCollector {
+    settings: LoadSettings,
+    clock: SharedClock,
+}
 
 impl LoadAverageCollector {
     /// Creates a new LoadAverageCollector instance
     pub fn new() -> Self {
-        LoadAverageCollector {}
+        Self::with_settings(LoadSettings::default())
+    }
+
+    /// Creates a LoadAverageCollector using the `load` section of the monitoring settings
+    pub fn with_settings(settings: LoadSettings) -> Self {
+        LoadAverageCollector {
+            settings,
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
     }
 }
 
@@ -53,7 +76,11 @@ impl MetricCollector for LoadAverageCollector {
     ///   "load_1min": 1.5,
     ///   "load_5min": 1.2,
     ///   "load_15min": 0.9,
-    ///   "cpu_cores": 8
+    ///   "load_1min_per_core": 0.19,
+    ///   "load_5min_per_core": 0.15,
+    ///   "load_15min_per_core": 0.11,
+    ///   "cpu_cores": 8,
+    ///   "overloaded": false
     /// }
     /// ```
     ///
@@ -69,13 +96,16 @@ impl MetricCollector for LoadAverageCollector {
         // Get CPU count for context
         let cpu_count = num_cpus::get();
 
+        // Load per core is comparable across machines; guard against a 0 count
+        let per_core = |load: f64| load / cpu_count.max(1) as f64;
+
         // Create BSON document with load average data
-        let doc = doc! {
+        let mut doc = doc! {
             // Node identifier (from configuration key)
             "node": node_id,
 
             // Timestamp when metric was collected (UTC)
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
 
             // Load average over 1 minute
             // Useful for detecting immediate spikes in system load
@@ -89,11 +119,20 @@ impl MetricCollector for LoadAverageCollector {
             // Useful for understanding longer-term system behavior
             "load_15min": load_avg.fifteen,
 
+            // Load averages divided by core count (1.0 = every core busy)
+            "load_1min_per_core": per_core(load_avg.one),
+            "load_5min_per_core": per_core(load_avg.five),
+            "load_15min_per_core": per_core(load_avg.fifteen),
+
             // Number of CPU cores for context
             // Helps interpret whether load values are high or normal
             "cpu_cores": cpu_count as i32,
         };
 
+        if let Some(overloaded) = is_overloaded(load_avg.one, cpu_count, self.settings.overload_factor) {
+            doc.insert("overloaded", overloaded);
+        }
+
         debug!(
             "Load average: 1min={:.2}, 5min={:.2}, 15min={:.2} (CPUs: {})",
             load_avg.one, load_avg.five, load_avg.fifteen, cpu_count
@@ -103,8 +142,26 @@ impl MetricCollector for LoadAverageCollector {
     }
 }
 
+/// Whether `load_1min` exceeds `factor` times the core count; None when the
+/// flag is switched off (`factor` 0)
+pub(super) fn is_overloaded(load_1min: f64, cpu_count: usize, factor: f64) -> Option<bool> {
+    (factor > 0.0).then(|| load_1min > cpu_count.max(1) as f64 * factor)
+}
+
 impl Default for LoadAverageCollector {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overloaded_is_relative_to_cores() {
+        assert_eq!(is_overloaded(33.0, 8, 4.0), Some(true));
+        assert_eq!(is_overloaded(32.0, 8, 4.0), Some(false));
+        assert_eq!(is_overloaded(1500.0, 64, 0.0), None);
+    }
+}
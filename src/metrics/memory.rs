@@ -1,25 +1,78 @@
 // Memory metric collector
 //
 // Collects system memory usage metrics including RAM and swap.
+// Inside a memory-limited container (cgroup v1/v2), reports the cgroup's
+// view instead of the host's.
+//
+// On Linux, per-device swap usage is read from /proc/swaps.
 
 use sysinfo::System;
 use async_trait::async_trait;
-use bson::{doc, Document};
-use chrono::Utc;
+use bson::{doc, Bson, Document};
 use std::error::Error;
-use tracing::debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, warn};
 
+use super::numeric::{size, size_field, size_unit, Scale};
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::SizeUnits;
 
-pub struct MemoryCollector {}
+/// Memory usage collector
+///
+/// # Container Awareness
+/// On Linux, when the collector's own cgroup has a memory limit below the
+/// host total (`/sys/fs/cgroup/memory.max` on v2,
+/// `/sys/fs/cgroup/memory/memory.limit_in_bytes` on v1), total and available
+/// memory are taken from the cgroup and the document carries
+/// `cgroup_limited: true`. Otherwise host values are reported and the flag is false.
+///
+/// # Usable Memory
+/// `available_mib` is the OS's own estimate and means different things per
+/// platform (see `usable_memory`); free memory is much lower on Linux and
+/// macOS because it excludes reclaimable cache. `usable_mib` is the one field
+/// to chart as "memory left": available where the OS reports it, free otherwise.
+///
+/// # Swap Devices
+/// On Linux, `swap_devices` lists every active swap area from `/proc/swaps`
+/// (host view, even when cgroup-limited), so zram can be told apart from a
+/// disk swapfile. The aggregate swap fields are unaffected. Other platforms
+/// omit the array.
+///
+/// # Units
+/// Sizes follow `numeric.size_units`: `total_mib` etc. by default, `_mb`
+/// fields in decimal or legacy units. Megabyte fields are whole numbers.
+pub struct MemoryCollector {
+    clock: SharedClock,
+    units: SizeUnits,
+    /// Set once the zero-total warning was logged
+    zero_total_warned: AtomicBool,
+}
 
 impl MemoryCollector {
     pub fn new() -> Self {
-        MemoryCollector {}
+        MemoryCollector {
+            clock: system_clock(),
+            units: SizeUnits::default(),
+            zero_total_warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of the size fields (`numeric.size_units`)
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
     }
 
-    fn bytes_to_mb(bytes: u64) -> i64 {
-        (bytes / (1024 * 1024)) as i64
+    /// Whole megabytes (MiB or MB) in the configured units
+    fn megabytes(&self, bytes: u64) -> i64 {
+        size(bytes, Scale::Mega, self.units) as i64
     }
 
     fn calculate_percentage(used: u64, total: u64) -> f64 {
@@ -44,11 +97,16 @@ impl MetricCollector for MemoryCollector {
     /// {
     ///   "node": "0001-0001",
     ///   "timestamp": "...",
-    ///   "total_mb": 24048,
-    ///   "swap_total_mb": 0,
-    ///   "available_mb": 21317,
+    ///   "total_mib": 24048,
+    ///   "swap_total_mib": 0,
+    ///   "available_mib": 21317,
+    ///   "usable_mib": 21317,
     ///   "used_percent": 11.35,
-    ///   "swap_used_percent": 0.0
+    ///   "swap_used_percent": 0.0,
+    ///   "cgroup_limited": false,
+    ///   "swap_devices": [
+    ///     { "name": "/dev/zram0", "type": "partition", "size_kib": 4194300, "used_kib": 512, "priority": 100 }
+    ///   ]
     /// }
     /// ```
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
@@ -57,38 +115,208 @@ impl MetricCollector for MemoryCollector {
         let mut sys = System::new();
         sys.refresh_memory();
 
-        let total_memory     = sys.total_memory();
-        let available_memory = sys.available_memory();
-        let used_memory      = sys.used_memory();
-        let total_swap       = sys.total_swap();
-        let used_swap        = sys.used_swap();
+        let mut total_memory     = sys.total_memory();
+        let mut available_memory = sys.available_memory();
+        let mut free_memory      = sys.free_memory();
+        let mut used_memory      = sys.used_memory();
+        let total_swap           = sys.total_swap();
+        let mut used_swap        = sys.used_swap();
+
+        // Only a limit below the host total changes what this process can use
+        let cgroup = sys.cgroup_limits().filter(|l| l.total_memory < total_memory);
+        let cgroup_limited = cgroup.is_some();
+        if let Some(limits) = cgroup {
+            total_memory     = limits.total_memory;
+            available_memory = limits.free_memory;
+            free_memory      = limits.free_memory;
+            used_memory      = limits.total_memory.saturating_sub(limits.free_memory);
+            used_swap        = total_swap.saturating_sub(limits.free_swap);
+        }
+
+        let mut doc = self.to_document(node_id, MemoryReading {
+            total: total_memory,
+            available: available_memory,
+            free: free_memory,
+            used: used_memory,
+            swap_total: total_swap,
+            swap_used: used_swap,
+            cgroup_limited,
+        })?;
+
+        if let Some(devices) = read_swap_devices(self.units).await {
+            debug!("Swap devices: {}", devices.len());
+            doc.insert("swap_devices", devices);
+        }
+
+        Ok(doc)
+    }
+}
+
+/// Memory figures in bytes, from sysinfo or the collector's cgroup
+struct MemoryReading {
+    total: u64,
+    available: u64,
+    free: u64,
+    used: u64,
+    swap_total: u64,
+    swap_used: u64,
+    cgroup_limited: bool,
+}
+
+impl MemoryCollector {
+    /// Builds the document, or fails when the total memory reads as 0
+    ///
+    /// Some sandboxes and unusual kernels report no memory at all; every
+    /// percentage would then be 0 and the document would look like an idle
+    /// host. Failing the collection stores nothing instead, so the window
+    /// has no document rather than a misleading one.
+    fn to_document(&self, node_id: &str, reading: MemoryReading) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        if reading.total == 0 {
+            if !self.zero_total_warned.swap(true, Ordering::Relaxed) {
+                warn!("Total memory reads as 0 on this system; Memory documents are not stored until it doesn't");
+            }
+            return Err("total memory reported as 0, memory usage unavailable".into());
+        }
 
-        let used_percent      = Self::calculate_percentage(used_memory, total_memory);
-        let swap_used_percent = Self::calculate_percentage(used_swap, total_swap);
+        let used_percent      = Self::calculate_percentage(reading.used, reading.total);
+        let swap_used_percent = Self::calculate_percentage(reading.swap_used, reading.swap_total);
 
+        let mb = |stem| size_field(stem, Scale::Mega, self.units);
         let doc = doc! {
             "node":             node_id,
-            "timestamp":        Utc::now(),
-            "total_mb":         Self::bytes_to_mb(total_memory),
-            "swap_total_mb":    Self::bytes_to_mb(total_swap),
-            "available_mb":     Self::bytes_to_mb(available_memory),
+            "timestamp":        self.clock.now(),
+            mb("total"):        self.megabytes(reading.total),
+            mb("swap_total"):   self.megabytes(reading.swap_total),
+            mb("available"):    self.megabytes(reading.available),
+            mb("usable"):       self.megabytes(usable_memory(reading.available, reading.free, reading.total)),
             "used_percent":     used_percent,
             "swap_used_percent": swap_used_percent,
+            "cgroup_limited":   reading.cgroup_limited,
         };
 
         debug!(
-            "Memory: available={} MB, used={:.1}%, swap={:.1}%",
-            Self::bytes_to_mb(available_memory),
+            "Memory: available={} {}, used={:.1}%, swap={:.1}%",
+            self.megabytes(reading.available),
+            size_unit(Scale::Mega, self.units),
             used_percent,
             swap_used_percent
         );
-
         Ok(doc)
     }
 }
 
+/// Memory that can be allocated without swapping, comparable across platforms
+///
+/// `available` counts memory the OS can hand out, reclaimable caches included:
+/// `MemAvailable` on Linux, free + inactive + purgeable pages on macOS, the
+/// same as free on Windows. `free` counts only unused pages, which on Linux
+/// and macOS sits near zero on a healthy host. `available` is used whenever
+/// the OS reports a value (non-zero, at most `total`), `free` otherwise, e.g.
+/// on Linux kernels older than 3.14 without `MemAvailable`.
+fn usable_memory(available: u64, free: u64, total: u64) -> u64 {
+    if available > 0 && available <= total {
+        available
+    } else {
+        free.min(total)
+    }
+}
+
+/// Active swap areas, or None where /proc/swaps is unavailable
+#[cfg(target_os = "linux")]
+async fn read_swap_devices(units: SizeUnits) -> Option<Vec<Bson>> {
+    match tokio::fs::read_to_string("/proc/swaps").await {
+        Ok(contents) => Some(parse_swaps(&contents, units).into_iter().map(Bson::Document).collect()),
+        Err(e) => {
+            debug!("Failed to read /proc/swaps: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn read_swap_devices(_units: SizeUnits) -> Option<Vec<Bson>> {
+    None
+}
+
+/// Parses /proc/swaps (`Filename Type Size Used Priority`, sizes in KiB)
+///
+/// Sizes are converted to whole kilobytes (KiB or kB) in `units`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_swaps(contents: &str, units: SizeUnits) -> Vec<Document> {
+    let kb = |kib: u64| size(kib.saturating_mul(1024), Scale::Kilo, units) as i64;
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            // Paths are octal-escaped like /proc/mounts
+            let name = fields.next()?.replace("\\040", " ").replace("\\011", "\t");
+            let kind = fields.next()?;
+            let size_kib: u64 = fields.next()?.parse().ok()?;
+            let used_kib: u64 = fields.next()?.parse().ok()?;
+            let priority: i32 = fields.next()?.parse().ok()?;
+            Some(doc! {
+                "name": name,
+                "type": kind,
+                size_field("size", Scale::Kilo, units): kb(size_kib),
+                size_field("used", Scale::Kilo, units): kb(used_kib),
+                "priority": priority,
+            })
+        })
+        .collect()
+}
+
 impl Default for MemoryCollector {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_total_memory_is_an_error() {
+        let collector = MemoryCollector::new();
+        let zeroed = MemoryReading {
+            total: 0, available: 0, free: 0, used: 0, swap_total: 0, swap_used: 0, cgroup_limited: false,
+        };
+        assert!(collector.to_document("n1", zeroed).is_err());
+
+        let reading = MemoryReading {
+            total: 8 << 30, available: 6 << 30, free: 1 << 30, used: 2 << 30, swap_total: 0, swap_used: 0,
+            cgroup_limited: false,
+        };
+        let doc = collector.to_document("n1", reading).unwrap();
+        assert_eq!(doc.get_f64("used_percent").unwrap(), 25.0);
+        assert_eq!(doc.get_i64("usable_mib").unwrap(), 6144);
+    }
+
+    #[test]
+    fn test_usable_memory_prefers_available() {
+        assert_eq!(usable_memory(6_000, 500, 8_000), 6_000);
+        // No MemAvailable (old kernel) or a nonsensical value: fall back to free
+        assert_eq!(usable_memory(0, 500, 8_000), 500);
+        assert_eq!(usable_memory(9_000, 500, 8_000), 500);
+    }
+
+    #[test]
+    fn test_parse_swaps() {
+        let contents = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                        /dev/zram0                              partition\t4194300\t\t512\t\t100\n\
+                        /swap\\040file                          file\t\t2097148\t\t0\t\t-2\n";
+        assert_eq!(
+            parse_swaps(contents, SizeUnits::Binary),
+            [
+                doc! { "name": "/dev/zram0", "type": "partition", "size_kib": 4194300_i64, "used_kib": 512_i64, "priority": 100 },
+                doc! { "name": "/swap file", "type": "file", "size_kib": 2097148_i64, "used_kib": 0_i64, "priority": -2 },
+            ]
+        );
+        assert_eq!(
+            parse_swaps(contents, SizeUnits::Decimal)[0],
+            doc! { "name": "/dev/zram0", "type": "partition", "size_kb": 4294963_i64, "used_kb": 524_i64, "priority": 100 }
+        );
+        assert!(parse_swaps("Filename Type Size Used Priority\n", SizeUnits::Binary).is_empty());
+    }
+}
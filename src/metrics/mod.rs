@@ -6,17 +6,39 @@
 use async_trait::async_trait;
 use bson::Document;
 use std::error::Error;
+use tracing::debug;
+
+use crate::clock::SharedClock;
+use crate::config::MonitoringSettings;
 
 // Re-export all metric implementations
 pub mod load_average;
 pub mod memory;
 pub mod disk;
+pub mod network;
+pub mod entropy;
+pub mod pressure;
 pub mod docker;
 pub mod processes_cpu;
 pub mod processes_ram;
+pub mod cgroup;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod numeric;
 pub mod docker_events;
 pub mod docker_logs;
+pub mod docker_disk;
 pub mod system_events;
+pub mod canary;
+pub mod describe;
+#[cfg(target_os = "linux")]
+pub mod kernel_log;
+#[cfg(target_os = "linux")]
+pub mod smart;
+#[cfg(feature = "ssh")]
+pub mod remote;
+#[cfg(test)]
+pub mod testing;
 
 /// Core trait that all metric collectors must implement.
 ///
@@ -64,6 +86,11 @@ pub trait MetricCollector: Send + Sync {
 ///
 /// This function instantiates all available metric collectors and returns them
 /// as trait objects. When adding a new metric type, add its instantiation here.
+/// Collector-specific options (e.g. the `disk` section) are taken from the
+/// settings loaded at startup, and every collector timestamps its documents
+/// with the given clock. Metrics disabled in the settings are left out.
+/// With `remote.host` set, the SSH collectors stand in for the local ones
+/// (the Canary, which reads nothing from the host, is kept).
 ///
 /// # Returns
 /// Vector of boxed MetricCollector trait objects, one for each metric type
@@ -74,33 +101,140 @@ pub trait MetricCollector: Send + Sync {
 /// 2. Implement the `MetricCollector` trait
 /// 3. Add the module to the re-exports at the top of this file
 /// 4. Add instantiation here: `Box::new(network::NetworkCollector::new())`
-pub fn create_all_collectors() -> Vec<Box<dyn MetricCollector>> {
-    vec![
+pub fn create_all_collectors(
+    settings: &MonitoringSettings,
+    clock: &SharedClock,
+) -> Vec<Box<dyn MetricCollector>> {
+    if settings.remote.is_enabled() {
+        let mut collectors = remote_collectors(settings, clock);
+        collectors.extend(canary_collector(settings, clock));
+        return retain_enabled(collectors, settings);
+    }
+
+    let mut collectors: Vec<Box<dyn MetricCollector>> = vec![
         // Load average monitoring (1min, 5min, 15min averages)
-        Box::new(load_average::LoadAverageCollector::new()),
+        Box::new(load_average::LoadAverageCollector::with_settings(settings.load.clone())
+                .with_clock(clock.clone())),
 
         // Memory usage monitoring (total, used, available, swap)
-        Box::new(memory::MemoryCollector::new()),
+        Box::new(memory::MemoryCollector::new()
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())),
 
         // Disk space monitoring (total, used, free for all mounted filesystems)
-        Box::new(disk::DiskCollector::new()),
+        Box::new(disk::DiskCollector::with_settings(settings.disk.clone())
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())),
+
+        // Network throughput for physical interfaces (loopback/virtual excluded by default)
+        Box::new(network::NetworkCollector::with_settings(settings.network.clone())
+                .with_byte_counts(settings.numeric.byte_counts)
+                .with_clock(clock.clone())),
 
         // Docker container stats (CPU, memory, network I/O per container)
-        Box::new(docker::DockerCollector::new()),
+        Box::new(docker::DockerCollector::with_settings(settings.docker.clone())
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())),
 
-        // Top host processes by CPU, filtered to >1% usage (non-Docker, kernel, system services)
-        Box::new(processes_cpu::ProcessCPUSnapshotCollector::new()),
+        // Top host processes by CPU, filtered to >1% usage (with container attribution)
+        Box::new(processes_cpu::ProcessCPUSnapshotCollector::with_settings(settings.processes.clone())
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())),
 
         // Top host processes by RAM, filtered to >1% of total system memory
-        Box::new(processes_ram::ProcessRAMSnapshotCollector::new()),
+        Box::new(processes_ram::ProcessRAMSnapshotCollector::with_settings(settings.processes.clone())
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())),
 
         // Docker lifecycle events (start, stop, die, OOM-kill, restart)
-        Box::new(docker_events::DockerEventsCollector::new()),
+        Box::new(docker_events::DockerEventsCollector::new().with_clock(clock.clone())),
 
         // stdout/stderr from all running containers (batched per interval)
-        Box::new(docker_logs::DockerLogsCollector::new()),
+        Box::new(docker_logs::DockerLogsCollector::new().with_clock(clock.clone())),
 
         // Kernel and systemd error events via journalctl (Linux only)
-        Box::new(system_events::SystemEventsCollector::new()),
-    ]
+        Box::new(system_events::SystemEventsCollector::new().with_clock(clock.clone())),
+    ];
+
+    // Kernel entropy pool (Linux only: /proc/sys/kernel/random/entropy_avail)
+    #[cfg(target_os = "linux")]
+    collectors.push(Box::new(entropy::EntropyCollector::new().with_clock(clock.clone())));
+
+    // Pressure stall information (Linux 4.20+ with PSI enabled: /proc/pressure)
+    #[cfg(target_os = "linux")]
+    if pressure::PsiCollector::is_supported() {
+        collectors.push(Box::new(pressure::PsiCollector::new().with_clock(clock.clone())));
+    } else {
+        debug!("PSI not available (/proc/pressure/cpu unreadable), skipping Pressure metric");
+    }
+
+    // OOM kills and kernel errors from the ring buffer (Linux: /dev/kmsg)
+    // (opened only when enabled, since the descriptor holds a read position)
+    #[cfg(target_os = "linux")]
+    if settings.metric("KernelLog").enabled {
+        match kernel_log::KernelLogCollector::open() {
+            Ok(collector) => collectors.push(Box::new(collector.with_clock(clock.clone()))),
+            Err(e) => tracing::warn!(
+                "Cannot open /dev/kmsg ({}), skipping KernelLog metric; it needs root or CAP_SYSLOG",
+                e
+            ),
+        }
+    }
+
+    // Disk SMART health via smartctl (Linux, opt-in: needs root and smartmontools)
+    #[cfg(target_os = "linux")]
+    if settings.smart.enabled {
+        collectors.push(Box::new(smart::SmartCollector::with_settings(settings.smart.clone())
+                .with_clock(clock.clone())));
+    }
+
+    // Image, container, volume and build cache disk usage (opt-in: the call is expensive)
+    if settings.docker_disk.enabled {
+        collectors.push(Box::new(docker_disk::DockerDiskCollector::new()
+                .with_size_units(settings.numeric.size_units)
+                .with_clock(clock.clone())));
+    }
+
+    // Liveness document that never fails (opt-in)
+    collectors.extend(canary_collector(settings, clock));
+
+    retain_enabled(collectors, settings)
+}
+
+/// The Canary collector, with `canary.enabled`
+fn canary_collector(settings: &MonitoringSettings, clock: &SharedClock) -> Option<Box<dyn MetricCollector>> {
+    settings
+        .canary
+        .enabled
+        .then(|| Box::new(canary::CanaryCollector::new().with_clock(clock.clone())) as Box<dyn MetricCollector>)
+}
+
+/// Drops the metrics switched off with `metrics.<Name>.enabled: false`
+fn retain_enabled(
+    mut collectors: Vec<Box<dyn MetricCollector>>,
+    settings: &MonitoringSettings,
+) -> Vec<Box<dyn MetricCollector>> {
+    collectors.retain(|collector| {
+        let enabled = settings.metric(collector.name()).enabled;
+        if !enabled {
+            debug!("Metric '{}' is disabled in settings", collector.name());
+        }
+        enabled
+    });
+    collectors
+}
+
+/// LoadAverage, Memory and DiskSpace of `remote.host`, read over SSH
+#[cfg(feature = "ssh")]
+fn remote_collectors(settings: &MonitoringSettings, clock: &SharedClock) -> Vec<Box<dyn MetricCollector>> {
+    remote::create_collectors(settings, clock)
+}
+
+#[cfg(not(feature = "ssh"))]
+fn remote_collectors(settings: &MonitoringSettings, _clock: &SharedClock) -> Vec<Box<dyn MetricCollector>> {
+    tracing::warn!(
+        "'{}' sets remote.host but this build has no SSH support (cargo feature \"ssh\"); nothing is collected",
+        settings.key
+    );
+    Vec::new()
 }
@@ -0,0 +1,330 @@
+// Network metric collector
+//
+// Collects per-interface throughput for physical network interfaces
+// Loopback and virtual interfaces (Docker bridges, veth pairs) are excluded by default
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use sysinfo::Networks;
+use tracing::debug;
+
+use super::numeric::byte_count;
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::{ByteCountType, NetworkSettings};
+
+/// Cumulative byte counters from the previous collection, used to compute rates
+struct CounterSnapshot {
+    taken_at: DateTime<Utc>,
+    /// Interface name → (total received bytes, total transmitted bytes)
+    totals: HashMap<String, (u64, u64)>,
+}
+
+/// Network interface throughput collector
+///
+/// # What is Collected
+/// For each included interface:
+/// - Interface name
+/// - Cumulative bytes received/transmitted (Int64, or Decimal128 with
+///   `numeric.byte_counts: "decimal128"` for exact values past i64::MAX)
+/// - Receive/transmit rate in bytes per second
+///
+/// Plus `total_rx_bytes_per_sec` / `total_tx_bytes_per_sec` summed across
+/// the included interfaces.
+///
+/// Rates need two readings, so the first collection after startup reports
+/// counters only.
+///
+/// # IP Address Inventory
+/// With `network.ip_addresses`, the document also lists the current IPv4 and
+/// IPv6 addresses of the included interfaces as `ip_addresses`. Loopback
+/// addresses are never listed.
+///
+/// # Interface Filtering
+/// `network.include` and `network.exclude` hold name patterns where `*`
+/// matches any run of characters (e.g. `"veth*"`). An interface is reported
+/// if it matches an include pattern (or `include` is empty) and no exclude
+/// pattern. The default exclude list covers loopback and common virtual
+/// interfaces (`docker*`, `veth*`, `br-*`, `virbr*`, ...).
+///
+/// # Platform Support
+/// - Linux: Full support via /proc/net/dev
+/// - macOS: Full support
+/// - Windows: Full support
+pub struct NetworkCollector {
+    settings: NetworkSettings,
+    previous: Mutex<Option<CounterSnapshot>>,
+    /// BSON type of the cumulative `rx_bytes` / `tx_bytes` counters
+    byte_counts: ByteCountType,
+    clock: SharedClock,
+}
+
+impl NetworkCollector {
+    /// Creates a new NetworkCollector instance with default settings
+    pub fn new() -> Self {
+        Self::with_settings(NetworkSettings::default())
+    }
+
+    /// Creates a NetworkCollector using the `network` section of the monitoring settings
+    pub fn with_settings(settings: NetworkSettings) -> Self {
+        NetworkCollector {
+            settings,
+            previous: Mutex::new(None),
+            byte_counts: ByteCountType::default(),
+            clock: system_clock(),
+        }
+    }
+
+    /// Sets the BSON type of the cumulative byte counters (`numeric.byte_counts`)
+    pub fn with_byte_counts(mut self, byte_counts: ByteCountType) -> Self {
+        self.byte_counts = byte_counts;
+        self
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns true if the interface passes the include/exclude patterns
+    fn is_included(&self, interface: &str) -> bool {
+        let included = self.settings.include.is_empty()
+            || self.settings.include.iter().any(|p| matches_pattern(p, interface));
+        included && !self.settings.exclude.iter().any(|p| matches_pattern(p, interface))
+    }
+
+    /// `{ interface, address, family }` for every non-loopback address of an included interface
+    fn ip_addresses(&self) -> Vec<Document> {
+        match if_addrs::get_if_addrs() {
+            Ok(interfaces) => self.address_documents(interfaces.into_iter().map(|iface| {
+                let ip = iface.ip();
+                (iface.name, ip)
+            })),
+            Err(e) => {
+                debug!("Failed to list interface addresses: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// `ip_addresses` entries for (interface, address) pairs, sorted by
+    /// interface, family and address
+    fn address_documents(&self, interfaces: impl IntoIterator<Item = (String, IpAddr)>) -> Vec<Document> {
+        let mut addresses: Vec<(String, &'static str, String)> = interfaces
+            .into_iter()
+            .filter(|(name, ip)| !ip.is_loopback() && self.is_included(name))
+            .map(|(name, ip)| {
+                let family = if ip.is_ipv4() { "ipv4" } else { "ipv6" };
+                (name, family, ip.to_string())
+            })
+            .collect();
+        addresses.sort();
+
+        addresses
+            .into_iter()
+            .map(|(interface, family, address)| doc! {
+                "interface": interface,
+                "address": address,
+                "family": family,
+            })
+            .collect()
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of characters
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: exact match
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[async_trait]
+impl MetricCollector for NetworkCollector {
+    fn name(&self) -> &str {
+        "Network"
+    }
+
+    /// Collects throughput for every included network interface
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "...",
+    ///   "interface_count": 1,
+    ///   "total_rx_bytes_per_sec": 5321.4,
+    ///   "total_tx_bytes_per_sec": 1204.9,
+    ///   "interfaces": [
+    ///     {
+    ///       "interface": "eth0",
+    ///       "rx_bytes": 918273645,
+    ///       "tx_bytes": 123456789,
+    ///       "rx_bytes_per_sec": 5321.4,
+    ///       "tx_bytes_per_sec": 1204.9
+    ///     }
+    ///   ],
+    ///   "ip_addresses": [
+    ///     { "interface": "eth0", "address": "10.0.0.12", "family": "ipv4" }
+    ///   ]
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting network interface metrics");
+
+        let networks = Networks::new_with_refreshed_list();
+        let now = self.clock.now();
+
+        let mut totals = HashMap::new();
+        for (name, data) in networks.list() {
+            if self.is_included(name) {
+                totals.insert(name.clone(), (data.total_received(), data.total_transmitted()));
+            }
+        }
+
+        let mut previous = self.previous.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = previous
+            .as_ref()
+            .map(|p| (now - p.taken_at).num_milliseconds() as f64 / 1000.0)
+            .filter(|secs| *secs > 0.0);
+
+        let mut names: Vec<&String> = totals.keys().collect();
+        names.sort();
+
+        let mut interfaces = Vec::with_capacity(names.len());
+        let mut total_rx_rate = 0.0;
+        let mut total_tx_rate = 0.0;
+
+        for name in names {
+            let (rx, tx) = totals[name];
+            let mut entry = doc! {
+                "interface": name.as_str(),
+                "rx_bytes": byte_count(rx, self.byte_counts),
+                "tx_bytes": byte_count(tx, self.byte_counts),
+            };
+
+            let prev = previous.as_ref().and_then(|p| p.totals.get(name));
+            if let (Some(secs), Some((prev_rx, prev_tx))) = (elapsed, prev) {
+                // saturating_sub: counters restart from zero when an interface is reset
+                let rx_rate = rx.saturating_sub(*prev_rx) as f64 / secs;
+                let tx_rate = tx.saturating_sub(*prev_tx) as f64 / secs;
+                entry.insert("rx_bytes_per_sec", rx_rate);
+                entry.insert("tx_bytes_per_sec", tx_rate);
+                total_rx_rate += rx_rate;
+                total_tx_rate += tx_rate;
+            }
+
+            interfaces.push(entry);
+        }
+
+        let mut result = doc! {
+            "node": node_id,
+            "timestamp": now,
+            "interface_count": interfaces.len() as i32,
+        };
+        if elapsed.is_some() {
+            result.insert("total_rx_bytes_per_sec", total_rx_rate);
+            result.insert("total_tx_bytes_per_sec", total_tx_rate);
+        }
+
+        debug!(
+            "Network: {} interface(s), rx={:.0} B/s, tx={:.0} B/s",
+            interfaces.len(),
+            total_rx_rate,
+            total_tx_rate
+        );
+
+        result.insert("interfaces", interfaces);
+        if self.settings.ip_addresses {
+            result.insert("ip_addresses", self.ip_addresses());
+        }
+        *previous = Some(CounterSnapshot { taken_at: now, totals });
+
+        Ok(result)
+    }
+}
+
+impl Default for NetworkCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(name: &str, address: &str) -> (String, IpAddr) {
+        (name.to_string(), address.parse().unwrap())
+    }
+
+    #[test]
+    fn test_ip_addresses_skip_loopback_and_filtered_interfaces() {
+        let interfaces = || {
+            vec![
+                interface("eth1", "10.0.1.5"),
+                interface("lo", "127.0.0.1"),
+                interface("eth0", "fe80::1"),
+                interface("docker0", "172.17.0.1"),
+                interface("eth0", "192.168.1.10"),
+                // Loopback addresses are skipped whatever the interface
+                interface("eth0", "::1"),
+            ]
+        };
+        let listed = |collector: &NetworkCollector| -> Vec<(String, String, String)> {
+            collector
+                .address_documents(interfaces())
+                .iter()
+                .map(|d| {
+                    let field = |key| d.get_str(key).unwrap().to_string();
+                    (field("interface"), field("family"), field("address"))
+                })
+                .collect()
+        };
+        let entry = |i: &str, f: &str, a: &str| (i.to_string(), f.to_string(), a.to_string());
+
+        // Default excludes drop docker0; output is sorted
+        let defaults = NetworkCollector::new();
+        assert_eq!(
+            listed(&defaults),
+            [
+                entry("eth0", "ipv4", "192.168.1.10"),
+                entry("eth0", "ipv6", "fe80::1"),
+                entry("eth1", "ipv4", "10.0.1.5"),
+            ]
+        );
+
+        let only_eth1 = NetworkCollector::with_settings(NetworkSettings {
+            include: vec!["eth1".to_string()],
+            ..NetworkSettings::default()
+        });
+        assert_eq!(listed(&only_eth1), [entry("eth1", "ipv4", "10.0.1.5")]);
+
+        let eth_excluded = NetworkCollector::with_settings(NetworkSettings {
+            exclude: vec!["eth*".to_string()],
+            ..NetworkSettings::default()
+        });
+        assert_eq!(listed(&eth_excluded), [entry("docker0", "ipv4", "172.17.0.1")]);
+    }
+}
@@ -0,0 +1,121 @@
+// Numeric helpers - explicit BSON types for values read from the OS
+//
+// Conventions used by every collector:
+// - raw byte counters (e.g. cumulative interface bytes) → Int64, or Decimal128
+//   when `numeric.byte_counts` is "decimal128"
+// - sizes converted to MiB/GiB (or MB/GB, per `numeric.size_units`), rates
+//   and percentages → Double
+// - small counts (cores, interfaces) → Int32; pids → Int64
+//
+// `u64 as i64` silently wraps above i64::MAX, so conversions go through here.
+
+use bson::{Bson, Decimal128};
+
+use crate::config::{ByteCountType, SizeUnits};
+
+/// Converts to i64, saturating at i64::MAX instead of wrapping negative
+pub fn saturating_i64(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+/// Encodes a raw byte counter as the configured BSON type
+pub fn byte_count(bytes: u64, kind: ByteCountType) -> Bson {
+    match kind {
+        ByteCountType::Int64 => Bson::Int64(saturating_i64(bytes)),
+        ByteCountType::Decimal128 => Bson::Decimal128(decimal128_from_u64(bytes)),
+    }
+}
+
+/// Magnitude of a converted size
+#[derive(Debug, Clone, Copy)]
+pub enum Scale {
+    Kilo,
+    Mega,
+    Giga,
+}
+
+impl Scale {
+    fn power(self) -> i32 {
+        match self {
+            Scale::Kilo => 1,
+            Scale::Mega => 2,
+            Scale::Giga => 3,
+        }
+    }
+}
+
+/// Converts bytes to `scale` in the base of `units`
+pub fn size(bytes: u64, scale: Scale, units: SizeUnits) -> f64 {
+    let base: f64 = match units {
+        SizeUnits::Binary | SizeUnits::Legacy => 1024.0,
+        SizeUnits::Decimal => 1000.0,
+    };
+    bytes as f64 / base.powi(scale.power())
+}
+
+/// Unit label of `scale` in `units`, as written to `metric_schema` (e.g. "MiB")
+pub fn size_unit(scale: Scale, units: SizeUnits) -> &'static str {
+    match (units, scale) {
+        (SizeUnits::Decimal, Scale::Kilo) => "kB",
+        (SizeUnits::Decimal, Scale::Mega) => "MB",
+        (SizeUnits::Decimal, Scale::Giga) => "GB",
+        (_, Scale::Kilo) => "KiB",
+        (_, Scale::Mega) => "MiB",
+        (_, Scale::Giga) => "GiB",
+    }
+}
+
+/// Field name for a size: `<stem>_mib` in binary units, `<stem>_mb` otherwise
+pub fn size_field(stem: &str, scale: Scale, units: SizeUnits) -> String {
+    let suffix = match scale {
+        Scale::Kilo => "k",
+        Scale::Mega => "m",
+        Scale::Giga => "g",
+    };
+    match units {
+        SizeUnits::Binary => format!("{}_{}ib", stem, suffix),
+        SizeUnits::Decimal | SizeUnits::Legacy => format!("{}_{}b", stem, suffix),
+    }
+}
+
+/// Exact Decimal128 for an unsigned integer
+///
+/// IEEE 754-2008 BID layout (little-endian): the low 64 bits hold the
+/// coefficient, the high 64 bits the biased exponent (6176 for 10^0) shifted
+/// left by 49, sign bit clear.
+fn decimal128_from_u64(value: u64) -> Decimal128 {
+    const EXPONENT_BIAS: u64 = 6176;
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&value.to_le_bytes());
+    bytes[8..].copy_from_slice(&(EXPONENT_BIAS << 49).to_le_bytes());
+    Decimal128::from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_count_types() {
+        assert_eq!(byte_count(42, ByteCountType::Int64), Bson::Int64(42));
+        assert_eq!(byte_count(u64::MAX, ByteCountType::Int64), Bson::Int64(i64::MAX));
+
+        for value in [0, 1, 918_273_645, u64::MAX] {
+            let expected: Decimal128 = value.to_string().parse().unwrap();
+            assert_eq!(byte_count(value, ByteCountType::Decimal128), Bson::Decimal128(expected));
+        }
+    }
+
+    #[test]
+    fn test_size_units() {
+        let bytes = 1_500_000_000;
+        assert_eq!(size(bytes, Scale::Giga, SizeUnits::Decimal), 1.5);
+        assert_eq!(size(3 * 1024 * 1024, Scale::Mega, SizeUnits::Binary), 3.0);
+        assert_eq!(size(3 * 1024 * 1024, Scale::Mega, SizeUnits::Legacy), 3.0);
+
+        assert_eq!(size_field("used", Scale::Giga, SizeUnits::Binary), "used_gib");
+        assert_eq!(size_field("used", Scale::Giga, SizeUnits::Decimal), "used_gb");
+        assert_eq!(size_field("total", Scale::Mega, SizeUnits::Legacy), "total_mb");
+        assert_eq!(size_unit(Scale::Mega, SizeUnits::Legacy), "MiB");
+    }
+}
@@ -0,0 +1,154 @@
+// Pressure stall information (PSI) collector
+//
+// Reports the share of wall time tasks were stalled waiting for CPU, I/O or
+// memory, from /proc/pressure/{cpu,io,memory}. Unlike load average, PSI
+// separates the resources and measures lost time rather than queue length, so
+// I/O and memory contention show up before throughput drops.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use std::error::Error;
+use tracing::debug;
+
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+
+/// Resources with a file under /proc/pressure
+const RESOURCES: [&str; 3] = ["cpu", "io", "memory"];
+
+/// Pressure stall information collector
+///
+/// # What is Collected
+/// For each resource (`cpu`, `io`, `memory`) and line (`some`, `full`), the
+/// percentage of time stalled averaged over 10s, 60s and 300s, as
+/// `<resource>_<line>_avg10` etc.:
+/// - `some`: at least one task was stalled on the resource
+/// - `full`: all non-idle tasks were stalled at once (no `cpu_full_*` before
+///   Linux 5.13)
+///
+/// # Platform Support
+/// - Linux 4.20+ with PSI enabled: registered only if /proc/pressure/cpu is
+///   readable (`psi=1` on kernels built with `CONFIG_PSI_DEFAULT_DISABLED`)
+/// - Other platforms: not registered
+pub struct PsiCollector {
+    clock: SharedClock,
+}
+
+impl PsiCollector {
+    /// Creates a new PsiCollector instance
+    pub fn new() -> Self {
+        PsiCollector {
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Whether the kernel exposes PSI
+    pub fn is_supported() -> bool {
+        std::fs::read_to_string("/proc/pressure/cpu").is_ok()
+    }
+}
+
+/// Parses one /proc/pressure file into `(line, [avg10, avg60, avg300])`
+///
+/// Lines look like `some avg10=0.32 avg60=0.37 avg300=0.25 total=15814774`;
+/// malformed lines are skipped.
+fn parse_psi(contents: &str) -> Vec<(&str, [f64; 3])> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let kind = parts.next()?;
+            let mut averages = [None; 3];
+            for part in parts {
+                let (key, value) = part.split_once('=')?;
+                let slot = match key {
+                    "avg10" => 0,
+                    "avg60" => 1,
+                    "avg300" => 2,
+                    _ => continue,
+                };
+                averages[slot] = Some(value.parse().ok()?);
+            }
+            Some((kind, [averages[0]?, averages[1]?, averages[2]?]))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl MetricCollector for PsiCollector {
+    /// Returns the metric name
+    fn name(&self) -> &str {
+        "Pressure"
+    }
+
+    /// Collects the current PSI averages for every resource
+    ///
+    /// A resource whose file can't be read (e.g. `memory` without the memory
+    /// controller) is left out.
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "...",
+    ///   "cpu_some_avg10": 1.8,
+    ///   "cpu_some_avg60": 3.79,
+    ///   "cpu_some_avg300": 3.78,
+    ///   "io_full_avg10": 0.05,
+    ///   ...
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting pressure stall metrics");
+
+        let mut doc = doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+        };
+
+        for resource in RESOURCES {
+            let contents = match tokio::fs::read_to_string(format!("/proc/pressure/{}", resource)).await {
+                Ok(contents) => contents,
+                Err(e) => {
+                    debug!("PSI for '{}' unavailable: {}", resource, e);
+                    continue;
+                }
+            };
+            for (kind, [avg10, avg60, avg300]) in parse_psi(&contents) {
+                doc.insert(format!("{}_{}_avg10", resource, kind), avg10);
+                doc.insert(format!("{}_{}_avg60", resource, kind), avg60);
+                doc.insert(format!("{}_{}_avg300", resource, kind), avg300);
+            }
+        }
+
+        Ok(doc)
+    }
+}
+
+impl Default for PsiCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_psi() {
+        let io = "some avg10=0.32 avg60=0.37 avg300=0.25 total=15814774\n\
+                  full avg10=0.05 avg60=0.08 avg300=0.07 total=10705180\n";
+        assert_eq!(parse_psi(io), [("some", [0.32, 0.37, 0.25]), ("full", [0.05, 0.08, 0.07])]);
+
+        // Pre-5.13 cpu file: `some` only; a truncated line is skipped
+        let cpu = "some avg10=12.50 avg60=3.79 avg300=0.00 total=180030271\nfull avg10=1.00\n";
+        assert_eq!(parse_psi(cpu), [("some", [12.5, 3.79, 0.0])]);
+    }
+}
@@ -5,12 +5,15 @@
 
 use async_trait::async_trait;
 use bson::{doc, Document};
-use chrono::Utc;
 use std::error::Error;
 use sysinfo::System;
 use tracing::debug;
 
+use super::cgroup::ContainerAttribution;
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use super::numeric::{size, size_field, Scale};
+use crate::config::{ProcessSettings, SizeUnits};
 
 /// Processes below this CPU usage are considered noise and dropped —
 /// they add no diagnostic value for root-cause analysis.
@@ -25,11 +28,40 @@ const MAX_PROCESSES: usize = 10;
 /// less than `CPU_THRESHOLD_PERCENT` CPU, sorts by CPU usage descending,
 /// and stores at most `MAX_PROCESSES`. Covers non-Docker, kernel, and system
 /// service processes that the Docker stats collector cannot see.
-pub struct ProcessCPUSnapshotCollector;
+///
+/// Processes running inside a container get a `container` sub-document
+/// (`id`, plus `name` when the Docker join is enabled) so host-level load
+/// can be traced back to the container; see `processes` settings.
+pub struct ProcessCPUSnapshotCollector {
+    containers: ContainerAttribution,
+    clock: SharedClock,
+    units: SizeUnits,
+}
 
 impl ProcessCPUSnapshotCollector {
     pub fn new() -> Self {
-        ProcessCPUSnapshotCollector
+        Self::with_settings(ProcessSettings::default())
+    }
+
+    /// Creates a collector using the `processes` section of the monitoring settings
+    pub fn with_settings(settings: ProcessSettings) -> Self {
+        ProcessCPUSnapshotCollector {
+            containers: ContainerAttribution::with_settings(settings),
+            clock: system_clock(),
+            units: SizeUnits::default(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of `memory_mib` (`numeric.size_units`)
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
     }
 }
 
@@ -60,18 +92,25 @@ impl MetricCollector for ProcessCPUSnapshotCollector {
                 .unwrap_or(std::cmp::Ordering::Equal)
         });
 
+        processes.truncate(MAX_PROCESSES);
+        let pids: Vec<u32> = processes.iter().map(|p| p.pid().as_u32()).collect();
+        let mut containers = self.containers.resolve(&pids).await;
+
         let top_processes: Vec<Document> = processes
             .iter()
-            .take(MAX_PROCESSES)
             .map(|p| {
-                doc! {
+                let mut entry = doc! {
                     "pid": p.pid().as_u32() as i64,
                     "name": p.name().to_string(),
                     "cpu_percent": p.cpu_usage() as f64,
-                    "memory_mb": p.memory() as f64 / (1024.0 * 1024.0),
+                    size_field("memory", Scale::Mega, self.units): size(p.memory(), Scale::Mega, self.units),
                     "memory_percent": calculate_percentage(p.memory(), total_memory),
                     "status": format!("{:?}", p.status()),
+                };
+                if let Some(container) = containers.remove(&p.pid().as_u32()) {
+                    entry.insert("container", container);
                 }
+                entry
             })
             .collect();
 
@@ -84,7 +123,7 @@ impl MetricCollector for ProcessCPUSnapshotCollector {
 
         let doc = doc! {
             "node": node_id,
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
             "processes": top_processes,
         };
 
@@ -5,12 +5,15 @@
 
 use async_trait::async_trait;
 use bson::{doc, Document};
-use chrono::Utc;
 use std::error::Error;
 use sysinfo::System;
 use tracing::debug;
 
+use super::cgroup::ContainerAttribution;
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use super::numeric::{size, size_field, Scale};
+use crate::config::{ProcessSettings, SizeUnits};
 
 /// Processes using less than this percentage of total system RAM are
 /// considered noise and dropped — they add no diagnostic value for
@@ -27,11 +30,39 @@ const MAX_PROCESSES: usize = 10;
 /// memory usage descending, and stores at most `MAX_PROCESSES`. Covers
 /// non-Docker, kernel, and system service processes that the Docker stats
 /// collector cannot see.
-pub struct ProcessRAMSnapshotCollector;
+///
+/// Processes running inside a container get a `container` sub-document
+/// (`id`, plus `name` when the Docker join is enabled); see `processes` settings.
+pub struct ProcessRAMSnapshotCollector {
+    containers: ContainerAttribution,
+    clock: SharedClock,
+    units: SizeUnits,
+}
 
 impl ProcessRAMSnapshotCollector {
     pub fn new() -> Self {
-        ProcessRAMSnapshotCollector
+        Self::with_settings(ProcessSettings::default())
+    }
+
+    /// Creates a collector using the `processes` section of the monitoring settings
+    pub fn with_settings(settings: ProcessSettings) -> Self {
+        ProcessRAMSnapshotCollector {
+            containers: ContainerAttribution::with_settings(settings),
+            clock: system_clock(),
+            units: SizeUnits::default(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the units of `memory_mib` (`numeric.size_units`)
+    pub fn with_size_units(mut self, units: SizeUnits) -> Self {
+        self.units = units;
+        self
     }
 }
 
@@ -56,20 +87,27 @@ impl MetricCollector for ProcessRAMSnapshotCollector {
             .filter(|p| calculate_percentage(p.memory(), total_memory) > MEMORY_THRESHOLD_PERCENT)
             .collect();
 
-        processes.sort_by(|a, b| b.memory().cmp(&a.memory()));
+        processes.sort_by_key(|p| std::cmp::Reverse(p.memory()));
+
+        processes.truncate(MAX_PROCESSES);
+        let pids: Vec<u32> = processes.iter().map(|p| p.pid().as_u32()).collect();
+        let mut containers = self.containers.resolve(&pids).await;
 
         let top_processes: Vec<Document> = processes
             .iter()
-            .take(MAX_PROCESSES)
             .map(|p| {
-                doc! {
+                let mut entry = doc! {
                     "pid": p.pid().as_u32() as i64,
                     "name": p.name().to_string(),
-                    "memory_mb": p.memory() as f64 / (1024.0 * 1024.0),
+                    size_field("memory", Scale::Mega, self.units): size(p.memory(), Scale::Mega, self.units),
                     "memory_percent": calculate_percentage(p.memory(), total_memory),
                     "cpu_percent": p.cpu_usage() as f64,
                     "status": format!("{:?}", p.status()),
+                };
+                if let Some(container) = containers.remove(&p.pid().as_u32()) {
+                    entry.insert("container", container);
                 }
+                entry
             })
             .collect();
 
@@ -82,7 +120,7 @@ impl MetricCollector for ProcessRAMSnapshotCollector {
 
         let doc = doc! {
             "node": node_id,
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
             "processes": top_processes,
         };
 
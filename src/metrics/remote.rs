@@ -0,0 +1,373 @@
+// Remote collection - LoadAverage, Memory and DiskSpace of a host over SSH
+//
+// Some appliances can't run this binary. With `remote.host` set, a settings
+// key is collected by logging in over SSH and reading `/proc/loadavg`,
+// `/proc/meminfo`, `df` and `/proc/mounts`, which any Linux shell has; the
+// output is parsed into the same documents the local collectors produce.
+// libssh2 is blocking, so each command runs on a blocking thread. The three
+// collectors of one host share a session, reopened after any failure.
+// Compiled with the `ssh` feature only.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use std::error::Error;
+use std::io::Read;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use super::disk::DiskCollector;
+use super::load_average::is_overloaded;
+use super::numeric::{size, size_field, Scale};
+use super::MetricCollector;
+use crate::clock::SharedClock;
+use crate::config::{DiskSettings, LoadSettings, MonitoringSettings, RemoteSettings, SizeUnits};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// DiskSpace command: capacities from POSIX `df`, filesystem types from
+/// `/proc/mounts`, separated by `MOUNTS_MARKER`
+const DISK_COMMAND: &str = "df -P -k && echo --mounts-- && cat /proc/mounts";
+const MOUNTS_MARKER: &str = "--mounts--";
+
+/// Creates the remote LoadAverage, Memory and DiskSpace collectors for
+/// `settings.remote.host`, sharing one SSH connection
+pub fn create_collectors(settings: &MonitoringSettings, clock: &SharedClock) -> Vec<Box<dyn MetricCollector>> {
+    let remote = &settings.remote;
+    info!("Collecting '{}' over SSH from {}@{}:{}", settings.key, remote.user, remote.host, remote.port);
+    if remote.known_hosts.is_empty() {
+        warn!("remote.known_hosts is empty: the host key of {} is not verified", remote.host);
+    }
+    let transport = Arc::new(SshTransport::new(remote.clone()));
+    [
+        RemoteMetric::LoadAverage(settings.load.clone()),
+        RemoteMetric::Memory,
+        RemoteMetric::DiskSpace(settings.disk.clone()),
+    ]
+    .into_iter()
+    .map(|metric| {
+        Box::new(RemoteCollector {
+            metric,
+            transport: Arc::clone(&transport),
+            units: settings.numeric.size_units,
+            clock: clock.clone(),
+        }) as Box<dyn MetricCollector>
+    })
+    .collect()
+}
+
+/// One SSH connection, opened on first use
+struct SshTransport {
+    settings: RemoteSettings,
+    session: Mutex<Option<Session>>,
+}
+
+impl SshTransport {
+    fn new(settings: RemoteSettings) -> Self {
+        SshTransport {
+            settings,
+            session: Mutex::new(None),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.command_timeout_secs.max(1))
+    }
+
+    /// Runs `command` on the host and returns its stdout
+    async fn run(self: &Arc<Self>, command: &'static str) -> Result<String, BoxError> {
+        let transport = Arc::clone(self);
+        tokio::task::spawn_blocking(move || transport.run_blocking(command)).await?
+    }
+
+    fn run_blocking(&self, command: &str) -> Result<String, BoxError> {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        if session.is_none() {
+            *session = Some(self.connect()?);
+        }
+        let result = self.exec(session.as_ref().expect("session was just opened"), command);
+        if result.is_err() {
+            // Reconnect on the next command rather than reuse a broken session
+            *session = None;
+        }
+        result
+    }
+
+    fn exec(&self, session: &Session, command: &str) -> Result<String, BoxError> {
+        let mut channel = session.channel_session()?;
+        channel.exec(command)?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+        match channel.exit_status()? {
+            0 => Ok(output),
+            status => Err(format!("'{}' exited with status {} on {}", command, status, self.settings.host).into()),
+        }
+    }
+
+    fn connect(&self) -> Result<Session, BoxError> {
+        let remote = &self.settings;
+        let address = (remote.host.as_str(), remote.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("{} resolves to no address", remote.host))?;
+        let tcp = TcpStream::connect_timeout(&address, self.timeout())?;
+
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(self.timeout().as_millis() as u32);
+        session.handshake()?;
+        self.verify_host_key(&session)?;
+
+        if let Some(key_path) = &remote.key_path {
+            session.userauth_pubkey_file(&remote.user, None, Path::new(key_path), None)?;
+        } else if let Some(password_file) = &remote.password_file {
+            let password = std::fs::read_to_string(password_file)
+                .map_err(|e| format!("Failed to read remote.password_file '{}': {}", password_file, e))?;
+            session.userauth_password(&remote.user, password.trim_end_matches(['\r', '\n']))?;
+        } else {
+            session.userauth_agent(&remote.user)?;
+        }
+        if !session.authenticated() {
+            return Err(format!("SSH authentication as '{}' on {} failed", remote.user, remote.host).into());
+        }
+        debug!("SSH session to {}@{}:{} open", remote.user, remote.host, remote.port);
+        Ok(session)
+    }
+
+    /// Checks the host key against `remote.known_hosts`, unless it is empty
+    fn verify_host_key(&self, session: &Session) -> Result<(), BoxError> {
+        let remote = &self.settings;
+        if remote.known_hosts.is_empty() {
+            return Ok(());
+        }
+        let path = match (remote.known_hosts.strip_prefix("~/"), std::env::var("HOME")) {
+            (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+            _ => Path::new(&remote.known_hosts).to_path_buf(),
+        };
+        let mut known_hosts = session.known_hosts()?;
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(|e| format!("Failed to read known_hosts '{}': {}", path.display(), e))?;
+        let (key, _) = session.host_key().ok_or("server sent no host key")?;
+        match known_hosts.check_port(&remote.host, remote.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(format!("{} is not in '{}'", remote.host, path.display()).into()),
+            CheckResult::Mismatch => Err(format!("host key of {} does not match '{}'", remote.host, path.display()).into()),
+            CheckResult::Failure => Err(format!("checking the host key of {} failed", remote.host).into()),
+        }
+    }
+}
+
+/// Which local collector a remote one stands in for
+enum RemoteMetric {
+    LoadAverage(LoadSettings),
+    Memory,
+    DiskSpace(DiskSettings),
+}
+
+/// A local collector's document, built from command output on the remote host
+///
+/// Fields the commands can't provide are left out: `swap_devices` for
+/// Memory, and DiskSpace is not deduplicated or filtered by usage.
+/// `cgroup_limited` is always false.
+struct RemoteCollector {
+    metric: RemoteMetric,
+    transport: Arc<SshTransport>,
+    units: SizeUnits,
+    clock: SharedClock,
+}
+
+#[async_trait]
+impl MetricCollector for RemoteCollector {
+    fn name(&self) -> &str {
+        match self.metric {
+            RemoteMetric::LoadAverage(_) => "LoadAverage",
+            RemoteMetric::Memory => "Memory",
+            RemoteMetric::DiskSpace(_) => "DiskSpace",
+        }
+    }
+
+    async fn collect(&self, node_id: &str) -> Result<Document, BoxError> {
+        debug!("Collecting {} from {} over SSH", self.name(), self.transport.settings.host);
+        let mut doc = doc! { "node": node_id, "timestamp": self.clock.now() };
+        let fields = match &self.metric {
+            RemoteMetric::LoadAverage(settings) => {
+                let output = self.transport.run("cat /proc/loadavg && nproc").await?;
+                parse_load(&output, settings)?
+            }
+            RemoteMetric::Memory => {
+                let output = self.transport.run("cat /proc/meminfo").await?;
+                parse_meminfo(&output, self.units)?
+            }
+            RemoteMetric::DiskSpace(settings) => {
+                let output = self.transport.run(DISK_COMMAND).await?;
+                parse_disks(&output, settings, self.units)
+            }
+        };
+        doc.extend(fields);
+        Ok(doc)
+    }
+}
+
+/// LoadAverage fields from `/proc/loadavg` followed by `nproc`
+fn parse_load(output: &str, settings: &LoadSettings) -> Result<Document, BoxError> {
+    let mut lines = output.lines();
+    let loads: Vec<f64> = lines
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .take(3)
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let [one, five, fifteen] = loads[..] else {
+        return Err(format!("unexpected /proc/loadavg: {:?}", output).into());
+    };
+    let cpu_count: usize = lines.next().unwrap_or("").trim().parse()?;
+    let per_core = |load: f64| load / cpu_count.max(1) as f64;
+
+    let mut doc = doc! {
+        "load_1min": one,
+        "load_5min": five,
+        "load_15min": fifteen,
+        "load_1min_per_core": per_core(one),
+        "load_5min_per_core": per_core(five),
+        "load_15min_per_core": per_core(fifteen),
+        "cpu_cores": cpu_count as i32,
+    };
+    if let Some(overloaded) = is_overloaded(one, cpu_count, settings.overload_factor) {
+        doc.insert("overloaded", overloaded);
+    }
+    Ok(doc)
+}
+
+/// Memory fields from `/proc/meminfo` (values in kB)
+fn parse_meminfo(output: &str, units: SizeUnits) -> Result<Document, BoxError> {
+    let bytes = |key: &str| -> Option<u64> {
+        output.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix(':')?;
+            value.split_whitespace().next()?.parse::<u64>().ok().map(|kib| kib * 1024)
+        })
+    };
+    let total = bytes("MemTotal").filter(|t| *t > 0).ok_or("MemTotal missing or 0 in /proc/meminfo")?;
+    let free = bytes("MemFree").unwrap_or(0);
+    // MemAvailable is missing before Linux 3.14
+    let available = bytes("MemAvailable").filter(|a| *a <= total).unwrap_or(free);
+    let swap_total = bytes("SwapTotal").unwrap_or(0);
+    let swap_used = swap_total.saturating_sub(bytes("SwapFree").unwrap_or(0));
+
+    let percent = |used: u64, total: u64| if total == 0 { 0.0 } else { used as f64 / total as f64 * 100.0 };
+    let mb = |stem| size_field(stem, Scale::Mega, units);
+    let megabytes = |bytes| size(bytes, Scale::Mega, units) as i64;
+    Ok(doc! {
+        mb("total"):         megabytes(total),
+        mb("swap_total"):    megabytes(swap_total),
+        mb("available"):     megabytes(available),
+        mb("usable"):        megabytes(available),
+        "used_percent":      percent(total.saturating_sub(available), total),
+        "swap_used_percent": percent(swap_used, swap_total),
+        "cgroup_limited":    false,
+    })
+}
+
+/// DiskSpace `disks` from POSIX `df -P -k`, then the marker line, then
+/// `/proc/mounts` for filesystem types
+///
+/// Only device-backed (`/dev/...`) and network (`host:/export`) filesystems
+/// are kept, which leaves out tmpfs, overlay and the like.
+fn parse_disks(output: &str, settings: &DiskSettings, units: SizeUnits) -> Document {
+    let (df, mounts) = output.split_once(MOUNTS_MARKER).unwrap_or((output, ""));
+    let filesystem = |mount_point: &str| {
+        mounts
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|fields| fields.len() >= 3 && fields[1].replace("\\040", " ") == mount_point)
+            .map(|fields| fields[2].to_string())
+            .unwrap_or_default()
+    };
+    let gb = |stem| size_field(stem, Scale::Giga, units);
+
+    let mut disks: Vec<Document> = df
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // Filesystem 1024-blocks Used Available Capacity Mounted-on; the
+            // mount point may contain spaces, so it is the rest of the line
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let device = fields[0];
+            if !device.starts_with("/dev/") && !device.contains(":/") {
+                return None;
+            }
+            let total = fields[1].parse::<u64>().ok()? * 1024;
+            let used = fields[2].parse::<u64>().ok()? * 1024;
+            let available = fields[3].parse::<u64>().ok()? * 1024;
+            let mount_point = fields[5..].join(" ");
+            let used_percent = if total == 0 { 0.0 } else { used as f64 / total as f64 * 100.0 };
+
+            let mut disk = doc! {
+                "mount_point": &mount_point,
+                "device": device,
+                "filesystem": filesystem(&mount_point),
+                gb("total"): size(total, Scale::Giga, units),
+                gb("used"): size(used, Scale::Giga, units),
+                gb("available"): size(available, Scale::Giga, units),
+                "used_percent": used_percent,
+            };
+            if let Some(low_space) = DiskCollector::is_low_space(available, total, settings) {
+                disk.insert("low_space", low_space);
+            }
+            Some(disk)
+        })
+        .collect();
+    disks.sort_by(|a, b| a.get_str("mount_point").unwrap_or("").cmp(b.get_str("mount_point").unwrap_or("")));
+    doc! { "disks": disks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_load_and_meminfo() {
+        let load = parse_load("2.50 1.00 0.50 3/412 9120\n2\n", &LoadSettings::default()).unwrap();
+        assert_eq!(load.get_f64("load_1min_per_core").unwrap(), 1.25);
+        assert_eq!(load.get_i32("cpu_cores").unwrap(), 2);
+        assert!(!load.get_bool("overloaded").unwrap());
+        assert!(parse_load("garbage\n", &LoadSettings::default()).is_err());
+
+        let meminfo = "MemTotal:        2048000 kB\nMemFree:          100000 kB\n\
+                       MemAvailable:    1024000 kB\nSwapTotal:       1024000 kB\nSwapFree:         768000 kB\n";
+        let memory = parse_meminfo(meminfo, SizeUnits::Binary).unwrap();
+        assert_eq!(memory.get_i64("total_mib").unwrap(), 2000);
+        assert_eq!(memory.get_i64("available_mib").unwrap(), 1000);
+        assert_eq!(memory.get_f64("used_percent").unwrap(), 50.0);
+        assert_eq!(memory.get_f64("swap_used_percent").unwrap(), 25.0);
+    }
+
+    #[test]
+    fn test_parse_disks_keeps_real_filesystems() {
+        let output = "Filesystem     1024-blocks    Used Available Capacity Mounted on\n\
+                      /dev/sda1         20971520 19922944   1048576      95% /\n\
+                      tmpfs               102400        0    102400       0% /run\n\
+                      nas:/export      104857600 1048576 103809024       1% /mnt/my share\n\
+                      --mounts--\n\
+                      /dev/sda1 / ext4 rw,relatime 0 0\n\
+                      nas:/export /mnt/my\\040share nfs4 rw 0 0\n";
+        let doc = parse_disks(output, &DiskSettings::default(), SizeUnits::Binary);
+        let disks: Vec<&Document> = doc.get_array("disks").unwrap().iter().filter_map(|d| d.as_document()).collect();
+        assert_eq!(disks.len(), 2);
+        assert_eq!(disks[0].get_str("mount_point").unwrap(), "/");
+        assert_eq!(disks[0].get_str("filesystem").unwrap(), "ext4");
+        assert_eq!(disks[0].get_f64("total_gib").unwrap(), 20.0);
+        assert!(disks[0].get_bool("low_space").unwrap());
+        assert_eq!(disks[1].get_str("mount_point").unwrap(), "/mnt/my share");
+        assert_eq!(disks[1].get_str("filesystem").unwrap(), "nfs4");
+        assert!(!disks[1].get_bool("low_space").unwrap());
+    }
+}
@@ -0,0 +1,285 @@
+// Disk SMART health collector
+//
+// Capacity and I/O counters look normal on a drive that is about to fail;
+// its SMART self-assessment and reallocated sector count don't. This asks
+// `smartctl` (smartmontools) for each configured device, or every device
+// `smartctl --scan` finds, at `smart.interval_secs`. smartctl needs root,
+// so the collector is only registered with `smart.enabled`.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::SmartSettings;
+
+const SMARTCTL: &str = "smartctl";
+
+/// smartctl exit status bits meaning the device couldn't be read at all:
+/// command line not parsed (bit 0), device open or identify failed (bit 1)
+const UNREADABLE_BITS: i32 = 0b11;
+
+/// ATA attribute id of `Reallocated_Sector_Ct`
+const ATA_REALLOCATED_SECTORS: i64 = 5;
+
+/// Disk SMART health collector
+///
+/// # What is Collected
+/// Per device, from `smartctl --json -i -H -A`:
+/// - `health`: overall self-assessment, "PASSED" or "FAILED"
+/// - `reallocated_sectors`: ATA attribute 5 raw value, or the SCSI grown
+///   defect list length (absent for NVMe, which has no equivalent)
+/// - `reallocated_increase`: growth since the previous reading of the device
+///   (absent on the first reading)
+/// - `temperature_c`, `power_on_hours`, `model`, `serial`
+/// - `error` instead when smartctl couldn't read the device or timed out
+///
+/// plus `failed_count`, the number of devices whose health is "FAILED".
+///
+/// # Platform Support
+/// - Linux: registered only with `smart.enabled`; needs root and smartmontools
+/// - Other platforms: not registered
+pub struct SmartCollector {
+    settings: SmartSettings,
+    /// Last reallocated sector count per device, for `reallocated_increase`
+    last_reallocated: Mutex<HashMap<String, i64>>,
+    clock: SharedClock,
+}
+
+impl SmartCollector {
+    /// Creates a collector for the devices in `settings`
+    pub fn with_settings(settings: SmartSettings) -> Self {
+        SmartCollector {
+            settings,
+            last_reallocated: Mutex::new(HashMap::new()),
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock used for document timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Configured devices, else the (name, type) pairs `smartctl --scan` reports
+    async fn devices(&self) -> Result<Vec<(String, Option<String>)>, Box<dyn Error + Send + Sync>> {
+        if !self.settings.devices.is_empty() {
+            return Ok(self.settings.devices.iter().map(|d| (d.clone(), None)).collect());
+        }
+        let output = smartctl(&["--scan", "--json"], self.timeout())
+            .await?
+            .ok_or("smartctl --scan timed out")?;
+        Ok(parse_scan(&serde_json::from_slice(&output.stdout)?))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.command_timeout_secs.max(1))
+    }
+
+    /// Reads one device, returning its document
+    async fn read_device(&self, device: &str, device_type: Option<&str>) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        let mut args = vec!["--json", "-i", "-H", "-A"];
+        if let Some(device_type) = device_type {
+            args.extend(["-d", device_type]);
+        }
+        args.push(device);
+
+        let Some(output) = smartctl(&args, self.timeout()).await? else {
+            return Ok(doc! { "device": device, "error": "smartctl timed out" });
+        };
+        let exit_code = output.status.code().unwrap_or(-1);
+        let report = match serde_json::from_slice::<Value>(&output.stdout) {
+            Ok(report) => report,
+            Err(e) => {
+                return Ok(doc! { "device": device, "error": format!("unreadable smartctl output (exit {}): {}", exit_code, e) })
+            }
+        };
+        let mut reading = parse_device(device, &report, exit_code);
+
+        if let Ok(reallocated) = reading.get_i64("reallocated_sectors") {
+            let mut last = self.last_reallocated.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(previous) = last.insert(device.to_string(), reallocated) {
+                let increase = reallocated - previous;
+                if increase > 0 {
+                    warn!("{}: reallocated sectors rose by {} to {}", device, increase, reallocated);
+                }
+                reading.insert("reallocated_increase", increase);
+            }
+        }
+        if reading.get_str("health") == Ok("FAILED") {
+            warn!("{}: SMART overall health self-assessment FAILED", device);
+        }
+        Ok(reading)
+    }
+}
+
+/// Runs smartctl with `args`; None if it didn't finish within `timeout`
+async fn smartctl(args: &[&str], timeout: Duration) -> std::io::Result<Option<Output>> {
+    let output = Command::new(SMARTCTL).args(args).kill_on_drop(true).output();
+    match tokio::time::timeout(timeout, output).await {
+        Ok(output) => output.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// (name, type) of every device in `smartctl --scan --json` output
+fn parse_scan(scan: &Value) -> Vec<(String, Option<String>)> {
+    scan["devices"]
+        .as_array()
+        .map(|devices| {
+            devices
+                .iter()
+                .filter_map(|d| {
+                    let name = d["name"].as_str()?.to_string();
+                    Some((name, d["type"].as_str().map(str::to_string)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds one device's document from `smartctl --json -i -H -A` output
+///
+/// Fields smartctl didn't report are left out rather than stored as null.
+fn parse_device(device: &str, report: &Value, exit_code: i32) -> Document {
+    let mut reading = doc! { "device": device };
+    if exit_code & UNREADABLE_BITS != 0 {
+        let message = report["smartctl"]["messages"][0]["string"]
+            .as_str()
+            .unwrap_or("device could not be read");
+        reading.insert("error", format!("smartctl exit {}: {}", exit_code, message));
+        return reading;
+    }
+
+    if let Some(model) = report["model_name"].as_str() {
+        reading.insert("model", model);
+    }
+    if let Some(serial) = report["serial_number"].as_str() {
+        reading.insert("serial", serial);
+    }
+    if let Some(passed) = report["smart_status"]["passed"].as_bool() {
+        reading.insert("health", if passed { "PASSED" } else { "FAILED" });
+    }
+
+    let ata_reallocated = report["ata_smart_attributes"]["table"].as_array().and_then(|table| {
+        table
+            .iter()
+            .find(|attribute| attribute["id"].as_i64() == Some(ATA_REALLOCATED_SECTORS))
+            .and_then(|attribute| attribute["raw"]["value"].as_i64())
+    });
+    if let Some(reallocated) = ata_reallocated.or_else(|| report["scsi_grown_defect_list"].as_i64()) {
+        reading.insert("reallocated_sectors", reallocated);
+    }
+    if let Some(temperature) = report["temperature"]["current"].as_i64() {
+        reading.insert("temperature_c", temperature);
+    }
+    if let Some(hours) = report["power_on_time"]["hours"].as_i64() {
+        reading.insert("power_on_hours", hours);
+    }
+    reading
+}
+
+#[async_trait]
+impl MetricCollector for SmartCollector {
+    fn name(&self) -> &str {
+        "DiskSmart"
+    }
+
+    /// Reads every device in turn
+    ///
+    /// # Returns BSON Document Structure
+    /// ```json
+    /// {
+    ///   "node": "0001-0001",
+    ///   "timestamp": "...",
+    ///   "failed_count": 0,
+    ///   "devices": [
+    ///     { "device": "/dev/sda", "model": "Samsung SSD 870 EVO 1TB", "serial": "S6P...",
+    ///       "health": "PASSED", "reallocated_sectors": 0, "reallocated_increase": 0,
+    ///       "temperature_c": 31, "power_on_hours": 14021 }
+    ///   ]
+    /// }
+    /// ```
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        debug!("Collecting SMART health");
+
+        let mut devices = Vec::new();
+        for (device, device_type) in self.devices().await? {
+            devices.push(self.read_device(&device, device_type.as_deref()).await?);
+        }
+        let failed_count = devices.iter().filter(|d| d.get_str("health") == Ok("FAILED")).count() as i64;
+        debug!("Read SMART health of {} device(s), {} failed", devices.len(), failed_count);
+
+        Ok(doc! {
+            "node": node_id,
+            "timestamp": self.clock.now(),
+            "failed_count": failed_count,
+            "devices": devices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_ata_and_nvme_reports() {
+        let ata = json!({
+            "smartctl": { "exit_status": 8 },
+            "model_name": "WDC WD40EFRX", "serial_number": "WD-1234",
+            "smart_status": { "passed": false },
+            "ata_smart_attributes": { "table": [
+                { "id": 1, "name": "Raw_Read_Error_Rate", "raw": { "value": 12 } },
+                { "id": 5, "name": "Reallocated_Sector_Ct", "raw": { "value": 48 } },
+            ] },
+            "temperature": { "current": 41 },
+            "power_on_time": { "hours": 30211 },
+        });
+        assert_eq!(
+            parse_device("/dev/sda", &ata, 8),
+            doc! {
+                "device": "/dev/sda", "model": "WDC WD40EFRX", "serial": "WD-1234", "health": "FAILED",
+                "reallocated_sectors": 48_i64, "temperature_c": 41_i64, "power_on_hours": 30211_i64,
+            }
+        );
+
+        let nvme = json!({
+            "smart_status": { "passed": true },
+            "nvme_smart_health_information_log": { "media_errors": 0 },
+            "temperature": { "current": 35 },
+        });
+        assert_eq!(
+            parse_device("/dev/nvme0", &nvme, 0),
+            doc! { "device": "/dev/nvme0", "health": "PASSED", "temperature_c": 35_i64 }
+        );
+
+        let missing = json!({ "smartctl": { "messages": [{ "string": "Smartctl open device: /dev/sdz failed" }] } });
+        assert_eq!(
+            parse_device("/dev/sdz", &missing, 2),
+            doc! { "device": "/dev/sdz", "error": "smartctl exit 2: Smartctl open device: /dev/sdz failed" }
+        );
+    }
+
+    #[test]
+    fn test_parse_scan() {
+        let scan = json!({ "devices": [
+            { "name": "/dev/sda", "type": "sat", "protocol": "ATA" },
+            { "name": "/dev/nvme0", "type": "nvme" },
+        ] });
+        assert_eq!(
+            parse_scan(&scan),
+            [("/dev/sda".to_string(), Some("sat".to_string())), ("/dev/nvme0".to_string(), Some("nvme".to_string()))]
+        );
+    }
+}
@@ -13,6 +13,7 @@ use tokio::sync::Mutex;
 use tracing::{debug, warn};
 
 use super::MetricCollector;
+use crate::clock::{system_clock, SharedClock};
 
 /// System journal event collector
 ///
@@ -23,14 +24,23 @@ use super::MetricCollector;
 pub struct SystemEventsCollector {
     /// Tracks the end time of the previous poll window
     last_poll: Mutex<Option<DateTime<Utc>>>,
+    /// Source of poll windows and document timestamps
+    clock: SharedClock,
 }
 
 impl SystemEventsCollector {
     pub fn new() -> Self {
         SystemEventsCollector {
             last_poll: Mutex::new(None),
+            clock: system_clock(),
         }
     }
+
+    /// Replaces the clock used for poll windows and timestamps (tests use a `FakeClock`)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 #[async_trait]
@@ -42,7 +52,7 @@ impl MetricCollector for SystemEventsCollector {
     async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
         debug!("Collecting system events");
 
-        let now = Utc::now();
+        let now = self.clock.now();
         let mut last_poll = self.last_poll.lock().await;
         // On first run, look back 60 seconds
         let since = last_poll.unwrap_or_else(|| now - chrono::Duration::seconds(60));
@@ -81,7 +91,7 @@ impl MetricCollector for SystemEventsCollector {
 
         let doc = doc! {
             "node": node_id,
-            "timestamp": Utc::now(),
+            "timestamp": self.clock.now(),
             "events": events,
         };
 
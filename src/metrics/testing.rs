@@ -0,0 +1,77 @@
+// Test collectors - deterministic stand-ins for scheduler tests
+//
+// Scheduler tests pass these to `MetricScheduler` / the task loops in place
+// of the real collectors from `create_all_collectors`, so no test touches
+// sysinfo, /proc or Docker. Pair them with `storage::memory::MemoryBackend`
+// and `FakeClock`.
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use std::error::Error;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::MetricCollector;
+use crate::clock::SharedClock;
+
+/// Returns the same fields on every call, stamped with `node` and the clock's time
+pub struct ConstantCollector {
+    name: String,
+    fields: Document,
+    clock: SharedClock,
+}
+
+impl ConstantCollector {
+    pub fn new(name: &str, fields: Document, clock: SharedClock) -> Self {
+        ConstantCollector {
+            name: name.to_string(),
+            fields,
+            clock,
+        }
+    }
+}
+
+#[async_trait]
+impl MetricCollector for ConstantCollector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        let mut doc = doc! { "node": node_id, "timestamp": self.clock.now() };
+        doc.extend(self.fields.clone());
+        Ok(doc)
+    }
+}
+
+/// Fails the first `failures` calls, then behaves like `ConstantCollector`
+pub struct FailingCollector {
+    inner: ConstantCollector,
+    remaining_failures: AtomicU32,
+}
+
+impl FailingCollector {
+    pub fn new(name: &str, failures: u32, fields: Document, clock: SharedClock) -> Self {
+        FailingCollector {
+            inner: ConstantCollector::new(name, fields, clock),
+            remaining_failures: AtomicU32::new(failures),
+        }
+    }
+}
+
+#[async_trait]
+impl MetricCollector for FailingCollector {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn collect(&self, node_id: &str) -> Result<Document, Box<dyn Error + Send + Sync>> {
+        let failed = self
+            .remaining_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if failed {
+            return Err("injected collection failure".into());
+        }
+        self.inner.collect(node_id).await
+    }
+}
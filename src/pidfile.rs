@@ -0,0 +1,61 @@
+// PID file module - traditional init integration (SysV, supervisord)
+//
+// Writes the process ID at startup and removes the file when the handle is
+// dropped on clean shutdown. A file left behind by a crashed run is replaced;
+// a file pointing at a live process aborts startup to prevent double-launch.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Owned PID file, removed from disk on drop
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the current PID to `path`
+    ///
+    /// Fails if the file already exists and names a process that is still running.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            match contents.trim().parse::<u32>() {
+                Ok(pid) if pid != std::process::id() && process_alive(pid) => bail!(
+                    "PID file {} belongs to running process {}; is another metrics-collector already running?",
+                    path.display(),
+                    pid
+                ),
+                _ => warn!("Replacing stale PID file {}", path.display()),
+            }
+        }
+
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write PID file {}", path.display()))?;
+        info!("Wrote PID {} to {}", std::process::id(), path.display());
+
+        Ok(PidFile { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Returns true if a process with `pid` exists
+///
+/// Uses procfs on Linux; elsewhere any recorded PID is treated as live so the
+/// operator has to remove the file deliberately.
+fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    } else {
+        true
+    }
+}
@@ -0,0 +1,179 @@
+// Replica set module - periodic health report for the MongoDB replica set
+//
+// The collector already holds a MongoDB client, so it can report on the
+// database it writes to: every interval, `replSetGetStatus` is summarized
+// into member states and the largest secondary replication lag, logged and
+// stored in `replica_set_status`.
+//
+// The command needs the `clusterMonitor` role (or `replSetGetStatus` on the
+// cluster). Without it, or when not connected to a replica set, each poll
+// logs and skips; collection is never affected.
+
+use bson::{doc, Bson, Document};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::clock::SharedClock;
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::{MetricStorage, ReplicaSetStatusError};
+
+/// Collection receiving each summary
+pub const REPLICA_SET_COLLECTION: &str = "replica_set_status";
+
+/// Reduces a `replSetGetStatus` reply to the fields worth storing
+///
+/// Lag is the primary's last applied optime minus the member's, in seconds;
+/// `max_lag_secs` covers secondaries only and is absent without a primary
+/// or secondary to compare.
+fn summarize(status: &Document) -> Document {
+    let members = status.get_array("members").map(Vec::as_slice).unwrap_or_default();
+    let member_docs: Vec<&Document> = members.iter().filter_map(Bson::as_document).collect();
+
+    let optime = |member: &Document| member.get_datetime("optimeDate").ok().map(|d| d.timestamp_millis());
+    let primary = member_docs.iter().find(|m| m.get_str("stateStr") == Ok("PRIMARY"));
+    let primary_optime = primary.and_then(|p| optime(p));
+
+    let mut max_lag_secs: Option<f64> = None;
+    let summaries: Vec<Document> = member_docs
+        .iter()
+        .map(|member| {
+            let state = member.get_str("stateStr").unwrap_or("UNKNOWN");
+            // `health` is 1 or 0, as a double or an integer depending on the server
+            let healthy = match member.get("health") {
+                Some(Bson::Double(h)) => *h == 1.0,
+                Some(Bson::Int32(h)) => *h == 1,
+                Some(Bson::Int64(h)) => *h == 1,
+                _ => false,
+            };
+            let mut summary = doc! {
+                "name": member.get_str("name").unwrap_or_default(),
+                "state": state,
+                "healthy": healthy,
+            };
+            if state == "SECONDARY" {
+                if let (Some(primary), Some(own)) = (primary_optime, optime(member)) {
+                    let lag = (primary - own).max(0) as f64 / 1000.0;
+                    summary.insert("lag_secs", lag);
+                    max_lag_secs = Some(max_lag_secs.map_or(lag, |m| m.max(lag)));
+                }
+            }
+            summary
+        })
+        .collect();
+
+    let mut summary = doc! {
+        "set": status.get_str("set").unwrap_or_default(),
+        "primary": primary.and_then(|p| p.get_str("name").ok()),
+        "members": summaries,
+    };
+    if let Some(max_lag_secs) = max_lag_secs {
+        summary.insert("max_lag_secs", max_lag_secs);
+    }
+    summary
+}
+
+/// Polls `replSetGetStatus` until shutdown, storing one summary per interval
+///
+/// The first reading is taken right away, then once per `interval`.
+pub async fn run_replica_set_task(
+    storage: Arc<MetricStorage>,
+    interval: Duration,
+    clock: SharedClock,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!(
+        "Starting replica set status task every {}s → '{}'",
+        interval.as_secs(),
+        REPLICA_SET_COLLECTION
+    );
+
+    let mut timer = tokio::time::interval(interval);
+    // Warn the first time a poll is skipped, then only at debug level
+    let mut warned = false;
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        let status = match storage.replica_set_status().await {
+            Ok(status) => status,
+            Err(e) => {
+                let hint = match e {
+                    ReplicaSetStatusError::Unauthorized => " (grant the clusterMonitor role)",
+                    _ => "",
+                };
+                if warned {
+                    debug!("Skipping replica set status: {}{}", e, hint);
+                } else {
+                    warn!("Skipping replica set status: {}{}", e, hint);
+                    warned = true;
+                }
+                continue;
+            }
+        };
+        warned = false;
+
+        let mut summary = summarize(&status);
+        info!(
+            set = summary.get_str("set").unwrap_or_default(),
+            max_lag_secs = summary.get_f64("max_lag_secs").ok(),
+            "Replica set '{}': primary {}, max secondary lag {}",
+            summary.get_str("set").unwrap_or_default(),
+            summary.get_str("primary").unwrap_or("none"),
+            summary
+                .get_f64("max_lag_secs")
+                .map(|lag| format!("{:.1}s", lag))
+                .unwrap_or_else(|_| "n/a".to_string()),
+        );
+
+        summary.insert("timestamp", clock.now());
+        storage
+            .store_metric_safe(REPLICA_SET_COLLECTION, "ReplicaSetStatus", summary)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::DateTime;
+
+    #[test]
+    fn test_summarize_replica_set_status() {
+        let at = |millis: i64| DateTime::from_millis(1_700_000_000_000 + millis);
+        let status = doc! {
+            "set": "rs0",
+            "members": [
+                { "name": "db1:27017", "health": 1.0, "stateStr": "PRIMARY", "optimeDate": at(10_000) },
+                { "name": "db2:27017", "health": 1.0, "stateStr": "SECONDARY", "optimeDate": at(9_500) },
+                { "name": "db3:27017", "health": 1.0, "stateStr": "SECONDARY", "optimeDate": at(2_000) },
+                { "name": "db4:27017", "health": 0.0, "stateStr": "(not reachable/healthy)" },
+            ],
+        };
+
+        let summary = summarize(&status);
+        assert_eq!(summary.get_str("primary").unwrap(), "db1:27017");
+        assert_eq!(summary.get_f64("max_lag_secs").unwrap(), 8.0);
+
+        let members = summary.get_array("members").unwrap();
+        assert_eq!(
+            members[1].as_document().unwrap(),
+            &doc! { "name": "db2:27017", "state": "SECONDARY", "healthy": true, "lag_secs": 0.5 }
+        );
+        assert_eq!(
+            members[3].as_document().unwrap(),
+            &doc! { "name": "db4:27017", "state": "(not reachable/healthy)", "healthy": false }
+        );
+
+        // No primary: nothing to measure lag against
+        let no_primary = doc! { "set": "rs0", "members": [
+            { "name": "db2:27017", "health": 1, "stateStr": "SECONDARY", "optimeDate": at(0) },
+        ] };
+        let summary = summarize(&no_primary);
+        assert_eq!(summary.get("primary"), Some(&Bson::Null));
+        assert!(summary.get("max_lag_secs").is_none());
+    }
+}
@@ -0,0 +1,126 @@
+// Retention module - keeps the newest N documents per node in chosen collections
+//
+// A TTL index expires by age, so the footprint of a collection follows its
+// write frequency; a capped collection bounds the total but not per node.
+// With `retention.keep_last`, a background task periodically deletes this
+// node's documents beyond the newest N of each listed collection.
+//
+// Each pass deletes at most `max_deletes` documents per collection and node, so
+// a large backlog (e.g. right after N is lowered) is worked off over several
+// passes instead of in one burst. Every node prunes only its own documents:
+// those under its key and under any `metrics.<Name>.node_id` override.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::config::{MonitoringSettings, RetentionSettings};
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// Node ids whose documents this process prunes: the settings key, then each
+/// metric's `node_id` override, sorted and without repeats
+pub fn retention_node_ids(settings: &MonitoringSettings) -> Vec<String> {
+    let mut overrides: Vec<String> = settings
+        .metrics
+        .keys()
+        .map(|metric_name| settings.metric_node_id(metric_name))
+        .filter(|node_id| *node_id != settings.key)
+        .collect();
+    overrides.sort();
+    overrides.dedup();
+
+    let mut node_ids = vec![settings.key.clone()];
+    node_ids.extend(overrides);
+    node_ids
+}
+
+/// Prunes every configured collection for each of `node_ids` once per
+/// interval until shutdown
+///
+/// The first pass runs right away. Collections with `keep_last` 0 are skipped:
+/// deleting everything is what dropping the collection is for.
+pub async fn run_retention_task(
+    storage: Arc<MetricStorage>,
+    node_ids: Vec<String>,
+    settings: RetentionSettings,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut targets: Vec<(String, u64)> = Vec::new();
+    for (collection, keep) in &settings.keep_last {
+        if *keep == 0 {
+            warn!("Ignoring retention.keep_last of 0 for '{}'", collection);
+        } else {
+            targets.push((collection.clone(), *keep));
+        }
+    }
+    targets.sort();
+    if targets.is_empty() {
+        return;
+    }
+
+    info!(
+        "Starting retention task every {}s: {}",
+        settings.interval_secs,
+        targets
+            .iter()
+            .map(|(collection, keep)| format!("'{}' keeps {}", collection, keep))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut timer = tokio::time::interval(Duration::from_secs(settings.interval_secs.max(1)));
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        for (collection, keep) in &targets {
+            for node_id in &node_ids {
+                match storage.prune_excess(collection, node_id, *keep, settings.max_deletes.max(1)).await {
+                    Ok(0) => debug!("'{}' ({}): nothing beyond the newest {} to prune", collection, node_id, keep),
+                    Ok(deleted) => info!(
+                        "'{}' ({}): pruned {} document(s) beyond the newest {}",
+                        collection, node_id, deleted, keep
+                    ),
+                    Err(e) => warn!("Failed to prune '{}' ({}): {}", collection, node_id, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(extra: serde_json::Value) -> MonitoringSettings {
+        let mut value = serde_json::json!({
+            "key": "host-a", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+        });
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_retention_node_ids_include_metric_overrides() {
+        let settings = settings(serde_json::json!({
+            "metrics": {
+                "Memory":    { "node_id": "vm-1" },
+                "CPU":       { "node_id": "vm-1" },
+                "Disk":      { "node_id": "host-a" },
+                "Network":   { "node_id": "switch-0" },
+                "DiskSpace": { "sample_rate": 0.5 },
+            },
+        }));
+
+        assert_eq!(retention_node_ids(&settings), ["host-a", "switch-0", "vm-1"]);
+    }
+
+    #[test]
+    fn test_retention_node_ids_default_to_the_key() {
+        assert_eq!(retention_node_ids(&settings(serde_json::json!({}))), ["host-a"]);
+    }
+}
@@ -0,0 +1,117 @@
+// Rollup module - periodically summarizes aggregated metrics into long-retention collections
+//
+// Raw windows (one document per store_timeout) are expensive to keep forever.
+// The rollup task wakes up once per rollup period, summarizes the period that
+// just ended for every aggregated collection, and writes one document per
+// collection into `<collection><suffix>` (e.g. `memory_metrics_hourly`).
+
+use std::sync::Arc;
+use std::time::Duration;
+use bson::doc;
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::aggregator::PASSTHROUGH_FIELDS;
+use crate::clock::SharedClock;
+use crate::config::RollupSettings;
+use crate::scheduler::wait_for_shutdown;
+use crate::storage::MetricStorage;
+
+/// Runs the rollup loop until shutdown is signalled.
+///
+/// Each cycle sleeps until the next period boundary plus `grace_secs` (so the
+/// last flush of the period has landed), then summarizes
+/// `[boundary - period, boundary)` for every collection in `collections`.
+pub async fn run_rollup_task(
+    storage: Arc<MetricStorage>,
+    node_id: String,
+    collections: Vec<String>,
+    settings: RollupSettings,
+    grace_secs: u64,
+    clock: SharedClock,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let period = settings.period_secs.max(60) as i64;
+
+    info!(
+        "Starting rollup task: {} collection(s) every {}s → '<collection>{}'",
+        collections.len(),
+        period,
+        settings.suffix
+    );
+
+    loop {
+        let now = clock.now().timestamp();
+        let boundary = (now / period + 1) * period;
+        let wake_at = boundary + grace_secs as i64;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs((wake_at - now).max(0) as u64)) => {}
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+
+        let (Some(end), Some(start)) = (
+            Utc.timestamp_opt(boundary, 0).single(),
+            Utc.timestamp_opt(boundary - period, 0).single(),
+        ) else {
+            continue;
+        };
+
+        for collection in &collections {
+            let target = format!("{}{}", collection, settings.suffix);
+            rollup_collection(&storage, &node_id, collection, &target, start, end).await;
+        }
+    }
+}
+
+/// Summarizes one collection's window and stores the result in `target`.
+async fn rollup_collection(
+    storage: &MetricStorage,
+    node_id: &str,
+    collection: &str,
+    target: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) {
+    let (document_count, fields) = match storage
+        .summarize_window(collection, node_id, start, end)
+        .await
+    {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            debug!("No documents in '{}' for rollup window starting {}", collection, start);
+            return;
+        }
+        Err(e) => {
+            warn!("Rollup of '{}' failed: {}", collection, e);
+            return;
+        }
+    };
+
+    if fields.is_empty() {
+        debug!("'{}' has no top-level numeric fields to roll up", collection);
+        return;
+    }
+
+    let mut rollup = doc! {
+        "node": node_id,
+        "timestamp": start,
+        "period_start": start,
+        "period_end": end,
+        "document_count": document_count as i64,
+    };
+
+    for (field, value) in fields {
+        // Constant fields keep their plain form, matching the source documents
+        if PASSTHROUGH_FIELDS.contains(&field.as_str()) {
+            if let Some(max) = value.as_document().and_then(|d| d.get("max")) {
+                rollup.insert(field, max.clone());
+            }
+        } else {
+            rollup.insert(field, value);
+        }
+    }
+
+    storage.store_metric_safe(target, "Rollup", rollup).await;
+    debug!("Rolled up {} document(s) from '{}' into '{}'", document_count, collection, target);
+}
@@ -6,169 +6,1273 @@
 //
 // After each successful flush, settings are reloaded from MongoDB so that
 // timeout changes take effect on the next window.
+//
+// When the shutdown channel flips to true, aggregated tasks flush what they
+// have buffered, every task returns, and `start` completes.
+//
+// Each task also measures the time between its collect ticks and warns when
+// it drifts from the configured interval (scheduler starvation).
+//
+// Before storage, documents run through the configured transform pipeline
+// (tags, rounding, projection, then secret scrubbing; see transform.rs).
+//
+// During the first `startup_grace_secs` nothing is stored: documents are
+// collected (and windows flushed) as usual, then logged and dropped.
+//
+// With `store_on_change_only`, a document equal to the last stored one
+// (ignoring `timestamp`, `monotonic_ns` and `timestamp_us`/`timestamp_ns`)
+// is skipped until `max_unchanged_secs` have passed.
+//
+// Every stored document carries a per-node, per-metric `seq`. A gap in `seq`
+// means a document was lost (failed store, crash between collect and store).
+// With `mark_gaps`, downtime is recorded too: a task starting long after the
+// node's last stored document first stores a `status: "gap"` placeholder
+// covering the silence, so an outage is explicit in the series.
+//
+// With `config_history.enabled`, the effective settings are snapshotted at
+// startup and after each reload that changed them (see config_history.rs).
+//
+// A metric paused through the admin server (`POST /pause/{metric}`) skips
+// collection, and so storage, on every tick until it is resumed.
 
-use std::sync::Arc;
+use bson::{doc, Document};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
-use tokio::time::interval;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tokio::time::{interval, interval_at, Instant, Interval};
 use tokio::select;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::aggregator::{DockerMetricBuffer, MetricBuffer};
-use crate::config::{ConfigManager, MonitoringSettings};
+use crate::adaptive::{AdaptiveInterval, LastValues, LOAD_METRIC};
+use crate::anomaly::AnomalyDetector;
+use crate::build_info::{self, run_agent_info_task};
+use crate::clock::{SharedClock, TimestampPrecision};
+use crate::config::{
+    render_collection_template, ConfigManager, DockerStorage, DriftSettings, IntegrityMode, MetricSettings,
+    MonitoringSettings,
+};
+use crate::config_history::ConfigHistory;
+use crate::delta::DeltaEncoder;
+use crate::events::{
+    EventRecorder, EVENT_CONFIG_RELOAD, EVENT_INTERVAL_DRIFT, EVENT_START, EVENT_STORAGE_RECOVERED,
+    EVENT_TASK_PANIC,
+};
 use crate::metrics::MetricCollector;
-use crate::storage::MetricStorage;
+use crate::heartbeat::run_heartbeat_task;
+use crate::retention::{retention_node_ids, run_retention_task};
+use crate::rollup::run_rollup_task;
+use crate::schema::{apply_validator, publish_schema};
+use crate::storage::{assign_deterministic_id, sanitize_non_finite, MetricStorage};
+use crate::integrity;
+use crate::kubernetes;
+use crate::transform::{unwind, Pipeline, ScrubTransform};
 
 /// Maps a metric name to its hardcoded MongoDB collection name.
-fn collection_for(metric_name: &str) -> &'static str {
+pub fn collection_for(metric_name: &str) -> &'static str {
     match metric_name {
         "LoadAverage"        => "load_average_metrics",
         "Memory"             => "memory_metrics",
         "DiskSpace"          => "disk_metrics",
+        "Network"            => "network_metrics",
+        "Entropy"            => "entropy_metrics",
+        "Pressure"           => "pressure_metrics",
         "DockerStats"        => "docker_metrics",
         "ProcessCPUSnapshot" => "process_cpu_logs",
         "ProcessRAMSnapshot" => "process_ram_logs",
         "DockerEvents"       => "docker_event_logs",
         "DockerLogs"         => "docker_container_logs",
         "SystemEvents"       => "system_event_logs",
+        "KernelLog"          => "kernel_logs",
+        "DiskSmart"          => "disk_smart_logs",
+        "DockerDisk"         => "docker_disk_logs",
+        "Canary"             => "canary",
         _                    => "unknown_metrics",
     }
 }
 
+/// Collection a metric's documents are stored in: its
+/// `metrics.<Name>.collection`, else `collection_template` rendered for it,
+/// else the built-in name from `collection_for`
+pub fn collection_name(metric_name: &str, settings: &MonitoringSettings) -> String {
+    if let Some(collection) = settings.metric(metric_name).collection {
+        return collection;
+    }
+    match &settings.collection_template {
+        Some(template) => render_collection_template(template, metric_name),
+        None => collection_for(metric_name).to_string(),
+    }
+}
+
 /// Metrics that are unaggregatable log/event snapshots — no numeric fields to
 /// average, so each collected document is written as-is instead of being
 /// buffered and flushed once per `store_timeout` window.
-fn is_log_metric(metric_name: &str) -> bool {
+pub fn is_log_metric(metric_name: &str) -> bool {
     matches!(
         metric_name,
         "ProcessCPUSnapshot" | "ProcessRAMSnapshot" | "DockerEvents" | "DockerLogs" | "SystemEvents"
+            | "KernelLog" | "DiskSmart" | "DockerDisk" | "Canary"
     )
 }
 
+/// The array holding a metric's entries, checked by `store_when_empty`;
+/// None for metrics without one
+pub fn array_field_for(metric_name: &str) -> Option<&'static str> {
+    match metric_name {
+        "DiskSpace" => Some("disks"),
+        "Network" => Some("interfaces"),
+        "DockerStats" | "DockerLogs" => Some("containers"),
+        "ProcessCPUSnapshot" | "ProcessRAMSnapshot" => Some("processes"),
+        "DockerEvents" | "SystemEvents" => Some("events"),
+        "DiskSmart" => Some("devices"),
+        _ => None,
+    }
+}
+
+/// True (and logs) if `doc`'s main array is empty and
+/// `metrics.<Name>.store_when_empty` is off
+fn skip_empty(metric_name: &str, doc: &Document, metric_settings: &MetricSettings) -> bool {
+    if metric_settings.store_when_empty {
+        return false;
+    }
+    let Some(field) = array_field_for(metric_name) else {
+        return false;
+    };
+    let empty = doc.get_array(field).is_ok_and(|entries| entries.is_empty());
+    if empty {
+        debug!("'{}' has no {}, skipping store", metric_name, field);
+    }
+    empty
+}
+
 /// Returns the collection interval (seconds) that applies to a given metric.
 /// Anything that talks to the Docker daemon (stats, events, container logs)
 /// shares `collect_docker_timeout` so they don't hit it at different rates;
+/// DiskSmart and DockerDisk have their own, much longer `smart.interval_secs`
+/// and `docker_disk.interval_secs`, and Canary its `canary.interval_secs`;
 /// everything else uses the general `collect_timeout`.
-fn collect_timeout_for(metric_name: &str, settings: &MonitoringSettings) -> u64 {
+pub fn collect_timeout_for(metric_name: &str, settings: &MonitoringSettings) -> u64 {
     match metric_name {
         "DockerStats" | "DockerEvents" | "DockerLogs" => settings.collect_docker_timeout,
+        "DiskSmart" => settings.smart.interval_secs.max(1),
+        "DockerDisk" => settings.docker_disk.interval_secs.max(1),
+        "Canary" => settings.canary.interval_secs.max(1),
         _ => settings.collect_timeout,
     }
 }
 
+/// Resolved per-metric schedule and storage options, for the startup banner
+pub fn effective_metrics(
+    collectors: &[Box<dyn MetricCollector>],
+    settings: &MonitoringSettings,
+) -> serde_json::Value {
+    let metrics: serde_json::Map<String, serde_json::Value> = collectors
+        .iter()
+        .map(|collector| {
+            let metric_name = collector.name();
+            let metric = settings.metric(metric_name);
+            let collect_secs = collect_timeout_for(metric_name, settings);
+            let aggregated = !is_log_metric(metric_name);
+            // Log metrics store every collected document
+            let store_secs = if aggregated { settings.store_timeout } else { collect_secs };
+            let collection = if metric_name == "DockerStats" {
+                docker_collection(metric_name, settings)
+            } else {
+                collection_name(metric_name, settings)
+            };
+            let entry = serde_json::json!({
+                "collection": collection,
+                "collect_secs": collect_secs,
+                "store_secs": store_secs,
+                "aggregated": aggregated,
+                "sample_rate": metric.sample_rate,
+                "id_bucket_secs": metric.id_bucket_secs,
+                "store_on_change_only": metric.store_on_change_only,
+                "max_unchanged_secs": metric.max_unchanged_secs,
+                "integrity": format!("{:?}", metric.integrity).to_lowercase(),
+                "unwind": metric.unwind,
+                "mark_gaps": metric.mark_gaps,
+                "validate": metric.validate,
+                "node_id": settings.metric_node_id(metric_name),
+                "monotonic": metric.monotonic,
+                "anomaly_fields": metric.anomaly_fields,
+                "anomaly_zscore": metric.anomaly_zscore,
+                "store_when_empty": metric.store_when_empty,
+                "collect_immediately": metric.collect_immediately,
+                "required": metric.required,
+            });
+            (metric_name.to_string(), entry)
+        })
+        .collect();
+    serde_json::Value::Object(metrics)
+}
+
+/// Longest a required metric's trial collection may take at startup
+const REQUIRED_TRIAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Collects every `metrics.<Name>.required` metric of a node once, without
+/// storing anything, and returns why each one that can't run failed
+///
+/// A required metric without a collector (disabled, its opt-in section off,
+/// unsupported on this platform, or failed to open) fails as well. The trials
+/// run concurrently, each bounded by `REQUIRED_TRIAL_TIMEOUT`.
+pub async fn trial_required_metrics(collectors: &[Box<dyn MetricCollector>], settings: &MonitoringSettings) -> Vec<String> {
+    let mut required: Vec<&str> = settings
+        .metrics
+        .iter()
+        .filter(|(_, metric)| metric.required)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    required.sort_unstable();
+
+    let trials = required.into_iter().map(|metric_name| async move {
+        let Some(collector) = collectors.iter().find(|c| c.name() == metric_name) else {
+            return Some(format!(
+                "'{}' is required on '{}' but not collected there (disabled, not enabled in its section, or unsupported)",
+                metric_name, settings.key
+            ));
+        };
+        let node_id = settings.metric_node_id(metric_name);
+        match tokio::time::timeout(REQUIRED_TRIAL_TIMEOUT, collector.collect(&node_id)).await {
+            Ok(Ok(_)) => {
+                info!("Required metric '{}' on '{}' collected", metric_name, settings.key);
+                None
+            }
+            Ok(Err(e)) => Some(format!("Required metric '{}' on '{}' failed: {}", metric_name, settings.key, e)),
+            Err(_) => Some(format!(
+                "Required metric '{}' on '{}' timed out after {:?}",
+                metric_name, settings.key, REQUIRED_TRIAL_TIMEOUT
+            )),
+        }
+    });
+    futures_util::future::join_all(trials).await.into_iter().flatten().collect()
+}
+
+/// Monotonic sequence number for one metric's documents on this node
+///
+/// Also carries the node's last integrity hash, which `integrity: "chain"`
+/// links each new document to.
+struct Sequence {
+    next: i64,
+    last_hash: Option<String>,
+    /// Timestamp of the newest document stored before this run
+    resumed_from: Option<DateTime<Utc>>,
+}
+
+impl Sequence {
+    /// Resumes after the highest `seq` already stored, so numbering survives restarts
+    async fn resume(storage: &MetricStorage, collection: &str, node_id: &str) -> Self {
+        let (next, resumed_from) = match storage.latest_seq(collection, node_id).await {
+            Ok(Some((seq, timestamp))) => (seq + 1, Some(timestamp)),
+            Ok(None) => (1, None),
+            Err(e) => {
+                warn!("Failed to read last seq from '{}', starting at 1: {}", collection, e);
+                (1, None)
+            }
+        };
+        let last_hash = match storage.latest_integrity_hash(collection, node_id).await {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!("Failed to read last integrity hash from '{}', starting a new chain: {}", collection, e);
+                None
+            }
+        };
+        Sequence { next, last_hash, resumed_from }
+    }
+
+    /// With `mark_gaps`, stores a gap placeholder if the node stored nothing
+    /// for longer than `GAP_INTERVALS` × `interval_secs` before this run
+    ///
+    /// The placeholder takes a `seq` and is sealed like any other document,
+    /// but gets no deterministic `_id` (it would collide with the first real
+    /// document of the same bucket) and no transforms.
+    async fn mark_gap(
+        &mut self,
+        ctx: &TaskContext,
+        collection: &str,
+        metric_name: &str,
+        interval_secs: u64,
+        metric_settings: &MetricSettings,
+    ) {
+        if !metric_settings.mark_gaps {
+            return;
+        }
+        let Some(from) = self.resumed_from else {
+            return;
+        };
+        let Some(marker) = gap_marker(&ctx.node_id, from, ctx.clock.now(), interval_secs) else {
+            return;
+        };
+        warn!(
+            "'{}': nothing stored since {}, recording the gap",
+            metric_name,
+            from.to_rfc3339()
+        );
+        let settings = MetricSettings { id_bucket_secs: None, unwind: None, ..metric_settings.clone() };
+        self.store_one(&ctx.storage, collection, metric_name, marker, &settings, None).await;
+    }
+
+    /// Stamps `doc` with the next `seq` and stores it.
+    ///
+    /// The number is consumed even if the store fails, so the failure shows
+    /// up as a gap rather than being silently renumbered over. With
+    /// `id_bucket_secs` set, the document also gets a deterministic `_id`;
+    /// with `integrity` set, it is sealed last, and in chain mode the chain
+    /// only advances once the document is stored.
+    ///
+    /// With `unwind` set, each row is stored as its own document with its own
+    /// `seq` (and `_id` suffixed with the row index); returns true only if
+    /// every row was stored. `companions` (the anomalies found alongside) are
+    /// stored with it. With `--transactions`, the rows and companions are
+    /// stored in one transaction, the rows chained to each other before it
+    /// commits.
+    async fn store(
+        &mut self,
+        storage: &MetricStorage,
+        collection: &str,
+        metric_name: &str,
+        doc: Document,
+        metric_settings: &MetricSettings,
+        companions: Vec<(String, Document)>,
+    ) -> bool {
+        let rows = match &metric_settings.unwind {
+            Some(field) => unwind(doc, field).into_iter().map(|doc| (doc, true)).collect(),
+            None => vec![(doc, false)],
+        };
+        if storage.is_transactional(collection) {
+            let mut last_hash = self.last_hash.clone();
+            let mut writes = Vec::new();
+            for (row, (doc, unwound)) in rows.into_iter().enumerate() {
+                let row = unwound.then_some(row);
+                let (doc, hash) = self.seal(collection, metric_name, doc, metric_settings, row, last_hash.as_deref());
+                last_hash = hash.or(last_hash);
+                writes.push((collection.to_string(), doc));
+            }
+            writes.extend(companions);
+            let stored = storage.store_all_safe(metric_name, writes).await;
+            if stored {
+                self.last_hash = last_hash;
+            }
+            return stored;
+        }
+        let mut stored = true;
+        for (row, (doc, unwound)) in rows.into_iter().enumerate() {
+            let row = unwound.then_some(row);
+            stored &= self
+                .store_one(storage, collection, metric_name, doc, metric_settings, row)
+                .await;
+        }
+        storage.store_all_safe("Anomaly", companions).await;
+        stored
+    }
+
+    async fn store_one(
+        &mut self,
+        storage: &MetricStorage,
+        collection: &str,
+        metric_name: &str,
+        doc: Document,
+        metric_settings: &MetricSettings,
+        row: Option<usize>,
+    ) -> bool {
+        let last_hash = self.last_hash.clone();
+        let (doc, hash) = self.seal(collection, metric_name, doc, metric_settings, row, last_hash.as_deref());
+        let stored = storage.store_metric_safe(collection, metric_name, doc).await;
+        if stored && hash.is_some() {
+            self.last_hash = hash;
+        }
+        stored
+    }
+
+    /// Stamps `doc` with `_id`, the next `seq` and its integrity hash,
+    /// chained to `last_hash` in chain mode; returns it with that hash
+    fn seal(
+        &mut self,
+        collection: &str,
+        metric_name: &str,
+        mut doc: Document,
+        metric_settings: &MetricSettings,
+        row: Option<usize>,
+        last_hash: Option<&str>,
+    ) -> (Document, Option<String>) {
+        if let Some(bucket_secs) = metric_settings.id_bucket_secs {
+            assign_deterministic_id(&mut doc, metric_name, bucket_secs);
+            if let (Some(row), Ok(id)) = (row, doc.get_str("_id")) {
+                let id = format!("{}:{}", id, row);
+                doc.insert("_id", id);
+            }
+        }
+        doc.insert("seq", self.next);
+        self.next += 1;
+
+        // Before sealing, so the hash covers what is actually stored
+        sanitize_non_finite(&mut doc, collection);
+        let hash = match metric_settings.integrity {
+            IntegrityMode::Off => None,
+            IntegrityMode::Hash => Some(integrity::seal(&mut doc, None)),
+            IntegrityMode::Chain => Some(integrity::seal(&mut doc, last_hash)),
+        };
+        (doc, hash)
+    }
+}
+
+/// Reference point of `monotonic_ns`; set on first use, which `main` makes
+/// the start of the process
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Pins `monotonic_ns` zero to now, if nothing has used it yet
+pub fn mark_process_start() {
+    PROCESS_START.get_or_init(Instant::now);
+}
+
+/// With `metrics.<Name>.monotonic`, stamps `doc` with the nanoseconds since
+/// process start, from the same monotonic clock the tasks schedule with
+fn stamp_monotonic(doc: &mut Document, metric_settings: &MetricSettings) {
+    if metric_settings.monotonic {
+        let elapsed = PROCESS_START.get_or_init(Instant::now).elapsed();
+        doc.insert("monotonic_ns", i64::try_from(elapsed.as_nanos()).unwrap_or(i64::MAX));
+    }
+}
+
+/// With `--timestamp-precision us|ns`, sets `timestamp` to `at`, read by the
+/// scheduler at full precision, and adds it as `timestamp_us`/`timestamp_ns`
+///
+/// The BSON date keeps only milliseconds, which is all MongoDB stores; the
+/// integer is the same instant, so it always truncates to `timestamp`.
+fn stamp_timestamp(doc: &mut Document, at: DateTime<Utc>, precision: TimestampPrecision) {
+    if let Some(field) = precision.field() {
+        doc.insert("timestamp", at);
+        doc.insert(field, precision.epoch(at));
+    }
+}
+
+/// Stamps `doc` with the settings' `config_revision`, if they carry one
+fn stamp_config_revision(doc: &mut Document, settings: &MonitoringSettings) {
+    if let Some(revision) = &settings.config_revision {
+        doc.insert("config_revision", revision.clone());
+    }
+}
+
+/// Silence longer than this many storage intervals counts as an outage
+const GAP_INTERVALS: i64 = 2;
+
+/// Placeholder document for an outage between `from` and `to`
+///
+/// None unless the silence exceeds `GAP_INTERVALS` × `interval_secs`.
+fn gap_marker(node_id: &str, from: DateTime<Utc>, to: DateTime<Utc>, interval_secs: u64) -> Option<Document> {
+    let gap_secs = (to - from).num_seconds();
+    if gap_secs <= GAP_INTERVALS * interval_secs.max(1) as i64 {
+        return None;
+    }
+    Some(doc! {
+        "node": node_id,
+        "timestamp": to,
+        "status": "gap",
+        "from": from,
+        "to": to,
+        "gap_secs": gap_secs,
+    })
+}
+
+/// Seconds between stored documents a gap is measured against
+///
+/// With `store_on_change_only`, up to `max_unchanged_secs` pass without a
+/// store on a healthy node.
+fn gap_interval(store_interval_secs: u64, metric_settings: &MetricSettings) -> u64 {
+    if metric_settings.store_on_change_only {
+        store_interval_secs.max(metric_settings.max_unchanged_secs)
+    } else {
+        store_interval_secs
+    }
+}
+
+/// Decides which documents are persisted under a metric's `sample_rate`
+///
+/// Seeded from the node and metric name, so a given node keeps the same
+/// sampling pattern across restarts and runs are reproducible.
+struct Sampler {
+    rng: StdRng,
+}
+
+/// 64-bit FNV-1a of `parts`, each followed by a zero byte
+///
+/// Fixed by its definition, unlike `DefaultHasher`, so the seed it gives
+/// doesn't change with the Rust release the collector is built with.
+fn fnv1a(parts: &[&str]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    parts
+        .iter()
+        .flat_map(|part| part.bytes().chain([0]))
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+impl Sampler {
+    fn new(node_id: &str, metric_name: &str) -> Self {
+        Sampler {
+            rng: StdRng::seed_from_u64(fnv1a(&[node_id, metric_name])),
+        }
+    }
+
+    /// Returns true if the next document should be stored
+    fn keep(&mut self, sample_rate: f64) -> bool {
+        if sample_rate >= 1.0 {
+            return true;
+        }
+        sample_rate > 0.0 && self.rng.gen::<f64>() < sample_rate
+    }
+}
+
+/// Suppresses unchanged documents under a metric's `store_on_change_only`
+///
+/// Suppressed documents take no seq, like sampled-out ones.
+#[derive(Default)]
+struct ChangeFilter {
+    /// Last stored document without its timestamp
+    last: Option<Document>,
+    last_stored_at: Option<Instant>,
+}
+
+impl ChangeFilter {
+    /// Returns true unless `doc` repeats the last stored document within `max_unchanged_secs`
+    fn should_store(&self, doc: &Document, settings: &MetricSettings, now: Instant) -> bool {
+        if !settings.store_on_change_only {
+            return true;
+        }
+        let (Some(last), Some(stored_at)) = (&self.last, self.last_stored_at) else {
+            return true;
+        };
+        now.duration_since(stored_at) >= Duration::from_secs(settings.max_unchanged_secs)
+            || without_timestamp(doc) != *last
+    }
+
+    /// Remembers `doc` after it was stored successfully
+    fn stored(&mut self, doc: &Document, now: Instant) {
+        self.last = Some(without_timestamp(doc));
+        self.last_stored_at = Some(now);
+    }
+}
+
+/// `doc` without the fields that differ on every document: `timestamp`,
+/// `monotonic_ns` and the `--timestamp-precision` field
+fn without_timestamp(doc: &Document) -> Document {
+    let mut doc = doc.clone();
+    doc.remove("timestamp");
+    doc.remove("monotonic_ns");
+    for precision in [TimestampPrecision::Micros, TimestampPrecision::Nanos] {
+        if let Some(field) = precision.field() {
+            doc.remove(field);
+        }
+    }
+    doc
+}
+
+/// Tracks consecutive failed stores so recovery can be recorded as an event
+#[derive(Default)]
+struct StorageHealth {
+    failed: u32,
+}
+
+impl StorageHealth {
+    async fn observe(&mut self, stored: bool, events: &EventRecorder, metric_name: &str) {
+        if !stored {
+            self.failed += 1;
+        } else if self.failed > 0 {
+            events
+                .record(
+                    EVENT_STORAGE_RECOVERED,
+                    format!("'{}' stored again after {} failed write(s)", metric_name, self.failed),
+                )
+                .await;
+            self.failed = 0;
+        }
+    }
+}
+
+/// Measures the gap between consecutive collect ticks against the interval
+///
+/// A tick arriving much later than the interval (or early, when missed ticks
+/// burst) means the task was starved, typically by a collector blocking the
+/// runtime. Only ticks within one window are compared: the first tick of a
+/// window fires right after the previous flush.
+#[derive(Default)]
+struct DriftMonitor {
+    last_tick: Option<Instant>,
+    drifted: u32,
+    worst_secs: f64,
+}
+
+impl DriftMonitor {
+    /// Called on every collect tick
+    fn tick(&mut self, interval_secs: u64, settings: &DriftSettings, metric_name: &str) {
+        let now = Instant::now();
+        let Some(last) = self.last_tick.replace(now) else {
+            return;
+        };
+
+        let elapsed = now.duration_since(last).as_secs_f64();
+        let drift = elapsed - interval_secs as f64;
+        if drift.abs() > interval_secs as f64 * settings.tolerance_percent / 100.0 {
+            warn!(
+                "'{}' tick came {:.2}s after the previous one (interval {}s, drift {:+.2}s)",
+                metric_name, elapsed, interval_secs, drift
+            );
+            self.drifted += 1;
+            if drift.abs() > self.worst_secs.abs() {
+                self.worst_secs = drift;
+            }
+        }
+    }
+
+    /// Ends a window, recording one `interval_drift` event if any tick drifted
+    async fn finish_window(&mut self, settings: &DriftSettings, events: &EventRecorder, metric_name: &str) {
+        if self.drifted > 0 && settings.record_events {
+            events
+                .record(
+                    EVENT_INTERVAL_DRIFT,
+                    format!(
+                        "'{}': {} tick(s) outside tolerance, worst drift {:+.2}s",
+                        metric_name, self.drifted, self.worst_secs
+                    ),
+                )
+                .await;
+        }
+        *self = DriftMonitor::default();
+    }
+}
+
+/// Checks a collected document's `anomaly_fields`, returning one document
+/// per anomaly for `anomalies.collection`
+///
+/// Skipped during the startup grace period, whose values are not settled.
+fn check_anomalies(
+    ctx: &TaskContext,
+    detector: &mut AnomalyDetector,
+    metric_name: &str,
+    doc: &Document,
+    settings: &MonitoringSettings,
+) -> Vec<(String, Document)> {
+    let metric_settings = settings.metric(metric_name);
+    if metric_settings.anomaly_fields.is_empty() || Instant::now() < ctx.store_from {
+        return Vec::new();
+    }
+    let mut writes = Vec::new();
+    for anomaly in detector.observe(doc, metric_name, &metric_settings, &settings.anomalies) {
+        warn!(
+            "'{}' anomaly: {} = {} (z-score {:.1})",
+            metric_name,
+            anomaly.get_str("field").unwrap_or(""),
+            anomaly.get("value").map(ToString::to_string).unwrap_or_default(),
+            anomaly.get_f64("zscore").unwrap_or(0.0)
+        );
+        writes.push((settings.anomalies.collection.clone(), anomaly));
+    }
+    writes
+}
+
+/// Anomaly documents waiting for the metric document they were found in
+///
+/// With `--transactions` they are held until that document is stored and go
+/// into its transaction; otherwise they are stored as soon as they are found.
+/// For aggregated metrics that means holding a window's anomalies until its
+/// flush.
+#[derive(Default)]
+struct HeldAnomalies(Vec<(String, Document)>);
+
+impl HeldAnomalies {
+    /// Holds `found` for the next document stored in `collection`, or stores
+    /// it now if that collection isn't written in transactions
+    async fn add(&mut self, ctx: &TaskContext, collection: &str, found: Vec<(String, Document)>) {
+        if ctx.storage.is_transactional(collection) {
+            self.0.extend(found);
+        } else {
+            ctx.storage.store_all_safe("Anomaly", found).await;
+        }
+    }
+
+    /// Hands the held documents to `Sequence::store`
+    fn take(&mut self) -> Vec<(String, Document)> {
+        std::mem::take(&mut self.0)
+    }
+
+    /// Stores whatever is still held on its own, when no metric document was
+    /// stored to carry it (dropped, unchanged, sampled out)
+    async fn flush(&mut self, ctx: &TaskContext) {
+        ctx.storage.store_all_safe("Anomaly", self.take()).await;
+    }
+}
+
+/// Reloads settings after a flush, recording an event and a snapshot when they changed
+async fn reload_settings(ctx: &TaskContext, metric_name: &str, settings: &mut MonitoringSettings) {
+    match ctx.config_manager.reload_settings(&ctx.key).await {
+        Ok(new) => {
+            if new != *settings {
+                ctx.events
+                    .record(EVENT_CONFIG_RELOAD, format!("'{}' picked up changed settings", metric_name))
+                    .await;
+                ctx.history.record(&new, "reload").await;
+            }
+            *settings = new;
+        }
+        Err(e) => warn!("Failed to reload settings for '{}': {}", metric_name, e),
+    }
+}
+
+/// Resolves once `true` is sent on the shutdown channel.
+///
+/// If the sender is dropped without signalling, this never resolves.
+pub async fn wait_for_shutdown(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// When a task with `collect_immediately: false` first collects: one
+/// `interval_secs` from now; None collects at once
+fn first_collection_at(metric_settings: &MetricSettings, interval_secs: u64) -> Option<Instant> {
+    (!metric_settings.collect_immediately).then(|| Instant::now() + Duration::from_secs(interval_secs))
+}
+
+/// Timer for one window's collections, ticking every `period_secs`: at once,
+/// or from `first_at` while that is still ahead
+///
+/// A new timer is made every window, so the delayed start holds even when
+/// it lies past the first window's end.
+fn window_timer(period_secs: u64, first_at: Option<Instant>) -> Interval {
+    let period = Duration::from_secs(period_secs);
+    match first_at.filter(|at| *at > Instant::now()) {
+        Some(at) => interval_at(at, period),
+        None => interval(period),
+    }
+}
+
+/// Resolves on the next flush-and-reload request.
+///
+/// If the sender is dropped (or was never there), this never resolves.
+async fn wait_for_reload(reload: &mut watch::Receiver<()>) {
+    if reload.changed().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Per-metric pause flags, shared by the metric tasks and the admin server
+///
+/// Keyed by metric name: with `--all-keys`, pausing a metric pauses it for
+/// every node. In memory only, so a restart resumes everything.
+#[derive(Clone, Default)]
+pub struct PauseFlags {
+    flags: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl PauseFlags {
+    /// Returns the flag a task for `metric_name` checks, creating it unpaused
+    fn register(&self, metric_name: &str) -> Arc<AtomicBool> {
+        let mut flags = self.flags.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(flags.entry(metric_name.to_string()).or_default())
+    }
+
+    /// Pauses or resumes a metric; false if no task runs that metric
+    pub fn set(&self, metric_name: &str, paused: bool) -> bool {
+        let flags = self.flags.lock().unwrap_or_else(|e| e.into_inner());
+        match flags.get(metric_name) {
+            Some(flag) => {
+                flag.store(paused, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the paused metrics, sorted
+    pub fn paused(&self) -> Vec<String> {
+        let flags = self.flags.lock().unwrap_or_else(|e| e.into_inner());
+        let mut paused: Vec<String> = flags
+            .iter()
+            .filter(|(_, flag)| flag.load(Ordering::Relaxed))
+            .map(|(name, _)| name.clone())
+            .collect();
+        paused.sort();
+        paused
+    }
+}
+
+/// One task's view of its pause flag, logging each pause and resume once
+struct PauseGate {
+    flag: Arc<AtomicBool>,
+    paused: bool,
+}
+
+impl PauseGate {
+    fn new(flag: Arc<AtomicBool>) -> Self {
+        PauseGate { flag, paused: false }
+    }
+
+    /// True if this tick's collection should be skipped
+    fn skip(&mut self, metric_name: &str) -> bool {
+        let paused = self.flag.load(Ordering::Relaxed);
+        if paused != self.paused {
+            if paused {
+                info!("'{}' paused, skipping collection until resumed", metric_name);
+            } else {
+                info!("'{}' resumed", metric_name);
+            }
+            self.paused = paused;
+        } else if paused {
+            debug!("'{}' paused, skipping collection", metric_name);
+        }
+        paused
+    }
+}
+
+/// Collectors run at the same time by `collect_once`
+const COLLECT_ONCE_CONCURRENCY: usize = 4;
+
+/// Result of one collector in `collect_once`
+pub struct CollectOutcome {
+    pub metric_name: String,
+    pub duration: Duration,
+    /// The collected document, or the collection error
+    pub result: Result<Document, String>,
+    /// Whether the document was written (false on failure or without `store`)
+    pub stored: bool,
+}
+
+/// Everything a metric task needs besides its collector and settings
+struct TaskContext {
+    storage: Arc<MetricStorage>,
+    config_manager: Arc<ConfigManager>,
+    /// Settings key the task reloads its settings from
+    key: String,
+    /// Node id of the metric's documents: the key unless `metrics.<Name>.node_id`
+    node_id: String,
+    clock: SharedClock,
+    events: EventRecorder,
+    history: ConfigHistory,
+    shutdown: watch::Receiver<bool>,
+    /// Changes when the task should flush and reload its settings now (SIGHUP)
+    reload: watch::Receiver<()>,
+    /// End of the startup grace period; nothing is stored before it
+    store_from: Instant,
+    /// Set once the task has logged that it is in the grace period
+    grace_logged: bool,
+    /// Tags detected from the environment (Kubernetes downward API)
+    auto_tags: BTreeMap<String, String>,
+    /// Set while the metric is paused through the admin server
+    paused: Arc<AtomicBool>,
+    /// Latest documents of this scheduler's metrics (adaptive intervals)
+    last_values: LastValues,
+    /// Precision the documents are stamped with
+    timestamp_precision: TimestampPrecision,
+}
+
+impl TaskContext {
+    /// Returns true if `doc` falls in the startup grace period
+    ///
+    /// Logs at info once per task, then each dropped document at debug.
+    fn in_startup_grace(&mut self, metric_name: &str, doc: &Document) -> bool {
+        let in_grace = Instant::now() < self.store_from;
+        if in_grace {
+            if !self.grace_logged {
+                info!("'{}' within startup grace period, not storing", metric_name);
+                self.grace_logged = true;
+            }
+            debug!("'{}' dropped in startup grace period: {}", metric_name, doc);
+        }
+        in_grace
+    }
+
+    /// Collect interval for the next window: `base_secs`, widened under load
+    /// with `adaptive.enabled` (never for LoadAverage, which measures it)
+    fn collect_interval(
+        &self,
+        adaptive: &mut AdaptiveInterval,
+        metric_name: &str,
+        base_secs: u64,
+        settings: &MonitoringSettings,
+    ) -> u64 {
+        if matches!(metric_name, LOAD_METRIC | "Canary") {
+            return base_secs;
+        }
+        let max_age = Duration::from_secs(settings.store_timeout.saturating_mul(2));
+        let load = self.last_values.load_per_core(max_age);
+        adaptive.next(metric_name, base_secs, settings.store_timeout, load, &settings.adaptive, Instant::now())
+    }
+}
+
 pub struct MetricScheduler {
     config_manager: Arc<ConfigManager>,
     storage: Arc<MetricStorage>,
     node_id: String,
+    clock: SharedClock,
+    shutdown: watch::Receiver<bool>,
+    /// Tags added to every document (e.g. from cloud metadata)
+    tags: BTreeMap<String, String>,
+    /// Pause flags toggled by the admin server
+    pause: PauseFlags,
+    /// Fired once every background task has been spawned
+    online: Option<oneshot::Sender<()>>,
+    /// Flush-and-reload requests; never fires unless set with `with_reload_signal`
+    reload: watch::Receiver<()>,
+    /// Latest document of each metric, shared by the tasks
+    last_values: LastValues,
+    /// `--timestamp-precision`
+    timestamp_precision: TimestampPrecision,
 }
 
 impl MetricScheduler {
     pub fn new(
-        config_manager: ConfigManager,
-        storage: MetricStorage,
+        config_manager: Arc<ConfigManager>,
+        storage: Arc<MetricStorage>,
         node_id: String,
+        clock: SharedClock,
+        shutdown: watch::Receiver<bool>,
     ) -> Self {
         MetricScheduler {
-            config_manager: Arc::new(config_manager),
-            storage: Arc::new(storage),
+            config_manager,
+            storage,
             node_id,
+            clock,
+            shutdown,
+            tags: BTreeMap::new(),
+            pause: PauseFlags::default(),
+            online: None,
+            reload: watch::channel(()).1,
+            last_values: LastValues::default(),
+            timestamp_precision: TimestampPrecision::default(),
         }
     }
 
-    /// Starts all metric collection tasks. Runs until all tasks stop (should be forever).
-    pub async fn start(self, collectors: Vec<Box<dyn MetricCollector>>, initial_settings: MonitoringSettings) {
+    /// Stamps documents at `precision` instead of milliseconds
+    pub fn with_timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.timestamp_precision = precision;
+        self
+    }
+
+    /// Signals `online` once startup (events, schema, validators, config
+    /// history) is done and the metric tasks are running; `--all-keys` uses
+    /// it to bound how many schedulers start at once
+    pub fn with_online_signal(mut self, online: oneshot::Sender<()>) -> Self {
+        self.online = Some(online);
+        self
+    }
+
+    /// Makes every task flush its window and reload its settings whenever
+    /// `reload` changes, instead of waiting for the end of the window (SIGHUP)
+    pub fn with_reload_signal(mut self, reload: watch::Receiver<()>) -> Self {
+        self.reload = reload;
+        self
+    }
+
+    /// Shares pause flags with the admin server (`POST /pause/{metric}`)
+    pub fn with_pause_flags(mut self, pause: PauseFlags) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Adds `tags` to every document, ahead of the configured transforms
+    pub fn with_tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Starts all metric collection tasks.
+    ///
+    /// Runs until shutdown is signalled and every task has flushed and returned.
+    pub async fn start(mut self, collectors: Vec<Box<dyn MetricCollector>>, initial_settings: MonitoringSettings) {
         info!("Starting metric scheduler for node: {}", self.node_id);
 
-        let mut handles = Vec::new();
+        let events = EventRecorder::new(
+            Arc::clone(&self.storage),
+            self.node_id.clone(),
+            initial_settings.events.clone(),
+            Arc::clone(&self.clock),
+        );
+        events
+            .record(EVENT_START, format!("version {}, {} collector(s)", build_info::summary(), collectors.len()))
+            .await;
+
+        if initial_settings.schema.enabled {
+            for collector in &collectors {
+                let metric_name = collector.name();
+                let aggregated  = !is_log_metric(metric_name);
+                publish_schema(
+                    &self.storage,
+                    &initial_settings.schema,
+                    metric_name,
+                    &collection_name(metric_name, &initial_settings),
+                    aggregated,
+                    initial_settings.numeric.size_units,
+                )
+                .await;
+            }
+        }
+
+        for collector in &collectors {
+            let metric_name = collector.name();
+            if initial_settings.metric(metric_name).validate {
+                apply_validator(
+                    &self.storage,
+                    metric_name,
+                    &collection_name(metric_name, &initial_settings),
+                    !is_log_metric(metric_name),
+                    initial_settings.numeric.size_units,
+                )
+                .await;
+            }
+        }
+
+        if initial_settings.docker.storage == DockerStorage::PerContainer
+            && collectors.iter().any(|c| c.name() == "DockerStats")
+        {
+            ensure_time_series(&self.storage, &initial_settings.docker.container_collection).await;
+        }
+
+        if initial_settings.adaptive.enabled && !collectors.iter().any(|c| c.name() == LOAD_METRIC) {
+            warn!("adaptive.enabled, but {} is not collected: collect intervals stay as configured", LOAD_METRIC);
+        }
+
+        let store_from = Instant::now() + Duration::from_secs(initial_settings.startup_grace_secs);
+        if initial_settings.startup_grace_secs > 0 {
+            info!(
+                "Startup grace period: nothing is stored for the first {}s",
+                initial_settings.startup_grace_secs
+            );
+        }
+
+        let mut auto_tags = self.tags.clone();
+        auto_tags.extend(kubernetes::downward_api_tags());
+
+        let history = ConfigHistory::new(
+            Arc::clone(&self.storage),
+            self.node_id.clone(),
+            initial_settings.config_history.clone(),
+            auto_tags.clone(),
+            Arc::clone(&self.clock),
+        );
+        history.record(&initial_settings, "start").await;
+
+        let mut tasks = JoinSet::new();
+
+        if initial_settings.rollup.enabled {
+            let rollup_collections: Vec<String> = collectors
+                .iter()
+                .map(|c| c.name())
+                .filter(|name| !is_log_metric(name))
+                .map(|name| collection_name(name, &initial_settings))
+                .collect();
+            let storage  = Arc::clone(&self.storage);
+            let node_id  = self.node_id.clone();
+            let rollup   = initial_settings.rollup.clone();
+            // Give the last flush of each window time to land before summarizing it
+            let grace    = initial_settings.store_timeout + 5;
+            let clock    = Arc::clone(&self.clock);
+            let shutdown = self.shutdown.clone();
+
+            tasks.spawn(async move {
+                run_rollup_task(storage, node_id, rollup_collections, rollup, grace, clock, shutdown).await;
+            });
+        }
+
+        if !initial_settings.retention.keep_last.is_empty() {
+            tasks.spawn(run_retention_task(
+                Arc::clone(&self.storage),
+                retention_node_ids(&initial_settings),
+                initial_settings.retention.clone(),
+                self.shutdown.clone(),
+            ));
+        }
+
+        if initial_settings.agent_info.enabled {
+            tasks.spawn(run_agent_info_task(
+                Arc::clone(&self.storage),
+                self.node_id.clone(),
+                initial_settings.agent_info.clone(),
+                Arc::clone(&self.clock),
+                self.shutdown.clone(),
+            ));
+        }
+
+        if initial_settings.heartbeat.enabled {
+            tasks.spawn(run_heartbeat_task(
+                Arc::clone(&self.storage),
+                self.node_id.clone(),
+                initial_settings.heartbeat.clone(),
+                events.clone(),
+                Arc::clone(&self.clock),
+                self.shutdown.clone(),
+            ));
+        }
 
         for collector in collectors {
             let metric_name = collector.name().to_string();
-            let storage      = Arc::clone(&self.storage);
-            let config_mgr   = Arc::clone(&self.config_manager);
-            let node_id      = self.node_id.clone();
-            let settings     = initial_settings.clone();
+            let settings    = initial_settings.clone();
+            let ctx = TaskContext {
+                storage:        Arc::clone(&self.storage),
+                config_manager: Arc::clone(&self.config_manager),
+                key:            self.node_id.clone(),
+                node_id:        settings.metric_node_id(&metric_name),
+                clock:          Arc::clone(&self.clock),
+                events:         events.clone(),
+                history:        history.clone(),
+                shutdown:       self.shutdown.clone(),
+                reload:         self.reload.clone(),
+                store_from,
+                grace_logged:   false,
+                auto_tags:      auto_tags.clone(),
+                paused:         self.pause.register(&metric_name),
+                last_values:    self.last_values.clone(),
+                timestamp_precision: self.timestamp_precision,
+            };
 
             info!(
                 "Scheduling '{}' → collection '{}' (collect: {}s, store: {}s)",
                 metric_name,
-                collection_for(&metric_name),
+                collection_name(&metric_name, &settings),
                 collect_timeout_for(&metric_name, &settings),
                 settings.store_timeout,
             );
+            if ctx.node_id != ctx.key {
+                info!("'{}' collects as node '{}'", metric_name, ctx.node_id);
+            }
 
-            let handle = if metric_name == "DockerStats" {
-                tokio::spawn(async move {
-                    run_docker_task(collector, storage, config_mgr, node_id, settings).await;
-                })
+            if metric_name == "DockerStats" {
+                tasks.spawn(run_docker_task(collector, ctx, settings));
             } else if is_log_metric(&metric_name) {
-                tokio::spawn(async move {
-                    run_log_task(collector, storage, config_mgr, node_id, settings).await;
-                })
+                tasks.spawn(run_log_task(collector, ctx, settings));
             } else {
-                tokio::spawn(async move {
-                    run_standard_task(collector, storage, config_mgr, node_id, settings).await;
-                })
-            };
-
-            handles.push(handle);
+                tasks.spawn(run_standard_task(collector, ctx, settings));
+            }
         }
 
-        info!("Started {} metric collection task(s)", handles.len());
+        info!("Started {} background task(s)", tasks.len());
+        if let Some(online) = self.online.take() {
+            let _ = online.send(());
+        }
 
-        for handle in handles {
-            if let Err(e) = handle.await {
+        // join_next reports panics as they happen rather than in spawn order
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
                 error!("Metric collection task panicked: {}", e);
+                events.record(EVENT_TASK_PANIC, e.to_string()).await;
             }
         }
 
-        error!("All metric collection tasks have stopped");
+        if *self.shutdown.borrow() {
+            info!("All tasks for node {} stopped after shutdown", self.node_id);
+        } else {
+            error!("All metric collection tasks have stopped");
+        }
     }
 
-    /// One-shot collection for all metrics (testing/manual use). Stores raw samples directly.
-    #[allow(dead_code)]
-    pub async fn collect_once(&self, collectors: Vec<Box<dyn MetricCollector>>) -> usize {
+    /// One-shot collection for all metrics (`--once`, preflight checks, tests)
+    ///
+    /// Runs up to `COLLECT_ONCE_CONCURRENCY` collectors at a time and returns
+    /// one outcome per collector, in input order. With `store`, raw samples
+    /// are written directly (no aggregation, transforms or `seq`; only the
+    /// `scrub` pass).
+    /// `settings` supplies per-metric `node_id` overrides. With `only`, the
+    /// other collectors are dropped unrun (`--once --metrics`).
+    pub async fn collect_once(
+        &self,
+        mut collectors: Vec<Box<dyn MetricCollector>>,
+        settings: &MonitoringSettings,
+        store: bool,
+        only: Option<&[String]>,
+    ) -> Vec<CollectOutcome> {
         info!("Running one-time metric collection");
-
-        let mut success_count = 0;
-        let total_count = collectors.len();
-
-        for collector in collectors {
-            let metric_name = collector.name();
-            let collection  = collection_for(metric_name);
-
-            match collector.collect(&self.node_id).await {
-                Ok(document) => {
-                    self.storage
-                        .store_metric_safe(collection, metric_name, document)
-                        .await;
-                    success_count += 1;
-                }
-                Err(e) => {
-                    error!("Failed to collect metric '{}': {}", metric_name, e);
+        if let Some(only) = only {
+            collectors.retain(|collector| only.iter().any(|name| name == collector.name()));
+            for name in only {
+                if !collectors.iter().any(|collector| collector.name() == name) {
+                    warn!("'{}' is not collected on '{}' (disabled or unsupported here)", name, self.node_id);
                 }
             }
         }
 
-        info!("One-time collection complete: {}/{} metrics succeeded", success_count, total_count);
-        success_count
+        let scrub = settings.scrub.enabled.then(|| ScrubTransform::new(&settings.scrub));
+        let scrub = scrub.as_ref();
+        let outcomes: Vec<CollectOutcome> = stream::iter(collectors)
+            .map(|collector| async move {
+                let metric_name = collector.name().to_string();
+                let node_id     = settings.metric(&metric_name).node_id.unwrap_or_else(|| self.node_id.clone());
+                let started     = Instant::now();
+                let result      = collector.collect(&node_id).await.map_err(|e| e.to_string());
+                let duration    = started.elapsed();
+
+                let stored = match &result {
+                    Ok(document) if store => {
+                        let mut document = document.clone();
+                        stamp_timestamp(&mut document, self.clock.now(), self.timestamp_precision);
+                        if let Some(scrub) = scrub {
+                            scrub.apply(&metric_name, &mut document);
+                        }
+                        stamp_config_revision(&mut document, settings);
+                        // Numbered like a scheduled document, so a validated collection accepts it
+                        let collection = collection_name(&metric_name, settings);
+                        let mut seq = Sequence::resume(&self.storage, &collection, &node_id).await;
+                        let metric_settings = settings.metric(&metric_name);
+                        seq.store(&self.storage, &collection, &metric_name, document, &metric_settings, Vec::new())
+                            .await
+                    }
+                    Ok(_) => false,
+                    Err(e) => {
+                        error!("Failed to collect metric '{}': {}", metric_name, e);
+                        false
+                    }
+                };
+                CollectOutcome { metric_name, duration, result, stored }
+            })
+            .buffered(COLLECT_ONCE_CONCURRENCY)
+            .collect()
+            .await;
+
+        let succeeded = outcomes.iter().filter(|o| o.result.is_ok()).count();
+        info!("One-time collection complete: {}/{} metrics succeeded", succeeded, outcomes.len());
+        outcomes
     }
 }
 
 /// Collection + aggregation loop for LoadAverage, Memory, DiskSpace.
+///
+/// On shutdown the current window is flushed early so buffered samples aren't lost;
+/// a reload request (SIGHUP) flushes it early too, then reloads the settings.
 async fn run_standard_task(
     collector: Box<dyn MetricCollector>,
-    storage: Arc<MetricStorage>,
-    config_manager: Arc<ConfigManager>,
-    node_id: String,
+    mut ctx: TaskContext,
     mut settings: MonitoringSettings,
 ) {
     let metric_name = collector.name();
-    let collection  = collection_for(metric_name);
+    let collection  = &collection_name(metric_name, &settings);
     let mut buffer  = MetricBuffer::new();
+    let mut seq     = Sequence::resume(&ctx.storage, collection, &ctx.node_id).await;
+    let mut health  = StorageHealth::default();
+    let mut sampler = Sampler::new(&ctx.node_id, metric_name);
+    let mut drift   = DriftMonitor::default();
+    let mut changes = ChangeFilter::default();
+    let mut anomalies = AnomalyDetector::default();
+    let mut held      = HeldAnomalies::default();
+    let mut adaptive = AdaptiveInterval::default();
+    let mut pipeline = Pipeline::with_auto_tags(&ctx.auto_tags);
+    let mut pause   = PauseGate::new(Arc::clone(&ctx.paused));
 
     info!("Starting collection loop for '{}'", metric_name);
+    let metric_settings = settings.metric(metric_name);
+    let interval_secs = gap_interval(settings.store_timeout, &metric_settings);
+    seq.mark_gap(&ctx, collection, metric_name, interval_secs, &metric_settings).await;
+    let first_at = first_collection_at(&metric_settings, settings.collect_timeout);
 
-    loop {
-        let mut collect_timer = interval(Duration::from_secs(settings.collect_timeout));
+    let mut stopping = false;
+    while !stopping {
+        let mut reload_now = false;
+        let collect_secs = ctx.collect_interval(&mut adaptive, metric_name, settings.collect_timeout, &settings);
+        let mut collect_timer = window_timer(collect_secs, first_at);
         let flush_sleep = tokio::time::sleep(Duration::from_secs(settings.store_timeout));
         tokio::pin!(flush_sleep);
 
@@ -176,27 +1280,71 @@ async fn run_standard_task(
         loop {
             select! {
                 _ = collect_timer.tick() => {
-                    match collector.collect(&node_id).await {
-                        Ok(doc) => buffer.push(&doc),
+                    drift.tick(collect_secs, &settings.drift, metric_name);
+                    if pause.skip(metric_name) {
+                        continue;
+                    }
+                    match collector.collect(&ctx.node_id).await {
+                        Ok(doc) => {
+                            let found = check_anomalies(&ctx, &mut anomalies, metric_name, &doc, &settings);
+                            held.add(&ctx, collection, found).await;
+                            ctx.last_values.record(metric_name, &doc);
+                            buffer.push(&doc);
+                        }
                         Err(e)  => error!("Failed to collect '{}': {}", metric_name, e),
                     }
                 }
                 _ = &mut flush_sleep => { break; }
+                _ = wait_for_reload(&mut ctx.reload) => {
+                    info!("Flushing '{}' early and reloading settings", metric_name);
+                    reload_now = true;
+                    break;
+                }
+                _ = wait_for_shutdown(&mut ctx.shutdown) => { stopping = true; break; }
             }
         }
+        drift.finish_window(&settings.drift, &ctx.events, metric_name).await;
 
         // Flush buffer and store
-        match buffer.flush(&node_id) {
-            Some(doc) => {
-                storage.store_metric_safe(collection, metric_name, doc).await;
+        let flushed_at = ctx.clock.now();
+        match buffer.flush(&ctx.node_id, flushed_at) {
+            Some(mut doc) => {
+                stamp_timestamp(&mut doc, flushed_at, ctx.timestamp_precision);
+                stamp_monotonic(&mut doc, &settings.metric(metric_name));
+                stamp_config_revision(&mut doc, &settings);
+                pipeline.refresh(&settings.transforms, &settings.scrub);
+                pipeline.apply(metric_name, &mut doc);
+
+                // Sampled-out windows take no seq, so gaps still mean lost data
+                let metric_settings = settings.metric(metric_name);
+                let now = Instant::now();
+                if ctx.in_startup_grace(metric_name, &doc) {
+                    // Dropped: no seq is consumed, so storage starts at the next seq
+                } else if skip_empty(metric_name, &doc, &metric_settings) {
+                    // Nothing to record; no seq is consumed either
+                } else if !changes.should_store(&doc, &metric_settings, now) {
+                    debug!("'{}' unchanged, skipping store", metric_name);
+                } else if sampler.keep(metric_settings.sample_rate) {
+                    let stored = seq
+                        .store(&ctx.storage, collection, metric_name, doc.clone(), &metric_settings, held.take())
+                        .await;
+                    if stored {
+                        changes.stored(&doc, now);
+                    }
+                    health.observe(stored, &ctx.events, metric_name).await;
+                }
                 // Reload settings right after storing
-                match config_manager.reload_settings(&node_id).await {
-                    Ok(new)  => settings = new,
-                    Err(e)   => warn!("Failed to reload settings for '{}': {}", metric_name, e),
+                if !stopping {
+                    reload_settings(&ctx, metric_name, &mut settings).await;
                 }
             }
+            // Nothing to flush, but a reload was asked for
+            None if reload_now => reload_settings(&ctx, metric_name, &mut settings).await,
+            None if pause.paused => debug!("'{}' paused, nothing to flush", metric_name),
             None => warn!("Not enough samples for '{}', skipping flush", metric_name),
         }
+        // Anomalies of a window whose document wasn't stored
+        held.flush(&ctx).await;
     }
 }
 
@@ -209,64 +1357,197 @@ async fn run_standard_task(
 /// to pick up `collect_timeout` changes without needing a restart.
 async fn run_log_task(
     collector: Box<dyn MetricCollector>,
-    storage: Arc<MetricStorage>,
-    config_manager: Arc<ConfigManager>,
-    node_id: String,
+    mut ctx: TaskContext,
     mut settings: MonitoringSettings,
 ) {
     let metric_name = collector.name();
-    let collection  = collection_for(metric_name);
+    let collection  = &collection_name(metric_name, &settings);
+    let mut seq     = Sequence::resume(&ctx.storage, collection, &ctx.node_id).await;
+    let mut health  = StorageHealth::default();
+    let mut sampler = Sampler::new(&ctx.node_id, metric_name);
+    let mut drift   = DriftMonitor::default();
+    let mut changes = ChangeFilter::default();
+    let mut anomalies = AnomalyDetector::default();
+    let mut held      = HeldAnomalies::default();
+    let mut adaptive = AdaptiveInterval::default();
+    let mut pipeline = Pipeline::with_auto_tags(&ctx.auto_tags);
+    let mut pause   = PauseGate::new(Arc::clone(&ctx.paused));
 
     info!("Starting log collection loop for '{}'", metric_name);
+    let metric_settings = settings.metric(metric_name);
+    let interval_secs = gap_interval(collect_timeout_for(metric_name, &settings), &metric_settings);
+    seq.mark_gap(&ctx, collection, metric_name, interval_secs, &metric_settings).await;
+    let first_at = first_collection_at(&metric_settings, collect_timeout_for(metric_name, &settings));
 
     loop {
-        let mut collect_timer = interval(Duration::from_secs(collect_timeout_for(metric_name, &settings)));
+        let base_secs = collect_timeout_for(metric_name, &settings);
+        let collect_timeout = ctx.collect_interval(&mut adaptive, metric_name, base_secs, &settings);
+        let mut collect_timer = window_timer(collect_timeout, first_at);
         let reload_sleep = tokio::time::sleep(Duration::from_secs(settings.store_timeout));
         tokio::pin!(reload_sleep);
 
         loop {
             select! {
                 _ = collect_timer.tick() => {
-                    match collector.collect(&node_id).await {
-                        Ok(doc) => storage.store_metric_safe(collection, metric_name, doc).await,
+                    drift.tick(collect_timeout, &settings.drift, metric_name);
+                    if pause.skip(metric_name) {
+                        continue;
+                    }
+                    let metric_settings = settings.metric(metric_name);
+                    let now = Instant::now();
+                    match collector.collect(&ctx.node_id).await {
+                        Ok(mut doc) => {
+                            stamp_timestamp(&mut doc, ctx.clock.now(), ctx.timestamp_precision);
+                            let found = check_anomalies(&ctx, &mut anomalies, metric_name, &doc, &settings);
+                            held.add(&ctx, collection, found).await;
+                            stamp_monotonic(&mut doc, &metric_settings);
+                            stamp_config_revision(&mut doc, &settings);
+                            pipeline.refresh(&settings.transforms, &settings.scrub);
+                            pipeline.apply(metric_name, &mut doc);
+
+                            if ctx.in_startup_grace(metric_name, &doc) {
+                                // Dropped: no seq is consumed, so storage starts at the next seq
+                            } else if skip_empty(metric_name, &doc, &metric_settings) {
+                                // Nothing to record; no seq is consumed either
+                            } else if !changes.should_store(&doc, &metric_settings, now) {
+                                debug!("'{}' unchanged, skipping store", metric_name);
+                            } else if sampler.keep(metric_settings.sample_rate) {
+                                let stored = seq
+                                    .store(&ctx.storage, collection, metric_name, doc.clone(), &metric_settings, held.take())
+                                    .await;
+                                if stored {
+                                    changes.stored(&doc, now);
+                                }
+                                health.observe(stored, &ctx.events, metric_name).await;
+                            }
+                            held.flush(&ctx).await;
+                        }
                         Err(e)  => error!("Failed to collect '{}': {}", metric_name, e),
                     }
                 }
                 _ = &mut reload_sleep => { break; }
+                _ = wait_for_reload(&mut ctx.reload) => {
+                    info!("Reloading settings for '{}'", metric_name);
+                    break;
+                }
+                _ = wait_for_shutdown(&mut ctx.shutdown) => {
+                    info!("Stopping log collection loop for '{}'", metric_name);
+                    return;
+                }
             }
         }
+        drift.finish_window(&settings.drift, &ctx.events, metric_name).await;
+
+        reload_settings(&ctx, metric_name, &mut settings).await;
+    }
+}
+
+/// Collection DockerStats is stored in: `docker.container_collection` with
+/// `docker.storage: "per_container"`, else its `collection_name`
+pub fn docker_collection(metric_name: &str, settings: &MonitoringSettings) -> String {
+    match settings.docker.storage {
+        DockerStorage::PerContainer => settings.docker.container_collection.clone(),
+        DockerStorage::Combined => collection_name(metric_name, settings),
+    }
+}
 
-        match config_manager.reload_settings(&node_id).await {
-            Ok(new) => settings = new,
-            Err(e)  => warn!("Failed to reload settings for '{}': {}", metric_name, e),
+/// Creates the per-container time-series collection unless it exists
+async fn ensure_time_series(storage: &MetricStorage, collection: &str) {
+    match storage.create_time_series(collection, "container").await {
+        Ok(true) => info!("Created time-series collection '{}' (meta field 'container')", collection),
+        Ok(false) => debug!("Collection '{}' already exists", collection),
+        Err(e) => warn!("Failed to create time-series collection '{}', rows go to a regular one: {}", collection, e),
+    }
+}
+
+/// Moves each container's `id` and `name` into a `container` sub-document,
+/// the meta field of the per-container time-series collection
+///
+/// Unwinding the result on `containers` gives one row per container, with
+/// `container` at the top level as time-series collections require.
+fn with_container_meta(mut doc: Document) -> Document {
+    let Ok(containers) = doc.get_array_mut("containers") else {
+        return doc;
+    };
+    for container in containers.iter_mut().filter_map(|c| c.as_document_mut()) {
+        let mut meta = Document::new();
+        for key in ["id", "name"] {
+            if let Some(value) = container.remove(key) {
+                meta.insert(key, value);
+            }
         }
+        container.insert("container", meta);
     }
+    doc
 }
 
 /// Collection + aggregation loop for DockerStats.
+///
+/// On shutdown the current window is flushed early so buffered samples aren't lost;
+/// a reload request (SIGHUP) flushes it early too, then reloads the settings.
 async fn run_docker_task(
     collector: Box<dyn MetricCollector>,
-    storage: Arc<MetricStorage>,
-    config_manager: Arc<ConfigManager>,
-    node_id: String,
+    mut ctx: TaskContext,
     mut settings: MonitoringSettings,
 ) {
     let metric_name = collector.name();
-    let collection  = collection_for(metric_name);
-    let mut buffer  = DockerMetricBuffer::new();
+    // Read once at startup, like the rest of the docker section
+    let per_container = settings.docker.storage == DockerStorage::PerContainer;
+    let collection  = &docker_collection(metric_name, &settings);
+    let mut buffer  = DockerMetricBuffer::new().with_size_units(settings.numeric.size_units);
+    let mut seq     = Sequence::resume(&ctx.storage, collection, &ctx.node_id).await;
+    let mut health  = StorageHealth::default();
+    let mut sampler = Sampler::new(&ctx.node_id, metric_name);
+    let mut drift   = DriftMonitor::default();
+    let mut changes = ChangeFilter::default();
+    let mut anomalies = AnomalyDetector::default();
+    let mut held      = HeldAnomalies::default();
+    let mut adaptive = AdaptiveInterval::default();
+    let mut pipeline = Pipeline::with_auto_tags(&ctx.auto_tags);
+    let mut pause   = PauseGate::new(Arc::clone(&ctx.paused));
 
     info!("Starting collection loop for '{}'", metric_name);
+    let metric_settings = settings.metric(metric_name);
+    let interval_secs = gap_interval(settings.store_timeout, &metric_settings);
+    seq.mark_gap(&ctx, collection, metric_name, interval_secs, &metric_settings).await;
 
-    loop {
-        let mut collect_timer = interval(Duration::from_secs(settings.collect_docker_timeout));
+    if per_container && metric_settings.unwind.is_some() {
+        warn!("'{}': unwind ignored, documents are stored per container", metric_name);
+    }
+    let mut deltas = None;
+    if settings.docker.delta_snapshots {
+        if per_container {
+            warn!("'{}': docker.delta_snapshots ignored, documents are stored per container", metric_name);
+        } else if metric_settings.unwind.is_some() {
+            warn!("'{}': docker.delta_snapshots ignored, documents are unwound", metric_name);
+        } else {
+            deltas = Some(DeltaEncoder::new(&settings.docker));
+        }
+    }
+
+    let first_at = first_collection_at(&metric_settings, settings.collect_docker_timeout);
+
+    let mut stopping = false;
+    while !stopping {
+        let mut reload_now = false;
+        let collect_secs = ctx.collect_interval(&mut adaptive, metric_name, settings.collect_docker_timeout, &settings);
+        let mut collect_timer = window_timer(collect_secs, first_at);
         let flush_sleep = tokio::time::sleep(Duration::from_secs(settings.store_timeout));
         tokio::pin!(flush_sleep);
 
         loop {
             select! {
                 _ = collect_timer.tick() => {
-                    match collector.collect(&node_id).await {
-                        Ok(doc) => buffer.push(&doc),
+                    drift.tick(collect_secs, &settings.drift, metric_name);
+                    if pause.skip(metric_name) {
+                        continue;
+                    }
+                    match collector.collect(&ctx.node_id).await {
+                        Ok(doc) => {
+                            let found = check_anomalies(&ctx, &mut anomalies, metric_name, &doc, &settings);
+                            held.add(&ctx, collection, found).await;
+                            buffer.push(&doc);
+                        }
                         Err(e)  => {
                             error!("Failed to collect '{}': {}", metric_name, e);
                             warn!(
@@ -278,18 +1559,533 @@ async fn run_docker_task(
                     }
                 }
                 _ = &mut flush_sleep => { break; }
+                _ = wait_for_reload(&mut ctx.reload) => {
+                    info!("Flushing '{}' early and reloading settings", metric_name);
+                    reload_now = true;
+                    break;
+                }
+                _ = wait_for_shutdown(&mut ctx.shutdown) => { stopping = true; break; }
             }
         }
+        drift.finish_window(&settings.drift, &ctx.events, metric_name).await;
 
-        match buffer.flush(&node_id) {
-            Some(doc) => {
-                storage.store_metric_safe(collection, metric_name, doc).await;
-                match config_manager.reload_settings(&node_id).await {
-                    Ok(new)  => settings = new,
-                    Err(e)   => warn!("Failed to reload settings for '{}': {}", metric_name, e),
+        let flushed_at = ctx.clock.now();
+        match buffer.flush(&ctx.node_id, flushed_at) {
+            Some(mut doc) => {
+                stamp_timestamp(&mut doc, flushed_at, ctx.timestamp_precision);
+                stamp_monotonic(&mut doc, &settings.metric(metric_name));
+                stamp_config_revision(&mut doc, &settings);
+                pipeline.refresh(&settings.transforms, &settings.scrub);
+                pipeline.apply(metric_name, &mut doc);
+
+                // Sampled-out windows take no seq, so gaps still mean lost data
+                let metric_settings = settings.metric(metric_name);
+                let now = Instant::now();
+                if ctx.in_startup_grace(metric_name, &doc) {
+                    // Dropped: no seq is consumed, so storage starts at the next seq
+                } else if skip_empty(metric_name, &doc, &metric_settings) {
+                    // Nothing to record; no seq is consumed either
+                } else if !changes.should_store(&doc, &metric_settings, now) {
+                    debug!("'{}' unchanged, skipping store", metric_name);
+                } else if sampler.keep(metric_settings.sample_rate) {
+                    let to_store = match &mut deltas {
+                        Some(deltas) => deltas.encode(doc.clone(), seq.next),
+                        None => doc.clone(),
+                    };
+                    let stored = if per_container {
+                        let rows = MetricSettings { unwind: Some("containers".to_string()), ..metric_settings.clone() };
+                        seq.store(&ctx.storage, collection, metric_name, with_container_meta(to_store), &rows, held.take())
+                            .await
+                    } else {
+                        seq.store(&ctx.storage, collection, metric_name, to_store, &metric_settings, held.take())
+                            .await
+                    };
+                    if let Some(deltas) = &mut deltas {
+                        deltas.stored(stored);
+                    }
+                    if stored {
+                        changes.stored(&doc, now);
+                    }
+                    health.observe(stored, &ctx.events, metric_name).await;
+                }
+                if !stopping {
+                    reload_settings(&ctx, metric_name, &mut settings).await;
                 }
             }
+            // Nothing to flush, but a reload was asked for
+            None if reload_now => reload_settings(&ctx, metric_name, &mut settings).await,
+            None if pause.paused => debug!("'{}' paused, nothing to flush", metric_name),
             None => warn!("Not enough samples for '{}', skipping flush", metric_name),
         }
+        // Anomalies of a window whose document wasn't stored
+        held.flush(&ctx).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use crate::config::StaticSettingsSource;
+    use crate::metrics::testing::{ConstantCollector, FailingCollector};
+    use crate::storage::memory::MemoryBackend;
+    use bson::doc;
+
+    fn test_settings() -> MonitoringSettings {
+        serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+        }))
+        .unwrap()
+    }
+
+    /// Node "n1"'s scheduler over `settings`, storing to the returned
+    /// in-memory backend until `true` is sent on the returned sender
+    async fn test_scheduler(
+        settings: &MonitoringSettings,
+        clock: SharedClock,
+    ) -> (MetricScheduler, MemoryBackend, watch::Sender<bool>) {
+        let config_manager = ConfigManager::for_tests(Box::new(StaticSettingsSource(settings.clone()))).await;
+        let backend = MemoryBackend::new();
+        let storage = MetricStorage::new(config_manager.client(), config_manager.database_name())
+            .with_backend(Box::new(backend.clone()));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let scheduler =
+            MetricScheduler::new(Arc::new(config_manager), Arc::new(storage), "n1".to_string(), clock, shutdown_rx);
+        (scheduler, backend, shutdown_tx)
+    }
+
+    /// Runs one node's scheduler against an in-memory backend for `run_for`
+    async fn run_scheduler(
+        collectors: Vec<Box<dyn MetricCollector>>,
+        settings: MonitoringSettings,
+        run_for: Duration,
+    ) -> MemoryBackend {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let (scheduler, backend, shutdown_tx) = test_scheduler(&settings, clock).await;
+        let run = tokio::spawn(scheduler.start(collectors, settings));
+        tokio::time::sleep(run_for).await;
+        shutdown_tx.send(true).unwrap();
+        run.await.unwrap();
+        backend
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_standard_task_aggregates_each_window() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![Box::new(ConstantCollector::new(
+            "Memory",
+            doc! { "used_percent": 40.0, "total_mib": 1024_i64 },
+            clock,
+        ))];
+
+        // Collecting every 7s keeps every tick off the 60s window boundaries
+        // and the 150s shutdown, so each sample lands in a known window
+        let mut settings = test_settings();
+        settings.collect_timeout = 7;
+
+        // Two full 60s windows, then shutdown flushes the partial third one
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(150)).await;
+        let docs = backend.documents("memory_metrics");
+
+        assert_eq!(docs.len(), 3);
+        // Ticks at 0..=56s and 60..=116s, then 120..=148s
+        let counts: Vec<i32> = docs.iter().map(|d| d.get_i32("sample_count").unwrap()).collect();
+        assert_eq!(counts, [9, 9, 5]);
+        assert_eq!(docs[0].get_document("used_percent").unwrap(), &doc! { "avg": 40.0, "min": 40.0, "max": 40.0 });
+        let seqs: Vec<i64> = docs.iter().map(|d| d.get_i64("seq").unwrap()).collect();
+        assert_eq!(seqs, [1, 2, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_config_revision_is_stamped() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, clock))];
+        let mut settings = test_settings();
+        settings.config_revision = Some(bson::Bson::String("a1b2c3d".to_string()));
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(70)).await;
+        let docs = backend.documents("memory_metrics");
+        assert!(!docs.is_empty());
+        assert!(docs.iter().all(|d| d.get_str("config_revision") == Ok("a1b2c3d")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_signal_flushes_window_early() {
+        let settings = test_settings();
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock)))];
+        let (reload_tx, reload_rx) = watch::channel(());
+
+        let (scheduler, backend, shutdown_tx) = test_scheduler(&settings, clock).await;
+        let scheduler = scheduler.with_reload_signal(reload_rx);
+        let run = tokio::spawn(scheduler.start(collectors, settings));
+        // Ticks at 0, 5, ..., 20s, then the reload flushes the window
+        tokio::time::sleep(Duration::from_secs(22)).await;
+        reload_tx.send_replace(());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(backend.documents("memory_metrics").len(), 1);
+
+        // The task carries on with a fresh window: ticks at 22, 27 and 32s
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        shutdown_tx.send(true).unwrap();
+        run.await.unwrap();
+        let counts: Vec<i32> = backend
+            .documents("memory_metrics")
+            .iter()
+            .map(|d| d.get_i32("sample_count").unwrap())
+            .collect();
+        assert_eq!(counts, [5, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_once_reports_each_metric_in_order() {
+        let settings = test_settings();
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let (scheduler, backend, _shutdown_tx) = test_scheduler(&settings, Arc::clone(&clock)).await;
+
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock))),
+            Box::new(FailingCollector::new("DiskSpace", 1, doc! {}, Arc::clone(&clock))),
+            Box::new(ConstantCollector::new("LoadAverage", doc! { "load_1min": 0.5 }, clock)),
+        ];
+        let outcomes = scheduler.collect_once(collectors, &settings, true, None).await;
+
+        let summary: Vec<(&str, bool, bool)> = outcomes
+            .iter()
+            .map(|o| (o.metric_name.as_str(), o.result.is_ok(), o.stored))
+            .collect();
+        assert_eq!(summary, [("Memory", true, true), ("DiskSpace", false, false), ("LoadAverage", true, true)]);
+        assert_eq!(backend.documents("memory_metrics").len(), 1);
+        assert!(backend.documents("disk_metrics").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_precision_adds_epoch_field() {
+        let settings = test_settings();
+        let at = chrono::DateTime::from_timestamp(1_775_649_605, 250_123_456).unwrap();
+        let clock: SharedClock = Arc::new(FakeClock::new(at));
+        let (scheduler, backend, _shutdown_tx) = test_scheduler(&settings, Arc::clone(&clock)).await;
+        let scheduler = scheduler.with_timestamp_precision(TimestampPrecision::Nanos);
+
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, clock))];
+        scheduler.collect_once(collectors, &settings, true, None).await;
+
+        let stored = backend.documents("memory_metrics");
+        assert_eq!(stored[0].get_i64("timestamp_ns").unwrap(), 1_775_649_605_250_123_456);
+        // The BSON date keeps the same instant, truncated to milliseconds
+        assert_eq!(stored[0].get_datetime("timestamp").unwrap().timestamp_millis(), 1_775_649_605_250);
+        assert!(!stored[0].contains_key("timestamp_us"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_metric_node_id_override() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock))),
+            Box::new(ConstantCollector::new("LoadAverage", doc! { "load_1min": 0.5 }, clock)),
+        ];
+        let mut settings = test_settings();
+        settings.metrics.insert(
+            "Memory".to_string(),
+            MetricSettings { node_id: Some("proxied".to_string()), ..Default::default() },
+        );
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(70)).await;
+        let node = |collection: &str| backend.documents(collection)[0].get_str("node").unwrap().to_string();
+        assert_eq!(node("memory_metrics"), "proxied");
+        assert_eq!(node("load_average_metrics"), "n1");
+    }
+
+    #[tokio::test]
+    async fn test_trial_required_metrics_reports_failing_and_missing() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock))),
+            Box::new(FailingCollector::new("DockerStats", 1, doc! {}, Arc::clone(&clock))),
+            Box::new(FailingCollector::new("DockerLogs", 1, doc! {}, clock)),
+        ];
+        let mut settings = test_settings();
+        for name in ["Memory", "DockerStats", "DiskSmart"] {
+            settings.metrics.insert(name.to_string(), MetricSettings { required: true, ..Default::default() });
+        }
+
+        // DockerLogs fails too, but isn't required
+        let failures = trial_required_metrics(&collectors, &settings).await;
+        assert_eq!(failures.len(), 2, "{:?}", failures);
+        assert!(failures[0].starts_with("'DiskSmart' is required on 'n1' but not collected"), "{}", failures[0]);
+        assert!(failures[1].starts_with("Required metric 'DockerStats' on 'n1' failed"), "{}", failures[1]);
+
+        settings.metrics.clear();
+        assert!(trial_required_metrics(&collectors, &settings).await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_monotonic_ns_is_opt_in() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock))),
+            Box::new(ConstantCollector::new("LoadAverage", doc! { "load_1min": 0.5 }, clock)),
+        ];
+        let mut settings = test_settings();
+        settings.metrics.insert("Memory".to_string(), MetricSettings { monotonic: true, ..Default::default() });
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(130)).await;
+        let stamps: Vec<i64> = backend
+            .documents("memory_metrics")
+            .iter()
+            .map(|d| d.get_i64("monotonic_ns").unwrap())
+            .collect();
+        // Flushes at 60 and 120s, 60s apart on the monotonic clock
+        assert!((59_000_000_000..=61_000_000_000).contains(&(stamps[1] - stamps[0])), "{:?}", stamps);
+        assert!(!backend.documents("load_average_metrics")[0].contains_key("monotonic_ns"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_collection_template_and_override() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, Arc::clone(&clock))),
+            Box::new(ConstantCollector::new("LoadAverage", doc! { "load_1min": 0.5 }, clock)),
+        ];
+        let mut settings = test_settings();
+        settings.collection_template = Some("{metric_snake}_samples".to_string());
+        settings.metrics.insert(
+            "Memory".to_string(),
+            MetricSettings { collection: Some("ram".to_string()), ..Default::default() },
+        );
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(70)).await;
+        assert!(!backend.documents("ram").is_empty());
+        assert!(!backend.documents("load_average_samples").is_empty());
+        assert!(backend.documents("memory_metrics").is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_docker_per_container_rows() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let stats = doc! { "containers": [
+            { "id": "a1", "name": "api", "cpu_percent": 10.0, "memory_percent": 20.0 },
+            { "id": "b2", "name": "db", "cpu_percent": 1.0, "memory_percent": 60.0 },
+        ] };
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("DockerStats", stats, clock))];
+        let mut settings = test_settings();
+        settings.docker.storage = DockerStorage::PerContainer;
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(70)).await;
+        assert!(backend.documents("docker_metrics").is_empty());
+        let rows = backend.documents("docker_container_metrics");
+        assert!(!rows.is_empty());
+        for row in &rows {
+            assert!(row.get("id").is_none() && row.get("containers").is_none());
+            let container = row.get_document("container").unwrap();
+            assert!(["a1", "b2"].contains(&container.get_str("id").unwrap()));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_task_skips_failed_collections() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![Box::new(FailingCollector::new(
+            "SystemEvents",
+            2,
+            doc! { "events": [] },
+            clock,
+        ))];
+
+        // Ticks at 0, 5, ..., 25s: the first two fail
+        let backend = run_scheduler(collectors, test_settings(), Duration::from_secs(27)).await;
+        let docs = backend.documents("system_event_logs");
+
+        let seqs: Vec<i64> = docs.iter().map(|d| d.get_i64("seq").unwrap()).collect();
+        assert_eq!(seqs, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_canary_written_while_collectors_fail() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(FailingCollector::new("SystemEvents", u32::MAX, doc! { "events": [] }, clock.clone())),
+            Box::new(crate::metrics::canary::CanaryCollector::new().with_clock(clock)),
+        ];
+        let mut settings = test_settings();
+        settings.canary.interval_secs = 10;
+
+        // Canary ticks at 0, 10, 20 and 30s
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(35)).await;
+        assert!(backend.documents("system_event_logs").is_empty());
+        let docs = backend.documents("canary");
+        let seqs: Vec<i64> = docs.iter().map(|d| d.get_i64("seq").unwrap()).collect();
+        assert_eq!(seqs, [1, 2, 3, 4]);
+        assert_eq!(docs[0].get_str("node").unwrap(), "n1");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_collect_immediately_false_delays_first_collection() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![
+            Box::new(ConstantCollector::new("SystemEvents", doc! { "events": [] }, clock.clone())),
+            Box::new(ConstantCollector::new("Memory", doc! { "used_percent": 40.0 }, clock)),
+        ];
+        let settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "metrics": {
+                "SystemEvents": { "collect_immediately": false },
+                "Memory": { "collect_immediately": false },
+            },
+        }))
+        .unwrap();
+
+        // Nothing at 0s: the first ticks come at 5s and 10s
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(12)).await;
+        assert_eq!(backend.documents("system_event_logs").len(), 2);
+        let memory = backend.documents("memory_metrics");
+        assert_eq!(memory[0].get_i32("sample_count").unwrap(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_empty_documents_skipped_unless_store_when_empty() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("SystemEvents", doc! { "events": [] }, clock))];
+        let settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "metrics": { "SystemEvents": { "store_when_empty": false } },
+        }))
+        .unwrap();
+
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(27)).await;
+        assert!(backend.documents("system_event_logs").is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_store_on_change_only_ignores_monotonic_ns() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("SystemEvents", doc! { "events": ["boot"] }, clock))];
+        let settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "metrics": { "SystemEvents": { "monotonic": true, "store_on_change_only": true, "max_unchanged_secs": 600 } },
+        }))
+        .unwrap();
+
+        // Six identical collections; only the first is stored, still with its monotonic_ns
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(27)).await;
+        let stored = backend.documents("system_event_logs");
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].contains_key("monotonic_ns"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_store_on_change_only_ignores_precise_timestamp() {
+        let settings: MonitoringSettings = serde_json::from_value(serde_json::json!({
+            "key": "n1", "collect_timeout": 5, "collect_docker_timeout": 20, "store_timeout": 60,
+            "metrics": { "SystemEvents": { "store_on_change_only": true, "max_unchanged_secs": 600 } },
+        }))
+        .unwrap();
+        let fake = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let clock: SharedClock = fake.clone();
+        let (scheduler, backend, shutdown_tx) = test_scheduler(&settings, Arc::clone(&clock)).await;
+        let scheduler = scheduler.with_timestamp_precision(TimestampPrecision::Nanos);
+
+        let collectors: Vec<Box<dyn MetricCollector>> =
+            vec![Box::new(ConstantCollector::new("SystemEvents", doc! { "events": ["boot"] }, clock))];
+        let run = tokio::spawn(scheduler.start(collectors, settings));
+        // Six identical collections, each stamped with a different timestamp_ns
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            fake.advance(chrono::Duration::milliseconds(5_000_123));
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        shutdown_tx.send(true).unwrap();
+        run.await.unwrap();
+
+        let stored = backend.documents("system_event_logs");
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].contains_key("timestamp_ns"));
+    }
+
+    #[test]
+    fn test_sampler_seed_is_a_fixed_hash() {
+        assert_eq!(fnv1a(&[]), 0xcbf2_9ce4_8422_2325);
+        // Pinned: a change here changes every node's sampling pattern
+        assert_eq!(fnv1a(&["n1", "Memory"]), 0xa104_db91_d392_21b9);
+        assert_ne!(fnv1a(&["n1", "Memory"]), fnv1a(&["n1M", "emory"]));
+    }
+
+    #[test]
+    fn test_change_filter_skips_repeats_until_max_interval() {
+        let settings = MetricSettings { store_on_change_only: true, max_unchanged_secs: 60, ..MetricSettings::default() };
+        let start = Instant::now();
+        let first = doc! { "node": "n1", "timestamp": bson::DateTime::from_millis(0), "used_percent": 50.0 };
+        let repeat = doc! { "node": "n1", "timestamp": bson::DateTime::from_millis(30_000), "used_percent": 50.0 };
+        let changed = doc! { "node": "n1", "timestamp": bson::DateTime::from_millis(30_000), "used_percent": 51.0 };
+
+        let mut changes = ChangeFilter::default();
+        assert!(changes.should_store(&first, &settings, start));
+        changes.stored(&first, start);
+
+        assert!(!changes.should_store(&repeat, &settings, start + Duration::from_secs(30)));
+        assert!(changes.should_store(&changed, &settings, start + Duration::from_secs(30)));
+        assert!(changes.should_store(&repeat, &settings, start + Duration::from_secs(60)));
+
+        let always = MetricSettings::default();
+        assert!(changes.should_store(&repeat, &always, start + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_gap_marker_after_long_silence() {
+        let from = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let at = |secs: i64| from + chrono::Duration::seconds(secs);
+
+        // Two intervals late is still a normal restart
+        assert_eq!(gap_marker("n1", from, at(120), 60), None);
+
+        let marker = gap_marker("n1", from, at(3600), 60).unwrap();
+        assert_eq!(marker.get_str("status"), Ok("gap"));
+        assert_eq!(marker.get_datetime("from").unwrap().to_chrono(), from);
+        assert_eq!(marker.get_i64("gap_secs"), Ok(3600));
+
+        let on_change = MetricSettings { store_on_change_only: true, max_unchanged_secs: 600, ..MetricSettings::default() };
+        assert_eq!(gap_interval(60, &on_change), 600);
+    }
+
+    #[test]
+    fn test_pause_flags_only_known_metrics() {
+        let pause = PauseFlags::default();
+        let flag = pause.register("Processes");
+        let mut gate = PauseGate::new(Arc::clone(&flag));
+
+        assert!(!pause.set("Nope", true));
+        assert!(pause.set("Processes", true));
+        assert_eq!(pause.paused(), ["Processes"]);
+        assert!(gate.skip("Processes"));
+
+        assert!(pause.set("Processes", false));
+        assert!(pause.paused().is_empty());
+        assert!(!gate.skip("Processes"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_startup_grace_drops_early_documents() {
+        let clock: SharedClock = Arc::new(FakeClock::new(chrono::Utc::now()));
+        let collectors: Vec<Box<dyn MetricCollector>> = vec![Box::new(ConstantCollector::new(
+            "SystemEvents",
+            doc! { "events": [] },
+            clock,
+        ))];
+        let settings = MonitoringSettings { startup_grace_secs: 12, ..test_settings() };
+
+        // Ticks at 0, 5 and 10s fall in the grace period; 15, 20 and 25s are stored
+        let backend = run_scheduler(collectors, settings, Duration::from_secs(27)).await;
+        let seqs: Vec<i64> = backend
+            .documents("system_event_logs")
+            .iter()
+            .map(|d| d.get_i64("seq").unwrap())
+            .collect();
+        assert_eq!(seqs, [1, 2, 3]);
     }
 }
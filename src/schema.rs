@@ -0,0 +1,503 @@
+// Schema module - machine-readable units/type reference for stored documents
+//
+// Downstream consumers otherwise have to guess units from field-name suffixes.
+// When enabled, the scheduler writes one `metric_schema` document per metric
+// collection at startup, keyed by collection name, and rewrites it only when
+// `SCHEMA_VERSION` or the field list (size units) differs from the stored one.
+//
+// The same field tables back the opt-in `$jsonSchema` collection validators
+// (`metrics.<Name>.validate`): MongoDB then rejects a document missing
+// `node`, `timestamp` or `seq`, or holding a field of the wrong type, so a
+// collector regression fails loudly instead of storing garbage.
+
+use bson::{doc, Document};
+use tracing::{debug, info, warn};
+
+use crate::config::{SchemaSettings, SizeUnits};
+use crate::metrics::numeric::{size_field, size_unit, Scale};
+use crate::storage::MetricStorage;
+
+/// Bump whenever a field is added, removed, renamed or changes unit/type
+pub const SCHEMA_VERSION: i32 = 22;
+
+/// One field description: (field path, unit, BSON type)
+///
+/// Nested array fields use dotted paths (e.g. `disks.used_gib`). Type
+/// `aggregate` means `{ "avg", "min", "max" }` doubles; `long|decimal` is a
+/// raw byte counter whose type follows `numeric.byte_counts`. Sizes are
+/// listed in binary units and renamed per `numeric.size_units`.
+type FieldSpec = (&'static str, &'static str, &'static str);
+
+/// Fields present in every document written by the scheduler
+const COMMON_FIELDS: &[FieldSpec] = &[
+    ("node",      "none",     "string"),
+    ("timestamp", "datetime", "date"),
+    ("seq",       "count",    "long"),
+];
+
+/// Extra field present in aggregated (buffered) documents
+const SAMPLE_COUNT: FieldSpec = ("sample_count", "count", "int");
+
+/// Opt-in field of every metric (`metrics.<Name>.monotonic`)
+const MONOTONIC_NS: FieldSpec = ("monotonic_ns", "nanoseconds", "long");
+
+/// Returns the field descriptions specific to one metric, or None if unknown
+fn fields_for(metric_name: &str) -> Option<&'static [FieldSpec]> {
+    let fields: &'static [FieldSpec] = match metric_name {
+        "LoadAverage" => &[
+            ("load_1min",           "load",    "aggregate"),
+            ("load_5min",           "load",    "aggregate"),
+            ("load_15min",          "load",    "aggregate"),
+            ("load_1min_per_core",  "ratio",   "aggregate"),
+            ("load_5min_per_core",  "ratio",   "aggregate"),
+            ("load_15min_per_core", "ratio",   "aggregate"),
+            ("cpu_cores",           "count",   "int"),
+            ("overloaded",          "none",    "bool"),
+        ],
+        "Memory" => &[
+            ("total_mib",         "MiB",     "long"),
+            ("swap_total_mib",    "MiB",     "long"),
+            ("available_mib",     "MiB",     "aggregate"),
+            ("usable_mib",        "MiB",     "aggregate"),
+            ("used_percent",      "percent", "aggregate"),
+            ("swap_used_percent", "percent", "aggregate"),
+            ("cgroup_limited",    "none",    "bool"),
+            ("swap_devices",          "none",  "array"),
+            ("swap_devices.name",     "none",  "string"),
+            ("swap_devices.type",     "none",  "string"),
+            ("swap_devices.size_kib", "KiB",   "long"),
+            ("swap_devices.used_kib", "KiB",   "long"),
+            ("swap_devices.priority", "none",  "int"),
+        ],
+        "DiskSpace" => &[
+            ("disks",               "none",    "array"),
+            ("disks.mount_point",   "none",    "string"),
+            ("disks.device",        "none",    "string"),
+            ("disks.filesystem",    "none",    "string"),
+            ("disks.total_gib",     "GiB",     "double"),
+            ("disks.used_gib",      "GiB",     "double"),
+            ("disks.available_gib", "GiB",     "double"),
+            ("disks.used_percent",  "percent", "double"),
+            ("disks.low_space",     "none",    "bool"),
+            ("disks.estimated_days_until_full", "days", "double"),
+            ("disks.mount_points",  "none",    "array"),
+            ("disks.error",         "none",    "string"),
+        ],
+        "Network" => &[
+            ("interface_count",             "count",          "aggregate"),
+            ("total_rx_bytes_per_sec",      "bytes/s",        "aggregate"),
+            ("total_tx_bytes_per_sec",      "bytes/s",        "aggregate"),
+            ("interfaces",                  "none",           "array"),
+            ("interfaces.interface",        "none",           "string"),
+            ("interfaces.rx_bytes",         "bytes",          "long|decimal"),
+            ("interfaces.tx_bytes",         "bytes",          "long|decimal"),
+            ("interfaces.rx_bytes_per_sec", "bytes/s",        "double"),
+            ("interfaces.tx_bytes_per_sec", "bytes/s",        "double"),
+            ("ip_addresses",                "none",           "array"),
+            ("ip_addresses.interface",      "none",           "string"),
+            ("ip_addresses.address",        "none",           "string"),
+            ("ip_addresses.family",         "none",           "string"),
+        ],
+        "Entropy" => &[
+            ("entropy_avail", "bits", "aggregate"),
+        ],
+        "Pressure" => &[
+            ("cpu_some_avg10",     "percent", "aggregate"),
+            ("cpu_some_avg60",     "percent", "aggregate"),
+            ("cpu_some_avg300",    "percent", "aggregate"),
+            ("cpu_full_avg10",     "percent", "aggregate"),
+            ("cpu_full_avg60",     "percent", "aggregate"),
+            ("cpu_full_avg300",    "percent", "aggregate"),
+            ("io_some_avg10",      "percent", "aggregate"),
+            ("io_some_avg60",      "percent", "aggregate"),
+            ("io_some_avg300",     "percent", "aggregate"),
+            ("io_full_avg10",      "percent", "aggregate"),
+            ("io_full_avg60",      "percent", "aggregate"),
+            ("io_full_avg300",     "percent", "aggregate"),
+            ("memory_some_avg10",  "percent", "aggregate"),
+            ("memory_some_avg60",  "percent", "aggregate"),
+            ("memory_some_avg300", "percent", "aggregate"),
+            ("memory_full_avg10",  "percent", "aggregate"),
+            ("memory_full_avg60",  "percent", "aggregate"),
+            ("memory_full_avg300", "percent", "aggregate"),
+        ],
+        "DockerStats" => &[
+            ("total_containers",            "count",   "int"),
+            ("container_count",             "count",   "int"),
+            ("snapshot",                    "none",    "string"),
+            ("base_seq",                    "count",   "long"),
+            ("containers",                  "none",    "array"),
+            ("containers.id",               "none",    "string"),
+            ("containers.name",             "none",    "string"),
+            ("containers.cpu_percent",      "percent", "aggregate"),
+            ("containers.memory_used_mib",  "MiB",     "aggregate"),
+            ("containers.memory_limit_mib", "MiB",     "double"),
+            ("containers.memory_percent",   "percent", "aggregate"),
+            ("containers.network_rx_mib",   "MiB",     "double"),
+            ("containers.network_tx_mib",   "MiB",     "double"),
+            ("containers.block_read_mib",   "MiB",     "double"),
+            ("containers.block_write_mib",  "MiB",     "double"),
+            ("containers.gpu_memory_mib",   "MiB",     "aggregate"),
+            ("containers.gpu_percent",      "percent", "aggregate"),
+            ("containers.image",            "none",    "string"),
+            ("containers.image_id",         "none",    "string"),
+            ("containers.labels",           "none",    "object"),
+            ("containers.error",            "none",    "string"),
+            ("containers.error_count",      "count",   "int"),
+        ],
+        "ProcessCPUSnapshot" | "ProcessRAMSnapshot" => &[
+            ("processes",                "none",    "array"),
+            ("processes.pid",            "none",    "long"),
+            ("processes.name",           "none",    "string"),
+            ("processes.cpu_percent",    "percent", "double"),
+            ("processes.memory_mib",     "MiB",     "double"),
+            ("processes.memory_percent", "percent", "double"),
+            ("processes.status",         "none",    "string"),
+            ("processes.container",      "none",    "object"),
+            ("processes.container.id",   "none",    "string"),
+            ("processes.container.name", "none",    "string"),
+        ],
+        "DockerEvents" => &[
+            ("events",                "none",     "array"),
+            ("events.event_time",     "datetime", "date"),
+            ("events.container_id",   "none",     "string"),
+            ("events.container_name", "none",     "string"),
+            ("events.action",         "none",     "string"),
+        ],
+        "DockerLogs" => &[
+            ("containers",                   "none", "array"),
+            ("containers.container_id",      "none", "string"),
+            ("containers.container_name",    "none", "string"),
+            ("containers.log_lines",         "none", "array"),
+            ("containers.log_lines.stream",  "none", "string"),
+            ("containers.log_lines.time",    "none", "string"),
+            ("containers.log_lines.message", "none", "string"),
+            ("containers.truncated",         "none", "bool"),
+        ],
+        "SystemEvents" => &[
+            ("events",            "none",     "array"),
+            ("events.event_time", "datetime", "date"),
+            ("events.priority",   "none",     "string"),
+            ("events.unit",       "none",     "string"),
+            ("events.message",    "none",     "string"),
+            ("events.hostname",   "none",     "string"),
+        ],
+        "KernelLog" => &[
+            ("oom_kills",        "count", "long"),
+            ("kernel_errors",    "count", "long"),
+            ("last_oom_process", "none",  "string"),
+        ],
+        "DiskSmart" => &[
+            ("failed_count",                 "count",   "long"),
+            ("devices",                      "none",    "array"),
+            ("devices.device",               "none",    "string"),
+            ("devices.model",                "none",    "string"),
+            ("devices.serial",               "none",    "string"),
+            ("devices.health",               "none",    "string"),
+            ("devices.reallocated_sectors",  "count",   "long"),
+            ("devices.reallocated_increase", "count",   "long"),
+            ("devices.temperature_c",        "celsius", "long"),
+            ("devices.power_on_hours",       "hours",   "long"),
+            ("devices.error",                "none",    "string"),
+        ],
+        "DockerDisk" => &[
+            ("images_count",                "count", "long"),
+            ("images_size_mib",             "MiB",   "double"),
+            ("images_reclaimable_mib",      "MiB",   "double"),
+            ("containers_count",            "count", "long"),
+            ("containers_size_mib",         "MiB",   "double"),
+            ("containers_reclaimable_mib",  "MiB",   "double"),
+            ("volumes_count",               "count", "long"),
+            ("volumes_size_mib",            "MiB",   "double"),
+            ("volumes_reclaimable_mib",     "MiB",   "double"),
+            ("build_cache_size_mib",        "MiB",   "double"),
+            ("build_cache_reclaimable_mib", "MiB",   "double"),
+            ("total_size_mib",              "MiB",   "double"),
+            ("reclaimable_mib",             "MiB",   "double"),
+        ],
+        "Canary" => &[],
+        _ => return None,
+    };
+    Some(fields)
+}
+
+/// Field name and unit of a spec under `units` (binary sizes renamed, others as-is)
+fn localize(name: &str, unit: &'static str, units: SizeUnits) -> (String, &'static str) {
+    let scale = match unit {
+        "KiB" => Scale::Kilo,
+        "MiB" => Scale::Mega,
+        "GiB" => Scale::Giga,
+        _ => return (name.to_string(), unit),
+    };
+    let stem = &name[..name.len() - "_kib".len()];
+    (size_field(stem, scale, units), size_unit(scale, units))
+}
+
+/// Every field of one metric's documents as (name, unit, type) under `units`,
+/// or None if the metric is unknown
+pub fn fields(metric_name: &str, aggregated: bool, units: SizeUnits) -> Option<Vec<(String, &'static str, &'static str)>> {
+    let specific = fields_for(metric_name)?;
+
+    let sample_count = aggregated.then_some(&SAMPLE_COUNT);
+    let fields = COMMON_FIELDS
+        .iter()
+        .chain(sample_count)
+        .chain([&MONOTONIC_NS])
+        .chain(specific)
+        .map(|(name, unit, kind)| {
+            let (name, unit) = localize(name, unit, units);
+            (name, unit, *kind)
+        })
+        .collect();
+    Some(fields)
+}
+
+/// Builds the schema document for one metric collection
+fn schema_document(metric_name: &str, collection: &str, aggregated: bool, units: SizeUnits) -> Option<Document> {
+    let fields: Vec<Document> = fields(metric_name, aggregated, units)?
+        .into_iter()
+        .map(|(name, unit, kind)| doc! { "field": name, "unit": unit, "type": kind })
+        .collect();
+
+    Some(doc! {
+        "_id": collection,
+        "collection": collection,
+        "metric": metric_name,
+        "schema_version": SCHEMA_VERSION,
+        "fields": fields,
+    })
+}
+
+/// `$jsonSchema` for one field type
+///
+/// Numbers accept any numeric BSON type, since a collector may store a count
+/// as int or long. An aggregate may also be a plain number: a window flushed
+/// with a single sample (shutdown, reload, adaptive intervals) stores the
+/// raw value. Apart from the common fields, any value may also be null:
+/// non-finite doubles are stored as null.
+fn property_schema(kind: &str, nullable: bool) -> Document {
+    let bson_type = match kind {
+        "string" => "string",
+        "date" => "date",
+        "bool" => "bool",
+        "array" => "array",
+        "object" => "object",
+        "aggregate" => return aggregate_schema(),
+        _ => "number",
+    };
+    if nullable {
+        doc! { "bsonType": [bson_type, "null"] }
+    } else {
+        doc! { "bsonType": bson_type }
+    }
+}
+
+/// `$jsonSchema` of an aggregate: `{ "avg", "min", "max" }`, or the raw
+/// number of a single-sample window
+fn aggregate_schema() -> Document {
+    let number = property_schema("double", true);
+    doc! {
+        "bsonType": ["number", "object", "null"],
+        "properties": { "avg": number.clone(), "min": number.clone(), "max": number },
+    }
+}
+
+/// Adds the property at dotted `path` below `properties`
+///
+/// Parents come first in the field tables; a child of an array describes
+/// its elements (`items`), a child of an object its fields.
+fn insert_property(properties: &mut Document, path: &str, kind: &str) {
+    let Some((parent, rest)) = path.split_once('.') else {
+        properties.insert(path, property_schema(kind, true));
+        return;
+    };
+    let Ok(parent) = properties.get_document_mut(parent) else {
+        return;
+    };
+    let is_array = parent.get_array("bsonType").is_ok_and(|types| types.contains(&"array".into()));
+    let container = if is_array {
+        if !parent.contains_key("items") {
+            parent.insert("items", doc! { "bsonType": "object" });
+        }
+        parent.get_document_mut("items").expect("items was just inserted")
+    } else {
+        parent
+    };
+    if !container.contains_key("properties") {
+        container.insert("properties", Document::new());
+    }
+    let children = container.get_document_mut("properties").expect("properties was just inserted");
+    insert_property(children, rest, kind);
+}
+
+/// Builds the `$jsonSchema` validator for one metric collection
+///
+/// Only the common fields are required: documents that carry just an
+/// `error`, gap markers and unwound rows all lack the metric's own fields.
+fn validator(metric_name: &str, aggregated: bool, units: SizeUnits) -> Option<Document> {
+    let specific = fields_for(metric_name)?;
+
+    let mut properties = Document::new();
+    for (name, _, kind) in COMMON_FIELDS {
+        properties.insert(*name, property_schema(kind, false));
+    }
+    let sample_count = aggregated.then_some(&SAMPLE_COUNT);
+    for (name, unit, kind) in sample_count.into_iter().chain([&MONOTONIC_NS]).chain(specific) {
+        let (name, _) = localize(name, unit, units);
+        insert_property(&mut properties, &name, kind);
+    }
+
+    let required: Vec<&str> = COMMON_FIELDS.iter().map(|(name, _, _)| *name).collect();
+    Some(doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": required,
+            "properties": properties,
+        }
+    })
+}
+
+/// Creates or updates the `$jsonSchema` validator of a metric collection
+///
+/// Arguments as for `publish_schema`. Failures (e.g. missing `collMod`
+/// privilege) are logged and otherwise ignored.
+pub async fn apply_validator(
+    storage: &MetricStorage,
+    metric_name: &str,
+    collection: &str,
+    aggregated: bool,
+    units: SizeUnits,
+) {
+    let Some(validator) = validator(metric_name, aggregated, units) else {
+        warn!("No schema description for metric '{}', not validating '{}'", metric_name, collection);
+        return;
+    };
+    match storage.set_validator(collection, validator).await {
+        Ok(()) => info!("Validator for '{}' set from schema v{}", collection, SCHEMA_VERSION),
+        Err(e) => warn!("Failed to set validator for '{}': {}", collection, e),
+    }
+}
+
+/// Writes the schema document for a collection unless the stored one is current
+///
+/// `aggregated` is true for metrics written through an aggregation buffer
+/// (adds `sample_count`); `units` is `numeric.size_units`. Failures are
+/// logged and otherwise ignored.
+pub async fn publish_schema(
+    storage: &MetricStorage,
+    settings: &SchemaSettings,
+    metric_name: &str,
+    collection: &str,
+    aggregated: bool,
+    units: SizeUnits,
+) {
+    let Some(schema) = schema_document(metric_name, collection, aggregated, units) else {
+        debug!("No schema description for metric '{}'", metric_name);
+        return;
+    };
+
+    match storage.find_by_id(&settings.collection, collection).await {
+        Ok(Some(existing))
+            if existing.get_i32("schema_version").ok() == Some(SCHEMA_VERSION)
+                && existing.get_array("fields").ok() == schema.get_array("fields").ok() =>
+        {
+            debug!("Schema for '{}' is current (v{})", collection, SCHEMA_VERSION);
+        }
+        Ok(_) => match storage.replace_by_id(&settings.collection, collection, schema).await {
+            Ok(()) => info!("Published schema v{} for '{}'", SCHEMA_VERSION, collection),
+            Err(e) => warn!("Failed to publish schema for '{}': {}", collection, e),
+        },
+        Err(e) => warn!("Failed to read schema for '{}': {}", collection, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::MetricBuffer;
+    use bson::Bson;
+
+    /// Whether `value` passes `schema`'s `bsonType` and `properties`, the
+    /// only keywords the generated validators use
+    fn conforms(value: &Bson, schema: &Document) -> bool {
+        let type_name = match value {
+            Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Decimal128(_) => "number",
+            Bson::String(_) => "string",
+            Bson::DateTime(_) => "date",
+            Bson::Boolean(_) => "bool",
+            Bson::Array(_) => "array",
+            Bson::Document(_) => "object",
+            Bson::Null => "null",
+            _ => "other",
+        };
+        let type_ok = match schema.get("bsonType") {
+            Some(Bson::String(t)) => t == type_name,
+            Some(Bson::Array(types)) => types.iter().any(|t| t.as_str() == Some(type_name)),
+            _ => true,
+        };
+        let (Bson::Document(fields), Ok(properties)) = (value, schema.get_document("properties")) else {
+            return type_ok;
+        };
+        type_ok
+            && fields.iter().all(|(key, value)| match properties.get_document(key) {
+                Ok(property) => conforms(value, property),
+                Err(_) => true,
+            })
+    }
+
+    #[test]
+    fn test_validator_accepts_single_sample_flush() {
+        let validator = validator("LoadAverage", true, SizeUnits::Binary).unwrap();
+        let schema = validator.get_document("$jsonSchema").unwrap();
+        let sample = doc! {
+            "node": "n1",
+            "timestamp": chrono::Utc::now(),
+            "load_1min": 1.5,
+            "load_1min_per_core": 0.4,
+            "cpu_cores": 4,
+            "overloaded": false,
+        };
+
+        // One sample (shutdown or reload flush): raw doubles
+        let mut buffer = MetricBuffer::new();
+        buffer.push(&sample);
+        let mut single = buffer.flush("n1", chrono::Utc::now()).unwrap();
+        single.insert("seq", 1_i64);
+        assert!(single.get_f64("load_1min").is_ok());
+        assert!(conforms(&Bson::Document(single), schema));
+
+        // Two samples: { avg, min, max }
+        buffer.push(&sample);
+        buffer.push(&sample);
+        let mut aggregated = buffer.flush("n1", chrono::Utc::now()).unwrap();
+        aggregated.insert("seq", 2_i64);
+        assert!(aggregated.get_document("load_1min").is_ok());
+        assert!(conforms(&Bson::Document(aggregated.clone()), schema));
+
+        // A wrong type is still caught
+        aggregated.insert("load_1min", "high");
+        assert!(!conforms(&Bson::Document(aggregated), schema));
+    }
+
+    #[test]
+    fn test_validator_nests_array_fields() {
+        let validator = validator("DiskSpace", true, SizeUnits::Binary).unwrap();
+        let schema = validator.get_document("$jsonSchema").unwrap();
+        let required: Vec<&str> = schema.get_array("required").unwrap().iter().filter_map(|v| v.as_str()).collect();
+        assert_eq!(required, ["node", "timestamp", "seq"]);
+
+        let properties = schema.get_document("properties").unwrap();
+        assert_eq!(properties.get_document("seq").unwrap(), &doc! { "bsonType": "number" });
+        assert_eq!(
+            properties.get_document("sample_count").unwrap(),
+            &doc! { "bsonType": ["number", "null"] }
+        );
+        let disk = properties
+            .get_document("disks")
+            .and_then(|disks| disks.get_document("items"))
+            .and_then(|items| items.get_document("properties"))
+            .unwrap();
+        assert_eq!(disk.get_document("used_gib").unwrap(), &doc! { "bsonType": ["number", "null"] });
+        assert!(disk.contains_key("mount_points"));
+    }
+}
@@ -1,14 +1,39 @@
-// Storage module - handles metric data persistence to MongoDB
+// Storage module - handles metric data persistence
 //
 // This module is responsible for:
 // 1. Inserting metric documents into their respective collections
 // 2. Handling storage errors gracefully
 // 3. Providing a simple interface for the scheduler to store metrics
+//
+// Metric documents go through a `StorageBackend` (MongoDB by default, MongoDB
+// time buckets, Kafka and SQLite behind the `kafka` / `sqlite` features). Reads — seq resume, rollups, schema
+// documents — and index creation always use MongoDB, which also holds the settings.
+
+pub mod bucket;
+pub mod outage;
+pub mod serialize;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(test)]
+pub mod memory;
 
-use bson::Document;
+use async_trait::async_trait;
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, Utc};
+use futures_util::stream::TryStreamExt;
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::InsertOneOptions;
 use mongodb::{Client, Collection};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+use outage::{Held, OutageBuffer};
 
 /// Errors that can occur during metric storage
 #[derive(Error, Debug)]
@@ -16,21 +41,341 @@ pub enum StorageError {
     #[error("MongoDB insert failed: {0}")]
     InsertError(#[from] mongodb::error::Error),
 
+    #[error("MongoDB insert timed out after {0:?}")]
+    InsertTimeout(Duration),
+
     #[allow(dead_code)]
     #[error("Invalid document format: {0}")]
     InvalidDocument(String),
+
+    #[allow(dead_code)]
+    #[error("{backend} write failed: {message}")]
+    BackendError { backend: &'static str, message: String },
+}
+
+impl StorageError {
+    /// True if MongoDB refused the document itself (collection validator),
+    /// so storing the same document again can't succeed
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self,
+            StorageError::InsertError(e)
+                if matches!(e.kind.as_ref(), ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == DOCUMENT_VALIDATION_FAILURE)
+        )
+    }
+}
+
+/// Destination for metric documents
+///
+/// Implementations must not block collection for long: a backend that can't
+/// keep up should fail fast (and log) rather than wait.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Short backend name for logs (e.g. "mongodb", "kafka")
+    fn name(&self) -> &'static str;
+
+    /// Writes one document destined for `collection`
+    async fn insert(&self, collection: &str, document: Document) -> Result<(), StorageError>;
+}
+
+/// Hook told about every metric write made through `store_metric_safe`
+///
+/// Called once per document with the final outcome, after any retry;
+/// `duration` covers all attempts. A document held in the outage buffer is
+/// reported as failed, then again when it is finally written. Must return
+/// quickly: it runs on the collection task.
+pub trait StorageObserver: Send + Sync {
+    fn on_store(&self, collection: &str, metric_name: &str, success: bool, duration: Duration);
+}
+
+/// Retries after a failed store in `store_metric_safe`
+pub const STORE_RETRIES: u32 = 1;
+
+/// Pause before each retry in `store_metric_safe`
+pub const STORE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Default number of retries the shared budget allows in a burst (`--retry-budget`)
+pub const DEFAULT_RETRY_BUDGET: u32 = 10;
+
+/// Default rate the retry budget refills at, in retries per second (`--retry-budget-refill`)
+pub const DEFAULT_RETRY_REFILL: f64 = 1.0;
+
+/// Token bucket bounding the retry rate across every task sharing a `MetricStorage`
+///
+/// Each retry in `store_metric_safe` takes one token. When MongoDB is down
+/// every task fails at once; with the bucket empty the extra attempts are
+/// skipped and the document is dropped straight away, so the total retry rate
+/// stays at `refill_per_sec` however many collectors are running.
+pub struct RetryBudget {
+    capacity: f64,
+    refill_per_sec: f64,
+    /// Tokens left and when they were last topped up
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RetryBudget {
+    /// Creates a full bucket; a capacity of 0 disables retries
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        RetryBudget {
+            capacity: capacity as f64,
+            refill_per_sec: refill_per_sec.max(0.0),
+            state: Mutex::new((capacity as f64, Instant::now())),
+        }
+    }
+
+    /// Takes one token if available
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, last) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.refill_per_sec).min(self.capacity);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRY_BUDGET, DEFAULT_RETRY_REFILL)
+    }
+}
+
+/// Default bound on a single metric insert (`--write-timeout`)
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default backend: one MongoDB collection per metric
+pub struct MongoBackend {
+    client: Client,
+    database_name: String,
+
+    /// Upper bound on one insert, including server selection and the
+    /// write concern acknowledgment
+    write_timeout: Duration,
 }
 
+impl MongoBackend {
+    pub fn new(client: &Client, database_name: &str) -> Self {
+        MongoBackend {
+            client: client.clone(),
+            database_name: database_name.to_string(),
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+        }
+    }
+
+    /// Replaces the per-insert timeout
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MongoBackend {
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    async fn insert(&self, collection_name: &str, document: Document) -> Result<(), StorageError> {
+        // Get the database instance
+        let db = self.client.database(&self.database_name);
+
+        // Get the collection (creates it if it doesn't exist)
+        let collection: Collection<Document> = db.collection(collection_name);
+
+        // The insert command has no maxTimeMS, so the client-side timeout
+        // bounds it (server selection, a stalled primary, replication). The
+        // write concern from the URI or client is kept as is and only gets a
+        // wtimeout, which bounds the server's replication wait under w > 1;
+        // without one configured the server default applies. A timed-out
+        // insert goes through the normal retry path instead of stalling the task.
+        let options = collection.write_concern().cloned().map(|mut concern| {
+            concern.w_timeout = Some(self.write_timeout);
+            InsertOneOptions::builder().write_concern(concern).build()
+        });
+
+        // Insert the document
+        // MongoDB will automatically add an _id field if not present
+        let deterministic_id = document.get_str("_id").ok().map(str::to_string);
+        let insert = collection.insert_one(document, options);
+        let Ok(result) = tokio::time::timeout(self.write_timeout, insert).await else {
+            error!(
+                "Insert into collection '{}' timed out after {:?}",
+                collection_name, self.write_timeout
+            );
+            return Err(StorageError::InsertTimeout(self.write_timeout));
+        };
+
+        match result {
+            Ok(result) => {
+                debug!(
+                    "Successfully stored metric with id: {:?} in collection '{}'",
+                    result.inserted_id, collection_name
+                );
+                Ok(())
+            }
+            // Same logical sample already stored (retry or replay): nothing to do
+            Err(e) if deterministic_id.is_some() && is_duplicate_key(&e) => {
+                debug!(
+                    "Document {:?} already in collection '{}', skipping",
+                    deterministic_id, collection_name
+                );
+                Ok(())
+            }
+            Err(e) => {
+                error!(
+                    "Failed to store metric in collection '{}': {}",
+                    collection_name, e
+                );
+                Err(StorageError::InsertError(e))
+            }
+        }
+    }
+}
+
+/// MongoDB duplicate key error code
+const DUPLICATE_KEY: i32 = 11000;
+
+/// MongoDB error code for a document failing the collection validator
+const DOCUMENT_VALIDATION_FAILURE: i32 = 121;
+
+/// Sets a deterministic `_id` of the form `<node>:<metric>:<bucket start epoch>`
+///
+/// Two documents for the same node and metric whose timestamps fall in the
+/// same `bucket_secs` window get the same `_id`, so a retried or replayed
+/// insert hits the unique `_id` index and is skipped by `store_metric`.
+/// Documents without `node` or `timestamp` are left unchanged.
+pub fn assign_deterministic_id(document: &mut Document, metric_name: &str, bucket_secs: u64) {
+    let (Ok(node), Ok(timestamp)) = (document.get_str("node"), document.get_datetime("timestamp")) else {
+        return;
+    };
+    let bucket = bucket_secs.max(1) as i64;
+    let bucket_start = (timestamp.timestamp_millis() / 1000).div_euclid(bucket) * bucket;
+    let id = format!("{}:{}:{}", node, metric_name, bucket_start);
+    document.insert("_id", id);
+}
+
+/// Replaces NaN and ±Infinity doubles in `document` with `null`, at any depth
+///
+/// A percentage or rate computed from a zero or missing denominator would
+/// otherwise be stored as NaN, which poisons every average and rollup it
+/// takes part in (and isn't valid JSON for the Kafka backend). Each replaced
+/// field is logged; returns their dotted paths (array items by index).
+pub fn sanitize_non_finite(document: &mut Document, collection_name: &str) -> Vec<String> {
+    fn walk(value: &mut Bson, path: String, replaced: &mut Vec<String>) {
+        match value {
+            Bson::Double(v) if !v.is_finite() => {
+                *value = Bson::Null;
+                replaced.push(path);
+            }
+            Bson::Document(doc) => {
+                for (key, value) in doc.iter_mut() {
+                    walk(value, format!("{}.{}", path, key), replaced);
+                }
+            }
+            Bson::Array(items) => {
+                for (i, value) in items.iter_mut().enumerate() {
+                    walk(value, format!("{}.{}", path, i), replaced);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut replaced = Vec::new();
+    for (key, value) in document.iter_mut() {
+        walk(value, key.clone(), &mut replaced);
+    }
+    for field in &replaced {
+        warn!("Non-finite value in '{}' field '{}' stored as null", collection_name, field);
+    }
+    replaced
+}
+
+/// Server error codes treated as success when preparing sharding
+/// (AlreadyInitialized, IndexOptionsConflict)
+const ALREADY_SHARDED: i32 = 23;
+const INDEX_OPTIONS_CONFLICT: i32 = 85;
+
+/// Server error code for a missing collection (NamespaceNotFound)
+const NAMESPACE_NOT_FOUND: i32 = 26;
+
+/// Server error code for creating a collection that exists (NamespaceExists)
+const NAMESPACE_EXISTS: i32 = 48;
+
+/// Server error codes `replSetGetStatus` fails with when it can't report
+/// (Unauthorized, NoReplicationEnabled, NotYetInitialized)
+const UNAUTHORIZED: i32 = 13;
+const NO_REPLICATION_ENABLED: i32 = 76;
+const NOT_YET_INITIALIZED: i32 = 94;
+
+/// Why `MetricStorage::replica_set_status` returned no status
+#[derive(Error, Debug)]
+pub enum ReplicaSetStatusError {
+    #[error("not authorized to run replSetGetStatus")]
+    Unauthorized,
+
+    #[error("not connected to an initialized replica set")]
+    NotReplicaSet,
+
+    #[error("replSetGetStatus failed: {0}")]
+    Other(#[from] mongodb::error::Error),
+}
+
+/// Returns true if a sharding command failed only because it was already done
+fn is_already_sharded(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Command(e) if e.code == ALREADY_SHARDED || e.message.contains("already sharded")
+    )
+}
+
+/// Returns true if the error is a unique index violation
+fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY
+    )
+}
+
+/// Upper bound on `MetricStorage::warm_up`, so startup is never held up for long
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Metric storage manager
 ///
-/// Handles the persistence of metric data to MongoDB.
+/// Handles the persistence of metric data through the configured backend.
 /// Each metric type is stored in its own collection as specified in the configuration.
 pub struct MetricStorage {
-    /// MongoDB client for database operations
+    /// MongoDB client for reads, metadata and index management
     client: Client,
 
     /// Database name where metrics are stored
     database_name: String,
+
+    /// Where metric documents are written (MongoDB unless replaced)
+    backend: Box<dyn StorageBackend>,
+
+    /// Shared limit on retries across all callers of `store_metric_safe`
+    retry_budget: RetryBudget,
+
+    /// Documents held while writes fail (`--outage-buffer`); None drops them
+    outage_buffer: Option<OutageBuffer>,
+
+    /// Told about each write's outcome; None (default) costs nothing
+    observer: Option<Arc<dyn StorageObserver>>,
+
+    /// Bound on one insert, or on one whole transaction
+    write_timeout: Duration,
+
+    /// Whether `store_all_safe` writes in a transaction (`--transactions`)
+    transactions: bool,
+
+    /// Time-series collections, which MongoDB can't write in a transaction
+    time_series: Mutex<HashSet<String>>,
 }
 
 impl MetricStorage {
@@ -48,9 +393,85 @@ impl MetricStorage {
         MetricStorage {
             client: client.clone(),
             database_name: database_name.to_string(),
+            backend: Box::new(MongoBackend::new(client, database_name)),
+            retry_budget: RetryBudget::default(),
+            outage_buffer: None,
+            observer: None,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            transactions: false,
+            time_series: Mutex::new(HashSet::new()),
         }
     }
 
+    /// Holds up to `capacity` documents per collection in memory when a store
+    /// fails, writing them in order once storage recovers (default: off)
+    pub fn with_outage_buffer(mut self, capacity: usize) -> Self {
+        self.outage_buffer = (capacity > 0).then(|| OutageBuffer::new(capacity));
+        self
+    }
+
+    /// Reports every write's outcome to `observer`, e.g. for tests or an
+    /// embedding application's own instrumentation
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_observer(mut self, observer: Arc<dyn StorageObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Replaces the shared retry budget (default: 10 retries, refilling at 1/s)
+    pub fn with_retry_budget(mut self, retry_budget: RetryBudget) -> Self {
+        self.retry_budget = retry_budget;
+        self
+    }
+
+    /// Bounds each MongoDB metric insert to `write_timeout` (default: 5s)
+    ///
+    /// Applies to the MongoDB backend; call before `with_backend`.
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self.backend = Box::new(
+            MongoBackend::new(&self.client, &self.database_name).with_write_timeout(write_timeout),
+        );
+        self
+    }
+
+    /// Replaces the backend metric documents are written to
+    pub fn with_backend(mut self, backend: Box<dyn StorageBackend>) -> Self {
+        info!("Metric documents will be written to the '{}' backend", backend.name());
+        self.backend = backend;
+        self
+    }
+
+    /// Writes each `store_all_safe` batch in one MongoDB transaction
+    ///
+    /// Needs the MongoDB backend and a replica set or sharded cluster; check
+    /// with `supports_transactions` first.
+    pub fn with_transactions(mut self) -> Self {
+        self.transactions = true;
+        self
+    }
+
+    /// Drops the older half of every outage buffer queue (`--max-rss-mib`);
+    /// None without an outage buffer
+    pub fn shed_outage_buffer(&self) -> Option<usize> {
+        self.outage_buffer.as_ref().map(OutageBuffer::shed)
+    }
+
+    /// Name of the backend metric documents are written to
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// MongoDB client used for reads (and by MongoDB-based backends)
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Database metrics are stored in
+    pub fn database_name(&self) -> &str {
+        &self.database_name
+    }
+
     /// Stores a metric document in the specified collection
     ///
     /// This is the main method called by the scheduler to persist metrics.
@@ -83,44 +504,22 @@ impl MetricStorage {
     pub async fn store_metric(
         &self,
         collection_name: &str,
-        document: Document,
+        mut document: Document,
     ) -> Result<(), StorageError> {
+        sanitize_non_finite(&mut document, collection_name);
         debug!(
             "Storing metric to collection '{}': {} bytes",
             collection_name,
             document.to_string().len()
         );
 
-        // Get the database instance
-        let db = self.client.database(&self.database_name);
-
-        // Get the collection (creates it if it doesn't exist)
-        let collection: Collection<Document> = db.collection(collection_name);
-
-        // Insert the document
-        // MongoDB will automatically add an _id field if not present
-        match collection.insert_one(document, None).await {
-            Ok(result) => {
-                debug!(
-                    "Successfully stored metric with id: {:?} in collection '{}'",
-                    result.inserted_id, collection_name
-                );
-                Ok(())
-            }
-            Err(e) => {
-                error!(
-                    "Failed to store metric in collection '{}': {}",
-                    collection_name, e
-                );
-                Err(StorageError::InsertError(e))
-            }
-        }
+        self.backend.insert(collection_name, document).await
     }
 
     /// Stores a metric with additional error handling and retry logic
     ///
     /// This is a wrapper around store_metric that provides:
-    /// - Automatic retry on transient failures
+    /// - Automatic retry on transient failures, while the shared retry budget lasts
     /// - More detailed error logging
     /// - Graceful degradation (logs error but doesn't fail)
     ///
@@ -129,6 +528,10 @@ impl MetricStorage {
     /// * `metric_name` - Name of the metric (for logging)
     /// * `document` - BSON document to store
     ///
+    /// # Returns
+    /// `true` if the document was stored (possibly after a retry), or held in
+    /// the outage buffer to be stored in order later
+    ///
     /// # Note
     /// This method never returns an error - it logs failures and continues.
     /// This ensures that a failure in storing one metric type doesn't
@@ -138,12 +541,187 @@ impl MetricStorage {
         collection_name: &str,
         metric_name: &str,
         document: Document,
-    ) {
-        // Attempt to store with a single retry on failure
-        const MAX_RETRIES: u32 = 1;
+    ) -> bool {
+        let Some(buffer) = &self.outage_buffer else {
+            return self.store_with_retries(collection_name, metric_name, document).await.is_ok();
+        };
 
-        for attempt in 0..=MAX_RETRIES {
-            match self.store_metric(collection_name, document.clone()).await {
+        let queue = buffer.queue(collection_name);
+        let mut queue = queue.lock().await;
+        if !queue.is_empty() && !self.drain(collection_name, &mut queue).await {
+            buffer.hold(&mut queue, collection_name, metric_name, document);
+            return true;
+        }
+        match self.store_with_retries(collection_name, metric_name, document.clone()).await {
+            Ok(()) => true,
+            Err(e) if e.is_rejected() => false,
+            Err(_) => {
+                buffer.hold(&mut queue, collection_name, metric_name, document);
+                true
+            }
+        }
+    }
+
+    /// Stores documents that belong together, e.g. the rows of one flush
+    ///
+    /// With `--transactions` they are written in one transaction: all of them
+    /// are stored or none. A failed transaction is retried once while the
+    /// retry budget lasts, then the whole batch is dropped; it is not held in
+    /// the outage buffer, which would replay it one document at a time.
+    /// Without transactions, or when a collection is time-series, each
+    /// document goes through `store_metric_safe` on its own.
+    ///
+    /// # Returns
+    /// `true` if every document was stored (or held)
+    pub async fn store_all_safe(&self, metric_name: &str, writes: Vec<(String, Document)>) -> bool {
+        if writes.len() < 2 || !writes.iter().all(|(collection, _)| self.is_transactional(collection)) {
+            let mut stored = true;
+            for (collection, document) in writes {
+                stored &= self.store_metric_safe(&collection, metric_name, document).await;
+            }
+            return stored;
+        }
+
+        let mut writes = writes;
+        for (collection, document) in &mut writes {
+            sanitize_non_finite(document, collection);
+        }
+        let started = Instant::now();
+        let mut attempt = 0;
+        let result = loop {
+            let e = match self.insert_in_transaction(&writes).await {
+                Ok(()) => break Ok(()),
+                Err(e) => e,
+            };
+            if e.is_rejected() || attempt >= STORE_RETRIES || !self.retry_budget.try_acquire() {
+                break Err(e);
+            }
+            warn!("Transaction storing {} {} document(s) failed: {}. Retrying...", writes.len(), metric_name, e);
+            tokio::time::sleep(STORE_RETRY_DELAY).await;
+            attempt += 1;
+        };
+        if let Err(e) = &result {
+            error!("Failed to store {} {} document(s) in a transaction, dropping them: {}", writes.len(), metric_name, e);
+        }
+        if let Some(observer) = &self.observer {
+            for (collection, _) in &writes {
+                observer.on_store(collection, metric_name, result.is_ok(), started.elapsed());
+            }
+        }
+        result.is_ok()
+    }
+
+    /// Whether `store_all_safe` writes to `collection` in a transaction
+    pub fn is_transactional(&self, collection_name: &str) -> bool {
+        self.transactions
+            && !self.time_series.lock().unwrap_or_else(|e| e.into_inner()).contains(collection_name)
+    }
+
+    /// Inserts every document in one transaction, bounded by the write timeout
+    ///
+    /// A document with a (deterministic) string `_id` is upserted with
+    /// `$setOnInsert`, so one already stored is skipped as `insert` skips it,
+    /// instead of a duplicate key error aborting the transaction.
+    async fn insert_in_transaction(&self, writes: &[(String, Document)]) -> Result<(), StorageError> {
+        let transaction = async {
+            let mut session = self.client.start_session(None).await?;
+            session.start_transaction(None).await?;
+            match self.insert_with_session(writes, &mut session).await {
+                Ok(()) => session.commit_transaction().await,
+                Err(e) => {
+                    if let Err(abort) = session.abort_transaction().await {
+                        debug!("Aborting the failed transaction failed too: {}", abort);
+                    }
+                    Err(e)
+                }
+            }
+        };
+        match tokio::time::timeout(self.write_timeout, transaction).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(StorageError::InsertTimeout(self.write_timeout)),
+        }
+    }
+
+    async fn insert_with_session(
+        &self,
+        writes: &[(String, Document)],
+        session: &mut mongodb::ClientSession,
+    ) -> mongodb::error::Result<()> {
+        use mongodb::options::UpdateOptions;
+
+        let db = self.client.database(&self.database_name);
+        for (collection_name, document) in writes {
+            let collection: Collection<Document> = db.collection(collection_name);
+            match document.get_str("_id") {
+                Ok(id) => {
+                    let mut fields = document.clone();
+                    fields.remove("_id");
+                    let options = UpdateOptions::builder().upsert(true).build();
+                    collection
+                        .update_one_with_session(doc! { "_id": id }, doc! { "$setOnInsert": fields }, options, session)
+                        .await?;
+                }
+                Err(_) => {
+                    collection.insert_one_with_session(document, None, session).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes held documents oldest first; true once the queue is empty
+    ///
+    /// Stops at the first failure, one attempt per call, so a still-failing
+    /// store doesn't hold up the caller for long.
+    async fn drain(&self, collection_name: &str, queue: &mut VecDeque<Held>) -> bool {
+        let mut stored = 0;
+        while let Some((metric_name, document)) = queue.front() {
+            let started = Instant::now();
+            let result = self.store_metric(collection_name, document.clone()).await;
+            if let Some(observer) = &self.observer {
+                observer.on_store(collection_name, metric_name, result.is_ok(), started.elapsed());
+            }
+            match result {
+                Ok(()) => stored += 1,
+                Err(e) if e.is_rejected() => {
+                    error!("Buffered document rejected by the '{}' validator, dropping it: {}", collection_name, e);
+                }
+                Err(_) => break,
+            }
+            queue.pop_front();
+        }
+        if stored > 0 {
+            info!("Stored {} buffered document(s) in '{}'", stored, collection_name);
+        }
+        queue.is_empty()
+    }
+
+    /// `store_retrying`, reporting the outcome to the observer if any
+    async fn store_with_retries(
+        &self,
+        collection_name: &str,
+        metric_name: &str,
+        document: Document,
+    ) -> Result<(), StorageError> {
+        let Some(observer) = &self.observer else {
+            return self.store_retrying(collection_name, metric_name, document).await;
+        };
+        let started = Instant::now();
+        let result = self.store_retrying(collection_name, metric_name, document).await;
+        observer.on_store(collection_name, metric_name, result.is_ok(), started.elapsed());
+        result
+    }
+
+    /// `store_metric` with one retry while the shared retry budget lasts
+    async fn store_retrying(
+        &self,
+        collection_name: &str,
+        metric_name: &str,
+        document: Document,
+    ) -> Result<(), StorageError> {
+        let mut attempt = 0;
+        loop {
+            let e = match self.store_metric(collection_name, document.clone()).await {
                 Ok(()) => {
                     if attempt > 0 {
                         info!(
@@ -151,29 +729,295 @@ impl MetricStorage {
                             metric_name, attempt
                         );
                     }
-                    return;
+                    return Ok(());
                 }
-                Err(e) => {
-                    if attempt < MAX_RETRIES {
-                        error!(
-                            "Failed to store {} metric (attempt {}): {}. Retrying...",
-                            metric_name,
-                            attempt + 1,
-                            e
-                        );
-                        // Brief delay before retry
-                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    } else {
-                        error!(
-                            "Failed to store {} metric after {} attempts: {}. Giving up.",
-                            metric_name,
-                            attempt + 1,
-                            e
-                        );
-                    }
+                Err(e) if e.is_rejected() => {
+                    error!("{} metric rejected by the collection validator, dropping it: {}", metric_name, e);
+                    return Err(e);
                 }
+                Err(e) => e,
+            };
+            if attempt < STORE_RETRIES && !self.retry_budget.try_acquire() {
+                warn!(
+                    "Failed to store {} metric: {}. Retry budget exhausted, not retrying",
+                    metric_name, e
+                );
+                return Err(e);
+            }
+            if attempt >= STORE_RETRIES {
+                error!(
+                    "Failed to store {} metric after {} attempts: {}. Giving up.",
+                    metric_name,
+                    attempt + 1,
+                    e
+                );
+                return Err(e);
+            }
+            error!(
+                "Failed to store {} metric (attempt {}): {}. Retrying...",
+                metric_name,
+                attempt + 1,
+                e
+            );
+            // Brief delay before retry
+            tokio::time::sleep(STORE_RETRY_DELAY).await;
+            attempt += 1;
+        }
+    }
+
+    /// Returns the `seq` and `timestamp` of the node's most recent document in a collection
+    ///
+    /// Uses the `(node, timestamp)` index: `seq` only grows, so the newest
+    /// document carries the highest value. Returns `None` when the node has no
+    /// documents yet or they predate sequence numbers.
+    pub async fn latest_seq(
+        &self,
+        collection_name: &str,
+        node_id: &str,
+    ) -> Result<Option<(i64, DateTime<Utc>)>, StorageError> {
+        use mongodb::options::FindOneOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = FindOneOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .projection(doc! { "seq": 1, "timestamp": 1 })
+            .build();
+
+        let latest = collection.find_one(doc! { "node": node_id }, options).await?;
+        Ok(latest.and_then(|d| {
+            let seq = match d.get("seq") {
+                Some(Bson::Int64(v)) => *v,
+                Some(Bson::Int32(v)) => *v as i64,
+                _ => return None,
+            };
+            let timestamp = d.get_datetime("timestamp").ok()?.to_chrono();
+            Some((seq, timestamp))
+        }))
+    }
+
+    /// Returns the integrity hash of the node's most recent document
+    ///
+    /// `None` when the newest document isn't sealed, so a chain restarts
+    /// with a fresh anchor after integrity is turned on or off.
+    pub async fn latest_integrity_hash(
+        &self,
+        collection_name: &str,
+        node_id: &str,
+    ) -> Result<Option<String>, StorageError> {
+        use mongodb::options::FindOneOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = FindOneOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .projection(doc! { "_integrity.hash": 1 })
+            .build();
+
+        let latest = collection.find_one(doc! { "node": node_id }, options).await?;
+        Ok(latest.and_then(|d| {
+            d.get_document("_integrity")
+                .and_then(|i| i.get_str("hash"))
+                .ok()
+                .map(str::to_string)
+        }))
+    }
+
+    /// Returns the `hash` field of the node's most recent document in a collection
+    pub async fn latest_hash(
+        &self,
+        collection_name: &str,
+        node_id: &str,
+    ) -> Result<Option<String>, StorageError> {
+        use mongodb::options::FindOneOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = FindOneOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .projection(doc! { "hash": 1 })
+            .build();
+
+        let latest = collection.find_one(doc! { "node": node_id }, options).await?;
+        Ok(latest.and_then(|d| d.get_str("hash").ok().map(str::to_string)))
+    }
+
+    /// Streams a collection oldest first per node, the order documents were sealed in
+    ///
+    /// Sorted by `(node, timestamp, seq)`, spilling to disk on large collections.
+    pub async fn find_in_store_order(
+        &self,
+        collection_name: &str,
+    ) -> Result<mongodb::Cursor<Document>, StorageError> {
+        use mongodb::options::FindOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = FindOptions::builder()
+            .sort(doc! { "node": 1, "timestamp": 1, "seq": 1 })
+            .allow_disk_use(true)
+            .build();
+        Ok(collection.find(doc! {}, options).await?)
+    }
+
+    /// Fetches a single document by string `_id`
+    pub async fn find_by_id(
+        &self,
+        collection_name: &str,
+        id: &str,
+    ) -> Result<Option<Document>, StorageError> {
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        Ok(collection.find_one(doc! { "_id": id }, None).await?)
+    }
+
+    /// Inserts or replaces the document with string `_id`
+    pub async fn replace_by_id(
+        &self,
+        collection_name: &str,
+        id: &str,
+        document: Document,
+    ) -> Result<(), StorageError> {
+        use mongodb::options::ReplaceOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = ReplaceOptions::builder().upsert(true).build();
+        collection
+            .replace_one(doc! { "_id": id }, document, options)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the node's documents beyond its newest `keep` in a collection
+    ///
+    /// At most `limit` documents go per call: their `_id`s are read through the
+    /// `(node, timestamp)` index, newest first after skipping `keep`, then
+    /// removed with one `$in` delete. Returns the number deleted.
+    pub async fn prune_excess(
+        &self,
+        collection_name: &str,
+        node_id: &str,
+        keep: u64,
+        limit: u64,
+    ) -> Result<u64, StorageError> {
+        use mongodb::options::FindOptions;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": -1, "_id": -1 })
+            .skip(keep)
+            .limit(limit as i64)
+            .projection(doc! { "_id": 1 })
+            .build();
+
+        let excess: Vec<Document> = collection.find(doc! { "node": node_id }, options).await?.try_collect().await?;
+        let ids: Vec<Bson> = excess.into_iter().filter_map(|mut d| d.remove("_id")).collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let result = collection.delete_many(doc! { "_id": { "$in": ids } }, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    /// Returns the newest `timestamp` of every node in a collection
+    ///
+    /// Documents without a `node` string or `timestamp` date are skipped.
+    pub async fn latest_timestamps(
+        &self,
+        collection_name: &str,
+    ) -> Result<HashMap<String, DateTime<Utc>>, StorageError> {
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let pipeline = vec![doc! { "$group": { "_id": "$node", "timestamp": { "$max": "$timestamp" } } }];
+
+        let mut cursor = collection.aggregate(pipeline, None).await?;
+        let mut latest = HashMap::new();
+        while let Some(row) = cursor.try_next().await? {
+            if let (Ok(node), Ok(timestamp)) = (row.get_str("_id"), row.get_datetime("timestamp")) {
+                latest.insert(node.to_string(), timestamp.to_chrono());
             }
         }
+        Ok(latest)
+    }
+
+    /// Summarizes one node's documents in a time window with an aggregation pipeline
+    ///
+    /// Every top-level numeric field is reduced to `{avg, min, max}`. Fields
+    /// that are already aggregated (`{avg, min, max}` sub-documents written by
+    /// the scheduler) contribute their own avg/min/max; plain numbers contribute
+    /// their value. Arrays, strings and booleans are ignored.
+    ///
+    /// # Arguments
+    /// * `collection_name` - Source collection to summarize
+    /// * `node_id` - Node whose documents are included
+    /// * `start` / `end` - Window bounds (`start` inclusive, `end` exclusive)
+    ///
+    /// # Returns
+    /// * `Ok(Some((document_count, fields)))` - One `{avg, min, max}` entry per field
+    /// * `Ok(None)` - No documents in the window
+    pub async fn summarize_window(
+        &self,
+        collection_name: &str,
+        node_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Option<(u64, Document)>, StorageError> {
+        let db = self.client.database(&self.database_name);
+        let collection: Collection<Document> = db.collection(collection_name);
+
+        let filter = doc! {
+            "node": node_id,
+            "timestamp": { "$gte": start, "$lt": end },
+        };
+
+        let document_count = collection.count_documents(filter.clone(), None).await?;
+        if document_count == 0 {
+            return Ok(None);
+        }
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$project": { "_id": 0, "fields": { "$objectToArray": "$$ROOT" } } },
+            doc! { "$unwind": "$fields" },
+            doc! { "$match": {
+                "fields.k": { "$nin": ["_id", "node", "timestamp", "sample_count", "seq"] },
+            } },
+            doc! { "$project": {
+                "k": "$fields.k",
+                "avg": { "$ifNull": ["$fields.v.avg", "$fields.v"] },
+                "min": { "$ifNull": ["$fields.v.min", "$fields.v"] },
+                "max": { "$ifNull": ["$fields.v.max", "$fields.v"] },
+            } },
+            doc! { "$match": { "avg": { "$type": "number" } } },
+            doc! { "$group": {
+                "_id": "$k",
+                "avg": { "$avg": "$avg" },
+                "min": { "$min": "$min" },
+                "max": { "$max": "$max" },
+            } },
+            doc! { "$sort": { "_id": 1 } },
+        ];
+
+        let mut cursor = collection.aggregate(pipeline, None).await?;
+        let mut fields = Document::new();
+
+        while let Some(row) = cursor.try_next().await? {
+            let Ok(name) = row.get_str("_id") else {
+                continue;
+            };
+            fields.insert(
+                name,
+                doc! {
+                    "avg": row.get("avg").cloned().unwrap_or(Bson::Null),
+                    "min": row.get("min").cloned().unwrap_or(Bson::Null),
+                    "max": row.get("max").cloned().unwrap_or(Bson::Null),
+                },
+            );
+        }
+
+        Ok(Some((document_count, fields)))
     }
 
     /// Creates recommended indexes for metric collections
@@ -226,4 +1070,370 @@ impl MetricStorage {
             }
         }
     }
+
+    /// Document count and on-disk size (bytes) of a collection via `collStats`
+    ///
+    /// Returns None if the collection doesn't exist yet.
+    pub async fn collection_stats(&self, collection_name: &str) -> Result<Option<(i64, i64)>, StorageError> {
+        let db = self.client.database(&self.database_name);
+        let reply = match db.run_command(doc! { "collStats": collection_name }, None).await {
+            Ok(reply) => reply,
+            Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(c) if c.code == NAMESPACE_NOT_FOUND) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(StorageError::InsertError(e)),
+        };
+
+        // Numeric fields come back as int32, int64 or double depending on size
+        let number = |field: &str| match reply.get(field) {
+            Some(Bson::Int32(v)) => *v as i64,
+            Some(Bson::Int64(v)) => *v,
+            Some(Bson::Double(v)) => *v as i64,
+            _ => 0,
+        };
+        Ok(Some((number("count"), number("storageSize"))))
+    }
+
+    /// Runs `replSetGetStatus` against the admin database
+    pub async fn replica_set_status(&self) -> Result<Document, ReplicaSetStatusError> {
+        let command = doc! { "replSetGetStatus": 1 };
+        match self.client.database("admin").run_command(command, None).await {
+            Ok(reply) => Ok(reply),
+            Err(e) => match e.kind.as_ref() {
+                ErrorKind::Command(c) if c.code == UNAUTHORIZED => Err(ReplicaSetStatusError::Unauthorized),
+                ErrorKind::Command(c) if c.code == NO_REPLICATION_ENABLED || c.code == NOT_YET_INITIALIZED => {
+                    Err(ReplicaSetStatusError::NotReplicaSet)
+                }
+                _ => Err(ReplicaSetStatusError::Other(e)),
+            },
+        }
+    }
+
+    /// Opens `connections` pooled connections to the primary before collection starts
+    ///
+    /// The startup connectivity check leaves at most one pooled connection,
+    /// so the metric tasks' first flushes would otherwise each open their own
+    /// (TCP, TLS and auth handshakes) at once. Runs that many `ping`s on the
+    /// metrics database concurrently with primary read preference, which
+    /// selects the server writes go to. Failures are logged, never fatal:
+    /// the first writes are then just slower, and retried as usual.
+    pub async fn warm_up(&self, connections: usize) {
+        use futures_util::future::join_all;
+        use mongodb::options::{ReadPreference, SelectionCriteria};
+
+        let started = Instant::now();
+        let database = self.client.database(&self.database_name);
+        let pings = (0..connections.max(1)).map(|_| {
+            database.run_command(
+                doc! { "ping": 1 },
+                SelectionCriteria::ReadPreference(ReadPreference::Primary),
+            )
+        });
+
+        match tokio::time::timeout(WARM_UP_TIMEOUT, join_all(pings)).await {
+            Ok(results) => match results.into_iter().find_map(Result::err) {
+                None => info!(
+                    "Warmed up {} connection(s) to '{}' in {}ms",
+                    connections.max(1),
+                    self.database_name,
+                    started.elapsed().as_millis()
+                ),
+                Some(e) => warn!("Connection warm-up failed, first writes may be slow: {}", e),
+            },
+            Err(_) => warn!(
+                "Connection warm-up timed out after {:?}, first writes may be slow",
+                WARM_UP_TIMEOUT
+            ),
+        }
+    }
+
+    /// Returns true if the server can run multi-document transactions: a
+    /// replica set member or a mongos router, not a standalone server
+    pub async fn supports_transactions(&self) -> Result<bool, StorageError> {
+        let reply = self
+            .client
+            .database("admin")
+            .run_command(doc! { "hello": 1 }, None)
+            .await?;
+        Ok(reply.contains_key("setName") || reply.get_str("msg") == Ok("isdbgrid"))
+    }
+
+    /// Returns true if the client is connected to a mongos router
+    pub async fn is_mongos(&self) -> Result<bool, StorageError> {
+        let reply = self
+            .client
+            .database("admin")
+            .run_command(doc! { "hello": 1 }, None)
+            .await?;
+        Ok(reply.get_str("msg") == Ok("isdbgrid"))
+    }
+
+    /// Enables sharding for the metrics database (required before MongoDB 6.0)
+    pub async fn enable_sharding(&self) -> Result<(), StorageError> {
+        let command = doc! { "enableSharding": &self.database_name };
+        match self.client.database("admin").run_command(command, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_already_sharded(&e) => Ok(()),
+            Err(e) => Err(StorageError::InsertError(e)),
+        }
+    }
+
+    /// Creates `collection_name` with `validator`, or replaces the validator
+    /// of an existing collection through `collMod`
+    ///
+    /// Documents already stored aren't re-checked; MongoDB applies the
+    /// validator to later inserts and updates.
+    pub async fn set_validator(&self, collection_name: &str, validator: Document) -> Result<(), StorageError> {
+        use mongodb::options::CreateCollectionOptions;
+
+        let db = self.client.database(&self.database_name);
+        let options = CreateCollectionOptions::builder().validator(validator.clone()).build();
+        match db.create_collection(collection_name, options).await {
+            Ok(()) => Ok(()),
+            Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(c) if c.code == NAMESPACE_EXISTS) => {
+                db.run_command(doc! { "collMod": collection_name, "validator": validator }, None)
+                    .await?;
+                Ok(())
+            }
+            Err(e) => Err(StorageError::InsertError(e)),
+        }
+    }
+
+    /// Creates `collection_name` as a time-series collection keyed on
+    /// `timestamp` with `meta_field` as its meta field
+    ///
+    /// Returns false if the collection already exists; it is left as is,
+    /// time-series or not.
+    pub async fn create_time_series(&self, collection_name: &str, meta_field: &str) -> Result<bool, StorageError> {
+        use mongodb::options::{CreateCollectionOptions, TimeseriesGranularity, TimeseriesOptions};
+
+        let timeseries = TimeseriesOptions::builder()
+            .time_field("timestamp".to_string())
+            .meta_field(Some(meta_field.to_string()))
+            .granularity(Some(TimeseriesGranularity::Minutes))
+            .build();
+        let options = CreateCollectionOptions::builder().timeseries(timeseries).build();
+        let db = self.client.database(&self.database_name);
+        self.time_series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(collection_name.to_string());
+        match db.create_collection(collection_name, options).await {
+            Ok(()) => Ok(true),
+            Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(c) if c.code == NAMESPACE_EXISTS) => Ok(false),
+            Err(e) => Err(StorageError::InsertError(e)),
+        }
+    }
+
+    /// Creates the index backing `shard_key` and shards the collection on it
+    ///
+    /// Must be connected to a mongos. A collection that is already sharded is
+    /// left as is, whatever its key.
+    pub async fn shard_collection(&self, collection_name: &str, shard_key: &Document) -> Result<(), StorageError> {
+        use mongodb::options::IndexOptions;
+        use mongodb::IndexModel;
+
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        let index = IndexModel::builder()
+            .keys(shard_key.clone())
+            .options(IndexOptions::builder().name("shard_key_idx".to_string()).build())
+            .build();
+        match collection.create_index(index, None).await {
+            Ok(_) => {}
+            // Same keys under another name: the index exists already
+            Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(c) if c.code == INDEX_OPTIONS_CONFLICT) => {}
+            Err(e) => return Err(StorageError::InsertError(e)),
+        }
+
+        let command = doc! {
+            "shardCollection": format!("{}.{}", self.database_name, collection_name),
+            "key": shard_key.clone(),
+        };
+        match self.client.database("admin").run_command(command, None).await {
+            Ok(_) => {
+                info!("Collection '{}' sharded on {}", collection_name, shard_key);
+                Ok(())
+            }
+            Err(e) if is_already_sharded(&e) => {
+                debug!("Collection '{}' is already sharded", collection_name);
+                Ok(())
+            }
+            Err(e) => Err(StorageError::InsertError(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Backend that fails every write and counts the attempts
+    struct DownBackend(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl StorageBackend for DownBackend {
+        fn name(&self) -> &'static str {
+            "down"
+        }
+
+        async fn insert(&self, _collection: &str, _document: Document) -> Result<(), StorageError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err(StorageError::InvalidDocument("unavailable".to_string()))
+        }
+    }
+
+    /// A `shardCollection`-style command failure
+    fn command_error(code: i32, code_name: &str, errmsg: &str) -> mongodb::error::Error {
+        let e = bson::from_document(doc! { "code": code, "codeName": code_name, "errmsg": errmsg }).unwrap();
+        mongodb::error::Error::from(ErrorKind::Command(e))
+    }
+
+    #[test]
+    fn test_is_already_sharded() {
+        assert!(is_already_sharded(&command_error(23, "AlreadyInitialized", "already initialized")));
+        // Older servers only say so in the message
+        assert!(is_already_sharded(&command_error(
+            20,
+            "IllegalOperation",
+            "collection monitoring.memory_metrics already sharded"
+        )));
+        // A real IllegalOperation must not read as shard-ready
+        assert!(!is_already_sharded(&command_error(
+            20,
+            "IllegalOperation",
+            "sharding not enabled for db monitoring"
+        )));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_budget_bounds_retries() {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9").await.unwrap();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let storage = MetricStorage::new(&client, "monitoring_test")
+            .with_backend(Box::new(DownBackend(Arc::clone(&attempts))))
+            .with_retry_budget(RetryBudget::new(2, 0.5));
+
+        // Two retries come out of the full bucket, later failures are not retried
+        for _ in 0..4 {
+            assert!(!storage.store_metric_safe("memory_metrics", "Memory", doc! {}).await);
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 4 + 2);
+
+        // With the two retry delays that makes 2s at 0.5/s: one more retry
+        tokio::time::advance(Duration::from_millis(1800)).await;
+        storage.store_metric_safe("memory_metrics", "Memory", doc! {}).await;
+        storage.store_metric_safe("memory_metrics", "Memory", doc! {}).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 6 + 2 + 1);
+    }
+
+    /// Backend storing into a `MemoryBackend` unless switched off
+    struct FlakyBackend(memory::MemoryBackend, Arc<AtomicBool>);
+
+    #[async_trait]
+    impl StorageBackend for FlakyBackend {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn insert(&self, collection: &str, document: Document) -> Result<(), StorageError> {
+            if self.1.load(Ordering::SeqCst) {
+                return Err(StorageError::InvalidDocument("unavailable".to_string()));
+            }
+            self.0.insert(collection, document).await
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_outage_buffer_stores_held_documents_in_order() {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9").await.unwrap();
+        let backend = memory::MemoryBackend::new();
+        let down = Arc::new(AtomicBool::new(false));
+        let storage = MetricStorage::new(&client, "monitoring_test")
+            .with_backend(Box::new(FlakyBackend(backend.clone(), Arc::clone(&down))))
+            .with_outage_buffer(3);
+
+        let store = |seq: i64| storage.store_metric_safe("memory_metrics", "Memory", doc! { "seq": seq });
+        assert!(store(1).await);
+        down.store(true, Ordering::SeqCst);
+        for seq in 2..=5 {
+            // Held rather than dropped; 2 is pushed out by 5
+            assert!(store(seq).await);
+        }
+        down.store(false, Ordering::SeqCst);
+        assert!(store(6).await);
+
+        let seqs: Vec<i64> = backend
+            .documents("memory_metrics")
+            .iter()
+            .map(|d| d.get_i64("seq").unwrap())
+            .collect();
+        assert_eq!(seqs, [1, 3, 4, 5, 6]);
+    }
+
+    /// Observer remembering (collection, metric, success) per write
+    #[derive(Default)]
+    struct RecordingObserver(Mutex<Vec<(String, String, bool)>>);
+
+    impl StorageObserver for RecordingObserver {
+        fn on_store(&self, collection: &str, metric_name: &str, success: bool, _duration: Duration) {
+            let mut writes = self.0.lock().unwrap();
+            writes.push((collection.to_string(), metric_name.to_string(), success));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_observer_sees_each_outcome() {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9").await.unwrap();
+        let down = Arc::new(AtomicBool::new(false));
+        let observer = Arc::new(RecordingObserver::default());
+        let storage = MetricStorage::new(&client, "monitoring_test")
+            .with_backend(Box::new(FlakyBackend(memory::MemoryBackend::new(), Arc::clone(&down))))
+            .with_outage_buffer(10)
+            .with_observer(observer.clone());
+
+        assert!(storage.store_metric_safe("memory_metrics", "Memory", doc! {}).await);
+        down.store(true, Ordering::SeqCst);
+        // Held: reported as failed once, after the retry
+        assert!(storage.store_metric_safe("disk_metrics", "DiskSpace", doc! {}).await);
+        down.store(false, Ordering::SeqCst);
+        assert!(storage.store_metric_safe("disk_metrics", "DiskSpace", doc! {}).await);
+
+        let writes = observer.0.lock().unwrap().clone();
+        let expected = [
+            ("memory_metrics", "Memory", true),
+            ("disk_metrics", "DiskSpace", false),
+            ("disk_metrics", "DiskSpace", true),
+            ("disk_metrics", "DiskSpace", true),
+        ];
+        let expected: Vec<(String, String, bool)> =
+            expected.iter().map(|(c, m, s)| (c.to_string(), m.to_string(), *s)).collect();
+        assert_eq!(writes, expected);
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_values_stored_as_null() {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9").await.unwrap();
+        let backend = memory::MemoryBackend::new();
+        let storage = MetricStorage::new(&client, "monitoring_test").with_backend(Box::new(backend.clone()));
+
+        let document = doc! {
+            "node": "n1",
+            "memory_percent": f64::NAN,
+            "cpu": { "avg": f64::INFINITY, "min": 1.0 },
+            "containers": [{ "cpu_percent": f64::NEG_INFINITY }],
+        };
+        assert!(storage.store_metric_safe("memory_metrics", "Memory", document).await);
+
+        assert_eq!(
+            backend.documents("memory_metrics"),
+            [doc! {
+                "node": "n1",
+                "memory_percent": Bson::Null,
+                "cpu": { "avg": Bson::Null, "min": 1.0 },
+                "containers": [{ "cpu_percent": Bson::Null }],
+            }]
+        );
+    }
 }
@@ -0,0 +1,214 @@
+// Bucket backend - MongoDB bucket pattern for high-frequency metrics
+//
+// Instead of one document per sample, samples are appended to a `samples`
+// array in one document per node per time bucket, with an upsert keyed on
+// `{node, bucket}`. A collection then holds one document (and one index
+// entry) per bucket rather than per sample.
+//
+// A bucket document is closed once it holds `max_samples` samples or gets near
+// the 16 MiB BSON limit: the update filter stops matching it and the upsert
+// starts a new document for the same `{node, bucket}`.
+
+use async_trait::async_trait;
+use bson::{doc, DateTime, Document};
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::{Client, Collection, IndexModel};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, error};
+
+use super::{StorageBackend, StorageError, DEFAULT_WRITE_TIMEOUT};
+
+/// Default bucket length (`--bucket-span`)
+pub const DEFAULT_BUCKET_SPAN: Duration = Duration::from_secs(3600);
+
+/// Default cap on samples per bucket document (`--bucket-max-samples`)
+pub const DEFAULT_BUCKET_MAX_SAMPLES: u32 = 500;
+
+/// Size at which a bucket document is closed, leaving 1 MiB of the 16 MiB
+/// BSON limit for the bucket's own fields and the last sample's overhead
+const MAX_BUCKET_BYTES: i64 = 15 * 1024 * 1024;
+
+/// Backend appending metric documents to per-node time buckets in MongoDB
+pub struct BucketBackend {
+    client: Client,
+    database_name: String,
+    span: Duration,
+    max_samples: u32,
+    write_timeout: Duration,
+    /// Collections whose `(node, bucket)` index this process has created
+    indexed: Mutex<HashSet<String>>,
+}
+
+impl BucketBackend {
+    pub fn new(client: &Client, database_name: &str, span: Duration, max_samples: u32) -> Self {
+        BucketBackend {
+            client: client.clone(),
+            database_name: database_name.to_string(),
+            span,
+            max_samples: max_samples.max(1),
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            indexed: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Replaces the per-update timeout
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    /// Creates the `(node, bucket)` index the upserts look buckets up by
+    ///
+    /// Once per collection; a failure is logged and retried on the next write.
+    async fn ensure_index(&self, collection: &Collection<Document>) {
+        if self.indexed.lock().unwrap_or_else(|e| e.into_inner()).contains(collection.name()) {
+            return;
+        }
+        let index = IndexModel::builder()
+            .keys(doc! { "node": 1, "bucket": 1 })
+            .options(IndexOptions::builder().name("node_bucket_idx".to_string()).build())
+            .build();
+        match collection.create_index(index, None).await {
+            Ok(_) => {
+                self.indexed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(collection.name().to_string());
+            }
+            Err(e) => error!("Failed to create bucket index on '{}': {}", collection.name(), e),
+        }
+    }
+}
+
+/// Builds the upsert filter and update appending `document` to its bucket
+///
+/// `node` stays on the bucket document only; each sample keeps its
+/// `timestamp` and other fields. The bucket document also tracks the
+/// newest `timestamp` and highest `seq`, so seq resume and `(node, timestamp)`
+/// range queries keep working at bucket granularity.
+fn bucket_update(
+    mut document: Document,
+    span: Duration,
+    max_samples: u32,
+) -> Result<(Document, Document), StorageError> {
+    let node = document
+        .get_str("node")
+        .map_err(|_| StorageError::InvalidDocument("document has no 'node'".to_string()))?
+        .to_string();
+    let timestamp = document
+        .get_datetime("timestamp")
+        .map_err(|_| StorageError::InvalidDocument("document has no 'timestamp'".to_string()))?
+        .to_owned();
+    document.remove("node");
+
+    let span_ms = (span.as_millis() as i64).max(1000);
+    let bucket = DateTime::from_millis(timestamp.timestamp_millis().div_euclid(span_ms) * span_ms);
+    let size = bson::to_vec(&document).map_err(|e| StorageError::InvalidDocument(e.to_string()))?.len() as i64;
+
+    let filter = doc! {
+        "node": node,
+        "bucket": bucket,
+        "count": { "$lt": max_samples as i64 },
+        "bytes": { "$lte": MAX_BUCKET_BYTES - size },
+    };
+
+    let mut max = doc! { "timestamp": timestamp };
+    if let Some(seq) = document.get("seq") {
+        max.insert("seq", seq.clone());
+    }
+    let update = doc! {
+        // $slice is a backstop: the count filter already keeps buckets under the cap
+        "$push": { "samples": { "$each": [document], "$slice": max_samples as i64 } },
+        "$inc": { "count": 1_i64, "bytes": size },
+        "$min": { "first": timestamp },
+        "$max": max,
+    };
+    Ok((filter, update))
+}
+
+#[async_trait]
+impl StorageBackend for BucketBackend {
+    fn name(&self) -> &'static str {
+        "bucket"
+    }
+
+    async fn insert(&self, collection_name: &str, document: Document) -> Result<(), StorageError> {
+        let collection: Collection<Document> =
+            self.client.database(&self.database_name).collection(collection_name);
+        self.ensure_index(&collection).await;
+
+        let (filter, update) = bucket_update(document, self.span, self.max_samples)?;
+        // As for `MongoBackend::insert`: keep the configured write concern
+        // (e.g. `w=majority` from the URI) and only bound its replication wait
+        let write_concern = collection.write_concern().cloned().map(|mut concern| {
+            concern.w_timeout = Some(self.write_timeout);
+            concern
+        });
+        let options = UpdateOptions::builder()
+            .upsert(true)
+            .write_concern(write_concern)
+            .build();
+
+        let update = collection.update_one(filter, update, options);
+        let Ok(result) = tokio::time::timeout(self.write_timeout, update).await else {
+            error!(
+                "Bucket update in collection '{}' timed out after {:?}",
+                collection_name, self.write_timeout
+            );
+            return Err(StorageError::InsertTimeout(self.write_timeout));
+        };
+
+        match result {
+            Ok(result) => {
+                if result.upserted_id.is_some() {
+                    debug!("Started a new bucket in collection '{}'", collection_name);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to append to bucket in collection '{}': {}", collection_name, e);
+                Err(StorageError::InsertError(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_update() {
+        let timestamp = DateTime::from_millis(1_700_000_123_000);
+        let sample = doc! { "node": "n1", "timestamp": timestamp, "seq": 42_i64, "load_1min": 0.5 };
+        let (filter, update) = bucket_update(sample, Duration::from_secs(3600), 100).unwrap();
+
+        let size = bson::to_vec(&doc! { "timestamp": timestamp, "seq": 42_i64, "load_1min": 0.5 })
+            .unwrap()
+            .len() as i64;
+        assert_eq!(
+            filter,
+            doc! {
+                "node": "n1",
+                "bucket": DateTime::from_millis(1_699_999_200_000),
+                "count": { "$lt": 100_i64 },
+                "bytes": { "$lte": MAX_BUCKET_BYTES - size },
+            }
+        );
+        assert_eq!(
+            update.get_document("$push").unwrap(),
+            &doc! { "samples": {
+                "$each": [{ "timestamp": timestamp, "seq": 42_i64, "load_1min": 0.5 }],
+                "$slice": 100_i64,
+            } }
+        );
+        assert_eq!(update.get_document("$max").unwrap(), &doc! { "timestamp": timestamp, "seq": 42_i64 });
+
+        assert!(matches!(
+            bucket_update(doc! { "load_1min": 0.5 }, Duration::from_secs(60), 10),
+            Err(StorageError::InvalidDocument(_))
+        ));
+    }
+}
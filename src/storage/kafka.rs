@@ -0,0 +1,111 @@
+// Kafka backend - publishes metric documents to a topic for stream processing
+//
+// Each document is serialized with `--serialize` (relaxed extended JSON by
+// default, or MessagePack / CBOR; see serialize.rs) and produced to one
+// configured topic, keyed by `node` so a node's documents stay ordered within
+// a partition. The source collection travels in the `collection` header, the
+// payload format in `content-type`.
+//
+// The producer queue is bounded: when it is full the document is dropped and
+// logged instead of blocking the collection loop.
+
+use async_trait::async_trait;
+use bson::Document;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use tracing::{info, warn};
+
+use super::serialize::Serializer;
+use super::{StorageBackend, StorageError};
+
+/// Backend producing every metric document to a single Kafka topic
+pub struct KafkaBackend {
+    producer: FutureProducer,
+    topic: String,
+    serializer: Serializer,
+}
+
+impl KafkaBackend {
+    /// Creates a producer for `brokers` (comma-separated `host:port` list)
+    ///
+    /// `max_in_flight` bounds the number of queued, not yet acknowledged
+    /// messages; once reached, new documents are dropped. Payloads are
+    /// encoded with `serializer`.
+    pub fn new(brokers: &str, topic: &str, max_in_flight: usize, serializer: Serializer) -> Result<Self, StorageError> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("queue.buffering.max.messages", max_in_flight.max(1).to_string())
+            .set("message.timeout.ms", "30000")
+            .create()
+            .map_err(|e| backend_error(e.to_string()))?;
+
+        info!(
+            "Kafka backend ready: brokers={}, topic='{}', max in-flight={}, payload={}",
+            brokers, topic, max_in_flight, serializer.name()
+        );
+
+        Ok(KafkaBackend {
+            producer,
+            topic: topic.to_string(),
+            serializer,
+        })
+    }
+}
+
+fn backend_error(message: String) -> StorageError {
+    StorageError::BackendError {
+        backend: "kafka",
+        message,
+    }
+}
+
+#[async_trait]
+impl StorageBackend for KafkaBackend {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn insert(&self, collection: &str, document: Document) -> Result<(), StorageError> {
+        let key = document.get_str("node").unwrap_or_default().to_string();
+        let payload = self.serializer.encode(document)?;
+
+        let record = FutureRecord::to(&self.topic)
+            .key(&key)
+            .payload(&payload)
+            .headers(
+                OwnedHeaders::new()
+                    .insert(Header {
+                        key: "collection",
+                        value: Some(collection),
+                    })
+                    .insert(Header {
+                        key: "content-type",
+                        value: Some(self.serializer.content_type()),
+                    }),
+            );
+
+        // send_result only enqueues; delivery is confirmed in the background
+        match self.producer.send_result(record) {
+            Ok(delivery) => {
+                let collection = collection.to_string();
+                tokio::spawn(async move {
+                    match delivery.await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err((e, _))) => {
+                            warn!("Kafka delivery failed for '{}' document: {}", collection, e)
+                        }
+                        Err(_) => warn!("Kafka delivery for '{}' document was cancelled", collection),
+                    }
+                });
+                Ok(())
+            }
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                warn!("Kafka producer queue full, dropping '{}' document", collection);
+                Err(backend_error("producer queue full, document dropped".to_string()))
+            }
+            Err((e, _)) => Err(backend_error(e.to_string())),
+        }
+    }
+}
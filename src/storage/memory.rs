@@ -0,0 +1,45 @@
+// In-memory backend - captures metric documents for tests
+//
+// Clones share the same buffer: keep one handle in the test and pass a clone
+// to `MetricStorage::with_backend`.
+
+use async_trait::async_trait;
+use bson::Document;
+use std::sync::{Arc, Mutex};
+
+use super::{StorageBackend, StorageError};
+
+/// Backend recording every inserted document, in insertion order
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    documents: Arc<Mutex<Vec<(String, Document)>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Documents written to `collection` so far
+    pub fn documents(&self, collection: &str) -> Vec<Document> {
+        self.documents
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(c, _)| c == collection)
+            .map(|(_, d)| d.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn insert(&self, collection: &str, document: Document) -> Result<(), StorageError> {
+        self.documents.lock().unwrap().push((collection.to_string(), document));
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+// Outage buffer - holds documents in memory while writes fail
+//
+// With `--outage-buffer <N>`, a document whose store still fails after the
+// retry is queued instead of dropped, up to N per collection. The next store
+// to that collection first writes the queue oldest first, so a sub-minute
+// MongoDB hiccup delays documents instead of losing them, and per collection
+// they still arrive in order. When a queue is full its oldest document is
+// dropped. The queue is in memory only: a restart during an outage loses it.
+// Under `--max-rss-mib`, the memory guard sheds the older half of every
+// queue whenever the process grows past its ceiling.
+
+use bson::Document;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// A queued document with the name of the metric it belongs to
+pub type Held = (String, Document);
+
+/// One collection's queued documents, oldest first
+pub type Queue = Arc<tokio::sync::Mutex<VecDeque<Held>>>;
+
+/// Bounded per-collection queues of documents waiting for storage to recover
+pub struct OutageBuffer {
+    capacity: usize,
+    queues: Mutex<HashMap<String, Queue>>,
+}
+
+impl OutageBuffer {
+    /// Creates the buffer; `capacity` is per collection and at least 1
+    pub fn new(capacity: usize) -> Self {
+        OutageBuffer {
+            capacity: capacity.max(1),
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The queue for `collection`; callers hold its lock across the write so
+    /// documents of one collection are stored in order
+    pub fn queue(&self, collection: &str) -> Queue {
+        let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(queues.entry(collection.to_string()).or_default())
+    }
+
+    /// Appends a document that couldn't be stored, dropping the oldest if full
+    pub fn hold(&self, queue: &mut VecDeque<Held>, collection: &str, metric_name: &str, document: Document) {
+        if queue.is_empty() {
+            warn!(
+                "Writes to '{}' failing, holding documents in memory (up to {})",
+                collection, self.capacity
+            );
+        }
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            warn!("Outage buffer for '{}' full, dropped its oldest document", collection);
+        }
+        queue.push_back((metric_name.to_string(), document));
+    }
+
+    /// Drops the older half of every queue (at least one document from a
+    /// non-empty one), returning how many were dropped
+    ///
+    /// A queue locked by a write in progress is skipped until the next call.
+    pub fn shed(&self) -> usize {
+        let queues: Vec<(String, Queue)> = {
+            let queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+            queues.iter().map(|(name, queue)| (name.clone(), Arc::clone(queue))).collect()
+        };
+        let mut dropped = 0;
+        for (collection, queue) in queues {
+            let Ok(mut queue) = queue.try_lock() else {
+                continue;
+            };
+            let count = queue.len().div_ceil(2);
+            if count > 0 {
+                queue.drain(..count);
+                warn!("Memory guard dropped the {} oldest held document(s) of '{}'", count, collection);
+                dropped += count;
+            }
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn test_hold_drops_oldest_when_full() {
+        let buffer = OutageBuffer::new(2);
+        let mut queue = VecDeque::new();
+        for seq in 1..=3_i64 {
+            buffer.hold(&mut queue, "memory_metrics", "Memory", doc! { "seq": seq });
+        }
+        let seqs: Vec<i64> = queue.iter().map(|(_, d)| d.get_i64("seq").unwrap()).collect();
+        assert_eq!(seqs, [2, 3]);
+    }
+
+    #[test]
+    fn test_shed_drops_older_half() {
+        let buffer = OutageBuffer::new(10);
+        let queue = buffer.queue("memory_metrics");
+        for seq in 1..=5_i64 {
+            buffer.hold(&mut queue.try_lock().unwrap(), "memory_metrics", "Memory", doc! { "seq": seq });
+        }
+        assert_eq!(buffer.shed(), 3);
+        let seqs: Vec<i64> = queue.try_lock().unwrap().iter().map(|(_, d)| d.get_i64("seq").unwrap()).collect();
+        assert_eq!(seqs, [4, 5]);
+    }
+}
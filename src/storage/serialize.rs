@@ -0,0 +1,394 @@
+// Serializers - document payload formats for streaming backends
+//
+// The MongoDB backends store native BSON. Backends that ship bytes elsewhere
+// (Kafka) encode each document with the `--serialize` format:
+//
+// | BSON        | json (default)              | msgpack                       | cbor                           |
+// |-------------|-----------------------------|-------------------------------|--------------------------------|
+// | DateTime    | `{"$date": "<RFC 3339>"}`   | timestamp extension (type -1) | tag 1, epoch seconds (int/float) |
+// | Decimal128  | `{"$numberDecimal": "..."}` | extension type 1, decimal string | tag 4, `[exponent, mantissa]` |
+// | ObjectId    | `{"$oid": "..."}`           | hex string                    | hex string                     |
+// | Binary      | `{"$binary": ...}`          | bin                           | byte string                    |
+//
+// JSON is relaxed extended JSON, as before. In MessagePack and CBOR every
+// other type maps to its native counterpart (Int32 and Int64 both become an
+// integer), and anything without one (regex, timestamp, ...) to its relaxed
+// extended JSON text. Times keep millisecond precision, except the top-level
+// `timestamp` of a document stamped under `--timestamp-precision us|ns`: it
+// takes the full time from `timestamp_us`/`timestamp_ns` (CBOR's float
+// seconds hold microseconds exactly, not nanoseconds). In CBOR, a
+// Decimal128 that is non-finite or whose mantissa doesn't fit a CBOR
+// integer becomes a float.
+//
+// The encoders, and the `rmpv`/`ciborium` crates, are only built with the
+// `kafka` feature; `Serializer` itself is always there to parse `--serialize`.
+
+#[cfg(feature = "kafka")]
+use bson::{Bson, Document};
+
+#[cfg(feature = "kafka")]
+use super::StorageError;
+
+/// MessagePack extension type of a Decimal128 (its decimal string as UTF-8)
+#[cfg(feature = "kafka")]
+const MSGPACK_DECIMAL: i8 = 1;
+
+/// MessagePack's predefined timestamp extension type
+#[cfg(feature = "kafka")]
+const MSGPACK_TIMESTAMP: i8 = -1;
+
+/// CBOR tag for an epoch-based date/time (RFC 8949 §3.4.2)
+#[cfg(feature = "kafka")]
+const CBOR_EPOCH: u64 = 1;
+
+/// CBOR tag for a decimal fraction (RFC 8949 §3.4.4)
+#[cfg(feature = "kafka")]
+const CBOR_DECIMAL: u64 = 4;
+
+/// Payload format of a streaming backend (`--serialize`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Serializer {
+    /// Relaxed extended JSON (default)
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Serializer {
+    /// Name as given to `--serialize`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Serializer::Json => "json",
+            Serializer::MessagePack => "msgpack",
+            Serializer::Cbor => "cbor",
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl Serializer {
+    /// MIME type of the payload, for a `content-type` header
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Serializer::Json => "application/json",
+            Serializer::MessagePack => "application/msgpack",
+            Serializer::Cbor => "application/cbor",
+        }
+    }
+
+    /// Encodes `document` in this format
+    pub fn encode(&self, document: Document) -> Result<Vec<u8>, StorageError> {
+        let invalid = |e: String| StorageError::InvalidDocument(e);
+        let precise = precise_timestamp(&document);
+        match self {
+            Serializer::Json => serde_json::to_vec(&Bson::Document(document).into_relaxed_extjson())
+                .map_err(|e| invalid(e.to_string())),
+            Serializer::MessagePack => {
+                let mut value = to_msgpack(Bson::Document(document));
+                if let (Some(nanos), rmpv::Value::Map(entries)) = (precise, &mut value) {
+                    if let Some((_, time)) = entries.iter_mut().find(|(key, _)| key.as_str() == Some("timestamp")) {
+                        *time = msgpack_timestamp(nanos);
+                    }
+                }
+                let mut payload = Vec::new();
+                rmpv::encode::write_value(&mut payload, &value).map_err(|e| invalid(e.to_string()))?;
+                Ok(payload)
+            }
+            Serializer::Cbor => {
+                let mut value = to_cbor(Bson::Document(document));
+                if let (Some(nanos), ciborium::Value::Map(entries)) = (precise, &mut value) {
+                    if let Some((_, time)) = entries.iter_mut().find(|(key, _)| key.as_text() == Some("timestamp")) {
+                        *time = cbor_epoch(nanos);
+                    }
+                }
+                let mut payload = Vec::new();
+                ciborium::into_writer(&value, &mut payload).map_err(|e| invalid(e.to_string()))?;
+                Ok(payload)
+            }
+        }
+    }
+}
+
+/// Nanoseconds since the epoch of the document's `timestamp`, from
+/// `timestamp_ns` or `timestamp_us` when present and consistent with it
+#[cfg(feature = "kafka")]
+fn precise_timestamp(document: &Document) -> Option<i128> {
+    let millis = document.get_datetime("timestamp").ok()?.timestamp_millis();
+    let nanos = match (document.get_i64("timestamp_ns"), document.get_i64("timestamp_us")) {
+        (Ok(nanos), _) => i128::from(nanos),
+        (_, Ok(micros)) => i128::from(micros) * 1000,
+        _ => return None,
+    };
+    // A stale or hand-edited field must not move the time
+    (nanos.div_euclid(1_000_000) == i128::from(millis)).then_some(nanos)
+}
+
+/// MessagePack timestamp 96: nanoseconds (u32) then seconds (i64), big-endian
+#[cfg(feature = "kafka")]
+fn msgpack_timestamp(nanos: i128) -> rmpv::Value {
+    let mut payload = (nanos.rem_euclid(1_000_000_000) as u32).to_be_bytes().to_vec();
+    payload.extend_from_slice(&(nanos.div_euclid(1_000_000_000) as i64).to_be_bytes());
+    rmpv::Value::Ext(MSGPACK_TIMESTAMP, payload)
+}
+
+/// CBOR epoch date/time: integer seconds when whole, else float seconds
+#[cfg(feature = "kafka")]
+fn cbor_epoch(nanos: i128) -> ciborium::Value {
+    use ciborium::Value;
+    let seconds = if nanos % 1_000_000_000 == 0 {
+        Value::Integer(((nanos / 1_000_000_000) as i64).into())
+    } else {
+        Value::Float(nanos as f64 / 1e9)
+    };
+    Value::Tag(CBOR_EPOCH, Box::new(seconds))
+}
+
+#[cfg(feature = "kafka")]
+fn to_msgpack(value: Bson) -> rmpv::Value {
+    use rmpv::Value;
+    match value {
+        Bson::Document(document) => Value::Map(
+            document
+                .into_iter()
+                .map(|(key, value)| (Value::from(key), to_msgpack(value)))
+                .collect(),
+        ),
+        Bson::Array(values) => Value::Array(values.into_iter().map(to_msgpack).collect()),
+        Bson::Double(v) => Value::F64(v),
+        Bson::String(v) => Value::from(v),
+        Bson::Boolean(v) => Value::Boolean(v),
+        Bson::Null | Bson::Undefined => Value::Nil,
+        Bson::Int32(v) => Value::from(v),
+        Bson::Int64(v) => Value::from(v),
+        Bson::DateTime(dt) => msgpack_timestamp(i128::from(dt.timestamp_millis()) * 1_000_000),
+        Bson::Decimal128(v) => Value::Ext(MSGPACK_DECIMAL, v.to_string().into_bytes()),
+        Bson::ObjectId(v) => Value::from(v.to_hex()),
+        Bson::Binary(v) => Value::Binary(v.bytes),
+        other => Value::from(other.into_relaxed_extjson().to_string()),
+    }
+}
+
+#[cfg(feature = "kafka")]
+fn to_cbor(value: Bson) -> ciborium::Value {
+    use ciborium::Value;
+    match value {
+        Bson::Document(document) => Value::Map(
+            document
+                .into_iter()
+                .map(|(key, value)| (Value::Text(key), to_cbor(value)))
+                .collect(),
+        ),
+        Bson::Array(values) => Value::Array(values.into_iter().map(to_cbor).collect()),
+        Bson::Double(v) => Value::Float(v),
+        Bson::String(v) => Value::Text(v),
+        Bson::Boolean(v) => Value::Bool(v),
+        Bson::Null | Bson::Undefined => Value::Null,
+        Bson::Int32(v) => Value::Integer(v.into()),
+        Bson::Int64(v) => Value::Integer(v.into()),
+        Bson::DateTime(dt) => cbor_epoch(i128::from(dt.timestamp_millis()) * 1_000_000),
+        Bson::Decimal128(v) => {
+            let text = v.to_string();
+            match decimal_parts(&text).and_then(|(exponent, mantissa)| Some((exponent, mantissa.try_into().ok()?))) {
+                Some((exponent, mantissa)) => Value::Tag(
+                    CBOR_DECIMAL,
+                    Box::new(Value::Array(vec![Value::Integer(exponent.into()), Value::Integer(mantissa)])),
+                ),
+                // NaN, the infinities, and mantissas too large for a CBOR integer
+                None => Value::Float(text.parse().unwrap_or(f64::NAN)),
+            }
+        }
+        Bson::ObjectId(v) => Value::Text(v.to_hex()),
+        Bson::Binary(v) => Value::Bytes(v.bytes),
+        other => Value::Text(other.into_relaxed_extjson().to_string()),
+    }
+}
+
+/// (exponent, mantissa) of a finite decimal string such as "-12.5E+3";
+/// None for NaN and the infinities
+#[cfg(feature = "kafka")]
+fn decimal_parts(text: &str) -> Option<(i64, i128)> {
+    let (number, exponent) = match text.split_once(['E', 'e']) {
+        Some((number, exponent)) => (number, exponent.parse::<i64>().ok()?),
+        None => (text, 0),
+    };
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let mantissa = format!("{}{}", integer, fraction).parse().ok()?;
+    Some((exponent - fraction.len() as i64, mantissa))
+}
+
+#[cfg(all(test, feature = "kafka"))]
+mod tests {
+    use super::*;
+    use bson::{doc, oid::ObjectId, Decimal128};
+
+    /// A metric document with every type the collectors produce
+    fn representative() -> Document {
+        doc! {
+            "node": "0001-0001",
+            "timestamp": bson::DateTime::from_millis(1_775_649_605_250),
+            "seq": 1234_i64,
+            "sample_count": 12,
+            "cpu_percent": { "avg": 25.1, "min": 18.0, "max": 42.5 },
+            "rx_bytes": "18446744073709551615".parse::<Decimal128>().unwrap(),
+            "ratio": "-0.0125".parse::<Decimal128>().unwrap(),
+            "overloaded": false,
+            "last_oom_process": Bson::Null,
+            "containers": [ { "name": "web", "ports": [80, 443] } ],
+            "_id": ObjectId::parse_str("65f1a2b3c4d5e6f708192a3b").unwrap(),
+        }
+    }
+
+    /// `value` with integers widened to Int64, and ObjectIds as hex strings
+    /// unless `keep_oid`
+    fn normalize(value: Bson, keep_oid: bool) -> Bson {
+        match value {
+            Bson::Document(d) => Bson::Document(d.into_iter().map(|(k, v)| (k, normalize(v, keep_oid))).collect()),
+            Bson::Array(a) => Bson::Array(a.into_iter().map(|v| normalize(v, keep_oid)).collect()),
+            Bson::Int32(v) => Bson::Int64(v.into()),
+            Bson::ObjectId(v) if !keep_oid => Bson::String(v.to_hex()),
+            other => other,
+        }
+    }
+
+    /// What a consumer decoding a payload should get back
+    fn expected(keep_oid: bool) -> Document {
+        match normalize(Bson::Document(representative()), keep_oid) {
+            Bson::Document(d) => d,
+            _ => unreachable!(),
+        }
+    }
+
+    fn from_msgpack(value: rmpv::Value) -> Bson {
+        use rmpv::Value;
+        match value {
+            Value::Map(entries) => Bson::Document(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.as_str().unwrap().to_string(), from_msgpack(v)))
+                    .collect(),
+            ),
+            Value::Array(values) => Bson::Array(values.into_iter().map(from_msgpack).collect()),
+            Value::F64(v) => Bson::Double(v),
+            Value::String(v) => Bson::String(v.into_str().unwrap()),
+            Value::Boolean(v) => Bson::Boolean(v),
+            Value::Nil => Bson::Null,
+            Value::Integer(v) => Bson::Int64(v.as_i64().unwrap()),
+            Value::Ext(MSGPACK_TIMESTAMP, payload) => {
+                let nanos = u32::from_be_bytes(payload[..4].try_into().unwrap());
+                let seconds = i64::from_be_bytes(payload[4..].try_into().unwrap());
+                Bson::DateTime(bson::DateTime::from_millis(seconds * 1000 + i64::from(nanos / 1_000_000)))
+            }
+            Value::Ext(MSGPACK_DECIMAL, payload) => {
+                Bson::Decimal128(String::from_utf8(payload).unwrap().parse().unwrap())
+            }
+            other => panic!("unexpected MessagePack value {:?}", other),
+        }
+    }
+
+    fn from_cbor(value: ciborium::Value) -> Bson {
+        use ciborium::Value;
+        match value {
+            Value::Map(entries) => Bson::Document(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.into_text().unwrap(), from_cbor(v)))
+                    .collect(),
+            ),
+            Value::Array(values) => Bson::Array(values.into_iter().map(from_cbor).collect()),
+            Value::Float(v) => Bson::Double(v),
+            Value::Text(v) => Bson::String(v),
+            Value::Bool(v) => Bson::Boolean(v),
+            Value::Null => Bson::Null,
+            Value::Integer(v) => Bson::Int64(i128::from(v) as i64),
+            Value::Tag(CBOR_EPOCH, seconds) => {
+                let millis = match *seconds {
+                    Value::Integer(s) => i128::from(s) as i64 * 1000,
+                    Value::Float(s) => (s * 1000.0).round() as i64,
+                    other => panic!("unexpected epoch {:?}", other),
+                };
+                Bson::DateTime(bson::DateTime::from_millis(millis))
+            }
+            Value::Tag(CBOR_DECIMAL, parts) => {
+                let parts = parts.into_array().unwrap();
+                let exponent = i128::from(parts[0].as_integer().unwrap());
+                let mantissa = i128::from(parts[1].as_integer().unwrap());
+                Bson::Decimal128(format!("{}E{}", mantissa, exponent).parse().unwrap())
+            }
+            other => panic!("unexpected CBOR value {:?}", other),
+        }
+    }
+
+    /// Decimal128 values compare by bit pattern, and "12.5" may come back as
+    /// "125E-1", so compare their (exponent, mantissa) instead
+    fn assert_same(decoded: Bson, expected: Document) {
+        let (Bson::Document(mut decoded), mut expected) = (decoded, expected) else {
+            panic!("not a document");
+        };
+        for field in ["rx_bytes", "ratio"] {
+            let value = |d: &mut Document| match d.remove(field) {
+                Some(Bson::Decimal128(v)) => decimal_parts(&v.to_string()),
+                other => panic!("{}: not a decimal: {:?}", field, other),
+            };
+            assert_eq!(value(&mut decoded), value(&mut expected), "{}", field);
+        }
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let payload = Serializer::Json.encode(representative()).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(json["timestamp"]["$date"], "2026-04-08T12:00:05.25Z");
+        assert_same(normalize(Bson::try_from(json).unwrap(), true), expected(true));
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let payload = Serializer::MessagePack.encode(representative()).unwrap();
+        let value = rmpv::decode::read_value(&mut payload.as_slice()).unwrap();
+        assert_same(from_msgpack(value), expected(false));
+    }
+
+    #[test]
+    fn test_cbor_round_trip() {
+        let payload = Serializer::Cbor.encode(representative()).unwrap();
+        let value: ciborium::Value = ciborium::from_reader(payload.as_slice()).unwrap();
+        assert_same(from_cbor(value), expected(false));
+    }
+
+    #[test]
+    fn test_precise_timestamp_from_us_and_ns_fields() {
+        let at = bson::DateTime::from_millis(1_775_649_605_250);
+        let nanos = 1_775_649_605_250_123_456_i64;
+
+        let stamped = doc! { "timestamp": at, "timestamp_ns": nanos, "value": 1 };
+        let payload = Serializer::MessagePack.encode(stamped).unwrap();
+        let value = rmpv::decode::read_value(&mut payload.as_slice()).unwrap();
+        let time = value.as_map().unwrap().iter().find(|(k, _)| k.as_str() == Some("timestamp")).unwrap();
+        let rmpv::Value::Ext(MSGPACK_TIMESTAMP, payload) = &time.1 else { panic!("not a timestamp") };
+        assert_eq!(u32::from_be_bytes(payload[..4].try_into().unwrap()), 250_123_456);
+        assert_eq!(i64::from_be_bytes(payload[4..].try_into().unwrap()), 1_775_649_605);
+
+        let stamped = doc! { "timestamp": at, "timestamp_us": nanos / 1000 };
+        let payload = Serializer::Cbor.encode(stamped).unwrap();
+        let value: ciborium::Value = ciborium::from_reader(payload.as_slice()).unwrap();
+        let (_, time) = &value.as_map().unwrap()[0];
+        let ciborium::Value::Tag(CBOR_EPOCH, seconds) = time else { panic!("not an epoch") };
+        let micros = (seconds.as_float().unwrap() * 1e6).round() as i64;
+        assert_eq!(micros, nanos / 1000);
+
+        // Fields that disagree with `timestamp` are ignored
+        assert_eq!(precise_timestamp(&doc! { "timestamp": at, "timestamp_us": 5_i64 }), None);
+        assert_eq!(precise_timestamp(&doc! { "timestamp": at }), None);
+    }
+
+    #[test]
+    fn test_decimal_parts() {
+        assert_eq!(decimal_parts("12345"), Some((0, 12345)));
+        assert_eq!(decimal_parts("-12.5E+3"), Some((2, -125)));
+        assert_eq!(decimal_parts("0.001"), Some((-3, 1)));
+        assert_eq!(decimal_parts("NaN"), None);
+        assert_eq!(decimal_parts("-Infinity"), None);
+    }
+}
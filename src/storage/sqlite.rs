@@ -0,0 +1,159 @@
+// SQLite backend - local store-and-forward buffer for edge devices
+//
+// Each collection becomes a table holding the document as relaxed extended
+// JSON, with `node` and `timestamp` (epoch milliseconds) in indexed columns
+// so a separate sync process can page through rows in order and ship them
+// upstream. Tables are created on first write.
+//
+// rusqlite is blocking, so every write runs on the blocking thread pool.
+
+use async_trait::async_trait;
+use bson::{Bson, Document};
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+use super::{StorageBackend, StorageError};
+
+/// Backend writing every metric document to a local SQLite database
+pub struct SqliteBackend {
+    connection: Arc<Mutex<Connection>>,
+    /// Tables already created by this process
+    tables: Arc<Mutex<HashSet<String>>>,
+}
+
+impl SqliteBackend {
+    /// Opens (or creates) the database file at `path`
+    ///
+    /// WAL mode lets a sync process read while the collector writes.
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let connection = Connection::open(path).map_err(backend_error)?;
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(backend_error)?;
+        connection
+            .busy_timeout(std::time::Duration::from_secs(5))
+            .map_err(backend_error)?;
+
+        info!("SQLite backend ready: {}", path.display());
+
+        Ok(SqliteBackend {
+            connection: Arc::new(Mutex::new(connection)),
+            tables: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+}
+
+fn backend_error(e: impl ToString) -> StorageError {
+    StorageError::BackendError {
+        backend: "sqlite",
+        message: e.to_string(),
+    }
+}
+
+/// Quotes a collection name for use as a table identifier
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Creates the table and its `(node, timestamp)` index if they don't exist
+///
+/// `doc_id` holds a deterministic `_id` when one is set; its unique
+/// constraint makes a replayed insert a no-op, as with MongoDB.
+fn ensure_table(connection: &Connection, collection: &str) -> rusqlite::Result<()> {
+    let table = quote_identifier(collection);
+    let index = quote_identifier(&format!("{}_node_timestamp", collection));
+    connection.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+             id        INTEGER PRIMARY KEY AUTOINCREMENT,
+             doc_id    TEXT UNIQUE,
+             node      TEXT NOT NULL,
+             timestamp INTEGER NOT NULL,
+             payload   TEXT NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS {index} ON {table} (node, timestamp);"
+    ))
+}
+
+#[async_trait]
+impl StorageBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert(&self, collection: &str, document: Document) -> Result<(), StorageError> {
+        let node = document.get_str("node").unwrap_or_default().to_string();
+        let timestamp = document
+            .get_datetime("timestamp")
+            .map(|t| t.timestamp_millis())
+            .unwrap_or_default();
+        let doc_id = document.get_str("_id").ok().map(str::to_string);
+        let payload = serde_json::to_string(&Bson::Document(document).into_relaxed_extjson())
+            .map_err(|e| StorageError::InvalidDocument(e.to_string()))?;
+
+        let connection = Arc::clone(&self.connection);
+        let tables = Arc::clone(&self.tables);
+        let collection = collection.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+
+            let mut tables = tables.lock().unwrap_or_else(|e| e.into_inner());
+            if !tables.contains(&collection) {
+                ensure_table(&connection, &collection).map_err(backend_error)?;
+                tables.insert(collection.clone());
+            }
+            drop(tables);
+
+            let inserted = connection
+                .execute(
+                    &format!(
+                        "INSERT OR IGNORE INTO {} (doc_id, node, timestamp, payload) VALUES (?1, ?2, ?3, ?4)",
+                        quote_identifier(&collection)
+                    ),
+                    params![doc_id, node, timestamp, payload],
+                )
+                .map_err(backend_error)?;
+
+            if inserted == 0 {
+                debug!("Document {:?} already in table '{}', skipping", doc_id, collection);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(backend_error)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    #[tokio::test]
+    async fn test_insert_creates_table_and_skips_duplicate_ids() {
+        let path = std::env::temp_dir().join(format!("metrics-collector-{}.db", std::process::id()));
+        let backend = SqliteBackend::open(&path).unwrap();
+
+        let timestamp = bson::DateTime::from_millis(1_700_000_000_000);
+        let document = doc! { "_id": "n1:Memory:1700000000", "node": "n1", "timestamp": timestamp, "used_percent": 42.5 };
+        backend.insert("memory_metrics", document.clone()).await.unwrap();
+        backend.insert("memory_metrics", document).await.unwrap();
+
+        let connection = backend.connection.lock().unwrap();
+        let (count, node, stored_at, payload): (i64, String, i64, String) = connection
+            .query_row(
+                "SELECT COUNT(*), node, timestamp, payload FROM memory_metrics",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        drop(connection);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!((count, node.as_str(), stored_at), (1, "n1", 1_700_000_000_000));
+        assert!(payload.contains("\"used_percent\":42.5"));
+    }
+}